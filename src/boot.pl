@@ -0,0 +1,861 @@
+% Predicates defined in Prolog on top of the builtins of the interpreter. Compiled before every
+% user program.
+
+% Helpers ----------------------------------------------------------------------------------
+
+% Looks at the tail before trying an element, so no choice point is left on the last one.
+'$member'(X, [Y|Ys]) :-
+    '$member'(Ys, X, Y).
+
+'$member'(_, X, X).
+'$member'([Y|Ys], X, _) :-
+    '$member'(Ys, X, Y).
+
+% Modules ----------------------------------------------------------------------------------
+
+% Arguments marked 0-9, `:`, `^` or `//` are goals, closures or grammar bodies. Called from a module other than
+% `user`, they are qualified with the module of the caller.
+:- meta_predicate
+    call(0), call(1, ?), call(2, ?, ?), call(3, ?, ?, ?), call(4, ?, ?, ?, ?),
+    call(5, ?, ?, ?, ?, ?), call(6, ?, ?, ?, ?, ?, ?), call(7, ?, ?, ?, ?, ?, ?, ?),
+    ';'(0, 0), '->'(0, 0), \+(0), once(0), ignore(0), forall(0, 0),
+    findall(?, 0, -), findall(?, 0, -, ?), bagof(?, ^, -), setof(?, ^, -),
+    aggregate_all(?, 0, -),
+    maplist(1, ?), maplist(2, ?, ?), maplist(3, ?, ?, ?), maplist(4, ?, ?, ?, ?),
+    foldl(3, ?, ?, ?), foldl(4, ?, ?, ?, ?), foldl(5, ?, ?, ?, ?, ?),
+    include(1, ?, ?), exclude(1, ?, ?),
+    with_output_to(?, 0), initialization(0), initialization(0, ?),
+    phrase(//, ?), phrase(//, ?, ?), freeze(?, 0), when(+, 0).
+
+Module:Goal :-
+    call(Module:Goal).
+
+% Control constructs -----------------------------------------------------------------------
+
+% Control constructs whose branches cut the clause, and the ones using *->, are compiled into
+% auxiliary predicates. These clauses run the others, whose branches contain no cut.
+';'((If -> Then), Else) :-
+    !,
+    '$if_then_else'(If, Then, Else).
+';'((If *-> Then), Else) :-
+    !,
+    (If *-> Then ; Else).
+';'(Either, _) :-
+    call(Either).
+';'(_, Or) :-
+    call(Or).
+
+'$if_then_else'(If, Then, _) :-
+    call(If),
+    !,
+    call(Then).
+'$if_then_else'(_, _, Else) :-
+    call(Else).
+
+'->'(If, Then) :-
+    call(If),
+    !,
+    call(Then).
+
+'*->'(If, Then) :-
+    (If *-> Then).
+
+\+ Goal :-
+    call(Goal),
+    !,
+    fail.
+\+ _.
+
+once(Goal) :-
+    call(Goal),
+    !.
+
+ignore(Goal) :-
+    call(Goal),
+    !.
+ignore(_).
+
+forall(Condition, Action) :-
+    \+ (call(Condition), \+ call(Action)).
+
+% All-solutions predicates ------------------------------------------------------------------
+
+findall(Template, Goal, Bag) :-
+    findall(Template, Goal, Bag, []).
+
+% Every solution is copied into a buffer which is kept outside of the global stack, so it
+% survives the backtracking into `Goal`.
+findall(Template, Goal, Bag, Tail) :-
+    '$findall_start',
+    '$findall_run'(Template, Goal),
+    '$findall_collect'(Bag0, Tail),
+    Bag = Bag0.
+
+'$findall_run'(Template, Goal) :-
+    call(Goal),
+    '$findall_add'(Template),
+    fail.
+'$findall_run'(_, _).
+
+% The witness is the list of variables of `Goal` that are neither in `Template` nor
+% existentially quantified with `^`. There is one solution per distinct binding of the witness.
+bagof(Template, Goal, Bag) :-
+    '$free_variables'(Template, Goal, Witness, Inner),
+    '$bagof'(Witness, Template, Inner, Bag).
+
+'$bagof'([], Template, Goal, Bag) :-
+    !,
+    findall(Template, Goal, Bag),
+    Bag \== [].
+'$bagof'(Witness, Template, Goal, Bag) :-
+    Witness \== [],
+    findall(Witness-Template, Goal, Pairs),
+    '$bagof_groups'(Pairs, Groups),
+    '$member'(Witness-Bag, Groups).
+
+
+setof(Template, Goal, Set) :-
+    bagof(Template, Goal, Bag),
+    sort(Bag, Set).
+
+aggregate_all(count, Goal, Count) :-
+    findall(x, Goal, Xs),
+    '$aggregate_count'(Xs, 0, Count).
+aggregate_all(sum(Expression), Goal, Sum) :-
+    findall(Expression, Goal, Expressions),
+    '$aggregate_sum'(Expressions, 0, Sum).
+aggregate_all(max(Expression), Goal, Max) :-
+    findall(Expression, Goal, [First|Expressions]),
+    Max0 is First,
+    '$aggregate_max'(Expressions, Max0, Max).
+aggregate_all(min(Expression), Goal, Min) :-
+    findall(Expression, Goal, [First|Expressions]),
+    Min0 is First,
+    '$aggregate_min'(Expressions, Min0, Min).
+aggregate_all(bag(Template), Goal, Bag) :-
+    findall(Template, Goal, Bag).
+aggregate_all(set(Template), Goal, Set) :-
+    findall(Template, Goal, Bag),
+    sort(Bag, Set).
+
+'$aggregate_count'([], Count, Count).
+'$aggregate_count'([_|Xs], Count0, Count) :-
+    Count1 is Count0 + 1,
+    '$aggregate_count'(Xs, Count1, Count).
+
+'$aggregate_sum'([], Sum, Sum).
+'$aggregate_sum'([X|Xs], Sum0, Sum) :-
+    Sum1 is Sum0 + X,
+    '$aggregate_sum'(Xs, Sum1, Sum).
+
+'$aggregate_max'([], Max, Max).
+'$aggregate_max'([X|Xs], Max0, Max) :-
+    Max1 is max(Max0, X),
+    '$aggregate_max'(Xs, Max1, Max).
+
+'$aggregate_min'([], Min, Min).
+'$aggregate_min'([X|Xs], Min0, Min) :-
+    Min1 is min(Min0, X),
+    '$aggregate_min'(Xs, Min1, Min).
+
+% library(apply) ---------------------------------------------------------------------------
+
+maplist(Goal, Xs) :-
+    '$maplist'(Xs, Goal).
+
+'$maplist'([], _).
+'$maplist'([X|Xs], Goal) :-
+    call(Goal, X),
+    '$maplist'(Xs, Goal).
+
+maplist(Goal, Xs, Ys) :-
+    '$maplist'(Xs, Ys, Goal).
+
+'$maplist'([], [], _).
+'$maplist'([X|Xs], [Y|Ys], Goal) :-
+    call(Goal, X, Y),
+    '$maplist'(Xs, Ys, Goal).
+
+maplist(Goal, Xs, Ys, Zs) :-
+    '$maplist'(Xs, Ys, Zs, Goal).
+
+'$maplist'([], [], [], _).
+'$maplist'([X|Xs], [Y|Ys], [Z|Zs], Goal) :-
+    call(Goal, X, Y, Z),
+    '$maplist'(Xs, Ys, Zs, Goal).
+
+maplist(Goal, Xs, Ys, Zs, Ws) :-
+    '$maplist'(Xs, Ys, Zs, Ws, Goal).
+
+'$maplist'([], [], [], [], _).
+'$maplist'([X|Xs], [Y|Ys], [Z|Zs], [W|Ws], Goal) :-
+    call(Goal, X, Y, Z, W),
+    '$maplist'(Xs, Ys, Zs, Ws, Goal).
+
+foldl(Goal, Xs, V0, V) :-
+    '$foldl'(Xs, Goal, V0, V).
+
+'$foldl'([], _, V, V).
+'$foldl'([X|Xs], Goal, V0, V) :-
+    call(Goal, X, V0, V1),
+    '$foldl'(Xs, Goal, V1, V).
+
+foldl(Goal, Xs, Ys, V0, V) :-
+    '$foldl'(Xs, Ys, Goal, V0, V).
+
+'$foldl'([], [], _, V, V).
+'$foldl'([X|Xs], [Y|Ys], Goal, V0, V) :-
+    call(Goal, X, Y, V0, V1),
+    '$foldl'(Xs, Ys, Goal, V1, V).
+
+foldl(Goal, Xs, Ys, Zs, V0, V) :-
+    '$foldl'(Xs, Ys, Zs, Goal, V0, V).
+
+'$foldl'([], [], [], _, V, V).
+'$foldl'([X|Xs], [Y|Ys], [Z|Zs], Goal, V0, V) :-
+    call(Goal, X, Y, Z, V0, V1),
+    '$foldl'(Xs, Ys, Zs, Goal, V1, V).
+
+include(Goal, Xs, Included) :-
+    '$include'(Xs, Goal, Included).
+
+'$include'([], _, []).
+'$include'([X|Xs], Goal, Included) :-
+    (   call(Goal, X)
+    ->  Included = [X|Included1]
+    ;   Included = Included1
+    ),
+    '$include'(Xs, Goal, Included1).
+
+exclude(Goal, Xs, Excluded) :-
+    '$exclude'(Xs, Goal, Excluded).
+
+'$exclude'([], _, []).
+'$exclude'([X|Xs], Goal, Excluded) :-
+    (   call(Goal, X)
+    ->  Excluded = Excluded1
+    ;   Excluded = [X|Excluded1]
+    ),
+    '$exclude'(Xs, Goal, Excluded1).
+
+% Grammar rules ----------------------------------------------------------------------------
+
+phrase(Body, List) :-
+    phrase(Body, List, []).
+
+phrase(Body, List, Rest) :-
+    '$dcg_body'(Body, S0, S, Goal),
+    S0 = List,
+    S = Rest,
+    call(Goal).
+
+% Dynamic database -------------------------------------------------------------------------
+
+% Both iterate over the clauses existing when they are called, so clauses added or removed
+% meanwhile don't affect them.
+retract(Clause) :-
+    '$clause_parts'(Clause, Head, Body),
+    '$clause_refs'(Head, Refs),
+    '$member'(Ref, Refs),
+    '$clause'(Ref, Head, Body),
+    '$erase'(Ref).
+
+retractall(Head) :-
+    functor(Head, Name, Arity),
+    dynamic(Name/Arity),
+    '$clause_refs'(Head, Refs),
+    '$retractall'(Refs, Head).
+
+'$retractall'([], _).
+'$retractall'([Ref|Refs], Head) :-
+    (   \+ \+ '$clause'(Ref, Head, _)
+    ->  ignore('$erase'(Ref))
+    ;   true
+    ),
+    '$retractall'(Refs, Head).
+
+'$clause_parts'(Clause, Head, Body) :-
+    nonvar(Clause),
+    Clause = (Head :- Body),
+    !.
+'$clause_parts'(Head, Head, true).
+
+% Reflection ---------------------------------------------------------------------------------
+
+clause(Head, Body) :-
+    '$clauses'(Head, Body, Clauses),
+    '$member'(Head-Body, Clauses).
+
+current_predicate(Indicator) :-
+    '$current_predicates'(Indicator, Indicators),
+    '$member'(Indicator, Indicators).
+
+predicate_property(Head, Property) :-
+    (   var(Head)
+    ->  '$current_predicates'(_, Indicators),
+        '$member'(Name/Arity, Indicators),
+        functor(Head, Name, Arity)
+    ;   true
+    ),
+    '$predicate_properties'(Head, Properties),
+    '$member'(Property, Properties).
+
+% Flags ------------------------------------------------------------------------------------
+
+current_prolog_flag(Flag, Value) :-
+    '$prolog_flags'(Flag, Flags),
+    (   nonvar(Flag)
+    ->  Flags = [Flag-Value]
+    ;   '$member'(Flag-Value, Flags)
+    ).
+
+% Coroutining -------------------------------------------------------------------------------
+
+% Binding attributed variables wakes the `attr_unify_hook/2` of the modules of their
+% attributes. The machine calls this before the next goal, with the arguments of that goal.
+'$wakeup'(Wakeups, Arguments) :-
+    '$attr_unify_hooks'(Wakeups),
+    '$restore_arguments'(Arguments).
+
+'$attr_unify_hooks'([]) :-
+    !.
+'$attr_unify_hooks'(['$wake'(Attributes, Value)|Wakeups]) :-
+    '$attr_unify_hook'(Attributes, Value),
+    '$attr_unify_hooks'(Wakeups).
+
+'$attr_unify_hook'([], _) :-
+    !.
+'$attr_unify_hook'(att(Module, Attribute, More), Value) :-
+    Module:attr_unify_hook(Attribute, Value),
+    '$attr_unify_hook'(More, Value).
+
+% The goals describing the attributes of `Variables`, as printed with the answers. Modules
+% describe their attributes with `attribute_goals/3`, the others are shown as put_attr/3.
+'$residual_goals'(Variables, Goals) :-
+    term_attvars(Variables, AttVars),
+    '$attvars_goals'(AttVars, Goals0, []),
+    '$unique_goals'(Goals0, [], Goals),
+    !.
+
+'$attvars_goals'([], Goals, Goals).
+'$attvars_goals'([Var|Vars], Goals0, Goals) :-
+    get_attrs(Var, Attributes),
+    '$attributes_goals'(Attributes, Var, Goals0, Goals1),
+    '$attvars_goals'(Vars, Goals1, Goals).
+
+'$attributes_goals'([], _, Goals, Goals).
+'$attributes_goals'(att(Module, Value, More), Var, Goals0, Goals) :-
+    (   '$module_defines'(Module, attribute_goals(_, _, _)),
+        Module:attribute_goals(Var, Goals0, Goals1)
+    ->  true
+    ;   Goals0 = [put_attr(Var, Module, Value)|Goals1]
+    ),
+    '$attributes_goals'(More, Var, Goals1, Goals).
+
+'$unique_goals'([], _, []).
+'$unique_goals'([Goal|Goals], Seen, Unique) :-
+    (   '$member_eq'(Goal, Seen)
+    ->  Unique = Unique1
+    ;   Unique = [Goal|Unique1]
+    ),
+    '$unique_goals'(Goals, [Goal|Seen], Unique1).
+
+'$member_eq'(X, [Y|Ys]) :-
+    (   X == Y
+    ->  true
+    ;   '$member_eq'(X, Ys)
+    ).
+
+% Adds `Goal` to the list of goals suspended on each of `Vars` for `Module`, unless it is
+% there already.
+'$suspend'([], _, _) :-
+    !.
+'$suspend'([Var|Vars], Module, Goal) :-
+    (   get_attr(Var, Module, Goals)
+    ->  (   '$member_eq'(Goal, Goals)
+        ->  true
+        ;   put_attr(Var, Module, [Goal|Goals])
+        )
+    ;   put_attr(Var, Module, [Goal])
+    ),
+    '$suspend'(Vars, Module, Goal).
+
+'$call_all'([]) :-
+    !.
+'$call_all'([Goal|Goals]) :-
+    call(Goal),
+    '$call_all'(Goals).
+
+freeze(Var, Goal) :-
+    (   nonvar(Var)
+    ->  call(Goal)
+    ;   get_attr(Var, freeze, Goals)
+    ->  put_attr(Var, freeze, (Goals, Goal))
+    ;   put_attr(Var, freeze, Goal)
+    ).
+
+frozen(Var, Goal) :-
+    (   var(Var),
+        get_attr(Var, freeze, Goals)
+    ->  Goal = freeze(Var, Goals)
+    ;   Goal = true
+    ).
+
+freeze:attr_unify_hook(Goals, Value) :-
+    '$freeze'(Value, Goals).
+freeze:attribute_goals(Var, [freeze(Var, Goals)|Rest], Rest) :-
+    get_attr(Var, freeze, Goals).
+
+'$freeze'(Value, Goals) :-
+    freeze(Value, Goals).
+
+% `X` and `Y` must not become equal. Until unification decides that, the goal is suspended
+% on their variables and checked again whenever one of them is bound.
+dif(X, Y) :-
+    X \== Y,
+    (   X \= Y
+    ->  true
+    ;   term_variables(X-Y, Vars),
+        '$suspend'(Vars, dif, dif(X, Y))
+    ).
+
+dif:attr_unify_hook(Goals, _) :-
+    '$call_all'(Goals).
+dif:attribute_goals(Var, Goals0, Goals) :-
+    get_attr(Var, dif, Difs),
+    '$dif_goals'(Difs, Goals0, Goals).
+
+'$dif_goals'([], Goals, Goals).
+'$dif_goals'([dif(X, Y)|Difs], Goals0, Goals) :-
+    (   X \= Y
+    ->  Goals0 = Goals1
+    ;   Goals0 = [dif(X, Y)|Goals1]
+    ),
+    '$dif_goals'(Difs, Goals1, Goals).
+
+% `Goal` runs once `Condition` holds. Conditions are nonvar/1, ground/1, ?=/2 and their
+% conjunctions and disjunctions. The branches of a disjunction share the flag `Done`, so
+% the goal runs only once.
+when(Condition, Goal) :-
+    must_be(callable, Condition),
+    '$when'(Condition, _, Goal).
+
+'$when'(nonvar(X), Done, Goal) :-
+    !,
+    (   nonvar(X)
+    ->  '$when_run'(Done, Goal)
+    ;   '$suspend'([X], when, '$when'(nonvar(X), Done, Goal))
+    ).
+'$when'(ground(X), Done, Goal) :-
+    !,
+    (   term_variables(X, [Var|_])
+    ->  '$suspend'([Var], when, '$when'(ground(X), Done, Goal))
+    ;   '$when_run'(Done, Goal)
+    ).
+'$when'(?=(X, Y), Done, Goal) :-
+    !,
+    (   (   X == Y
+        ;   X \= Y
+        )
+    ->  '$when_run'(Done, Goal)
+    ;   term_variables(X-Y, Vars),
+        '$suspend'(Vars, when, '$when'(?=(X, Y), Done, Goal))
+    ).
+'$when'((Condition1, Condition2), Done, Goal) :-
+    !,
+    '$when'(Condition1, _, '$when'(Condition2, Done, Goal)).
+'$when'((Condition1 ; Condition2), Done, Goal) :-
+    !,
+    '$when'(Condition1, Done, Goal),
+    (   Done == true
+    ->  true
+    ;   '$when'(Condition2, Done, Goal)
+    ).
+
+'$when_run'(Done, Goal) :-
+    (   Done == true
+    ->  true
+    ;   Done = true,
+        call(Goal)
+    ).
+
+when:attr_unify_hook(Goals, _) :-
+    '$call_all'(Goals).
+when:attribute_goals(Var, Goals0, Goals) :-
+    get_attr(Var, when, Whens),
+    '$when_goals'(Whens, Goals0, Goals).
+
+'$when_goals'([], Goals, Goals).
+'$when_goals'(['$when'(Condition, Done, Goal)|Whens], Goals0, Goals) :-
+    (   Done == true
+    ->  Goals0 = Goals1
+    ;   '$when_goal'(Condition, Goal, When),
+        Goals0 = [When|Goals1]
+    ),
+    '$when_goals'(Whens, Goals1, Goals).
+
+% A conjunction is suspended as a condition waiting for the rest of it.
+'$when_goal'(Condition, Goal, When) :-
+    (   nonvar(Goal),
+        Goal = '$when'(Condition1, _, Goal1)
+    ->  '$when_goal'((Condition, Condition1), Goal1, When)
+    ;   When = when(Condition, Goal)
+    ).
+
+% Text ---------------------------------------------------------------------------------------
+
+atom_concat(Prefix, Suffix, Atom) :-
+    '$atom_concat'(Prefix, Suffix, Atom, Splits),
+    '$member'(Prefix-Suffix, Splits).
+
+string_concat(Prefix, Suffix, String) :-
+    '$string_concat'(Prefix, Suffix, String, Splits),
+    '$member'(Prefix-Suffix, Splits).
+
+sub_atom(Atom, Before, Length, After, Sub) :-
+    '$sub_atom'(Atom, Before, Length, After, Sub, Solutions),
+    '$member'(s(Before, Length, After, Sub), Solutions).
+
+sub_string(String, Before, Length, After, Sub) :-
+    '$sub_string'(String, Before, Length, After, Sub, Solutions),
+    '$member'(s(Before, Length, After, Sub), Solutions).
+
+% Output -------------------------------------------------------------------------------------
+
+format(Format) :-
+    format(Format, []).
+
+write(Stream, Term) :-
+    write_term(Stream, Term, [numbervars(true)]).
+
+print(Stream, Term) :-
+    write_term(Stream, Term, [portray(true), numbervars(true), quoted(true)]).
+
+writeq(Stream, Term) :-
+    write_term(Stream, Term, [quoted(true), numbervars(true)]).
+
+write_canonical(Stream, Term) :-
+    write_term(Stream, Term, [quoted(true), ignore_ops(true)]).
+
+nl(Stream) :-
+    put_char(Stream, '\n').
+
+with_output_to(Sink, Goal) :-
+    current_output(Old),
+    '$memory_output'(Sink, Stream),
+    set_output(Stream),
+    (   call(Goal)
+    ->  set_output(Old),
+        '$memory_output_text'(Stream, Sink)
+    ;   set_output(Old),
+        close(Stream),
+        fail
+    ).
+
+% Streams ------------------------------------------------------------------------------------
+
+open(Source, Mode, Stream) :-
+    open(Source, Mode, Stream, []).
+
+close(Stream) :-
+    close(Stream, []).
+
+read(Term) :-
+    read_term(Term, []).
+
+read(Stream, Term) :-
+    read_term(Stream, Term, []).
+
+get_char(Char) :-
+    current_input(Stream),
+    get_char(Stream, Char).
+
+get_code(Code) :-
+    current_input(Stream),
+    get_code(Stream, Code).
+
+get_byte(Byte) :-
+    current_input(Stream),
+    get_byte(Stream, Byte).
+
+peek_char(Char) :-
+    current_input(Stream),
+    peek_char(Stream, Char).
+
+peek_code(Code) :-
+    current_input(Stream),
+    peek_code(Stream, Code).
+
+peek_byte(Byte) :-
+    current_input(Stream),
+    peek_byte(Stream, Byte).
+
+put_char(Char) :-
+    current_output(Stream),
+    put_char(Stream, Char).
+
+put_code(Code) :-
+    current_output(Stream),
+    put_code(Stream, Code).
+
+put_byte(Byte) :-
+    current_output(Stream),
+    put_byte(Stream, Byte).
+
+flush_output :-
+    current_output(Stream),
+    flush_output(Stream).
+
+at_end_of_stream :-
+    current_input(Stream),
+    at_end_of_stream(Stream).
+
+stream_property(Stream, Property) :-
+    '$streams'(Stream, Streams),
+    '$member'(Stream, Streams),
+    '$stream_properties'(Stream, Properties),
+    '$member'(Property, Properties).
+
+line_count(Stream, Count) :-
+    stream_property(Stream, position('$stream_position'(_, Count, _, _))).
+
+line_position(Stream, Count) :-
+    stream_property(Stream, position('$stream_position'(_, _, Count, _))).
+
+character_count(Stream, Count) :-
+    stream_property(Stream, position('$stream_position'(Count, _, _, _))).
+
+% Edinburgh-style I/O: files are opened on first use, with their name as alias, and `user`
+% stands for the standard streams.
+
+see(Source) :-
+    (   Source == user
+    ->  set_input(user_input)
+    ;   atom(Source),
+        stream_property(Stream, alias(Source))
+    ->  set_input(Stream)
+    ;   compound(Source)
+    ->  set_input(Source)
+    ;   open(Source, read, Stream, [alias(Source)]),
+        set_input(Stream)
+    ).
+
+seeing(Source) :-
+    current_input(Stream),
+    '$stream_name'(Stream, user_input, Source).
+
+seen :-
+    current_input(Stream),
+    close(Stream).
+
+tell(Sink) :-
+    '$tell'(Sink, write).
+
+append(Sink) :-
+    '$tell'(Sink, append).
+
+'$tell'(Sink, Mode) :-
+    (   Sink == user
+    ->  set_output(user_output)
+    ;   atom(Sink),
+        stream_property(Stream, alias(Sink))
+    ->  set_output(Stream)
+    ;   compound(Sink)
+    ->  set_output(Sink)
+    ;   open(Sink, Mode, Stream, [alias(Sink)]),
+        set_output(Stream)
+    ).
+
+telling(Sink) :-
+    current_output(Stream),
+    '$stream_name'(Stream, user_output, Sink).
+
+told :-
+    current_output(Stream),
+    close(Stream).
+
+'$stream_name'(Stream, User, Name) :-
+    (   stream_property(Stream, alias(User))
+    ->  Name = user
+    ;   stream_property(Stream, alias(Alias))
+    ->  Name = Alias
+    ;   Name = Stream
+    ).
+
+% Loading files ----------------------------------------------------------------------------
+
+consult(Files) :-
+    '$load_files'(Files, consult).
+
+ensure_loaded(Files) :-
+    (   '$compiler_directive'(ensure_loaded(Files))
+    ->  true
+    ;   '$load_files'(Files, ensure_loaded)
+    ).
+
+use_module(Spec) :-
+    (   '$compiler_directive'(use_module(Spec))
+    ->  true
+    ;   '$load_module'(Spec, Module),
+        '$compiler_directive'(use_module(Module))
+    ).
+
+use_module(Spec, Imports) :-
+    (   '$compiler_directive'(use_module(Spec, Imports))
+    ->  true
+    ;   '$load_module'(Spec, Module),
+        '$compiler_directive'(use_module(Module, Imports))
+    ).
+
+'$load_module'(Spec, Module) :-
+    '$load_files'(Spec, ensure_loaded),
+    '$absolute_file_name'(Spec, File),
+    '$file_module'(File, Module).
+
+'$load_files'([], _) :-
+    !.
+'$load_files'([Spec|Specs], How) :-
+    !,
+    '$load_files'(Spec, How),
+    '$load_files'(Specs, How).
+'$load_files'(Spec, How) :-
+    '$absolute_file_name'(Spec, File),
+    (   How == ensure_loaded,
+        '$loaded_file'(File)
+    ->  true
+    ;   '$load_file'(File)
+    ).
+
+% Clauses replace the ones of a previous load of the file. The goals of `initialization/1`
+% run once the whole file has been loaded.
+'$load_file'(File) :-
+    '$start_consult'(File),
+    '$load_source_file'(File),
+    '$end_consult'(Goals),
+    '$run_initialization'(Goals).
+
+'$load_source_file'(File) :-
+    open(File, read, Stream),
+    '$load_stream'(Stream),
+    close(Stream).
+
+'$load_stream'(Stream) :-
+    read_term(Stream, Term,
+              [syntax_errors(dec10), term_position('$stream_position'(_, Line, _, _))]),
+    '$load_stream'(Term, Line, Stream).
+
+'$load_stream'(Term, _, _) :-
+    Term == end_of_file,
+    !.
+'$load_stream'(Term, Line, Stream) :-
+    '$load_term'(Term, Line),
+    '$load_stream'(Stream).
+
+% Every term read is passed through expand_term/2, which may turn it into a list of clauses.
+% They are all reported at the line of the term read.
+'$load_term'(Term, Line) :-
+    expand_term(Term, Expanded),
+    (   is_list(Expanded)
+    ->  '$load_clauses'(Expanded, Line)
+    ;   '$load_clause'(Expanded, Line)
+    ).
+
+'$load_clauses'([], _).
+'$load_clauses'([Clause|Clauses], Line) :-
+    '$load_clause'(Clause, Line),
+    '$load_clauses'(Clauses, Line).
+
+'$load_clause'(Clause, Line) :-
+    var(Clause),
+    !,
+    '$add_clause'(Clause, Line).
+'$load_clause'((:- Directive), Line) :-
+    !,
+    expand_goal(Directive, Goal),
+    '$load_directive'(Goal, Line).
+'$load_clause'((Head :- Body), Line) :-
+    !,
+    expand_goal(Body, Goals),
+    '$add_clause'((Head :- Goals), Line).
+'$load_clause'(Clause, Line) :-
+    '$add_clause'(Clause, Line).
+
+% `include/1` reads the clauses of another file as if they were part of this one.
+'$load_directive'(include(Spec), _) :-
+    !,
+    '$absolute_file_name'(Spec, File),
+    '$load_source'(Source, File),
+    '$load_source_file'(File),
+    '$load_source'(_, Source).
+'$load_directive'(Directive, _) :-
+    '$compiler_directive'(Directive),
+    !.
+'$load_directive'(Goal, Line) :-
+    '$source_module'(Module),
+    (   call(Module:Goal)
+    ->  true
+    ;   '$directive_failed'(Goal, Line)
+    ).
+
+'$run_initialization'([]).
+'$run_initialization'([Goal|Goals]) :-
+    (   call(Goal)
+    ->  true
+    ;   '$directive_failed'(Goal, _)
+    ),
+    '$run_initialization'(Goals).
+
+initialization(Goal) :-
+    (   '$add_initialization'(Goal)
+    ->  true
+    ;   call(Goal)
+    ).
+
+initialization(Goal, When) :-
+    (   When == now
+    ->  call(Goal)
+    ;   initialization(Goal)
+    ).
+
+% Source transformation hooks --------------------------------------------------------------
+
+:- dynamic((term_expansion/2, goal_expansion/2)).
+
+% A term is rewritten by the first solution of term_expansion/2, otherwise grammar rules are
+% translated into clauses.
+expand_term(Term, Expanded) :-
+    (   var(Term)
+    ->  Expanded = Term
+    ;   term_expansion(Term, Expanded0)
+    ->  Expanded = Expanded0
+    ;   '$dcg_translate_rule'(Term, Expanded0)
+    ->  Expanded = Expanded0
+    ;   Expanded = Term
+    ).
+
+% The goals inside control constructs are rewritten by goal_expansion/2 until it fails or
+% leaves them unchanged.
+expand_goal(Goal, Goal) :-
+    var(Goal),
+    !.
+expand_goal((A, B), (ExpandedA, ExpandedB)) :-
+    !,
+    expand_goal(A, ExpandedA),
+    expand_goal(B, ExpandedB).
+expand_goal((A ; B), (ExpandedA ; ExpandedB)) :-
+    !,
+    expand_goal(A, ExpandedA),
+    expand_goal(B, ExpandedB).
+expand_goal((A -> B), (ExpandedA -> ExpandedB)) :-
+    !,
+    expand_goal(A, ExpandedA),
+    expand_goal(B, ExpandedB).
+expand_goal(\+ A, \+ ExpandedA) :-
+    !,
+    expand_goal(A, ExpandedA).
+expand_goal(Goal, Expanded) :-
+    (   goal_expansion(Goal, Next),
+        Next \== Goal
+    ->  expand_goal(Next, Expanded)
+    ;   Expanded = Goal
+    ).
@@ -1,15 +1,74 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    ops::Range,
+    sync::LazyLock,
+};
 
 use crate::{
     descriptor::DescriptorAllocator,
-    instructions::{DescriptorId, Instruction, RegisterId},
-    interpreter::InspectionVariable,
-    parsing::{AbstractFact, AbstractProgram, AbstractRule, AbstractTerm},
-    traversal::{
-        AbstractTermItem, DepthFirstIterator, FactIterator, QueryIterator, generate_term_id,
+    instructions::{Builtin, DescriptorId, Instruction, RegisterId, UNRESOLVED_ADDRESS},
+    interpreter::{ErrorCulprit, PrologError},
+    number::Number,
+    parsing::{
+        AbstractFact, AbstractProgram, AbstractRule, AbstractTerm, operators::OperatorTable,
+        parse_clauses, program_from_term,
     },
+    traversal::{AbstractTermItem, DepthFirstIterator, FactIterator, QueryIterator, term_id},
 };
 
+mod arithmetic;
+
+/// Predicates written in Prolog itself, loaded into every compiler before the user program.
+const PRELUDE: &str = include_str!("boot.pl");
+
+/// Libraries bundled with the crate. Each one is compiled the first time one of its predicates
+/// is called.
+const LIBRARIES: &[(&str, &str)] = &[
+    ("lists", include_str!("library/lists.pl")),
+    ("arithmetic", include_str!("library/arithmetic.pl")),
+    ("clpfd", include_str!("library/clpfd.pl")),
+];
+
+/// A bundled library, parsed once for all compilers.
+struct Library {
+    name: &'static str,
+    clauses: Vec<AbstractProgram>,
+    /// Name and arity of the predicates it defines.
+    predicates: HashSet<(String, usize)>,
+}
+
+static BUNDLED_LIBRARIES: LazyLock<Vec<Library>> = LazyLock::new(|| {
+    LIBRARIES
+        .iter()
+        .map(|(name, source)| {
+            let clauses = parse_clauses(source).expect("library to parse");
+            // Clauses for other modules, such as attribute hooks, are not loaded on demand.
+            let predicates = clauses
+                .iter()
+                .filter(|clause| unqualify_clause(clause).is_none())
+                .filter_map(|clause| match clause {
+                    AbstractProgram::Fact(fact) => Some(&fact.term),
+                    AbstractProgram::Rule(rule) => Some(&rule.head),
+                    AbstractProgram::Directive(_) => None,
+                })
+                .filter_map(|head| match head {
+                    AbstractTerm::Constant(name) => Some((name.clone(), 0)),
+                    AbstractTerm::Structure(name, arguments) => {
+                        Some((name.clone(), arguments.len()))
+                    }
+                    _ => None,
+                })
+                .collect();
+            Library {
+                name,
+                clauses,
+                predicates,
+            }
+        })
+        .collect()
+});
+
 pub trait CompileTarget<'a> {
     type OrderedIterator: Iterator<Item = AbstractTermItem<'a>>;
 
@@ -34,12 +93,36 @@ pub trait CompileTarget<'a> {
     fn instruction_for_variable(register: RegisterId) -> Instruction;
 
     fn instruction_for_sub_argument(register: RegisterId) -> Instruction;
+
+    fn instruction_for_number_argument(number: &Number, register: RegisterId) -> Instruction;
+
+    fn instruction_for_number(number: &Number) -> Instruction;
+
+    fn instruction_for_string_argument(string: &str, register: RegisterId) -> Instruction;
 }
 
 #[derive(Debug, Clone)]
 struct RegistryAllocator {
     registry_map: HashMap<RegisterIdentifier, RegisterAllocation>,
-    registry_ordered_list: Vec<RegisterIdentifier>,
+    register_count: usize,
+}
+
+/// Temporary registers shared by all terms of one chunk, i.e. the head and first goal of a rule.
+/// Temporary variables of the head are still live in the first goal so they have to keep their
+/// register and the goal must not reuse it for something else.
+#[derive(Debug, Clone, Default)]
+struct RegisterChunk {
+    variables: HashMap<DescriptorId, RegisterId>,
+    next_temporary: usize,
+}
+
+impl RegisterChunk {
+    fn new(arity: usize) -> Self {
+        RegisterChunk {
+            variables: HashMap::new(),
+            next_temporary: arity,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Copy)]
@@ -125,15 +208,11 @@ impl RegistryAllocator {
         term: &'a AbstractTerm,
         descriptor_allocator: &mut DescriptorAllocator,
         permanent_variables: &HashMap<DescriptorId, usize>,
+        chunk: &mut RegisterChunk,
     ) -> Self {
         let mut registry_map = HashMap::new();
 
-        let root_arguments = match term {
-            AbstractTerm::Structure(_, sub_terms) => sub_terms.len(),
-            _ => 0,
-        };
-
-        let mut child_index = root_arguments;
+        let mut child_index = term.arity().max(chunk.next_temporary);
 
         let iter = T::get_ordered_iterator(term);
 
@@ -142,12 +221,14 @@ impl RegistryAllocator {
             let needs_register = match term.term {
                 AbstractTerm::Variable(_) => true,
                 AbstractTerm::Constant(_) => true,
+                AbstractTerm::Number(_) | AbstractTerm::String(_) => true,
                 _ => term.level != 1,
             };
 
-            let descriptor_id = descriptor_allocator.get_or_set(term.term);
             let register_identifier = match term.term {
-                AbstractTerm::Variable(_) => RegisterIdentifier::Variable(descriptor_id),
+                AbstractTerm::Variable(_) => {
+                    RegisterIdentifier::Variable(descriptor_allocator.get_or_set(term.term))
+                }
                 _ => RegisterIdentifier::NonVariable(term.id),
             };
 
@@ -163,14 +244,28 @@ impl RegistryAllocator {
 
             let allocation = registry_map.get_mut(&register_identifier).unwrap();
 
+            let permanent_index = match register_identifier {
+                RegisterIdentifier::Variable(descriptor_id) => {
+                    permanent_variables.get(&descriptor_id)
+                }
+                RegisterIdentifier::NonVariable(_) => None,
+            };
+
             if needs_register && allocation.register.is_none() {
-                allocation.register =
-                    if let Some(permanent_index) = permanent_variables.get(&descriptor_id) {
-                        Some(RegisterId::Permanent(*permanent_index))
-                    } else {
+                allocation.register = match (permanent_index, register_identifier) {
+                    (Some(permanent_index), _) => Some(RegisterId::Permanent(*permanent_index)),
+                    (None, RegisterIdentifier::Variable(descriptor_id)) => {
+                        let register = chunk.variables.entry(descriptor_id).or_insert_with(|| {
+                            child_index += 1;
+                            RegisterId::Temporary(child_index - 1)
+                        });
+                        Some(*register)
+                    }
+                    (None, RegisterIdentifier::NonVariable(_)) => {
                         child_index += 1;
                         Some(RegisterId::Temporary(child_index - 1))
-                    };
+                    }
+                };
             }
 
             if needs_argument_register
@@ -184,30 +279,17 @@ impl RegistryAllocator {
             }
         }
 
-        let mut registry_ordered_list = Vec::new();
-        for i in 0..child_index {
-            let register = registry_map
-                .iter()
-                .find(|(_, alloc)| {
-                    alloc.register == Some(RegisterId::Temporary(i))
-                        || alloc.argument_register.contains(&RegisterId::Argument(i))
-                })
-                .unwrap()
-                .0;
-            registry_ordered_list.push(*register);
-        }
+        chunk.next_temporary = child_index;
 
         RegistryAllocator {
             registry_map,
-            registry_ordered_list,
+            register_count: child_index,
         }
     }
 
     fn get_register_raw(
         &self,
         term: &AbstractTerm,
-        parent_id: usize,
-        argument_index: usize,
         descriptor_allocator: &mut DescriptorAllocator,
     ) -> &RegisterAllocation {
         let identifier = match term {
@@ -215,7 +297,7 @@ impl RegistryAllocator {
                 let descriptor_id = descriptor_allocator.get_or_set(term);
                 RegisterIdentifier::Variable(descriptor_id)
             }
-            _ => RegisterIdentifier::NonVariable(generate_term_id(parent_id, argument_index)),
+            _ => RegisterIdentifier::NonVariable(term_id(term)),
         };
         self.registry_map.get(&identifier).unwrap()
     }
@@ -245,33 +327,142 @@ pub struct CompileArtifact {
     pub instructions: Vec<Instruction>,
     pub max_registers: usize,
     pub start_instruction_index: usize,
-    pub inspection_variables: Vec<InspectionVariable>,
+    /// The named variables of the query, whose values make up its answer.
+    pub query_variables: Vec<DescriptorId>,
 }
 
 #[derive(Debug, Clone)]
 struct IntermediateCompileArtifact {
     pub instructions: Vec<Instruction>,
-    pub register_allocator: RegistryAllocator,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Compiler {
     instructions: Vec<Instruction>,
     fact_call_map: HashMap<DescriptorId, usize>,
     last_fact_call_map: HashMap<DescriptorId, usize>,
     pub descriptor_allocator: DescriptorAllocator,
+    /// Operators used to write terms.
+    pub operators: OperatorTable,
     max_registers: usize,
+    dynamic_predicates: HashMap<DescriptorId, DynamicPredicate>,
+    /// The dynamic predicate of every clause that has not been reclaimed yet, by clause id.
+    clause_predicates: HashMap<usize, DescriptorId>,
+    generation: usize,
+    next_clause_id: usize,
+    /// Instructions of reclaimed clauses, which can be reused for new ones.
+    free_blocks: Vec<Range<usize>>,
+    /// Head and body of every clause of the static predicates, for `clause/2` and `listing/1`.
+    static_clauses: HashMap<DescriptorId, Vec<(AbstractTerm, AbstractTerm)>>,
+    /// Predicates defined by the prelude and the loaded libraries, which are treated like
+    /// builtins.
+    library_predicates: HashSet<DescriptorId>,
+    /// Names of the bundled libraries compiled so far.
+    loaded_libraries: HashSet<&'static str>,
+    /// The module clauses are added to, changed by `:- module/2`.
+    source_module: String,
+    /// Every module by name, including `user`.
+    modules: HashMap<String, Module>,
+    /// For every predicate declared with `:- meta_predicate`, which of its arguments are
+    /// goals or closures that need to know the module of the caller.
+    meta_predicates: HashMap<DescriptorId, Vec<bool>>,
+    /// The files loaded by `consult/1`, with the module each one defines, if any.
+    loaded_files: HashMap<String, Option<String>>,
+    /// The file every predicate loaded by `consult/1` comes from.
+    predicate_files: HashMap<DescriptorId, String>,
+    /// The number of queries compiled, which name their clauses.
+    queries: usize,
+    /// The number of auxiliary predicates compiled for control constructs, which name them.
+    control_predicates: usize,
+    /// The key of every static clause whose first argument is not a variable, by address.
+    clause_keys: HashMap<usize, ClauseKey>,
+}
+
+/// A namespace of predicates. Calls to predicates a module does not define are resolved
+/// through its imports first, then in `user`.
+#[derive(Debug, Clone, Default)]
+struct Module {
+    /// Name and arity of the predicates other modules can import.
+    exports: Vec<(String, usize)>,
+    /// The predicates imported from other modules, by name and arity.
+    imports: HashMap<(String, usize), DescriptorId>,
+}
+
+/// A predicate whose clauses can change while the program runs. Its clauses are not chained with
+/// choice point instructions, instead every call picks the clauses that exist when it starts.
+#[derive(Debug, Clone)]
+pub struct DynamicPredicate {
+    /// Address of the `TryClauses` instruction, followed by its `RetryClauses` instruction.
+    pub address: usize,
+    pub clauses: Vec<DynamicClause>,
+    /// Cleared by `abolish/1`. The predicate is kept until its clauses are reclaimed.
+    pub defined: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct DynamicClause {
+    pub id: usize,
+    /// Instructions of the clause, followed by the ones of the auxiliary predicates of its
+    /// control constructs.
+    pub code: Range<usize>,
+    pub head: AbstractTerm,
+    pub body: AbstractTerm,
+    /// Generation the clause was added in.
+    pub born: usize,
+    /// Generation the clause was retracted in.
+    pub erased: Option<usize>,
+}
+
+impl DynamicClause {
+    /// Whether a call started in `generation` sees this clause (the logical update view).
+    pub fn is_visible(&self, generation: usize) -> bool {
+        self.born <= generation && self.erased.is_none_or(|erased| erased > generation)
+    }
+}
+
+/// The principal functor of the first argument of a clause head. A call whose first argument
+/// has another one cannot match the clause, so trying the clause can be skipped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClauseKey {
+    /// An atom or the functor of a structure.
+    Functor(DescriptorId),
+    Integer(i64),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ClausePosition {
+    First,
+    Last,
 }
 
 impl Compiler {
     pub fn new() -> Self {
-        Compiler {
+        let mut compiler = Compiler {
             instructions: Vec::new(),
             fact_call_map: HashMap::new(),
             last_fact_call_map: HashMap::new(),
             descriptor_allocator: DescriptorAllocator::default(),
+            operators: OperatorTable::default(),
             max_registers: 0,
-        }
+            dynamic_predicates: HashMap::new(),
+            clause_predicates: HashMap::new(),
+            generation: 0,
+            next_clause_id: 0,
+            free_blocks: Vec::new(),
+            static_clauses: HashMap::new(),
+            library_predicates: HashSet::new(),
+            loaded_libraries: HashSet::new(),
+            source_module: "user".to_string(),
+            modules: HashMap::from([("user".to_string(), Module::default())]),
+            meta_predicates: HashMap::new(),
+            loaded_files: HashMap::new(),
+            predicate_files: HashMap::new(),
+            queries: 0,
+            control_predicates: 0,
+            clause_keys: HashMap::new(),
+        };
+        compiler.load_prelude();
+        compiler
     }
 
     pub fn reset(&mut self) {
@@ -280,12 +471,887 @@ impl Compiler {
         self.fact_call_map.clear();
         self.last_fact_call_map.clear();
         self.descriptor_allocator = DescriptorAllocator::default();
+        self.operators = OperatorTable::default();
+        self.dynamic_predicates.clear();
+        self.clause_predicates.clear();
+        self.generation = 0;
+        self.next_clause_id = 0;
+        self.free_blocks.clear();
+        self.static_clauses.clear();
+        self.library_predicates.clear();
+        self.loaded_libraries.clear();
+        self.source_module = "user".to_string();
+        self.modules = HashMap::from([("user".to_string(), Module::default())]);
+        self.meta_predicates.clear();
+        self.loaded_files.clear();
+        self.predicate_files.clear();
+        self.queries = 0;
+        self.control_predicates = 0;
+        self.clause_keys.clear();
+        self.load_prelude();
+    }
+
+    fn load_prelude(&mut self) {
+        for program in parse_clauses(PRELUDE).expect("prelude to parse") {
+            self.add_program(&program).expect("prelude to load");
+        }
+        self.library_predicates = self.fact_call_map.keys().copied().collect();
     }
 
-    pub fn add_program(&mut self, program: &AbstractProgram) {
+    /// All instructions compiled so far, including the ones of the prelude.
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    pub fn max_registers(&self) -> usize {
+        self.max_registers
+    }
+
+    /// Address of the first clause of the predicate `functor`, if it has any clauses.
+    pub fn predicate_address(&self, functor: DescriptorId) -> Option<usize> {
+        self.fact_call_map.get(&functor).copied()
+    }
+
+    /// Like `predicate_address`, but first loads the bundled library defining `functor` if the
+    /// predicate is not defined yet.
+    ///
+    /// A predicate of a module which the module does not define is looked up in its imports,
+    /// then in `user`.
+    pub fn resolve_predicate(&mut self, functor: DescriptorId) -> Option<usize> {
+        self.resolve_predicate_from(functor, &mut Vec::new())
+    }
+
+    /// Resolves `functor`, which is not one of the predicates in `visited`. Looking up an
+    /// exported predicate its module does not define leads back to the importing module.
+    fn resolve_predicate_from(
+        &mut self,
+        functor: DescriptorId,
+        visited: &mut Vec<DescriptorId>,
+    ) -> Option<usize> {
+        if let Some(address) = self.predicate_address(functor) {
+            return Some(address);
+        }
+        if visited.contains(&functor) {
+            return None;
+        }
+        visited.push(functor);
+        let descriptor = self.descriptor_allocator.get(functor);
+        let key = (descriptor.name.clone(), descriptor.arity());
+        let module = descriptor.module.as_deref().unwrap_or("user");
+        if let Some(imported) = self
+            .modules
+            .get(module)
+            .and_then(|module| module.imports.get(&key))
+        {
+            return self.resolve_predicate_from(*imported, visited);
+        }
+        if module != "user" {
+            let functor = self.descriptor_allocator.get_or_set_functor(&key.0, key.1);
+            return self.resolve_predicate_from(functor, visited);
+        }
+        let library = BUNDLED_LIBRARIES.iter().find(|library| {
+            !self.loaded_libraries.contains(library.name) && library.predicates.contains(&key)
+        })?;
+        self.load_library(library.name);
+        self.predicate_address(functor)
+    }
+
+    /// The predicate `functor` refers to after following imports, which is the one of the module
+    /// exporting it if `functor` is imported.
+    pub fn imported_predicate(&self, mut functor: DescriptorId) -> DescriptorId {
+        let mut visited = Vec::new();
+        while !visited.contains(&functor) {
+            visited.push(functor);
+            let descriptor = self.descriptor_allocator.get(functor);
+            let key = (descriptor.name.clone(), descriptor.arity());
+            let module = descriptor.module.as_deref().unwrap_or("user");
+            match self
+                .modules
+                .get(module)
+                .and_then(|module| module.imports.get(&key))
+            {
+                Some(imported) => functor = *imported,
+                None => break,
+            }
+        }
+        functor
+    }
+
+    /// Compiles the bundled library `name` unless it has been loaded already. Predicates the
+    /// program defines itself are kept, their clauses in the library are skipped. Returns false
+    /// if there is no such library.
+    pub fn load_library(&mut self, name: &str) -> bool {
+        let Some(library) = BUNDLED_LIBRARIES
+            .iter()
+            .find(|library| library.name == name)
+        else {
+            return false;
+        };
+        if !self.loaded_libraries.insert(library.name) {
+            return true;
+        }
+
+        let defined = self.fact_call_map.keys().copied().collect::<HashSet<_>>();
+        for clause in &library.clauses {
+            if unqualify_clause(clause).is_some() {
+                self.add_program(clause).expect("library to load");
+                continue;
+            }
+            let functor = match clause {
+                AbstractProgram::Fact(fact) => self.descriptor_allocator.get_or_set(&fact.term),
+                AbstractProgram::Rule(rule) => self.descriptor_allocator.get_or_set(&rule.head),
+                AbstractProgram::Directive(_) => {
+                    self.add_program(clause).expect("library to load");
+                    continue;
+                }
+            };
+            if !defined.contains(&functor) {
+                self.add_program(clause).expect("library to load");
+                self.library_predicates.insert(functor);
+            }
+        }
+        true
+    }
+
+    /// Every predicate that is currently defined, including the ones of the prelude.
+    pub fn predicates(&self) -> impl Iterator<Item = DescriptorId> {
+        self.fact_call_map.keys().copied()
+    }
+
+    /// Whether `functor` belongs to the system rather than to the user program: predicates of
+    /// the prelude and internal predicates, whose names start with `$`.
+    pub fn is_library(&self, functor: DescriptorId) -> bool {
+        self.library_predicates.contains(&functor)
+            || self.descriptor_allocator.get(functor).name.starts_with('$')
+    }
+
+    /// Head and body of the clauses of a static predicate, in order.
+    pub fn static_clauses(&self, functor: DescriptorId) -> &[(AbstractTerm, AbstractTerm)] {
+        self.static_clauses
+            .get(&functor)
+            .map_or(&[], |clauses| clauses.as_slice())
+    }
+
+    /// Adds a clause or executes a declaration. Directives which are no declaration cannot
+    /// run without a machine and raise `domain_error(directive, Name/Arity)`.
+    pub fn add_program(&mut self, program: &AbstractProgram) -> Result<(), PrologError> {
+        if let Some((module, program)) = unqualify_clause(program) {
+            let source_module = self.set_source_module(&module);
+            let result = self.add_program(&program);
+            self.set_source_module(&source_module);
+            return result;
+        }
         match program {
             AbstractProgram::Fact(fact) => self.add_fact(fact),
             AbstractProgram::Rule(rule) => self.add_rule(rule),
+            AbstractProgram::Directive(directive) => self.add_directive(directive),
+        }
+    }
+
+    fn add_directive(&mut self, directive: &AbstractTerm) -> Result<(), PrologError> {
+        if self.directive(directive)? {
+            return Ok(());
+        }
+        let functor = self
+            .descriptor_allocator
+            .get_or_set_functor(directive.name(), directive.arity());
+        Err(PrologError::DomainError {
+            domain: "directive",
+            culprit: ErrorCulprit::Indicator(functor),
+        })
+    }
+
+    /// Executes the declaration `directive`: `dynamic/1`, `module/2`, `meta_predicate/1`, or
+    /// `use_module/1,2` and `ensure_loaded/1` of a library or a module defined already.
+    /// Returns false if `directive` is none of them, it is then a goal to run.
+    pub fn directive(&mut self, directive: &AbstractTerm) -> Result<bool, PrologError> {
+        match directive {
+            AbstractTerm::Structure(name, arguments) if name == "dynamic" => {
+                let mut indicators = Vec::new();
+                if !collect_indicators(&arguments[0], &mut indicators) {
+                    return Ok(false);
+                }
+                for (name, arity) in indicators {
+                    let functor = self.descriptor_allocator.get_or_set_predicate(
+                        &self.source_module,
+                        &name,
+                        arity,
+                    );
+                    self.declare_dynamic(functor)?;
+                }
+            }
+            AbstractTerm::Structure(name, arguments) if name == "module" => {
+                let [AbstractTerm::Constant(module), exports] = &arguments[..] else {
+                    return Ok(false);
+                };
+                let mut indicators = Vec::new();
+                if !collect_indicators(exports, &mut indicators) {
+                    return Ok(false);
+                }
+                self.define_module(module, indicators);
+                // A module is imported into `user` when it is loaded.
+                self.import("user", module, None)?;
+                self.source_module = module.clone();
+            }
+            AbstractTerm::Structure(name, arguments)
+                if matches!(name.as_str(), "use_module" | "ensure_loaded") =>
+            {
+                let mut only = None;
+                if let Some(imports) = arguments.get(1) {
+                    let mut indicators = Vec::new();
+                    if !collect_indicators(imports, &mut indicators) {
+                        return Ok(false);
+                    }
+                    only = Some(indicators);
+                }
+                match &arguments[0] {
+                    AbstractTerm::Structure(functor, library)
+                        if functor == "library"
+                            && matches!(&library[..], [AbstractTerm::Constant(_)]) =>
+                    {
+                        let name = library[0].name();
+                        if !self.load_library(name) {
+                            let culprit = self.descriptor_allocator.get_or_set_functor(name, 0);
+                            return Err(PrologError::existence_error("library", culprit));
+                        }
+                    }
+                    AbstractTerm::Constant(module)
+                        if module != "user" && self.modules.contains_key(module) =>
+                    {
+                        let into = self.source_module.clone();
+                        self.import(&into, module, only.as_deref())?;
+                    }
+                    // A file to load.
+                    _ => return Ok(false),
+                }
+            }
+            AbstractTerm::Structure(name, arguments) if name == "meta_predicate" => {
+                let mut specifications = Vec::new();
+                collect_conjunction(&arguments[0], &mut specifications);
+                for specification in specifications {
+                    let AbstractTerm::Structure(name, arguments) = specification else {
+                        return Ok(false);
+                    };
+                    let functor = self.descriptor_allocator.get_or_set_predicate(
+                        &self.source_module,
+                        name,
+                        arguments.len(),
+                    );
+                    let goals = arguments.iter().map(is_module_sensitive).collect();
+                    self.meta_predicates.insert(functor, goals);
+                }
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// The module clauses are currently added to.
+    pub fn source_module(&self) -> &str {
+        &self.source_module
+    }
+
+    /// Makes `module` the module clauses are added to, and returns the previous one.
+    pub fn set_source_module(&mut self, module: &str) -> String {
+        self.modules.entry(module.to_string()).or_default();
+        std::mem::replace(&mut self.source_module, module.to_string())
+    }
+
+    fn define_module(&mut self, module: &str, exports: Vec<(String, usize)>) {
+        let module = self.modules.entry(module.to_string()).or_default();
+        module.exports = exports;
+    }
+
+    /// Makes the predicates exported by `from`, or only the ones in `only`, callable from
+    /// `into` without qualification. Importing a predicate `into` defines itself or already
+    /// imports from another module is not permitted.
+    pub fn import(
+        &mut self,
+        into: &str,
+        from: &str,
+        only: Option<&[(String, usize)]>,
+    ) -> Result<(), PrologError> {
+        let Some(module) = self.modules.get(from).filter(|_| from != "user") else {
+            let culprit = self.descriptor_allocator.get_or_set_functor(from, 0);
+            return Err(PrologError::existence_error("module", culprit));
+        };
+        let predicates = match only {
+            Some(only) => only.to_vec(),
+            None => module.exports.clone(),
+        };
+        self.modules.entry(into.to_string()).or_default();
+        for (name, arity) in predicates {
+            let imported = self
+                .descriptor_allocator
+                .get_or_set_predicate(from, &name, arity);
+            let local = self
+                .descriptor_allocator
+                .get_or_set_predicate(into, &name, arity);
+            let previous = self.modules[into].imports.get(&(name.clone(), arity));
+            if previous.is_some_and(|previous| *previous != imported)
+                || self.fact_call_map.contains_key(&local)
+            {
+                return Err(PrologError::permission_error(
+                    "import_into",
+                    "procedure",
+                    imported,
+                ));
+            }
+            self.modules
+                .get_mut(into)
+                .expect("module to exist")
+                .imports
+                .insert((name, arity), imported);
+        }
+        Ok(())
+    }
+
+    /// Which arguments of `name/arity`, called from `module`, are goals or closures.
+    pub fn meta_arguments(&mut self, module: &str, name: &str, arity: usize) -> Option<Vec<bool>> {
+        let local = self
+            .descriptor_allocator
+            .get_or_set_predicate(module, name, arity);
+        let imported = self
+            .modules
+            .get(module)
+            .and_then(|module| module.imports.get(&(name.to_string(), arity)))
+            .copied();
+        let global = self.descriptor_allocator.get_or_set_functor(name, arity);
+        [Some(local), imported, Some(global)]
+            .into_iter()
+            .flatten()
+            .find_map(|functor| self.meta_predicates.get(&functor).cloned())
+    }
+
+    /// The predicate a clause with `head` added to the source module belongs to.
+    fn head_functor(&mut self, head: &AbstractTerm) -> DescriptorId {
+        self.descriptor_allocator.get_or_set_predicate(
+            &self.source_module,
+            head.name(),
+            head.arity(),
+        )
+    }
+
+    /// Checks that the source module does not import the predicate of `head`, which would be
+    /// redefined by adding a clause to it.
+    fn check_local_definition(&self, head: &AbstractTerm) -> Result<(), PrologError> {
+        let key = (head.name().to_string(), head.arity());
+        match self.modules[&self.source_module].imports.get(&key) {
+            Some(imported) => Err(PrologError::permission_error(
+                "redefine",
+                "imported_procedure",
+                *imported,
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// The goals of `rule` with the module each one is called in. Qualified goals `M:G` are
+    /// replaced by `G`, and outside of `user` the goal and closure arguments of meta-predicates
+    /// are qualified with the source module.
+    fn goal_modules<'a>(&mut self, rule: &'a AbstractRule) -> (Cow<'a, AbstractRule>, Vec<String>) {
+        let plain = self.source_module == "user"
+            && !rule.goals.iter().any(|goal| unqualify_goal(goal).is_some());
+        if plain {
+            return (
+                Cow::Borrowed(rule),
+                vec![self.source_module.clone(); rule.goals.len()],
+            );
+        }
+
+        let mut modules = Vec::new();
+        let mut goals = Vec::new();
+        for goal in &rule.goals {
+            let mut module = self.source_module.clone();
+            let mut goal = goal;
+            while let Some((qualifier, inner)) = unqualify_goal(goal) {
+                module = qualifier.to_string();
+                goal = inner;
+            }
+            let goal = match (module.as_str(), goal) {
+                // Conjunctions are no predicate, they are called like a goal of `call/1`.
+                (_, AbstractTerm::Structure(name, arguments))
+                    if name == "," && arguments.len() == 2 =>
+                {
+                    let goal = qualify_goal(&module, goal);
+                    module = "user".to_string();
+                    AbstractTerm::Structure("call".to_string(), vec![goal])
+                }
+                ("user", _) => goal.clone(),
+                (_, AbstractTerm::Structure(name, arguments)) => {
+                    match self.meta_arguments(&module, name, arguments.len()) {
+                        Some(meta) => AbstractTerm::Structure(
+                            name.clone(),
+                            arguments
+                                .iter()
+                                .zip(meta)
+                                .map(|(argument, is_goal)| match is_goal {
+                                    true => qualify_goal(&module, argument),
+                                    false => argument.clone(),
+                                })
+                                .collect(),
+                        ),
+                        None => goal.clone(),
+                    }
+                }
+                _ => goal.clone(),
+            };
+            modules.push(module);
+            goals.push(goal);
+        }
+        let rule = AbstractRule {
+            head: rule.head.clone(),
+            goals,
+        };
+        (Cow::Owned(rule), modules)
+    }
+
+    /// Whether `file` has been loaded by `consult/1`.
+    pub fn is_loaded(&self, file: &str) -> bool {
+        self.loaded_files.contains_key(file)
+    }
+
+    /// The module defined by the loaded `file`, if it is a module file.
+    pub fn file_module(&self, file: &str) -> Option<&str> {
+        self.loaded_files.get(file)?.as_deref()
+    }
+
+    /// Starts loading `file`, whose clauses are added to `user` until it declares a module.
+    /// The predicates of a previous load of `file` are removed. Returns the source module to
+    /// restore with [`Compiler::end_file`].
+    pub fn start_file(&mut self, file: &str) -> String {
+        let predicates = self
+            .predicate_files
+            .iter()
+            .filter(|(_, owner)| owner.as_str() == file)
+            .map(|(functor, _)| *functor)
+            .collect::<Vec<_>>();
+        for functor in predicates {
+            self.wipe_predicate(functor);
+        }
+        self.loaded_files.insert(file.to_string(), None);
+        self.set_source_module("user")
+    }
+
+    /// Finishes loading `file` and makes `source_module` the source module again.
+    pub fn end_file(&mut self, file: &str, source_module: &str) {
+        let module = self.set_source_module(source_module);
+        if module != "user" {
+            self.loaded_files.insert(file.to_string(), Some(module));
+        }
+    }
+
+    /// Adds a clause read from `file`. A predicate defined elsewhere is replaced by the clauses
+    /// of `file`, which is reported by the returned warning.
+    pub fn add_file_clause(
+        &mut self,
+        program: &AbstractProgram,
+        file: &str,
+    ) -> Result<Option<String>, PrologError> {
+        if let Some((module, program)) = unqualify_clause(program) {
+            let source_module = self.set_source_module(&module);
+            let result = self.add_file_clause(&program, file);
+            self.set_source_module(&source_module);
+            return result;
+        }
+        let head = match program {
+            AbstractProgram::Fact(fact) => &fact.term,
+            AbstractProgram::Rule(rule) => &rule.head,
+            AbstractProgram::Directive(_) => unreachable!("directives are not clauses"),
+        };
+        self.check_local_definition(head)?;
+        let functor = self.head_functor(head);
+        let descriptor = self.descriptor_allocator.get(functor);
+        let dynamic = self.is_dynamic(functor);
+        if Builtin::lookup(&descriptor.name, descriptor.arity()).is_some()
+            || (self.library_predicates.contains(&functor) && !dynamic)
+            || matches!(
+                (descriptor.name.as_str(), descriptor.arity()),
+                (",", 2) | ("!", 0)
+            )
+        {
+            return Err(PrologError::permission_error(
+                "modify",
+                "static_procedure",
+                functor,
+            ));
+        }
+
+        let mut warning = None;
+        if dynamic {
+            self.assert_clause(program, ClausePosition::Last)?;
+        } else {
+            let owner = self.predicate_files.get(&functor);
+            if owner.is_none_or(|owner| owner != file) && self.fact_call_map.contains_key(&functor)
+            {
+                let previous = match owner {
+                    Some(owner) => format!(", previously loaded from {}", owner),
+                    None => String::new(),
+                };
+                warning = Some(format!(
+                    "Redefined static procedure {}{}",
+                    descriptor.pretty_name(),
+                    previous
+                ));
+                self.wipe_predicate(functor);
+            }
+            self.add_program(program)?;
+        }
+        self.predicate_files.insert(functor, file.to_string());
+        Ok(warning)
+    }
+
+    /// Removes all clauses of a predicate. A static predicate becomes unknown, calls compiled
+    /// for it are resolved again once it is defined anew. A dynamic one stays dynamic.
+    fn wipe_predicate(&mut self, functor: DescriptorId) {
+        self.predicate_files.remove(&functor);
+        if self.is_dynamic(functor) {
+            let ids = self.dynamic_predicates[&functor]
+                .clauses
+                .iter()
+                .map(|clause| clause.id)
+                .collect::<Vec<_>>();
+            for id in ids {
+                self.erase_clause(id);
+            }
+            return;
+        }
+        self.fact_call_map.remove(&functor);
+        self.last_fact_call_map.remove(&functor);
+        self.static_clauses.remove(&functor);
+        for instruction in &mut self.instructions {
+            if let Instruction::Call {
+                address,
+                functor: callee,
+            } = instruction
+                && *callee == functor
+            {
+                *address = UNRESOLVED_ADDRESS;
+            }
+        }
+    }
+
+    /// Clock of the dynamic database, advanced by every change to a dynamic predicate.
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    pub fn dynamic_predicate(&self, functor: DescriptorId) -> Option<&DynamicPredicate> {
+        self.dynamic_predicates.get(&functor)
+    }
+
+    pub fn is_dynamic(&self, functor: DescriptorId) -> bool {
+        self.dynamic_predicate(functor)
+            .is_some_and(|predicate| predicate.defined)
+    }
+
+    /// The predicate a clause added with `assertz/1` and friends belongs to.
+    pub fn clause_predicate(&self, id: usize) -> Option<DescriptorId> {
+        self.clause_predicates.get(&id).copied()
+    }
+
+    /// Makes `functor` a dynamic predicate without clauses, unless it already is one.
+    pub fn declare_dynamic(&mut self, functor: DescriptorId) -> Result<(), PrologError> {
+        if let Some(predicate) = self.dynamic_predicates.get_mut(&functor) {
+            predicate.defined = true;
+            self.fact_call_map.insert(functor, predicate.address);
+            return Ok(());
+        }
+        let descriptor = self.descriptor_allocator.get(functor);
+        // Conjunction and cut are compiled inline, so they have no clauses to protect them.
+        if self.fact_call_map.contains_key(&functor)
+            || Builtin::lookup(&descriptor.name, descriptor.arity()).is_some()
+            || matches!(
+                (descriptor.name.as_str(), descriptor.arity()),
+                (",", 2) | ("!", 0)
+            )
+        {
+            return Err(PrologError::permission_error(
+                "modify",
+                "static_procedure",
+                functor,
+            ));
+        }
+
+        self.instructions.push(Instruction::DebugComment {
+            message: Box::new(format!("{} (dynamic)", descriptor.pretty_name())),
+        });
+        let address = self.instructions.len();
+        self.instructions.push(Instruction::TryClauses { functor });
+        self.instructions
+            .push(Instruction::RetryClauses { functor });
+        self.fact_call_map.insert(functor, address);
+        self.dynamic_predicates.insert(
+            functor,
+            DynamicPredicate {
+                address,
+                clauses: Vec::new(),
+                defined: true,
+            },
+        );
+        Ok(())
+    }
+
+    /// Adds a clause to a dynamic predicate, declaring the predicate if it is not known yet.
+    /// Returns the id of the new clause.
+    pub fn assert_clause(
+        &mut self,
+        program: &AbstractProgram,
+        position: ClausePosition,
+    ) -> Result<usize, PrologError> {
+        if let Some((module, program)) = unqualify_clause(program) {
+            let source_module = self.set_source_module(&module);
+            let id = self.assert_clause(&program, position);
+            self.set_source_module(&source_module);
+            return id;
+        }
+        let head = match program {
+            AbstractProgram::Fact(fact) => &fact.term,
+            AbstractProgram::Rule(rule) => &rule.head,
+            AbstractProgram::Directive(_) => unreachable!("directives are not clauses"),
+        };
+        self.check_local_definition(head)?;
+        let functor = self.head_functor(head);
+        if !self.is_dynamic(functor) {
+            self.declare_dynamic(functor)?;
+        }
+        self.add_dynamic_clause(program, position)
+    }
+
+    fn add_dynamic_clause(
+        &mut self,
+        program: &AbstractProgram,
+        position: ClausePosition,
+    ) -> Result<usize, PrologError> {
+        let start = self.instructions.len();
+        let mut control_predicates = Vec::new();
+        let (functor, head, body) = match program {
+            AbstractProgram::Fact(fact) => {
+                self.compile_fact(fact, Instruction::NoOp);
+                let functor = self.head_functor(&fact.term);
+                (
+                    functor,
+                    fact.term.clone(),
+                    AbstractTerm::Constant("true".to_string()),
+                )
+            }
+            AbstractProgram::Rule(rule) => {
+                control_predicates = self.compile_rule(rule, Instruction::NoOp);
+                let functor = self.head_functor(&rule.head);
+                (functor, rule.head.clone(), rule.body())
+            }
+            AbstractProgram::Directive(_) => unreachable!("directives are not clauses"),
+        };
+        for rule in &control_predicates {
+            self.add_rule(rule)?;
+        }
+        let code = self.relocate_clause(start);
+
+        self.generation += 1;
+        let id = self.next_clause_id;
+        self.next_clause_id += 1;
+        let clause = DynamicClause {
+            id,
+            code,
+            head,
+            body,
+            born: self.generation,
+            erased: None,
+        };
+        let clauses = &mut self
+            .dynamic_predicates
+            .get_mut(&functor)
+            .expect("dynamic predicate to be declared")
+            .clauses;
+        match position {
+            ClausePosition::First => clauses.insert(0, clause),
+            ClausePosition::Last => clauses.push(clause),
+        }
+        self.clause_predicates.insert(id, functor);
+        Ok(id)
+    }
+
+    /// Moves the clause compiled at `start`, the end of the instructions, into the first
+    /// reclaimed block it fits in. The auxiliary predicates compiled after the clause move
+    /// along with it, and the addresses pointing into them are adjusted.
+    fn relocate_clause(&mut self, start: usize) -> Range<usize> {
+        let length = self.instructions.len() - start;
+        let Some(index) = self
+            .free_blocks
+            .iter()
+            .position(|block| block.len() >= length)
+        else {
+            return start..self.instructions.len();
+        };
+
+        let block = self.free_blocks.swap_remove(index);
+        let source = start..self.instructions.len();
+        let target = block.start..block.start + length;
+        let relocate = |address: &mut usize| {
+            if source.contains(address) {
+                *address = *address - source.start + target.start;
+            }
+        };
+        let mut code = self.instructions.split_off(start);
+        for instruction in &mut code {
+            match instruction {
+                Instruction::Call { address, .. }
+                | Instruction::TryMeElse {
+                    else_address: address,
+                }
+                | Instruction::RetryMeElse {
+                    else_address: address,
+                } => relocate(address),
+                _ => {}
+            }
+        }
+        self.fact_call_map
+            .values_mut()
+            .chain(self.last_fact_call_map.values_mut())
+            .for_each(relocate);
+        let keys = self
+            .clause_keys
+            .extract_if(|address, _| source.contains(address))
+            .collect::<Vec<_>>();
+        for (mut address, key) in keys {
+            relocate(&mut address);
+            self.clause_keys.insert(address, key);
+        }
+        self.instructions.splice(target.clone(), code);
+        if target.end < block.end {
+            self.free_blocks.push(target.end..block.end);
+        }
+        target
+    }
+
+    /// Retracts the clause `id`. Calls started before keep seeing it. Returns false if the
+    /// clause has been retracted already.
+    pub fn erase_clause(&mut self, id: usize) -> bool {
+        let Some(functor) = self.clause_predicate(id) else {
+            return false;
+        };
+        let generation = self.generation + 1;
+        let Some(clause) = self
+            .dynamic_predicates
+            .get_mut(&functor)
+            .and_then(|predicate| predicate.clauses.iter_mut().find(|clause| clause.id == id))
+            .filter(|clause| clause.erased.is_none())
+        else {
+            return false;
+        };
+        clause.erased = Some(generation);
+        self.generation = generation;
+        true
+    }
+
+    /// Removes all clauses of a dynamic predicate and makes it unknown again.
+    pub fn abolish(&mut self, functor: DescriptorId) -> Result<(), PrologError> {
+        if !self.is_dynamic(functor) {
+            if self.fact_call_map.contains_key(&functor) {
+                return Err(PrologError::permission_error(
+                    "modify",
+                    "static_procedure",
+                    functor,
+                ));
+            }
+            return Ok(());
+        }
+
+        let ids = self.dynamic_predicates[&functor]
+            .clauses
+            .iter()
+            .map(|clause| clause.id)
+            .collect::<Vec<_>>();
+        for id in ids {
+            self.erase_clause(id);
+        }
+        self.dynamic_predicates
+            .get_mut(&functor)
+            .expect("predicate to be dynamic")
+            .defined = false;
+        self.fact_call_map.remove(&functor);
+        Ok(())
+    }
+
+    /// Retracted clauses whose instructions have not been reclaimed yet.
+    pub fn erased_clauses(&self) -> impl Iterator<Item = (DescriptorId, &DynamicClause)> {
+        self.dynamic_predicates
+            .iter()
+            .flat_map(|(functor, predicate)| {
+                predicate
+                    .clauses
+                    .iter()
+                    .filter(|clause| clause.erased.is_some())
+                    .map(move |clause| (*functor, clause))
+            })
+    }
+
+    /// Drops a retracted clause and makes its instructions available to new clauses. Must only
+    /// be called once no execution can reach the clause anymore.
+    pub fn reclaim_clause(&mut self, id: usize) {
+        let Some(functor) = self.clause_predicates.remove(&id) else {
+            return;
+        };
+        let clauses = &mut self
+            .dynamic_predicates
+            .get_mut(&functor)
+            .expect("clause to belong to a dynamic predicate")
+            .clauses;
+        let index = clauses
+            .iter()
+            .position(|clause| clause.id == id)
+            .expect("clause to be known");
+        let clause = clauses.remove(index);
+        // The auxiliary predicates of its control constructs are dropped with the clause.
+        let control_predicates = self
+            .fact_call_map
+            .iter()
+            .filter(|(_, address)| clause.code.contains(address))
+            .map(|(functor, _)| *functor)
+            .collect::<Vec<_>>();
+        for functor in control_predicates {
+            self.fact_call_map.remove(&functor);
+            self.last_fact_call_map.remove(&functor);
+            self.static_clauses.remove(&functor);
+        }
+        self.clause_keys
+            .retain(|address, _| !clause.code.contains(address));
+        self.instructions[clause.code.clone()].fill(Instruction::NoOp);
+        self.free_blocks.push(clause.code);
+    }
+
+    /// Remembers the key of the first argument of `head`, the head of the static clause at
+    /// `address`.
+    fn set_clause_key(&mut self, address: usize, head: &AbstractTerm) {
+        let key = match head {
+            AbstractTerm::Structure(_, arguments) => match &arguments[0] {
+                argument @ (AbstractTerm::Constant(_) | AbstractTerm::Structure(..)) => Some(
+                    ClauseKey::Functor(self.descriptor_allocator.get_or_set(argument)),
+                ),
+                AbstractTerm::Number(Number::Integer(value)) => Some(ClauseKey::Integer(*value)),
+                _ => None,
+            },
+            _ => None,
+        };
+        match key {
+            Some(key) => self.clause_keys.insert(address, key),
+            None => self.clause_keys.remove(&address),
+        };
+    }
+
+    /// The first clause a call whose first argument has `key` can match, among the clause at
+    /// `address` and the ones after it. A call with an unbound first argument has no key and
+    /// can match every clause.
+    pub fn matching_clause(&self, mut address: usize, key: Option<ClauseKey>) -> Option<usize> {
+        loop {
+            let clause_key = self.clause_keys.get(&address);
+            if key.is_none() || clause_key.is_none() || clause_key == key.as_ref() {
+                return Some(address);
+            }
+            match self.instructions[address + 1] {
+                Instruction::RetryMeElse { else_address } => address = else_address,
+                _ => return None,
+            }
         }
     }
 
@@ -324,28 +1390,78 @@ impl Compiler {
         }
     }
 
-    pub fn add_rule(&mut self, rule: &AbstractRule) {
-        let permanent_variables =
-            RegistryAllocator::prepare_permanent_variables(&rule, &mut self.descriptor_allocator);
-
-        let root_descriptor_id = self.descriptor_allocator.get_or_set(&rule.head);
+    pub fn add_rule(&mut self, rule: &AbstractRule) -> Result<(), PrologError> {
+        self.check_local_definition(&rule.head)?;
+        let root_descriptor_id = self.head_functor(&rule.head);
+        if self.is_dynamic(root_descriptor_id) {
+            self.add_dynamic_clause(&AbstractProgram::Rule(rule.clone()), ClausePosition::Last)?;
+            return Ok(());
+        }
         let instruction_start = self.instructions.len();
+        // reserve noop for potential, choice point instruction.
+        let control_predicates = self.compile_rule(
+            rule,
+            self.get_callable_reserved_instruction(root_descriptor_id),
+        );
+        self.register_callable(root_descriptor_id, instruction_start);
+        self.set_clause_key(instruction_start, &rule.head);
+        self.static_clauses
+            .entry(root_descriptor_id)
+            .or_default()
+            .push((rule.head.clone(), rule.body()));
+        for rule in &control_predicates {
+            self.add_rule(rule)?;
+        }
+        Ok(())
+    }
+
+    /// Compiles `rule`, returning the clauses of the auxiliary predicates of its control
+    /// constructs, which must be added once the clause is complete.
+    fn compile_rule(
+        &mut self,
+        rule: &AbstractRule,
+        reserved_instruction: Instruction,
+    ) -> Vec<AbstractRule> {
+        let (expanded, control_predicates) = self.expand_control(rule);
+        let (rule, modules) = self.goal_modules(&expanded);
+        let rule = rule.as_ref();
+        let mut permanent_variables =
+            RegistryAllocator::prepare_permanent_variables(&rule, &mut self.descriptor_allocator);
 
         let mut processed = HashSet::<DescriptorId>::new();
 
         self.instructions.push(Instruction::DebugComment {
             message: Box::new(format!("{}/{} (head)", rule.head.name(), rule.head.arity())),
         });
-        // reserve noop for potential, choice point instruction.
-        self.instructions
-            .push(self.get_callable_reserved_instruction(root_descriptor_id));
+        self.instructions.push(reserved_instruction);
+        // A clause containing a cut remembers the cut barrier of its call in a permanent
+        // variable, as the barrier is overwritten by every call in between. Its control
+        // constructs receive the barrier in that variable to cut the clause.
+        let level = self
+            .descriptor_allocator
+            .get_or_set(&AbstractTerm::Variable(CUT_LEVEL.to_string()));
+        let cut_level = rule
+            .goals
+            .iter()
+            .any(|goal| is_cut(goal) || mentions_cut_level(goal))
+            .then(|| {
+                let index = permanent_variables.len();
+                RegisterId::Permanent(*permanent_variables.entry(level).or_insert(index))
+            });
         self.instructions.push(Instruction::Allocate {
             variables: permanent_variables.len(),
         });
+        if let Some(register) = cut_level {
+            self.instructions.push(Instruction::GetLevel { register });
+            processed.insert(level);
+        }
+        let first_goal_arity = rule.goals.first().map_or(0, |goal| goal.arity());
+        let mut chunk = RegisterChunk::new(rule.head.arity().max(first_goal_arity));
         let head = self.compile_for_target::<ProgramTarget>(
             &rule.head,
             &permanent_variables,
             &mut processed,
+            &mut chunk,
         );
         self.instructions.extend(head.instructions.clone());
 
@@ -353,102 +1469,226 @@ impl Compiler {
             message: Box::new(format!("{}/{} (body)", rule.head.name(), rule.head.arity())),
         });
 
-        for goal in &rule.goals {
+        for (goal_index, goal) in rule.goals.iter().enumerate() {
             self.instructions.push(Instruction::DebugComment {
                 message: Box::new(format!("{}/{} (goal)", goal.name(), goal.arity())),
             });
-            let query =
-                self.compile_for_target::<QueryTarget>(&goal, &permanent_variables, &mut processed);
+            if goal_index > 0 {
+                chunk = RegisterChunk::default();
+            }
+            if let Some(register) = cut_level
+                && is_cut(goal)
+            {
+                self.instructions.push(Instruction::Cut { register });
+                continue;
+            }
+            if let Some(instructions) = self.compile_inline_arithmetic(
+                goal,
+                &permanent_variables,
+                &mut processed,
+                &mut chunk,
+            ) {
+                self.instructions.extend(instructions);
+                continue;
+            }
+            let query = self.compile_for_target::<QueryTarget>(
+                goal,
+                &permanent_variables,
+                &mut processed,
+                &mut chunk,
+            );
             self.instructions.extend(query.instructions);
 
-            let descriptor_id = self.descriptor_allocator.get_or_set(&goal);
-            let call_address = self
-                .fact_call_map
-                .get(&descriptor_id)
-                .expect("term to exist");
+            if let Some(builtin) = Builtin::lookup(goal.name(), goal.arity()) {
+                self.instructions.push(Instruction::CallBuiltin { builtin });
+                continue;
+            }
+
+            let descriptor_id = self.descriptor_allocator.get_or_set_predicate(
+                &modules[goal_index],
+                goal.name(),
+                goal.arity(),
+            );
             self.instructions.push(Instruction::Call {
-                address: *call_address,
+                address: self
+                    .predicate_address(descriptor_id)
+                    .unwrap_or(UNRESOLVED_ADDRESS),
                 functor: descriptor_id,
             });
         }
 
         self.instructions.push(Instruction::Deallocate);
+        control_predicates
+    }
 
-        self.register_callable(root_descriptor_id, instruction_start);
+    /// Replaces the disjunctions and if-then-elses of `rule` whose branches cut the clause, and
+    /// the ones using `*->`, by calls of auxiliary predicates `'$control_N'(Variables...)`. The
+    /// clauses of these predicates are returned with the rewritten rule. A cut in a branch
+    /// becomes `'$cut'(Level)`, which cuts back to the cut barrier `Level` of `rule`. Other
+    /// control constructs are called through the predicates of the prelude.
+    fn expand_control<'a>(
+        &mut self,
+        rule: &'a AbstractRule,
+    ) -> (Cow<'a, AbstractRule>, Vec<AbstractRule>) {
+        if !rule.goals.iter().any(needs_expansion) {
+            return (Cow::Borrowed(rule), Vec::new());
+        }
+
+        let mut clauses = Vec::new();
+        let mut goals = Vec::new();
+        for goal in &rule.goals {
+            if !needs_expansion(goal) {
+                goals.push(goal.clone());
+                continue;
+            }
+            let construct = replace_cuts(goal);
+            let mut variables = Vec::new();
+            collect_variables(&construct, &mut variables);
+            let name = format!("$control_{}", self.control_predicates);
+            self.control_predicates += 1;
+            let head = if variables.is_empty() {
+                AbstractTerm::Constant(name)
+            } else {
+                AbstractTerm::Structure(name, variables)
+            };
+
+            // Within the auxiliary predicate, `$level` is its own cut barrier.
+            let aux_head = rename_cut_level(&head);
+            for body in control_clauses(rename_cut_level(&construct)) {
+                let clause =
+                    AbstractTerm::Structure(":-".to_string(), vec![aux_head.clone(), body]);
+                match program_from_term(clause) {
+                    Ok(AbstractProgram::Rule(rule)) => clauses.push(rule),
+                    _ => unreachable!("control clauses to be rules"),
+                }
+            }
+            goals.push(head);
+        }
+        let rule = AbstractRule {
+            head: rule.head.clone(),
+            goals,
+        };
+        (Cow::Owned(rule), clauses)
     }
 
-    pub fn add_fact(&mut self, fact: &AbstractFact) {
-        let root_descriptor_id = self.descriptor_allocator.get_or_set(&fact.term);
+    pub fn add_fact(&mut self, fact: &AbstractFact) -> Result<(), PrologError> {
+        self.check_local_definition(&fact.term)?;
+        let root_descriptor_id = self.head_functor(&fact.term);
+        if self.is_dynamic(root_descriptor_id) {
+            self.add_dynamic_clause(&AbstractProgram::Fact(fact.clone()), ClausePosition::Last)?;
+            return Ok(());
+        }
         let instruction_start = self.instructions.len();
+        // reserve noop for potential, choice point instruction.
+        self.compile_fact(
+            fact,
+            self.get_callable_reserved_instruction(root_descriptor_id),
+        );
+        self.register_callable(root_descriptor_id, instruction_start);
+        self.set_clause_key(instruction_start, &fact.term);
+        self.static_clauses
+            .entry(root_descriptor_id)
+            .or_default()
+            .push((
+                fact.term.clone(),
+                AbstractTerm::Constant("true".to_string()),
+            ));
+        Ok(())
+    }
 
+    fn compile_fact(&mut self, fact: &AbstractFact, reserved_instruction: Instruction) {
         self.instructions.push(Instruction::DebugComment {
             message: Box::new(format!("{}/{}", fact.name(), fact.arity())),
         });
-        // reserve noop for potential, choice point instruction.
-        self.instructions
-            .push(self.get_callable_reserved_instruction(root_descriptor_id));
+        self.instructions.push(reserved_instruction);
 
         let artifact = self.compile_for_target::<ProgramTarget>(
             &fact.term,
             &Default::default(),
             &mut HashSet::new(),
+            &mut RegisterChunk::default(),
         );
         self.instructions.extend(artifact.instructions.clone());
 
         self.instructions.push(Instruction::Proceed);
-
-        self.register_callable(root_descriptor_id, instruction_start);
     }
 
     pub fn compile(&mut self, query: &AbstractProgram) -> CompileArtifact {
+        // The program has been loaded, anything compiled from now on belongs to `user`.
+        self.source_module = "user".to_string();
         let query = match query {
             AbstractProgram::Fact(fact) => &fact.term,
             _ => todo!(),
         };
 
-        let root_descriptor_id = self.descriptor_allocator.get_or_set(query);
-        let call_address = *self.fact_call_map.get(&root_descriptor_id).unwrap();
+        // Variables starting with `_` are not part of the answer.
+        let mut variables = Vec::new();
+        let mut query_variables = Vec::new();
+        for item in DepthFirstIterator::new(query) {
+            if let AbstractTerm::Variable(name) = item.term
+                && !name.starts_with('_')
+            {
+                let descriptor_id = self.descriptor_allocator.get_or_set(item.term);
+                if !query_variables.contains(&descriptor_id) {
+                    query_variables.push(descriptor_id);
+                    variables.push(item.term.clone());
+                }
+            }
+        }
 
-        let artifact =
-            self.compile_for_target::<QueryTarget>(query, &Default::default(), &mut HashSet::new());
+        // The query runs as the body of a clause taking its variables as arguments. They are
+        // created before anything else, so the n-th variable is the n-th cell of the global
+        // stack. The cell after them receives the goals describing the attributes left on them.
+        let name = format!("$query_{}", self.queries);
+        self.queries += 1;
+        let residuals = AbstractTerm::Variable("Residuals#".to_string());
+        let body = AbstractTerm::Structure(
+            ",".to_string(),
+            vec![
+                query.clone(),
+                AbstractTerm::Structure(
+                    "$residual_goals".to_string(),
+                    vec![
+                        AbstractTerm::list(variables.clone(), AbstractTerm::Constant("[]".into())),
+                        residuals.clone(),
+                    ],
+                ),
+            ],
+        );
+        variables.push(residuals);
+        let head = AbstractTerm::Structure(name.clone(), variables);
+        let clause = AbstractTerm::Structure(":-".to_string(), vec![head, body]);
+        self.add_program(&program_from_term(clause).expect("query to be callable"))
+            .expect("query clause to be local");
+        let functor = self.descriptor_allocator.get_or_set_predicate(
+            "user",
+            &name,
+            query_variables.len() + 1,
+        );
 
         let start_instruction = self.instructions.len();
         self.instructions.push(Instruction::DebugComment {
             message: Box::new(format!("{}/{} (query)", query.name(), query.arity())),
         });
-        self.instructions.extend(artifact.instructions);
+        for index in 0..=query_variables.len() {
+            self.instructions.push(Instruction::PutVariable {
+                argument_register: RegisterId::Argument(index),
+                variable_register: RegisterId::Argument(index),
+            });
+        }
         self.instructions.push(Instruction::Call {
-            address: call_address,
-            functor: root_descriptor_id,
+            address: self
+                .predicate_address(functor)
+                .expect("query clause to be compiled"),
+            functor,
         });
-
-        let mut unique_variables = HashSet::new();
-        let inspection_variables = DepthFirstIterator::new(query)
-            .filter_map(|term| match term.term {
-                AbstractTerm::Variable(_) => {
-                    let descriptor_id = self.descriptor_allocator.get_or_set(&term.term);
-                    if unique_variables.contains(&descriptor_id) {
-                        return None;
-                    }
-                    unique_variables.insert(descriptor_id);
-
-                    let register = artifact
-                        .register_allocator
-                        .get_register(&term, &mut self.descriptor_allocator);
-                    Some(InspectionVariable {
-                        register: register.register.unwrap(),
-                        variable: descriptor_id,
-                    })
-                }
-                _ => None,
-            })
-            .collect();
+        self.instructions.push(Instruction::Halt);
 
         CompileArtifact {
             start_instruction_index: start_instruction,
             instructions: self.instructions.clone(),
             max_registers: self.max_registers,
-            inspection_variables,
+            query_variables,
         }
     }
 
@@ -457,18 +1697,72 @@ impl Compiler {
         root: &'a AbstractTerm,
         permanent_variables: &HashMap<DescriptorId, usize>,
         processed_vars: &mut HashSet<DescriptorId>,
+        chunk: &mut RegisterChunk,
     ) -> IntermediateCompileArtifact {
         let mut instructions = Vec::new();
 
-        let registry_allocator =
-            RegistryAllocator::new::<T>(root, &mut self.descriptor_allocator, permanent_variables);
+        let registry_allocator = RegistryAllocator::new::<T>(
+            root,
+            &mut self.descriptor_allocator,
+            permanent_variables,
+            chunk,
+        );
+
+        // Boxed numbers and strings occupy several heap cells, so they can't be written while
+        // the arguments of a structure are laid out. They are built upfront and referenced by
+        // register instead.
+        for term in T::get_ordered_iterator(root) {
+            let boxed = match term.term {
+                AbstractTerm::Number(number) => number.is_boxed(),
+                AbstractTerm::String(_) => true,
+                _ => false,
+            };
+            if !boxed || term.level <= 1 {
+                continue;
+            }
+            let register = registry_allocator
+                .get_register(&term, &mut self.descriptor_allocator)
+                .register
+                .unwrap();
+            instructions.push(match term.term {
+                AbstractTerm::Number(number) => Instruction::PutNumber {
+                    number: Box::new(number.clone()),
+                    register,
+                },
+                AbstractTerm::String(string) => Instruction::PutString {
+                    string: Box::new(string.clone()),
+                    register,
+                },
+                _ => unreachable!("only numbers and strings are boxed"),
+            });
+        }
+
         let iter = T::get_ordered_iterator(root);
 
         for term in iter {
-            let descriptor_id = self.descriptor_allocator.get_or_set(term.term);
             let register_allocation =
                 registry_allocator.get_register(&term, &mut self.descriptor_allocator);
 
+            if let AbstractTerm::Number(number) = term.term {
+                if term.level == 1 {
+                    instructions.push(T::instruction_for_number_argument(
+                        number,
+                        register_allocation.get_register_id(term.level, term.argument_index),
+                    ));
+                }
+                continue;
+            }
+            if let AbstractTerm::String(string) = term.term {
+                if term.level == 1 {
+                    instructions.push(T::instruction_for_string_argument(
+                        string,
+                        register_allocation.get_register_id(term.level, term.argument_index),
+                    ));
+                }
+                continue;
+            }
+
+            let descriptor_id = self.descriptor_allocator.get_or_set(term.term);
             let mut was_processed = processed_vars.contains(&descriptor_id);
 
             match term.term {
@@ -498,15 +1792,26 @@ impl Compiler {
                         descriptor_id,
                         register_allocation.get_register_id(term.level, term.argument_index),
                     ));
-                    for (sub_term_index, sub_term) in sub_terms.iter().enumerate() {
+                    for sub_term in sub_terms {
+                        if let AbstractTerm::Number(number) = sub_term
+                            && !number.is_boxed()
+                        {
+                            instructions.push(T::instruction_for_number(number));
+                            continue;
+                        }
+                        if let AbstractTerm::Number(_) | AbstractTerm::String(_) = sub_term {
+                            let sub_register_allocation = registry_allocator
+                                .get_register_raw(sub_term, &mut self.descriptor_allocator);
+                            instructions.push(T::instruction_for_value(
+                                sub_register_allocation.register.unwrap(),
+                            ));
+                            continue;
+                        }
+
                         let sub_descriptor_id = self.descriptor_allocator.get_or_set(sub_term);
 
-                        let sub_register_allocation = registry_allocator.get_register_raw(
-                            sub_term,
-                            term.id,
-                            sub_term_index,
-                            &mut self.descriptor_allocator,
-                        );
+                        let sub_register_allocation = registry_allocator
+                            .get_register_raw(sub_term, &mut self.descriptor_allocator);
 
                         let was_processed = !processed_vars.insert(sub_descriptor_id);
 
@@ -529,6 +1834,7 @@ impl Compiler {
                                     sub_register_allocation.register.unwrap(),
                                 ));
                             }
+                            AbstractTerm::Number(_) | AbstractTerm::String(_) => unreachable!(),
                         }
                     }
                 }
@@ -539,14 +1845,271 @@ impl Compiler {
             }
         }
 
-        self.max_registers = self
-            .max_registers
-            .max(registry_allocator.registry_ordered_list.len());
+        self.max_registers = self.max_registers.max(registry_allocator.register_count);
+
+        IntermediateCompileArtifact { instructions }
+    }
+}
+
+/// Collects the `Name/Arity` indicators of a declaration like `dynamic((a/1, b/2))` or
+/// `dynamic([a/1, b/2])`. Returns false if one of them is not a valid indicator.
+fn collect_indicators(specification: &AbstractTerm, indicators: &mut Vec<(String, usize)>) -> bool {
+    match specification {
+        AbstractTerm::Structure(name, arguments) if name == "," || name == "." => arguments
+            .iter()
+            .all(|argument| collect_indicators(argument, indicators)),
+        AbstractTerm::Constant(name) if name == "[]" => true,
+        AbstractTerm::Structure(name, arguments) if name == "/" => match &arguments[..] {
+            [
+                AbstractTerm::Constant(name),
+                AbstractTerm::Number(Number::Integer(arity)),
+            ] if *arity >= 0 => {
+                indicators.push((name.clone(), *arity as usize));
+                true
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Collects the terms of a conjunction.
+fn collect_conjunction<'a>(term: &'a AbstractTerm, terms: &mut Vec<&'a AbstractTerm>) {
+    match term {
+        AbstractTerm::Structure(name, arguments) if name == "," && arguments.len() == 2 => {
+            collect_conjunction(&arguments[0], terms);
+            collect_conjunction(&arguments[1], terms);
+        }
+        _ => terms.push(term),
+    }
+}
+
+/// Whether a `meta_predicate` argument specification stands for a goal or a closure: an
+/// integer, `:`, `^` or `//`.
+fn is_module_sensitive(specification: &AbstractTerm) -> bool {
+    match specification {
+        AbstractTerm::Number(Number::Integer(arity)) => (0..=9).contains(arity),
+        AbstractTerm::Constant(name) => matches!(name.as_str(), ":" | "^" | "//"),
+        _ => false,
+    }
+}
+
+/// Splits the goal `Module:Goal` with an atom as module.
+pub fn unqualify_goal(goal: &AbstractTerm) -> Option<(&str, &AbstractTerm)> {
+    match goal {
+        AbstractTerm::Structure(name, arguments) if name == ":" && arguments.len() == 2 => {
+            match &arguments[0] {
+                AbstractTerm::Constant(module) => Some((module, &arguments[1])),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Qualifies the goal or closure `term` with `module`. Control constructs are qualified goal
+/// by goal, so the predicates implementing them still match them.
+pub fn qualify_goal(module: &str, term: &AbstractTerm) -> AbstractTerm {
+    match term {
+        AbstractTerm::Structure(name, arguments)
+            if matches!(
+                (name.as_str(), arguments.len()),
+                (",", 2) | (";", 2) | ("->", 2) | ("\\+", 1)
+            ) =>
+        {
+            AbstractTerm::Structure(
+                name.clone(),
+                arguments
+                    .iter()
+                    .map(|argument| qualify_goal(module, argument))
+                    .collect(),
+            )
+        }
+        AbstractTerm::Structure(name, arguments) if name == "^" && arguments.len() == 2 => {
+            AbstractTerm::Structure(
+                name.clone(),
+                vec![arguments[0].clone(), qualify_goal(module, &arguments[1])],
+            )
+        }
+        AbstractTerm::Structure(name, arguments) if name == ":" && arguments.len() == 2 => {
+            term.clone()
+        }
+        _ => AbstractTerm::Structure(
+            ":".to_string(),
+            vec![AbstractTerm::Constant(module.to_string()), term.clone()],
+        ),
+    }
+}
 
-        IntermediateCompileArtifact {
-            instructions,
-            register_allocator: registry_allocator,
+/// Splits the clause `Module:Head :- Body` or `Module:(Head :- Body)` into its module and
+/// the unqualified clause.
+fn unqualify_clause(program: &AbstractProgram) -> Option<(String, AbstractProgram)> {
+    match program {
+        AbstractProgram::Fact(fact) => {
+            let (module, term) = unqualify_goal(&fact.term)?;
+            let clause = match term {
+                AbstractTerm::Structure(name, arguments)
+                    if name == ":-" && arguments.len() == 2 =>
+                {
+                    program_from_term(term.clone()).ok()?
+                }
+                _ => AbstractProgram::Fact(AbstractFact { term: term.clone() }),
+            };
+            Some((module.to_string(), clause))
         }
+        AbstractProgram::Rule(rule) => {
+            let (module, head) = unqualify_goal(&rule.head)?;
+            let rule = AbstractRule {
+                head: head.clone(),
+                goals: rule.goals.clone(),
+            };
+            Some((module.to_string(), AbstractProgram::Rule(rule)))
+        }
+        AbstractProgram::Directive(_) => None,
+    }
+}
+
+fn is_cut(goal: &AbstractTerm) -> bool {
+    matches!(goal, AbstractTerm::Constant(name) if name == "!")
+}
+
+/// The variable holding the cut barrier of a clause, for the cuts of its control constructs.
+/// Variables read from source never start with `$`.
+const CUT_LEVEL: &str = "$level";
+
+/// The variable the cut barrier of the calling clause is passed in to auxiliary predicates.
+const PARENT_CUT_LEVEL: &str = "Level#";
+
+/// Whether `goal` is a control construct compiled into an auxiliary predicate by
+/// [`Compiler::expand_control`].
+fn needs_expansion(goal: &AbstractTerm) -> bool {
+    match goal {
+        AbstractTerm::Structure(name, arguments)
+            if matches!(name.as_str(), ";" | "->" | "*->") && arguments.len() == 2 =>
+        {
+            uses_soft_cut(goal) || cuts_clause(goal)
+        }
+        _ => false,
+    }
+}
+
+fn uses_soft_cut(goal: &AbstractTerm) -> bool {
+    match goal {
+        AbstractTerm::Structure(name, arguments)
+            if matches!(name.as_str(), "," | ";" | "->" | "*->") && arguments.len() == 2 =>
+        {
+            name == "*->" || arguments.iter().any(uses_soft_cut)
+        }
+        _ => false,
+    }
+}
+
+/// Whether `goal` contains a cut which cuts the clause it is called from, rather than a cut
+/// local to a condition.
+fn cuts_clause(goal: &AbstractTerm) -> bool {
+    match goal {
+        AbstractTerm::Constant(name) => name == "!",
+        AbstractTerm::Structure(name, arguments) if arguments.len() == 2 => match name.as_str() {
+            "," | ";" => arguments.iter().any(cuts_clause),
+            "->" | "*->" => cuts_clause(&arguments[1]),
+            _ => false,
+        },
+        AbstractTerm::Structure(name, arguments) => name == "$cut" && arguments.len() == 1,
+        _ => false,
+    }
+}
+
+/// Replaces the cuts of `goal` found by [`cuts_clause`] by `'$cut'($level)`.
+fn replace_cuts(goal: &AbstractTerm) -> AbstractTerm {
+    match goal {
+        AbstractTerm::Constant(name) if name == "!" => AbstractTerm::Structure(
+            "$cut".to_string(),
+            vec![AbstractTerm::Variable(CUT_LEVEL.to_string())],
+        ),
+        AbstractTerm::Structure(name, arguments) if arguments.len() == 2 => match name.as_str() {
+            "," | ";" => {
+                AbstractTerm::Structure(name.clone(), arguments.iter().map(replace_cuts).collect())
+            }
+            "->" | "*->" => AbstractTerm::Structure(
+                name.clone(),
+                vec![arguments[0].clone(), replace_cuts(&arguments[1])],
+            ),
+            _ => goal.clone(),
+        },
+        _ => goal.clone(),
+    }
+}
+
+fn rename_cut_level(term: &AbstractTerm) -> AbstractTerm {
+    match term {
+        AbstractTerm::Variable(name) if name == CUT_LEVEL => {
+            AbstractTerm::Variable(PARENT_CUT_LEVEL.to_string())
+        }
+        AbstractTerm::Structure(name, arguments) => AbstractTerm::Structure(
+            name.clone(),
+            arguments.iter().map(rename_cut_level).collect(),
+        ),
+        _ => term.clone(),
+    }
+}
+
+fn mentions_cut_level(term: &AbstractTerm) -> bool {
+    match term {
+        AbstractTerm::Variable(name) => name == CUT_LEVEL,
+        AbstractTerm::Structure(_, arguments) => arguments.iter().any(mentions_cut_level),
+        _ => false,
+    }
+}
+
+/// Collects the distinct variables of `term` in order of their first occurrence.
+fn collect_variables(term: &AbstractTerm, variables: &mut Vec<AbstractTerm>) {
+    match term {
+        AbstractTerm::Variable(_) if !variables.contains(term) => variables.push(term.clone()),
+        AbstractTerm::Structure(_, arguments) => {
+            for argument in arguments {
+                collect_variables(argument, variables);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The clause bodies of the auxiliary predicate for the control construct `goal`. An
+/// if-then-else commits to its then branch with a cut, `*->` only removes the choice point
+/// of its else branch. Conditions containing a cut are called, as the cut is local to them.
+fn control_clauses(mut goal: AbstractTerm) -> Vec<AbstractTerm> {
+    let AbstractTerm::Structure(name, arguments) = &mut goal else {
+        unreachable!("control constructs to be structures");
+    };
+    let [mut first, second] =
+        <[AbstractTerm; 2]>::try_from(std::mem::take(arguments)).expect("binary construct");
+    let conjunction = |left, right| AbstractTerm::Structure(",".to_string(), vec![left, right]);
+    let condition = |condition: AbstractTerm| match cuts_clause(&condition) {
+        true => AbstractTerm::Structure("call".to_string(), vec![condition]),
+        false => condition,
+    };
+    let cut = || AbstractTerm::Constant("!".to_string());
+    match (name.as_str(), &mut first) {
+        (";", AbstractTerm::Structure(inner, arguments))
+            if matches!(inner.as_str(), "->" | "*->") && arguments.len() == 2 =>
+        {
+            let [test, then] =
+                <[AbstractTerm; 2]>::try_from(std::mem::take(arguments)).expect("binary construct");
+            let commit = match inner.as_str() {
+                "->" => cut(),
+                _ => AbstractTerm::Structure(
+                    "$soft_cut".to_string(),
+                    vec![AbstractTerm::Variable(CUT_LEVEL.to_string())],
+                ),
+            };
+            vec![
+                conjunction(condition(test), conjunction(commit, then)),
+                second,
+            ]
+        }
+        (";", _) => vec![first, second],
+        ("->", _) => vec![conjunction(condition(first), conjunction(cut(), second))],
+        (_, _) => vec![conjunction(condition(first), second)],
     }
 }
 
@@ -611,6 +2174,26 @@ impl<'a> CompileTarget<'a> for ProgramTarget {
     fn instruction_for_sub_argument(register: RegisterId) -> Instruction {
         Instruction::UnifyVariable { register }
     }
+
+    fn instruction_for_number_argument(number: &Number, register: RegisterId) -> Instruction {
+        Instruction::GetNumber {
+            number: Box::new(number.clone()),
+            register,
+        }
+    }
+
+    fn instruction_for_number(number: &Number) -> Instruction {
+        Instruction::UnifyNumber {
+            number: Box::new(number.clone()),
+        }
+    }
+
+    fn instruction_for_string_argument(string: &str, register: RegisterId) -> Instruction {
+        Instruction::GetString {
+            string: Box::new(string.to_string()),
+            register,
+        }
+    }
 }
 
 impl<'a> CompileTarget<'a> for QueryTarget {
@@ -671,4 +2254,24 @@ impl<'a> CompileTarget<'a> for QueryTarget {
     fn instruction_for_sub_argument(register: RegisterId) -> Instruction {
         Instruction::SetValue { register }
     }
+
+    fn instruction_for_number_argument(number: &Number, register: RegisterId) -> Instruction {
+        Instruction::PutNumber {
+            number: Box::new(number.clone()),
+            register,
+        }
+    }
+
+    fn instruction_for_number(number: &Number) -> Instruction {
+        Instruction::SetNumber {
+            number: Box::new(number.clone()),
+        }
+    }
+
+    fn instruction_for_string_argument(string: &str, register: RegisterId) -> Instruction {
+        Instruction::PutString {
+            string: Box::new(string.to_string()),
+            register,
+        }
+    }
 }
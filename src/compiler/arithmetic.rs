@@ -0,0 +1,166 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    compiler::{Compiler, RegisterChunk},
+    instructions::{DescriptorId, Instruction, RegisterId},
+    parsing::AbstractTerm,
+};
+
+impl Compiler {
+    /// Compiles `is/2` and the arithmetic comparisons into register based instructions, as long
+    /// as the shape of the expression is known at compile time. Returns `None` if the goal has to
+    /// go through the general builtin instead, e.g. because a variable is still unbound or the
+    /// expression uses an operation without a dedicated instruction.
+    pub(super) fn compile_inline_arithmetic(
+        &mut self,
+        goal: &AbstractTerm,
+        permanent_variables: &HashMap<DescriptorId, usize>,
+        processed_vars: &mut HashSet<DescriptorId>,
+        chunk: &mut RegisterChunk,
+    ) -> Option<Vec<Instruction>> {
+        let AbstractTerm::Structure(name, arguments) = goal else {
+            return None;
+        };
+        let [left, right] = arguments.as_slice() else {
+            return None;
+        };
+
+        let mut instructions = Vec::new();
+        let mut inline = InlineExpression {
+            compiler: self,
+            permanent_variables,
+            processed_vars,
+            chunk,
+            instructions: &mut instructions,
+        };
+
+        match name.as_str() {
+            "is" => {
+                // `X is Y` still has to evaluate whatever `Y` is bound to.
+                if !matches!(right, AbstractTerm::Structure(..)) {
+                    return None;
+                }
+                let result = inline.operand(right)?;
+                let instruction = inline.result(left, result)?;
+                inline.instructions.push(instruction);
+            }
+            "=:=" | "=\\=" | "<" | "=<" | ">" | ">=" => {
+                let left = inline.operand(left)?;
+                let right = inline.operand(right)?;
+                inline.instructions.push(match name.as_str() {
+                    "=:=" => Instruction::CompareEq { left, right },
+                    "=\\=" => Instruction::CompareNe { left, right },
+                    "<" => Instruction::CompareLt { left, right },
+                    "=<" => Instruction::CompareLe { left, right },
+                    ">" => Instruction::CompareGt { left, right },
+                    _ => Instruction::CompareGe { left, right },
+                });
+            }
+            _ => return None,
+        }
+
+        self.max_registers = self.max_registers.max(chunk.next_temporary);
+        Some(instructions)
+    }
+}
+
+struct InlineExpression<'a> {
+    compiler: &'a mut Compiler,
+    permanent_variables: &'a HashMap<DescriptorId, usize>,
+    processed_vars: &'a mut HashSet<DescriptorId>,
+    chunk: &'a mut RegisterChunk,
+    instructions: &'a mut Vec<Instruction>,
+}
+
+impl InlineExpression<'_> {
+    /// Emits the instructions computing `term` and returns the register holding its value.
+    fn operand(&mut self, term: &AbstractTerm) -> Option<RegisterId> {
+        match term {
+            AbstractTerm::Variable(_) => {
+                let descriptor_id = self.compiler.descriptor_allocator.get_or_set(term);
+                if !self.processed_vars.contains(&descriptor_id) {
+                    return None;
+                }
+                self.variable_register(descriptor_id)
+            }
+            AbstractTerm::Number(number) => {
+                let register = self.temporary();
+                self.instructions.push(Instruction::PutNumber {
+                    number: Box::new(number.clone()),
+                    register,
+                });
+                Some(register)
+            }
+            AbstractTerm::Structure(name, arguments) if arguments.len() == 2 => {
+                let left = self.operand(&arguments[0])?;
+                let right = self.operand(&arguments[1])?;
+                let target = self.temporary();
+                self.instructions.push(match name.as_str() {
+                    "+" => Instruction::Add {
+                        left,
+                        right,
+                        target,
+                    },
+                    "-" => Instruction::Subtract {
+                        left,
+                        right,
+                        target,
+                    },
+                    "*" => Instruction::Multiply {
+                        left,
+                        right,
+                        target,
+                    },
+                    _ => return None,
+                });
+                Some(target)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the instruction unifying the left hand side of `is/2` with the computed result.
+    fn result(&mut self, term: &AbstractTerm, result: RegisterId) -> Option<Instruction> {
+        match term {
+            AbstractTerm::Variable(_) => {
+                let descriptor_id = self.compiler.descriptor_allocator.get_or_set(term);
+                if self.processed_vars.insert(descriptor_id) {
+                    let register = match self.permanent_variables.get(&descriptor_id) {
+                        Some(index) => RegisterId::Permanent(*index),
+                        None => {
+                            let register = self.temporary();
+                            self.chunk.variables.insert(descriptor_id, register);
+                            register
+                        }
+                    };
+                    Some(Instruction::GetVariable {
+                        argument_register: result,
+                        variable_register: register,
+                    })
+                } else {
+                    Some(Instruction::GetValue {
+                        argument_register: result,
+                        value_register: self.variable_register(descriptor_id)?,
+                    })
+                }
+            }
+            AbstractTerm::Number(number) => Some(Instruction::GetNumber {
+                number: Box::new(number.clone()),
+                register: result,
+            }),
+            _ => None,
+        }
+    }
+
+    fn variable_register(&self, descriptor_id: DescriptorId) -> Option<RegisterId> {
+        match self.permanent_variables.get(&descriptor_id) {
+            Some(index) => Some(RegisterId::Permanent(*index)),
+            None => self.chunk.variables.get(&descriptor_id).copied(),
+        }
+    }
+
+    fn temporary(&mut self) -> RegisterId {
+        self.chunk.next_temporary += 1;
+        RegisterId::Temporary(self.chunk.next_temporary - 1)
+    }
+}
@@ -1,16 +1,22 @@
 use std::collections::HashMap;
 
-use crate::{instructions::DescriptorId, parsing::AbstractTerm};
+use crate::{instructions::DescriptorId, number::Number, parsing::AbstractTerm};
 
 #[derive(Debug, Clone)]
 pub struct TermDescriptor {
     pub name: String,
     pub kind: DescriptorKind,
+    /// The module of a predicate defined outside of the `user` module.
+    pub module: Option<String>,
 }
 
 impl TermDescriptor {
     pub fn new(name: String, kind: DescriptorKind) -> Self {
-        TermDescriptor { name, kind }
+        TermDescriptor {
+            name,
+            kind,
+            module: None,
+        }
     }
 
     pub fn arity(&self) -> usize {
@@ -20,9 +26,31 @@ impl TermDescriptor {
         }
     }
 
+    /// The predicate indicator `Name/Arity`, qualified as `Module:Name/Arity` for a predicate
+    /// outside of `user`.
+    pub fn indicator(&self) -> AbstractTerm {
+        let indicator = AbstractTerm::Structure(
+            "/".to_string(),
+            vec![
+                AbstractTerm::Constant(self.name.clone()),
+                AbstractTerm::Number(Number::Integer(self.arity() as i64)),
+            ],
+        );
+        match &self.module {
+            Some(module) => AbstractTerm::Structure(
+                ":".to_string(),
+                vec![AbstractTerm::Constant(module.clone()), indicator],
+            ),
+            None => indicator,
+        }
+    }
+
     pub fn pretty_name(&self) -> String {
         match &self.kind {
-            DescriptorKind::Functor { arity } => format!("{}/{}", self.name, arity),
+            DescriptorKind::Functor { arity } => match &self.module {
+                Some(module) => format!("{}:{}/{}", module, self.name, arity),
+                None => format!("{}/{}", self.name, arity),
+            },
             DescriptorKind::Variable => format!("{}(var)", self.name),
         }
     }
@@ -30,8 +58,20 @@ impl TermDescriptor {
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum DescriptorIdentifier {
-    Functor { name: String, arity: usize },
-    Named { name: String },
+    Functor {
+        name: String,
+        arity: usize,
+    },
+    Named {
+        name: String,
+    },
+    /// A predicate of a module other than `user`. Predicates of `user` share the descriptor of
+    /// their functor.
+    Predicate {
+        module: String,
+        name: String,
+        arity: usize,
+    },
 }
 
 impl From<&AbstractTerm> for DescriptorIdentifier {
@@ -46,6 +86,8 @@ impl From<&AbstractTerm> for DescriptorIdentifier {
                 name: name.clone(),
                 arity: 0,
             },
+            AbstractTerm::Number(_) => panic!("Numbers do not have descriptors"),
+            AbstractTerm::String(_) => panic!("Strings do not have descriptors"),
         }
     }
 }
@@ -56,7 +98,7 @@ pub enum DescriptorKind {
     Variable,
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct DescriptorAllocator {
     pub descriptor_map: HashMap<DescriptorIdentifier, DescriptorId>,
     pub descriptors: Vec<TermDescriptor>,
@@ -68,35 +110,60 @@ impl DescriptorAllocator {
     }
 
     pub fn get_or_set(&mut self, term: &AbstractTerm) -> DescriptorId {
-        let identifier = DescriptorIdentifier::from(term);
+        match term {
+            AbstractTerm::Structure(name, sub_terms) => {
+                self.get_or_set_functor(name, sub_terms.len())
+            }
+            AbstractTerm::Constant(name) => self.get_or_set_functor(name, 0),
+            AbstractTerm::Variable(name) => self.insert(
+                DescriptorIdentifier::Named { name: name.clone() },
+                TermDescriptor::new(name.clone(), DescriptorKind::Variable),
+            ),
+            AbstractTerm::Number(_) | AbstractTerm::String(_) => unreachable!(),
+        }
+    }
 
+    /// Returns the descriptor of `name/arity`, creating it if needed. Atoms are functors with an
+    /// arity of zero.
+    pub fn get_or_set_functor(&mut self, name: &str, arity: usize) -> DescriptorId {
+        self.insert(
+            DescriptorIdentifier::Functor {
+                name: name.to_string(),
+                arity,
+            },
+            TermDescriptor::new(name.to_string(), DescriptorKind::Functor { arity }),
+        )
+    }
+
+    /// Returns the descriptor of the predicate `name/arity` of `module`, creating it if needed.
+    pub fn get_or_set_predicate(&mut self, module: &str, name: &str, arity: usize) -> DescriptorId {
+        if module == "user" {
+            return self.get_or_set_functor(name, arity);
+        }
+        let mut descriptor =
+            TermDescriptor::new(name.to_string(), DescriptorKind::Functor { arity });
+        descriptor.module = Some(module.to_string());
+        self.insert(
+            DescriptorIdentifier::Predicate {
+                module: module.to_string(),
+                name: name.to_string(),
+                arity,
+            },
+            descriptor,
+        )
+    }
+
+    fn insert(
+        &mut self,
+        identifier: DescriptorIdentifier,
+        descriptor: TermDescriptor,
+    ) -> DescriptorId {
         if let Some(id) = self.descriptor_map.get(&identifier) {
             *id
         } else {
             let id = DescriptorId(self.descriptors.len());
             self.descriptor_map.insert(identifier, id);
-            match term {
-                AbstractTerm::Structure(name, sub_terms) => {
-                    self.descriptors.push(TermDescriptor {
-                        name: name.clone(),
-                        kind: DescriptorKind::Functor {
-                            arity: sub_terms.len(),
-                        },
-                    });
-                }
-                AbstractTerm::Constant(name) => {
-                    self.descriptors.push(TermDescriptor {
-                        name: name.clone(),
-                        kind: DescriptorKind::Functor { arity: 0 },
-                    });
-                }
-                AbstractTerm::Variable(name) => {
-                    self.descriptors.push(TermDescriptor {
-                        name: name.clone(),
-                        kind: DescriptorKind::Variable,
-                    });
-                }
-            }
+            self.descriptors.push(descriptor);
             id
         }
     }
@@ -1,6 +1,12 @@
+use crate::number::Number;
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Copy)]
 pub struct DescriptorId(pub usize);
 
+/// Address of calls to predicates that had no clauses when the call was compiled. The predicate
+/// is looked up again when the call is executed.
+pub const UNRESOLVED_ADDRESS: usize = usize::MAX;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum RegisterId {
     Argument(usize),
@@ -46,6 +52,17 @@ pub enum Instruction {
     SetConstant {
         constant: DescriptorId,
     },
+    PutNumber {
+        number: Box<Number>,
+        register: RegisterId,
+    },
+    SetNumber {
+        number: Box<Number>,
+    },
+    PutString {
+        string: Box<String>,
+        register: RegisterId,
+    },
     DebugComment {
         message: Box<String>,
     },
@@ -75,11 +92,65 @@ pub enum Instruction {
     UnifyConstant {
         constant: DescriptorId,
     },
+    GetNumber {
+        number: Box<Number>,
+        register: RegisterId,
+    },
+    UnifyNumber {
+        number: Box<Number>,
+    },
+    GetString {
+        string: Box<String>,
+        register: RegisterId,
+    },
+    // Arithmetic instructions ----------------------------
+    Add {
+        left: RegisterId,
+        right: RegisterId,
+        target: RegisterId,
+    },
+    Subtract {
+        left: RegisterId,
+        right: RegisterId,
+        target: RegisterId,
+    },
+    Multiply {
+        left: RegisterId,
+        right: RegisterId,
+        target: RegisterId,
+    },
+    CompareEq {
+        left: RegisterId,
+        right: RegisterId,
+    },
+    CompareNe {
+        left: RegisterId,
+        right: RegisterId,
+    },
+    CompareLt {
+        left: RegisterId,
+        right: RegisterId,
+    },
+    CompareLe {
+        left: RegisterId,
+        right: RegisterId,
+    },
+    CompareGt {
+        left: RegisterId,
+        right: RegisterId,
+    },
+    CompareGe {
+        left: RegisterId,
+        right: RegisterId,
+    },
     // Control Instructions ----------------------------
     Call {
         address: usize,
         functor: DescriptorId,
     },
+    CallBuiltin {
+        builtin: Builtin,
+    },
     Allocate {
         variables: usize,
     },
@@ -92,5 +163,623 @@ pub enum Instruction {
         else_address: usize,
     },
     TrustMe,
+    /// Entry point of a dynamic predicate. Tries the first of the clauses visible at this point
+    /// and leaves a choice point for the others.
+    TryClauses {
+        functor: DescriptorId,
+    },
+    /// Tries the next clause of a dynamic predicate when backtracking into its choice point.
+    RetryClauses {
+        functor: DescriptorId,
+    },
+    /// Stores the cut barrier of the current call in `register`, so a later `!` in the same
+    /// clause knows which choice points to remove.
+    GetLevel {
+        register: RegisterId,
+    },
+    /// Removes all choice points created since the level in `register` was taken.
+    Cut {
+        register: RegisterId,
+    },
     NoOp,
+    /// Ends the query. Backtracking may resume execution from a remaining choice point.
+    Halt,
+}
+
+/// Predicates implemented natively by the interpreter. Their arguments are passed in the argument
+/// registers just like for ordinary calls, but no environment or choice point is involved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Builtin {
+    Is,
+    ArithmeticEqual,
+    ArithmeticNotEqual,
+    ArithmeticLess,
+    ArithmeticLessOrEqual,
+    ArithmeticGreater,
+    ArithmeticGreaterOrEqual,
+    Var,
+    Nonvar,
+    Atom,
+    Number,
+    Integer,
+    Float,
+    String,
+    Atomic,
+    Compound,
+    Callable,
+    IsList,
+    /// `'$skip_list'(List, Length, Tail)`, for `length/2`.
+    SkipList,
+    Ground,
+    AcyclicTerm,
+    CyclicTerm,
+    MustBe,
+    /// `'$domain_error'(Domain, Culprit)`, raises a domain error from the bundled libraries.
+    DomainError,
+    Functor,
+    Arg,
+    Univ,
+    CopyTerm,
+    TermVariables,
+    Compare,
+    StructurallyEqual,
+    StructurallyNotEqual,
+    TermLess,
+    TermLessOrEqual,
+    TermGreater,
+    TermGreaterOrEqual,
+    Sort,
+    Msort,
+    Keysort,
+    Unify,
+    NotUnifiable,
+    UnifyWithOccursCheck,
+    True,
+    Fail,
+    False,
+    /// `call/N`, the goal followed by `N - 1` extra arguments.
+    Call(usize),
+    /// `'$cut'(Level)`, a cut inside a control construct, which cuts the clause whose cut
+    /// barrier is `Level`.
+    CutTo,
+    /// `'$soft_cut'(Level)`, drops the choice point at `Level` but keeps the ones created after
+    /// it, for the condition of `*->`.
+    SoftCut,
+    FindallStart,
+    FindallAdd,
+    FindallCollect,
+    FreeVariables,
+    BagofGroups,
+    Asserta,
+    Assertz,
+    Assert,
+    ClauseRefs,
+    ClauseParts,
+    Erase,
+    Abolish,
+    Dynamic,
+    Clauses,
+    CurrentPredicates,
+    PredicateProperties,
+    Listing,
+    Write,
+    Print,
+    Writeq,
+    WriteCanonical,
+    WriteTerm,
+    Nl,
+    Format,
+    FormatTo,
+    WriteTermTo,
+    Open,
+    Close,
+    SetInput,
+    SetOutput,
+    CurrentInput,
+    CurrentOutput,
+    GetChar,
+    GetCode,
+    GetByte,
+    PeekChar,
+    PeekCode,
+    PeekByte,
+    PutChar,
+    PutCode,
+    PutByte,
+    FlushOutput,
+    AtEndOfStream,
+    Streams,
+    StreamProperties,
+    MemoryOutput,
+    MemoryOutputText,
+    ReadTerm,
+    ReadTermFrom,
+    AtomLength,
+    StringLength,
+    AtomChars,
+    AtomCodes,
+    StringChars,
+    StringCodes,
+    CharCode,
+    NumberChars,
+    NumberCodes,
+    UpcaseAtom,
+    DowncaseAtom,
+    StringUpper,
+    StringLower,
+    StringToAtom,
+    StringCode,
+    AtomConcat,
+    StringConcat,
+    SplitString,
+    SubAtom,
+    SubString,
+    AbsoluteFileName,
+    StartConsult,
+    EndConsult,
+    LoadedFile,
+    FileModule,
+    LoadSource,
+    AddClause,
+    CompilerDirective,
+    SourceModule,
+    AddInitialization,
+    DirectiveFailed,
+    DcgBody,
+    DcgTranslateRule,
+    SetPrologFlag,
+    PrologFlags,
+    PutAttr,
+    GetAttr,
+    DelAttr,
+    GetAttrs,
+    Attvar,
+    TermAttvars,
+    ModuleDefines,
+    RestoreArguments,
+}
+
+impl Builtin {
+    const ALL: &[Builtin] = &[
+        Builtin::Is,
+        Builtin::ArithmeticEqual,
+        Builtin::ArithmeticNotEqual,
+        Builtin::ArithmeticLess,
+        Builtin::ArithmeticLessOrEqual,
+        Builtin::ArithmeticGreater,
+        Builtin::ArithmeticGreaterOrEqual,
+        Builtin::Var,
+        Builtin::Nonvar,
+        Builtin::Atom,
+        Builtin::Number,
+        Builtin::Integer,
+        Builtin::Float,
+        Builtin::String,
+        Builtin::Atomic,
+        Builtin::Compound,
+        Builtin::Callable,
+        Builtin::IsList,
+        Builtin::SkipList,
+        Builtin::Ground,
+        Builtin::AcyclicTerm,
+        Builtin::CyclicTerm,
+        Builtin::MustBe,
+        Builtin::DomainError,
+        Builtin::Functor,
+        Builtin::Arg,
+        Builtin::Univ,
+        Builtin::CopyTerm,
+        Builtin::TermVariables,
+        Builtin::Compare,
+        Builtin::StructurallyEqual,
+        Builtin::StructurallyNotEqual,
+        Builtin::TermLess,
+        Builtin::TermLessOrEqual,
+        Builtin::TermGreater,
+        Builtin::TermGreaterOrEqual,
+        Builtin::Sort,
+        Builtin::Msort,
+        Builtin::Keysort,
+        Builtin::Unify,
+        Builtin::NotUnifiable,
+        Builtin::UnifyWithOccursCheck,
+        Builtin::True,
+        Builtin::Fail,
+        Builtin::False,
+        Builtin::Call(1),
+        Builtin::Call(2),
+        Builtin::Call(3),
+        Builtin::Call(4),
+        Builtin::Call(5),
+        Builtin::Call(6),
+        Builtin::Call(7),
+        Builtin::Call(8),
+        Builtin::CutTo,
+        Builtin::SoftCut,
+        Builtin::FindallStart,
+        Builtin::FindallAdd,
+        Builtin::FindallCollect,
+        Builtin::FreeVariables,
+        Builtin::BagofGroups,
+        Builtin::Asserta,
+        Builtin::Assertz,
+        Builtin::Assert,
+        Builtin::ClauseRefs,
+        Builtin::ClauseParts,
+        Builtin::Erase,
+        Builtin::Abolish,
+        Builtin::Dynamic,
+        Builtin::Clauses,
+        Builtin::CurrentPredicates,
+        Builtin::PredicateProperties,
+        Builtin::Listing,
+        Builtin::Write,
+        Builtin::Print,
+        Builtin::Writeq,
+        Builtin::WriteCanonical,
+        Builtin::WriteTerm,
+        Builtin::Nl,
+        Builtin::Format,
+        Builtin::FormatTo,
+        Builtin::WriteTermTo,
+        Builtin::Open,
+        Builtin::Close,
+        Builtin::SetInput,
+        Builtin::SetOutput,
+        Builtin::CurrentInput,
+        Builtin::CurrentOutput,
+        Builtin::GetChar,
+        Builtin::GetCode,
+        Builtin::GetByte,
+        Builtin::PeekChar,
+        Builtin::PeekCode,
+        Builtin::PeekByte,
+        Builtin::PutChar,
+        Builtin::PutCode,
+        Builtin::PutByte,
+        Builtin::FlushOutput,
+        Builtin::AtEndOfStream,
+        Builtin::Streams,
+        Builtin::StreamProperties,
+        Builtin::MemoryOutput,
+        Builtin::MemoryOutputText,
+        Builtin::ReadTerm,
+        Builtin::ReadTermFrom,
+        Builtin::AtomLength,
+        Builtin::StringLength,
+        Builtin::AtomChars,
+        Builtin::AtomCodes,
+        Builtin::StringChars,
+        Builtin::StringCodes,
+        Builtin::CharCode,
+        Builtin::NumberChars,
+        Builtin::NumberCodes,
+        Builtin::UpcaseAtom,
+        Builtin::DowncaseAtom,
+        Builtin::StringUpper,
+        Builtin::StringLower,
+        Builtin::StringToAtom,
+        Builtin::StringCode,
+        Builtin::AtomConcat,
+        Builtin::StringConcat,
+        Builtin::SplitString,
+        Builtin::SubAtom,
+        Builtin::SubString,
+        Builtin::AbsoluteFileName,
+        Builtin::StartConsult,
+        Builtin::EndConsult,
+        Builtin::LoadedFile,
+        Builtin::FileModule,
+        Builtin::LoadSource,
+        Builtin::AddClause,
+        Builtin::CompilerDirective,
+        Builtin::SourceModule,
+        Builtin::AddInitialization,
+        Builtin::DirectiveFailed,
+        Builtin::DcgBody,
+        Builtin::DcgTranslateRule,
+        Builtin::SetPrologFlag,
+        Builtin::PrologFlags,
+        Builtin::PutAttr,
+        Builtin::GetAttr,
+        Builtin::DelAttr,
+        Builtin::GetAttrs,
+        Builtin::Attvar,
+        Builtin::TermAttvars,
+        Builtin::ModuleDefines,
+        Builtin::RestoreArguments,
+    ];
+
+    pub fn lookup(name: &str, arity: usize) -> Option<Builtin> {
+        Self::ALL
+            .iter()
+            .find(|builtin| builtin.name() == name && builtin.arity() == arity)
+            .copied()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Is => "is",
+            Builtin::ArithmeticEqual => "=:=",
+            Builtin::ArithmeticNotEqual => "=\\=",
+            Builtin::ArithmeticLess => "<",
+            Builtin::ArithmeticLessOrEqual => "=<",
+            Builtin::ArithmeticGreater => ">",
+            Builtin::ArithmeticGreaterOrEqual => ">=",
+            Builtin::Var => "var",
+            Builtin::Nonvar => "nonvar",
+            Builtin::Atom => "atom",
+            Builtin::Number => "number",
+            Builtin::Integer => "integer",
+            Builtin::Float => "float",
+            Builtin::String => "string",
+            Builtin::Atomic => "atomic",
+            Builtin::Compound => "compound",
+            Builtin::Callable => "callable",
+            Builtin::IsList => "is_list",
+            Builtin::SkipList => "$skip_list",
+            Builtin::Ground => "ground",
+            Builtin::AcyclicTerm => "acyclic_term",
+            Builtin::CyclicTerm => "cyclic_term",
+            Builtin::MustBe => "must_be",
+            Builtin::DomainError => "$domain_error",
+            Builtin::Functor => "functor",
+            Builtin::Arg => "arg",
+            Builtin::Univ => "=..",
+            Builtin::CopyTerm => "copy_term",
+            Builtin::TermVariables => "term_variables",
+            Builtin::Compare => "compare",
+            Builtin::StructurallyEqual => "==",
+            Builtin::StructurallyNotEqual => "\\==",
+            Builtin::TermLess => "@<",
+            Builtin::TermLessOrEqual => "@=<",
+            Builtin::TermGreater => "@>",
+            Builtin::TermGreaterOrEqual => "@>=",
+            Builtin::Sort => "sort",
+            Builtin::Msort => "msort",
+            Builtin::Keysort => "keysort",
+            Builtin::Unify => "=",
+            Builtin::NotUnifiable => "\\=",
+            Builtin::UnifyWithOccursCheck => "unify_with_occurs_check",
+            Builtin::True => "true",
+            Builtin::Fail => "fail",
+            Builtin::False => "false",
+            Builtin::Call(_) => "call",
+            Builtin::CutTo => "$cut",
+            Builtin::SoftCut => "$soft_cut",
+            Builtin::FindallStart => "$findall_start",
+            Builtin::FindallAdd => "$findall_add",
+            Builtin::FindallCollect => "$findall_collect",
+            Builtin::FreeVariables => "$free_variables",
+            Builtin::BagofGroups => "$bagof_groups",
+            Builtin::Asserta => "asserta",
+            Builtin::Assertz => "assertz",
+            Builtin::Assert => "assert",
+            Builtin::ClauseRefs => "$clause_refs",
+            Builtin::ClauseParts => "$clause",
+            Builtin::Erase => "$erase",
+            Builtin::Abolish => "abolish",
+            Builtin::Dynamic => "dynamic",
+            Builtin::Clauses => "$clauses",
+            Builtin::CurrentPredicates => "$current_predicates",
+            Builtin::PredicateProperties => "$predicate_properties",
+            Builtin::Listing => "listing",
+            Builtin::Write => "write",
+            Builtin::Print => "print",
+            Builtin::Writeq => "writeq",
+            Builtin::WriteCanonical => "write_canonical",
+            Builtin::WriteTerm | Builtin::WriteTermTo => "write_term",
+            Builtin::Nl => "nl",
+            Builtin::Format | Builtin::FormatTo => "format",
+            Builtin::Open => "open",
+            Builtin::Close => "close",
+            Builtin::SetInput => "set_input",
+            Builtin::SetOutput => "set_output",
+            Builtin::CurrentInput => "current_input",
+            Builtin::CurrentOutput => "current_output",
+            Builtin::GetChar => "get_char",
+            Builtin::GetCode => "get_code",
+            Builtin::GetByte => "get_byte",
+            Builtin::PeekChar => "peek_char",
+            Builtin::PeekCode => "peek_code",
+            Builtin::PeekByte => "peek_byte",
+            Builtin::PutChar => "put_char",
+            Builtin::PutCode => "put_code",
+            Builtin::PutByte => "put_byte",
+            Builtin::FlushOutput => "flush_output",
+            Builtin::AtEndOfStream => "at_end_of_stream",
+            Builtin::Streams => "$streams",
+            Builtin::StreamProperties => "$stream_properties",
+            Builtin::MemoryOutput => "$memory_output",
+            Builtin::MemoryOutputText => "$memory_output_text",
+            Builtin::ReadTerm | Builtin::ReadTermFrom => "read_term",
+            Builtin::AtomLength => "atom_length",
+            Builtin::StringLength => "string_length",
+            Builtin::AtomChars => "atom_chars",
+            Builtin::AtomCodes => "atom_codes",
+            Builtin::StringChars => "string_chars",
+            Builtin::StringCodes => "string_codes",
+            Builtin::CharCode => "char_code",
+            Builtin::NumberChars => "number_chars",
+            Builtin::NumberCodes => "number_codes",
+            Builtin::UpcaseAtom => "upcase_atom",
+            Builtin::DowncaseAtom => "downcase_atom",
+            Builtin::StringUpper => "string_upper",
+            Builtin::StringLower => "string_lower",
+            Builtin::StringToAtom => "string_to_atom",
+            Builtin::StringCode => "string_code",
+            Builtin::AtomConcat => "$atom_concat",
+            Builtin::StringConcat => "$string_concat",
+            Builtin::SplitString => "split_string",
+            Builtin::SubAtom => "$sub_atom",
+            Builtin::SubString => "$sub_string",
+            Builtin::AbsoluteFileName => "$absolute_file_name",
+            Builtin::StartConsult => "$start_consult",
+            Builtin::EndConsult => "$end_consult",
+            Builtin::LoadedFile => "$loaded_file",
+            Builtin::FileModule => "$file_module",
+            Builtin::LoadSource => "$load_source",
+            Builtin::AddClause => "$add_clause",
+            Builtin::CompilerDirective => "$compiler_directive",
+            Builtin::SourceModule => "$source_module",
+            Builtin::AddInitialization => "$add_initialization",
+            Builtin::DirectiveFailed => "$directive_failed",
+            Builtin::DcgBody => "$dcg_body",
+            Builtin::DcgTranslateRule => "$dcg_translate_rule",
+            Builtin::SetPrologFlag => "set_prolog_flag",
+            Builtin::PrologFlags => "$prolog_flags",
+            Builtin::PutAttr => "put_attr",
+            Builtin::GetAttr => "get_attr",
+            Builtin::DelAttr => "del_attr",
+            Builtin::GetAttrs => "get_attrs",
+            Builtin::Attvar => "attvar",
+            Builtin::TermAttvars => "term_attvars",
+            Builtin::ModuleDefines => "$module_defines",
+            Builtin::RestoreArguments => "$restore_arguments",
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        match self {
+            Builtin::Is
+            | Builtin::ArithmeticEqual
+            | Builtin::ArithmeticNotEqual
+            | Builtin::ArithmeticLess
+            | Builtin::ArithmeticLessOrEqual
+            | Builtin::ArithmeticGreater
+            | Builtin::ArithmeticGreaterOrEqual
+            | Builtin::Univ
+            | Builtin::MustBe
+            | Builtin::DomainError
+            | Builtin::CopyTerm
+            | Builtin::TermVariables
+            | Builtin::StructurallyEqual
+            | Builtin::StructurallyNotEqual
+            | Builtin::TermLess
+            | Builtin::TermLessOrEqual
+            | Builtin::TermGreater
+            | Builtin::TermGreaterOrEqual
+            | Builtin::Sort
+            | Builtin::Msort
+            | Builtin::Keysort
+            | Builtin::Unify
+            | Builtin::NotUnifiable
+            | Builtin::UnifyWithOccursCheck
+            | Builtin::FindallCollect
+            | Builtin::BagofGroups
+            | Builtin::ClauseRefs
+            | Builtin::CurrentPredicates
+            | Builtin::PredicateProperties
+            | Builtin::WriteTerm
+            | Builtin::Format
+            | Builtin::Close
+            | Builtin::GetChar
+            | Builtin::GetCode
+            | Builtin::GetByte
+            | Builtin::PeekChar
+            | Builtin::PeekCode
+            | Builtin::PeekByte
+            | Builtin::PutChar
+            | Builtin::PutCode
+            | Builtin::PutByte
+            | Builtin::Streams
+            | Builtin::StreamProperties
+            | Builtin::MemoryOutput
+            | Builtin::MemoryOutputText
+            | Builtin::ReadTerm
+            | Builtin::AtomLength
+            | Builtin::StringLength
+            | Builtin::AtomChars
+            | Builtin::AtomCodes
+            | Builtin::StringChars
+            | Builtin::StringCodes
+            | Builtin::CharCode
+            | Builtin::NumberChars
+            | Builtin::NumberCodes
+            | Builtin::UpcaseAtom
+            | Builtin::DowncaseAtom
+            | Builtin::StringUpper
+            | Builtin::StringLower
+            | Builtin::StringToAtom
+            | Builtin::AbsoluteFileName
+            | Builtin::FileModule
+            | Builtin::LoadSource
+            | Builtin::AddClause
+            | Builtin::DirectiveFailed
+            | Builtin::DcgTranslateRule
+            | Builtin::SetPrologFlag
+            | Builtin::PrologFlags
+            | Builtin::DelAttr
+            | Builtin::GetAttrs
+            | Builtin::TermAttvars
+            | Builtin::ModuleDefines => 2,
+            Builtin::Var
+            | Builtin::Nonvar
+            | Builtin::Atom
+            | Builtin::Number
+            | Builtin::Integer
+            | Builtin::Float
+            | Builtin::String
+            | Builtin::Atomic
+            | Builtin::Compound
+            | Builtin::Callable
+            | Builtin::IsList
+            | Builtin::Ground
+            | Builtin::AcyclicTerm
+            | Builtin::CyclicTerm
+            | Builtin::CutTo
+            | Builtin::SoftCut
+            | Builtin::FindallAdd
+            | Builtin::Asserta
+            | Builtin::Assertz
+            | Builtin::Assert
+            | Builtin::Erase
+            | Builtin::Abolish
+            | Builtin::Dynamic
+            | Builtin::Listing
+            | Builtin::Write
+            | Builtin::Print
+            | Builtin::Writeq
+            | Builtin::WriteCanonical
+            | Builtin::SetInput
+            | Builtin::SetOutput
+            | Builtin::CurrentInput
+            | Builtin::CurrentOutput
+            | Builtin::FlushOutput
+            | Builtin::AtEndOfStream
+            | Builtin::StartConsult
+            | Builtin::EndConsult
+            | Builtin::LoadedFile
+            | Builtin::CompilerDirective
+            | Builtin::SourceModule
+            | Builtin::AddInitialization
+            | Builtin::Attvar
+            | Builtin::RestoreArguments => 1,
+            Builtin::Functor
+            | Builtin::Arg
+            | Builtin::Compare
+            | Builtin::ClauseParts
+            | Builtin::Clauses
+            | Builtin::FormatTo
+            | Builtin::WriteTermTo
+            | Builtin::ReadTermFrom
+            | Builtin::StringCode
+            | Builtin::PutAttr
+            | Builtin::GetAttr
+            | Builtin::SkipList => 3,
+            Builtin::FreeVariables
+            | Builtin::Open
+            | Builtin::AtomConcat
+            | Builtin::StringConcat
+            | Builtin::SplitString
+            | Builtin::DcgBody => 4,
+            Builtin::SubAtom | Builtin::SubString => 6,
+            Builtin::Call(arity) => *arity,
+            Builtin::True
+            | Builtin::Fail
+            | Builtin::False
+            | Builtin::FindallStart
+            | Builtin::Nl => 0,
+        }
+    }
 }
@@ -0,0 +1,561 @@
+use std::cmp::Ordering;
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{FromPrimitive, One, Pow, Signed, ToPrimitive, Zero};
+
+use crate::{
+    instructions::RegisterId,
+    interpreter::{
+        Cell, CellAddress, Interpreter,
+        error::{ErrorCulprit, PrologError},
+    },
+    number::Number,
+};
+
+type ArithmeticResult = Result<Number, PrologError>;
+
+impl Interpreter {
+    /// Evaluates the arithmetic expression stored at `address`, as done by `is/2` and the
+    /// arithmetic comparison predicates.
+    pub(super) fn evaluate(&self, address: CellAddress) -> ArithmeticResult {
+        if self.structure(address).is_some() {
+            self.must_be_acyclic(address)?;
+        }
+        self.evaluate_expression(address)
+    }
+
+    fn evaluate_expression(&self, address: CellAddress) -> ArithmeticResult {
+        let address = self.deref_cell(address);
+        let cell = self.lookup_address(address);
+
+        if let Some(number) = self.read_number(cell) {
+            return Ok(number);
+        }
+
+        match cell {
+            Cell::Reference(_) | Cell::AttributedVariable(_) => {
+                Err(PrologError::InstantiationError)
+            }
+            Cell::Constant(descriptor_id) => {
+                let name = self
+                    .compiler
+                    .descriptor_allocator
+                    .get(*descriptor_id)
+                    .name
+                    .as_str();
+                evaluate_constant(name).ok_or(PrologError::TypeError {
+                    expected: "evaluable",
+                    culprit: ErrorCulprit::Indicator(*descriptor_id),
+                })
+            }
+            Cell::StructureRef(structure_address) => {
+                let Cell::Structure(descriptor_id) = self.global_stack[*structure_address] else {
+                    unreachable!("structure references always point to a structure");
+                };
+                let descriptor = self.compiler.descriptor_allocator.get(descriptor_id);
+                let arguments = (1..=descriptor.arity())
+                    .map(|i| {
+                        self.evaluate_expression(CellAddress::GlobalStack {
+                            index: structure_address + i,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let result = match arguments.as_slice() {
+                    [x] => evaluate_unary(&descriptor.name, x),
+                    [x, y] => evaluate_binary(&descriptor.name, x, y, self.flags.prefer_rationals),
+                    _ => None,
+                };
+
+                result.unwrap_or(Err(PrologError::TypeError {
+                    expected: "evaluable",
+                    culprit: ErrorCulprit::Indicator(descriptor_id),
+                }))
+            }
+            _ => Err(PrologError::type_error("evaluable", address)),
+        }
+    }
+
+    /// Compares the values of two arithmetic expressions.
+    pub(super) fn compare_arithmetic(
+        &self,
+        a: CellAddress,
+        b: CellAddress,
+    ) -> Result<Ordering, PrologError> {
+        let a = self.evaluate(a)?;
+        let b = self.evaluate(b)?;
+        Ok(a.compare(&b))
+    }
+
+    /// Executes an inlined binary operation such as `add`. Small integers are combined directly,
+    /// anything else (overflow, floats, bound expressions, ...) takes the path of `is/2`.
+    pub(super) fn execute_inline_arithmetic(
+        &mut self,
+        name: &str,
+        small: fn(i64, i64) -> Option<i64>,
+        left: RegisterId,
+        right: RegisterId,
+        target: RegisterId,
+    ) {
+        let left = CellAddress::Register { index: left };
+        let right = CellAddress::Register { index: right };
+
+        let result = match self.small_integer_operands(left, right) {
+            Some((x, y)) => small(x, y).map(Number::Integer),
+            None => None,
+        };
+        let result = match result {
+            Some(result) => Ok(result),
+            None => self.evaluate(left).and_then(|x| {
+                let y = self.evaluate(right)?;
+                evaluate_binary(name, &x, &y, self.flags.prefer_rationals)
+                    .expect("only evaluable operations are inlined")
+            }),
+        };
+
+        match result {
+            Ok(number) => {
+                let cell = self.allocate_number(&number);
+                *Self::lookup_register_mut(
+                    &mut self.environment_stack,
+                    &mut self.registers,
+                    target,
+                ) = cell;
+            }
+            Err(error) => self.raise(error),
+        }
+    }
+
+    /// Executes an inlined arithmetic comparison, backtracking if `accept` rejects the ordering.
+    pub(super) fn execute_inline_comparison(
+        &mut self,
+        left: RegisterId,
+        right: RegisterId,
+        accept: fn(Ordering) -> bool,
+    ) {
+        let left = CellAddress::Register { index: left };
+        let right = CellAddress::Register { index: right };
+
+        let ordering = match self.small_integer_operands(left, right) {
+            Some((x, y)) => Ok(x.cmp(&y)),
+            None => self.compare_arithmetic(left, right),
+        };
+
+        match ordering {
+            Ok(ordering) if accept(ordering) => {}
+            Ok(_) => self.backtrack(),
+            Err(error) => self.raise(error),
+        }
+    }
+
+    fn small_integer_operands(&self, left: CellAddress, right: CellAddress) -> Option<(i64, i64)> {
+        let left = self.lookup_address(self.deref_cell(left));
+        let right = self.lookup_address(self.deref_cell(right));
+        match (left, right) {
+            (Cell::Integer(x), Cell::Integer(y)) => Some((*x, *y)),
+            _ => None,
+        }
+    }
+}
+
+fn evaluate_constant(name: &str) -> Option<Number> {
+    match name {
+        "pi" => Some(Number::Float(std::f64::consts::PI)),
+        "e" => Some(Number::Float(std::f64::consts::E)),
+        "inf" | "infinite" => Some(Number::Float(f64::INFINITY)),
+        "nan" => Some(Number::Float(f64::NAN)),
+        "epsilon" => Some(Number::Float(f64::EPSILON)),
+        "max_tagged_integer" => Some(Number::Integer(i64::MAX)),
+        "min_tagged_integer" => Some(Number::Integer(i64::MIN)),
+        _ => None,
+    }
+}
+
+fn evaluate_unary(name: &str, x: &Number) -> Option<ArithmeticResult> {
+    let result = match name {
+        "-" => Ok(negate(x)),
+        "+" => Ok(x.clone()),
+        "abs" => Ok(if x.is_negative() {
+            negate(x)
+        } else {
+            x.clone()
+        }),
+        "sign" => Ok(match x {
+            Number::Float(value) if *value == 0.0 || value.is_nan() => Number::Float(*value),
+            Number::Float(value) => Number::Float(value.signum()),
+            _ => Number::Integer(match x.compare(&Number::Integer(0)) {
+                Ordering::Less => -1,
+                Ordering::Equal => 0,
+                Ordering::Greater => 1,
+            }),
+        }),
+        "float" => Ok(Number::Float(x.to_f64())),
+        "integer" => round_to_integer(x, Rounding::Nearest),
+        "float_integer_part" => Ok(Number::Float(x.to_f64().trunc())),
+        "float_fractional_part" => Ok(Number::Float(x.to_f64().fract())),
+        "truncate" => round_to_integer(x, Rounding::TowardZero),
+        "round" => round_to_integer(x, Rounding::Nearest),
+        "ceiling" => round_to_integer(x, Rounding::Up),
+        "floor" => round_to_integer(x, Rounding::Down),
+        "sqrt" => float_function(x, f64::sqrt),
+        "sin" => float_function(x, f64::sin),
+        "cos" => float_function(x, f64::cos),
+        "tan" => float_function(x, f64::tan),
+        "asin" => float_function(x, f64::asin),
+        "acos" => float_function(x, f64::acos),
+        "atan" => float_function(x, f64::atan),
+        "exp" => float_function(x, f64::exp),
+        "log" => {
+            if x.is_zero() || x.is_negative() {
+                Err(PrologError::EvaluationError("undefined"))
+            } else {
+                float_function(x, f64::ln)
+            }
+        }
+        "log2" => {
+            if x.is_zero() || x.is_negative() {
+                Err(PrologError::EvaluationError("undefined"))
+            } else {
+                float_function(x, f64::log2)
+            }
+        }
+        "\\" => integer_operation(x, |x| Ok(Number::from_bigint(!x))),
+        "msb" => integer_operation(x, |x| {
+            if x.is_positive() {
+                Ok(Number::Integer(x.bits() as i64 - 1))
+            } else {
+                Err(PrologError::TypeError {
+                    expected: "not_less_than_one",
+                    culprit: ErrorCulprit::Number(Number::from_bigint(x)),
+                })
+            }
+        }),
+        "succ" => add(x, &Number::Integer(1)),
+        "numerator" => rational_operation(x, |x| Number::from_bigint(x.numer().clone())),
+        "denominator" => rational_operation(x, |x| Number::from_bigint(x.denom().clone())),
+        "rational" | "rationalize" => match x {
+            Number::Float(value) => BigRational::from_float(*value)
+                .map(Number::from_rational)
+                .ok_or(PrologError::EvaluationError("undefined")),
+            _ => Ok(x.clone()),
+        },
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn evaluate_binary(
+    name: &str,
+    x: &Number,
+    y: &Number,
+    prefer_rationals: bool,
+) -> Option<ArithmeticResult> {
+    let result = match name {
+        "+" => add(x, y),
+        "-" => add(x, &negate(y)),
+        "*" => multiply(x, y),
+        "/" => divide(x, y, prefer_rationals),
+        "rdiv" => match (x.to_rational(), y.to_rational()) {
+            (Some(_), Some(b)) if b.is_zero() => Err(PrologError::EvaluationError("zero_divisor")),
+            (Some(a), Some(b)) => Ok(Number::from_rational(a / b)),
+            _ => Err(float_culprit("rational", x, y)),
+        },
+        "//" => integer_division(x, y, |a, b| a / b),
+        "div" => integer_division(x, y, |a, b| a.div_floor(&b)),
+        "mod" => integer_division(x, y, |a, b| a.mod_floor(&b)),
+        "rem" => integer_division(x, y, |a, b| a % b),
+        "min" => Ok(if y.compare(x) == Ordering::Less {
+            y.clone()
+        } else {
+            x.clone()
+        }),
+        "max" => Ok(if y.compare(x) == Ordering::Greater {
+            y.clone()
+        } else {
+            x.clone()
+        }),
+        "**" => power(x, y, true, prefer_rationals),
+        "^" => power(x, y, false, prefer_rationals),
+        ">>" => integer_binary(x, y, |a, b| {
+            let shift = b
+                .to_i64()
+                .and_then(i64::checked_neg)
+                .ok_or(PrologError::RepresentationError("shift"))?;
+            shift_left(a, shift)
+        }),
+        "<<" => integer_binary(x, y, |a, b| {
+            let shift = b
+                .to_i64()
+                .ok_or(PrologError::RepresentationError("shift"))?;
+            shift_left(a, shift)
+        }),
+        "/\\" => integer_binary(x, y, |a, b| Ok(a & b)),
+        "\\/" => integer_binary(x, y, |a, b| Ok(a | b)),
+        "xor" => integer_binary(x, y, |a, b| Ok(a ^ b)),
+        "gcd" => integer_binary(x, y, |a, b| Ok(a.gcd(&b))),
+        "atan2" | "atan" => check_float(x.to_f64().atan2(y.to_f64())),
+        "copysign" => Ok(Number::Float(x.to_f64().copysign(y.to_f64()))),
+        "log" => {
+            if x.is_zero() || x.is_negative() || y.is_zero() || y.is_negative() {
+                Err(PrologError::EvaluationError("undefined"))
+            } else {
+                check_float(y.to_f64().ln() / x.to_f64().ln())
+            }
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn negate(x: &Number) -> Number {
+    match x {
+        Number::Integer(value) => match value.checked_neg() {
+            Some(value) => Number::Integer(value),
+            None => Number::from_bigint(-BigInt::from(*value)),
+        },
+        Number::BigInteger(value) => Number::from_bigint(-value),
+        Number::Rational(value) => Number::Rational(-value),
+        Number::Float(value) => Number::Float(-value),
+    }
+}
+
+fn add(x: &Number, y: &Number) -> ArithmeticResult {
+    match (x, y) {
+        (Number::Integer(a), Number::Integer(b)) => Ok(match a.checked_add(*b) {
+            Some(value) => Number::Integer(value),
+            None => Number::from_bigint(BigInt::from(*a) + BigInt::from(*b)),
+        }),
+        (Number::Float(_), _) | (_, Number::Float(_)) => check_float(x.to_f64() + y.to_f64()),
+        _ => Ok(Number::from_rational(
+            x.to_rational().unwrap() + y.to_rational().unwrap(),
+        )),
+    }
+}
+
+fn multiply(x: &Number, y: &Number) -> ArithmeticResult {
+    match (x, y) {
+        (Number::Integer(a), Number::Integer(b)) => Ok(match a.checked_mul(*b) {
+            Some(value) => Number::Integer(value),
+            None => Number::from_bigint(BigInt::from(*a) * BigInt::from(*b)),
+        }),
+        (Number::Float(_), _) | (_, Number::Float(_)) => check_float(x.to_f64() * y.to_f64()),
+        _ => Ok(Number::from_rational(
+            x.to_rational().unwrap() * y.to_rational().unwrap(),
+        )),
+    }
+}
+
+fn divide(x: &Number, y: &Number, prefer_rationals: bool) -> ArithmeticResult {
+    if y.is_zero() {
+        return Err(PrologError::EvaluationError("zero_divisor"));
+    }
+    match (x, y) {
+        (Number::Float(_), _) | (_, Number::Float(_)) => check_float(x.to_f64() / y.to_f64()),
+        (Number::Rational(_), _) | (_, Number::Rational(_)) => Ok(Number::from_rational(
+            x.to_rational().unwrap() / y.to_rational().unwrap(),
+        )),
+        _ => {
+            let a = x.to_bigint().unwrap();
+            let b = y.to_bigint().unwrap();
+            if prefer_rationals || a.is_multiple_of(&b) {
+                Ok(Number::from_rational(BigRational::new(a, b)))
+            } else {
+                check_float(x.to_f64() / y.to_f64())
+            }
+        }
+    }
+}
+
+fn power(x: &Number, y: &Number, float_power: bool, prefer_rationals: bool) -> ArithmeticResult {
+    match (x, y) {
+        (Number::Float(_), _) | (_, Number::Float(_)) => check_float(x.to_f64().powf(y.to_f64())),
+        (_, Number::Rational(_)) => check_float(x.to_f64().powf(y.to_f64())),
+        (Number::Rational(base), _) => {
+            let exponent = y
+                .to_bigint()
+                .and_then(|exponent| exponent.to_i32())
+                .ok_or(PrologError::RepresentationError("max_integer"))?;
+            if base.is_zero() && exponent < 0 {
+                return Err(PrologError::EvaluationError("zero_divisor"));
+            }
+            let bits = base.numer().bits().max(base.denom().bits());
+            check_power_width(bits, exponent.unsigned_abs().into())?;
+            Ok(Number::from_rational(Pow::pow(base, exponent)))
+        }
+        _ => {
+            let base = x.to_bigint().unwrap();
+            let exponent = y.to_bigint().unwrap();
+            if exponent.is_negative() {
+                if base.is_one() {
+                    return Ok(Number::Integer(1));
+                }
+                if (-&base).is_one() {
+                    return Ok(Number::Integer(if exponent.is_even() { 1 } else { -1 }));
+                }
+                if base.is_zero() {
+                    return Err(PrologError::EvaluationError("zero_divisor"));
+                }
+                if prefer_rationals {
+                    let exponent = exponent
+                        .to_i32()
+                        .ok_or(PrologError::RepresentationError("max_integer"))?;
+                    return Ok(Number::from_rational(Pow::pow(
+                        BigRational::from_integer(base),
+                        exponent,
+                    )));
+                }
+                if float_power {
+                    return check_float(x.to_f64().powf(y.to_f64()));
+                }
+                return Err(PrologError::TypeError {
+                    expected: "float",
+                    culprit: ErrorCulprit::Number(x.clone()),
+                });
+            }
+            // Only the powers of 0, 1 and -1 stay small for any exponent.
+            if base.bits() <= 1 {
+                let exponent: u32 = match (exponent.is_zero(), exponent.is_even()) {
+                    (true, _) => 0,
+                    (false, true) => 2,
+                    (false, false) => 1,
+                };
+                return Ok(Number::from_bigint(Pow::pow(base, exponent)));
+            }
+            check_power_width(base.bits(), exponent.to_u64().unwrap_or(u64::MAX))?;
+            let exponent = exponent
+                .to_u32()
+                .ok_or(PrologError::RepresentationError("max_integer"))?;
+            Ok(Number::from_bigint(Pow::pow(base, exponent)))
+        }
+    }
+}
+
+/// The widest integer a left shift or a power may produce, in bits.
+const MAX_SHIFTED_BITS: u64 = 1 << 26;
+
+/// Raises the resource error of [`shift_left`] if a power of a base `bits` wide would be wider
+/// than [`MAX_SHIFTED_BITS`], before the power is computed.
+fn check_power_width(bits: u64, exponent: u64) -> Result<(), PrologError> {
+    match bits.saturating_mul(exponent) > MAX_SHIFTED_BITS {
+        true => Err(PrologError::ResourceError("memory")),
+        false => Ok(()),
+    }
+}
+
+/// `value << shift`, shifting right for a negative `shift`. Results wider than
+/// [`MAX_SHIFTED_BITS`] raise a resource error instead of exhausting memory.
+fn shift_left(value: BigInt, shift: i64) -> Result<BigInt, PrologError> {
+    let amount = usize::try_from(shift.unsigned_abs()).unwrap_or(usize::MAX);
+    if shift < 0 {
+        return Ok(value >> amount);
+    }
+    if value.is_zero() {
+        return Ok(value);
+    }
+    if value.bits().saturating_add(shift.unsigned_abs()) > MAX_SHIFTED_BITS {
+        return Err(PrologError::ResourceError("memory"));
+    }
+    Ok(value << amount)
+}
+
+#[derive(Clone, Copy)]
+enum Rounding {
+    TowardZero,
+    Nearest,
+    Up,
+    Down,
+}
+
+fn round_to_integer(x: &Number, rounding: Rounding) -> ArithmeticResult {
+    match x {
+        Number::Float(value) => {
+            let value = match rounding {
+                Rounding::TowardZero => value.trunc(),
+                Rounding::Nearest => value.round(),
+                Rounding::Up => value.ceil(),
+                Rounding::Down => value.floor(),
+            };
+            BigInt::from_f64(value)
+                .map(Number::from_bigint)
+                .ok_or(PrologError::EvaluationError("undefined"))
+        }
+        Number::Rational(value) => Ok(Number::from_rational(match rounding {
+            Rounding::TowardZero => value.trunc(),
+            Rounding::Nearest => value.round(),
+            Rounding::Up => value.ceil(),
+            Rounding::Down => value.floor(),
+        })),
+        _ => Ok(x.clone()),
+    }
+}
+
+fn float_function(x: &Number, function: fn(f64) -> f64) -> ArithmeticResult {
+    check_float(function(x.to_f64()))
+}
+
+fn check_float(value: f64) -> ArithmeticResult {
+    if value.is_nan() {
+        Err(PrologError::EvaluationError("undefined"))
+    } else if value.is_infinite() {
+        Err(PrologError::EvaluationError("float_overflow"))
+    } else {
+        Ok(Number::Float(value))
+    }
+}
+
+fn require_integer(x: &Number) -> Result<BigInt, PrologError> {
+    x.to_bigint().ok_or_else(|| PrologError::TypeError {
+        expected: "integer",
+        culprit: ErrorCulprit::Number(x.clone()),
+    })
+}
+
+fn float_culprit(expected: &'static str, x: &Number, y: &Number) -> PrologError {
+    let culprit = if matches!(x, Number::Float(_)) { x } else { y };
+    PrologError::TypeError {
+        expected,
+        culprit: ErrorCulprit::Number(culprit.clone()),
+    }
+}
+
+fn integer_operation(
+    x: &Number,
+    operation: impl FnOnce(BigInt) -> ArithmeticResult,
+) -> ArithmeticResult {
+    operation(require_integer(x)?)
+}
+
+fn integer_binary(
+    x: &Number,
+    y: &Number,
+    operation: impl FnOnce(BigInt, BigInt) -> Result<BigInt, PrologError>,
+) -> ArithmeticResult {
+    operation(require_integer(x)?, require_integer(y)?).map(Number::from_bigint)
+}
+
+fn integer_division(
+    x: &Number,
+    y: &Number,
+    operation: impl FnOnce(BigInt, BigInt) -> BigInt,
+) -> ArithmeticResult {
+    let a = require_integer(x)?;
+    let b = require_integer(y)?;
+    if b.is_zero() {
+        return Err(PrologError::EvaluationError("zero_divisor"));
+    }
+    Ok(Number::from_bigint(operation(a, b)))
+}
+
+fn rational_operation(
+    x: &Number,
+    operation: impl FnOnce(BigRational) -> Number,
+) -> ArithmeticResult {
+    x.to_rational()
+        .map(operation)
+        .ok_or_else(|| PrologError::TypeError {
+            expected: "rational",
+            culprit: ErrorCulprit::Number(x.clone()),
+        })
+}
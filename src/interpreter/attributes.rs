@@ -0,0 +1,216 @@
+use crate::{
+    instructions::DescriptorId,
+    interpreter::{
+        Cell, CellAddress, Interpreter,
+        builtins::argument,
+        error::{ErrorCulprit, PrologError},
+    },
+};
+
+impl Interpreter {
+    /// Runs the hooks of the attributed variables bound since the last goal, by calling
+    /// `'$wakeup'(Wakeups, Arguments)` with the first `arity` argument registers. They are
+    /// restored once the hooks have run, so the current instruction can be executed again.
+    pub(super) fn wake_up(&mut self, arity: usize) {
+        let wake = self
+            .compiler
+            .descriptor_allocator
+            .get_or_set_functor("$wake", 2);
+        let wakeups = std::mem::take(&mut self.wakeups)
+            .into_iter()
+            .map(|(attributes, value)| self.build_structure(wake, &[attributes, value]))
+            .collect::<Vec<_>>();
+        let tail = self.empty_list();
+        let wakeups = self.build_list(&wakeups, tail);
+        let arguments = (0..arity)
+            .map(|i| self.term_cell(argument(i)))
+            .collect::<Vec<_>>();
+        let tail = self.empty_list();
+        let arguments = self.build_list(&arguments, tail);
+
+        let functor = self
+            .compiler
+            .descriptor_allocator
+            .get_or_set_functor("$wakeup", 2);
+        let address = self
+            .compiler
+            .predicate_address(functor)
+            .expect("the boot program to define '$wakeup'/2");
+        self.load_arguments(&[wakeups, arguments]);
+        self.call_predicate(address, functor);
+    }
+
+    /// `'$restore_arguments'(Arguments)`, puts the list `Arguments` back into the argument
+    /// registers after the hooks run by [`Interpreter::wake_up`].
+    pub(super) fn restore_arguments(&mut self) -> Result<bool, PrologError> {
+        let arguments = self
+            .list_argument(argument(0))?
+            .into_iter()
+            .map(|address| self.term_cell(address))
+            .collect::<Vec<_>>();
+        self.load_arguments(&arguments);
+        Ok(true)
+    }
+
+    /// `put_attr(Var, Module, Value)`, sets the attribute of `Var` for `Module`. A variable
+    /// without attributes becomes an attributed variable.
+    pub(super) fn put_attr(&mut self) -> Result<bool, PrologError> {
+        let variable = self.deref_cell(argument(0));
+        let module = self.module_argument(argument(1))?;
+        let value = self.term_cell(argument(2));
+        match *self.lookup_address(variable) {
+            Cell::Reference(_) => {
+                let attributes = self.build_attributes(&[(module, value)]);
+                let index = self.global_stack.len();
+                self.global_stack
+                    .extend([Cell::AttributedVariable(index), attributes]);
+                self.bind_variable(variable, Cell::Reference(index));
+            }
+            Cell::AttributedVariable(index) => {
+                let mut attributes = self.attributes(index);
+                match attributes.iter_mut().find(|(name, _)| *name == module) {
+                    Some(attribute) => attribute.1 = value,
+                    None => attributes.push((module, value)),
+                }
+                self.set_attributes(index, &attributes);
+            }
+            _ => {
+                return Err(PrologError::UninstantiationError(ErrorCulprit::Address(
+                    argument(0),
+                )));
+            }
+        }
+        Ok(true)
+    }
+
+    /// `get_attr(Var, Module, Value)`, the attribute of `Var` for `Module`. Fails if it has none.
+    pub(super) fn get_attr(&mut self) -> Result<bool, PrologError> {
+        let module = self.module_argument(argument(1))?;
+        let Cell::AttributedVariable(index) = *self.value(argument(0)) else {
+            return Ok(false);
+        };
+        match self
+            .attributes(index)
+            .into_iter()
+            .find(|(name, _)| *name == module)
+        {
+            Some((_, value)) => Ok(self.unify_cell(argument(2), value)),
+            None => Ok(false),
+        }
+    }
+
+    /// `del_attr(Var, Module)`, removes the attribute of `Var` for `Module` if it has one. Without
+    /// attributes left, `Var` becomes a plain variable again.
+    pub(super) fn del_attr(&mut self) -> Result<bool, PrologError> {
+        let module = self.module_argument(argument(1))?;
+        if let Cell::AttributedVariable(index) = *self.value(argument(0)) {
+            let mut attributes = self.attributes(index);
+            attributes.retain(|(name, _)| *name != module);
+            self.set_attributes(index, &attributes);
+        }
+        Ok(true)
+    }
+
+    /// `get_attrs(Var, Attributes)`, all attributes of `Var` as `att(Module, Value, More)`.
+    pub(super) fn get_attrs(&mut self) -> Result<bool, PrologError> {
+        let Cell::AttributedVariable(index) = *self.value(argument(0)) else {
+            return Ok(false);
+        };
+        let attributes = self.global_stack[index + 1].clone();
+        Ok(self.unify_cell(argument(1), attributes))
+    }
+
+    /// `term_attvars(Term, Variables)`, the attributed variables of `Term` and, recursively, of
+    /// their attributes.
+    pub(super) fn term_attvars(&mut self) -> Result<bool, PrologError> {
+        let mut variables = Vec::new();
+        let mut pending = vec![argument(0)];
+        while let Some(address) = pending.pop() {
+            for index in self.variables(address) {
+                if matches!(self.global_stack[index], Cell::AttributedVariable(_))
+                    && !variables.contains(&index)
+                {
+                    variables.push(index);
+                    pending.push(CellAddress::GlobalStack { index: index + 1 });
+                }
+            }
+        }
+        let variables = variables
+            .into_iter()
+            .map(Cell::Reference)
+            .collect::<Vec<_>>();
+        let tail = self.empty_list();
+        let list = self.build_list(&variables, tail);
+        Ok(self.unify_cell(argument(1), list))
+    }
+
+    /// `'$module_defines'(Module, Head)`, whether `Module` itself has clauses for the predicate
+    /// of `Head`.
+    pub(super) fn module_defines(&mut self) -> Result<bool, PrologError> {
+        let module = self.module_argument(argument(0))?;
+        let module = self.compiler.descriptor_allocator.get(module).name.clone();
+        let functor = self.head_functor(argument(1))?;
+        let descriptor = self.compiler.descriptor_allocator.get(functor);
+        let (name, arity) = (descriptor.name.clone(), descriptor.arity());
+        let predicate = self
+            .compiler
+            .descriptor_allocator
+            .get_or_set_predicate(&module, &name, arity);
+        Ok(self.compiler.predicate_address(predicate).is_some())
+    }
+
+    /// The module atom at `address`.
+    fn module_argument(&self, address: CellAddress) -> Result<DescriptorId, PrologError> {
+        match self.value(address) {
+            Cell::Constant(id) => Ok(*id),
+            cell if cell.is_variable() => Err(PrologError::InstantiationError),
+            _ => Err(PrologError::type_error("atom", address)),
+        }
+    }
+
+    /// The attributes of the attributed variable at `index`, by module.
+    fn attributes(&self, index: usize) -> Vec<(DescriptorId, Cell)> {
+        let mut attributes = Vec::new();
+        let mut chain = CellAddress::GlobalStack { index: index + 1 };
+        while let Some((_, structure)) = self.structure(chain) {
+            let Cell::Constant(module) = *self.value(CellAddress::GlobalStack {
+                index: structure + 1,
+            }) else {
+                unreachable!("attributes are stored under their module");
+            };
+            let value = self.term_cell(CellAddress::GlobalStack {
+                index: structure + 2,
+            });
+            attributes.push((module, value));
+            chain = CellAddress::GlobalStack {
+                index: structure + 3,
+            };
+        }
+        attributes
+    }
+
+    /// Builds the chain `att(Module, Value, More)` of `attributes`, ending in `[]`.
+    fn build_attributes(&mut self, attributes: &[(DescriptorId, Cell)]) -> Cell {
+        let att = self
+            .compiler
+            .descriptor_allocator
+            .get_or_set_functor("att", 3);
+        let tail = self.empty_list();
+        attributes.iter().rev().fold(tail, |more, (module, value)| {
+            self.build_structure(att, &[Cell::Constant(*module), value.clone(), more])
+        })
+    }
+
+    /// Replaces the attributes of the attributed variable at `index`. Backtracking restores the
+    /// previous ones.
+    fn set_attributes(&mut self, index: usize, attributes: &[(DescriptorId, Cell)]) {
+        if attributes.is_empty() {
+            self.try_trail(CellAddress::GlobalStack { index });
+            self.global_stack[index] = Cell::Reference(index);
+            return;
+        }
+        let chain = self.build_attributes(attributes);
+        self.try_trail(CellAddress::GlobalStack { index: index + 1 });
+        self.global_stack[index + 1] = chain;
+    }
+}
@@ -0,0 +1,199 @@
+use std::cmp::Ordering;
+
+use crate::{
+    compiler::ClausePosition,
+    instructions::{Builtin, RegisterId},
+    interpreter::{
+        Cell, CellAddress, Interpreter, OccursCheck, error::PrologError, streams::IoUnit,
+        terms::ListShape, text::TextKind,
+    },
+    writer::WriteOptions,
+};
+
+impl Interpreter {
+    /// Executes a builtin predicate on the argument registers. Returns whether it succeeded,
+    /// failure is turned into backtracking by the caller.
+    pub(super) fn call_builtin(&mut self, builtin: Builtin) -> Result<bool, PrologError> {
+        match builtin {
+            Builtin::Is => {
+                let value = self.evaluate(argument(1))?;
+                let cell = self.allocate_number(&value);
+                self.global_stack.push(cell);
+                let result = CellAddress::GlobalStack {
+                    index: self.global_stack.len() - 1,
+                };
+                Ok(self.unify(argument(0), result))
+            }
+            Builtin::ArithmeticEqual => self.compare_arguments(|o| o == Ordering::Equal),
+            Builtin::ArithmeticNotEqual => self.compare_arguments(|o| o != Ordering::Equal),
+            Builtin::ArithmeticLess => self.compare_arguments(|o| o == Ordering::Less),
+            Builtin::ArithmeticLessOrEqual => self.compare_arguments(|o| o != Ordering::Greater),
+            Builtin::ArithmeticGreater => self.compare_arguments(|o| o == Ordering::Greater),
+            Builtin::ArithmeticGreaterOrEqual => self.compare_arguments(|o| o != Ordering::Less),
+            Builtin::Var => Ok(self.is_unbound(argument(0))),
+            Builtin::Nonvar => Ok(!self.is_unbound(argument(0))),
+            Builtin::Atom => Ok(matches!(self.value(argument(0)), Cell::Constant(_))),
+            Builtin::Number => Ok(self.read_number(self.value(argument(0))).is_some()),
+            Builtin::Integer => Ok(self.is_integer(argument(0))),
+            Builtin::Float => Ok(matches!(self.value(argument(0)), Cell::Float(_))),
+            Builtin::String => Ok(matches!(self.value(argument(0)), Cell::StringRef(_))),
+            Builtin::Atomic => Ok(self.is_atomic(argument(0))),
+            Builtin::Compound => Ok(self.structure(argument(0)).is_some()),
+            Builtin::Callable => Ok(matches!(
+                self.value(argument(0)),
+                Cell::Constant(_) | Cell::StructureRef(_)
+            )),
+            Builtin::IsList => Ok(matches!(self.read_list(argument(0)), ListShape::Proper(_))),
+            Builtin::SkipList => self.skip_list(),
+            Builtin::Ground => Ok(self.is_ground(argument(0))),
+            Builtin::AcyclicTerm => Ok(self.is_acyclic(argument(0))),
+            Builtin::CyclicTerm => Ok(!self.is_acyclic(argument(0))),
+            Builtin::MustBe => self.must_be(),
+            Builtin::DomainError => self.raise_domain_error(),
+            Builtin::Functor => self.functor(),
+            Builtin::Arg => self.arg(),
+            Builtin::Univ => self.univ(),
+            Builtin::CopyTerm => self.copy_term(),
+            Builtin::TermVariables => self.term_variables(),
+            Builtin::Compare => self.compare(),
+            Builtin::StructurallyEqual => self.compare_standard_order(|o| o == Ordering::Equal),
+            Builtin::StructurallyNotEqual => self.compare_standard_order(|o| o != Ordering::Equal),
+            Builtin::TermLess => self.compare_standard_order(|o| o == Ordering::Less),
+            Builtin::TermLessOrEqual => self.compare_standard_order(|o| o != Ordering::Greater),
+            Builtin::TermGreater => self.compare_standard_order(|o| o == Ordering::Greater),
+            Builtin::TermGreaterOrEqual => self.compare_standard_order(|o| o != Ordering::Less),
+            Builtin::Sort => self.sort(true),
+            Builtin::Msort => self.sort(false),
+            Builtin::Keysort => self.keysort(),
+            Builtin::Unify => Ok(self.unify(argument(0), argument(1))),
+            Builtin::NotUnifiable => {
+                let occurs_check = self.flags.occurs_check != OccursCheck::False;
+                Ok(!self.unifiable(argument(0), argument(1), occurs_check))
+            }
+            Builtin::UnifyWithOccursCheck => {
+                Ok(self.unify_terms(argument(0), argument(1), OccursCheck::True))
+            }
+            Builtin::True => Ok(true),
+            Builtin::Fail | Builtin::False => Ok(false),
+            Builtin::Call(arity) => self.call_closure(arity),
+            Builtin::CutTo => {
+                let level = self.cut_level(argument(0))?;
+                self.choice_point_stack.cut(level);
+                Ok(true)
+            }
+            Builtin::SoftCut => {
+                let level = self.cut_level(argument(0))?;
+                self.choice_point_stack.soft_cut(level);
+                Ok(true)
+            }
+            Builtin::FindallStart => self.findall_start(),
+            Builtin::FindallAdd => self.findall_add(),
+            Builtin::FindallCollect => self.findall_collect(),
+            Builtin::FreeVariables => self.free_variables(),
+            Builtin::BagofGroups => self.bagof_groups(),
+            Builtin::Asserta => self.assert(ClausePosition::First),
+            Builtin::Assertz | Builtin::Assert => self.assert(ClausePosition::Last),
+            Builtin::ClauseRefs => self.clause_refs(),
+            Builtin::ClauseParts => self.clause_parts(),
+            Builtin::Erase => self.erase(),
+            Builtin::Abolish => self.abolish(),
+            Builtin::Dynamic => self.dynamic(),
+            Builtin::Clauses => self.clauses(),
+            Builtin::CurrentPredicates => self.current_predicates(),
+            Builtin::PredicateProperties => self.predicate_properties(),
+            Builtin::Listing => self.listing(),
+            Builtin::Write => self.write(WriteOptions::write()),
+            Builtin::Print | Builtin::Writeq => self.write(WriteOptions::writeq()),
+            Builtin::WriteCanonical => self.write(WriteOptions::canonical()),
+            Builtin::WriteTerm => self.write_term(2),
+            Builtin::WriteTermTo => self.write_term(3),
+            Builtin::Nl => self.nl(),
+            Builtin::Format => self.format_2(),
+            Builtin::FormatTo => self.format_3(),
+            Builtin::Open => self.open(),
+            Builtin::Close => self.close(),
+            Builtin::SetInput => self.set_stream("input"),
+            Builtin::SetOutput => self.set_stream("output"),
+            Builtin::CurrentInput => self.current_stream("input"),
+            Builtin::CurrentOutput => self.current_stream("output"),
+            Builtin::GetChar => self.get(IoUnit::Char, false),
+            Builtin::GetCode => self.get(IoUnit::Code, false),
+            Builtin::GetByte => self.get(IoUnit::Byte, false),
+            Builtin::PeekChar => self.get(IoUnit::Char, true),
+            Builtin::PeekCode => self.get(IoUnit::Code, true),
+            Builtin::PeekByte => self.get(IoUnit::Byte, true),
+            Builtin::PutChar => self.put(IoUnit::Char),
+            Builtin::PutCode => self.put(IoUnit::Code),
+            Builtin::PutByte => self.put(IoUnit::Byte),
+            Builtin::FlushOutput => self.flush_output(),
+            Builtin::AtEndOfStream => self.at_end_of_stream(),
+            Builtin::Streams => self.stream_list(),
+            Builtin::StreamProperties => self.stream_properties(),
+            Builtin::MemoryOutput => self.memory_output(),
+            Builtin::MemoryOutputText => self.memory_output_text(),
+            Builtin::ReadTerm => self.read_term(2),
+            Builtin::ReadTermFrom => self.read_term(3),
+            Builtin::AtomLength => self.text_length(TextKind::Atom),
+            Builtin::StringLength => self.text_length(TextKind::String),
+            Builtin::AtomChars => self.text_list(TextKind::Atom, false),
+            Builtin::AtomCodes => self.text_list(TextKind::Atom, true),
+            Builtin::StringChars => self.text_list(TextKind::String, false),
+            Builtin::StringCodes => self.text_list(TextKind::String, true),
+            Builtin::CharCode => self.char_code(),
+            Builtin::NumberChars => self.number_text(false),
+            Builtin::NumberCodes => self.number_text(true),
+            Builtin::UpcaseAtom => self.change_case(TextKind::Atom, true),
+            Builtin::DowncaseAtom => self.change_case(TextKind::Atom, false),
+            Builtin::StringUpper => self.change_case(TextKind::String, true),
+            Builtin::StringLower => self.change_case(TextKind::String, false),
+            Builtin::StringToAtom => self.string_to_atom(),
+            Builtin::StringCode => self.string_code(),
+            Builtin::AtomConcat => self.text_concat(TextKind::Atom),
+            Builtin::StringConcat => self.text_concat(TextKind::String),
+            Builtin::SplitString => self.split_string(),
+            Builtin::SubAtom => self.sub_text(TextKind::Atom),
+            Builtin::SubString => self.sub_text(TextKind::String),
+            Builtin::AbsoluteFileName => self.absolute_file_name(),
+            Builtin::StartConsult => self.start_consult(),
+            Builtin::EndConsult => self.end_consult(),
+            Builtin::LoadedFile => self.loaded_file(),
+            Builtin::FileModule => self.file_module(),
+            Builtin::LoadSource => self.load_source(),
+            Builtin::AddClause => self.add_clause(),
+            Builtin::CompilerDirective => self.compiler_directive(),
+            Builtin::SourceModule => self.source_module(),
+            Builtin::AddInitialization => self.add_initialization(),
+            Builtin::DirectiveFailed => self.directive_failed(),
+            Builtin::DcgBody => self.dcg_body(),
+            Builtin::DcgTranslateRule => self.dcg_translate_rule(),
+            Builtin::SetPrologFlag => self.set_prolog_flag(),
+            Builtin::PrologFlags => self.prolog_flags(),
+            Builtin::PutAttr => self.put_attr(),
+            Builtin::GetAttr => self.get_attr(),
+            Builtin::DelAttr => self.del_attr(),
+            Builtin::GetAttrs => self.get_attrs(),
+            Builtin::Attvar => Ok(matches!(
+                self.value(argument(0)),
+                Cell::AttributedVariable(_)
+            )),
+            Builtin::TermAttvars => self.term_attvars(),
+            Builtin::ModuleDefines => self.module_defines(),
+            Builtin::RestoreArguments => self.restore_arguments(),
+        }
+    }
+
+    fn compare_standard_order(&self, accept: fn(Ordering) -> bool) -> Result<bool, PrologError> {
+        Ok(accept(self.compare_terms(argument(0), argument(1))))
+    }
+
+    fn compare_arguments(&self, accept: fn(Ordering) -> bool) -> Result<bool, PrologError> {
+        let ordering = self.compare_arithmetic(argument(0), argument(1))?;
+        Ok(accept(ordering))
+    }
+}
+
+pub(super) fn argument(index: usize) -> CellAddress {
+    CellAddress::Register {
+        index: RegisterId::Argument(index),
+    }
+}
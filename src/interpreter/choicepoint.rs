@@ -1,5 +1,8 @@
 use crate::interpreter::Cell;
 
+/// The next instruction of a choice point removed by [`ChoicePointStack::soft_cut`].
+const REMOVED_CHOICE_POINT: usize = usize::MAX;
+
 #[derive(Clone, Debug)]
 pub struct ChoicePointStack {
     raw_stack: Vec<u8>,
@@ -12,10 +15,27 @@ pub struct ChoicePointHead {
     pub num_arguments: usize,
     pub continuation_address: usize,
     pub environment_address: usize,
+    /// End of the environments protected by this choice point, see [`Self::environment_top`].
+    pub environment_top: usize,
     pub previous_address: usize,
     pub next_instruction_address: usize,
     pub trail_address: usize,
     pub stack_address: usize,
+    /// For choice points of dynamic predicates, the id of the next clause to try.
+    pub clause: usize,
+    /// For choice points of dynamic predicates, the generation the call started in.
+    pub generation: usize,
+}
+
+/// The state of the machine a choice point restores when backtracking to it.
+#[derive(Clone, Debug)]
+pub struct SavedState {
+    pub continuation_address: usize,
+    pub environment_address: usize,
+    /// End of the environment stack, see [`ChoicePointHead::environment_top`].
+    pub environment_top: usize,
+    pub trail_address: usize,
+    pub stack_address: usize,
 }
 
 #[derive(Clone, Debug)]
@@ -40,30 +60,42 @@ impl ChoicePointStack {
     pub fn push_choice_point(
         &mut self,
         num_arguments: usize,
-        continuation_address: usize,
-        environment_address: usize,
         next_instruction_address: usize,
-        trail_address: usize,
-        stack_address: usize,
+        state: SavedState,
     ) {
         let head_size = std::mem::size_of::<ChoicePointHead>();
+        // Older choice points may still protect environments above the current top.
+        let environment_top = state.environment_top.max(self.get_environment_top());
+        self.reserve(head_size + num_arguments * std::mem::size_of::<Cell>());
         let next_head = unsafe {
             let raw_ptr = self.raw_stack[self.next_address..self.next_address + head_size].as_ptr();
             let head = std::mem::transmute::<_, &mut ChoicePointHead>(raw_ptr);
             head
         };
         next_head.num_arguments = num_arguments;
-        next_head.continuation_address = continuation_address;
+        next_head.continuation_address = state.continuation_address;
         next_head.previous_address = self.last_address;
         next_head.next_instruction_address = next_instruction_address;
-        next_head.trail_address = trail_address;
-        next_head.stack_address = stack_address;
-        next_head.environment_address = environment_address;
+        next_head.trail_address = state.trail_address;
+        next_head.stack_address = state.stack_address;
+        next_head.environment_address = state.environment_address;
+        next_head.environment_top = environment_top;
+        next_head.clause = 0;
+        next_head.generation = 0;
 
         self.last_address = self.next_address;
         self.next_address += head_size + num_arguments * std::mem::size_of::<Cell>();
     }
 
+    /// Makes sure `size` more bytes fit on the stack, growing it if necessary.
+    fn reserve(&mut self, size: usize) {
+        let required = self.next_address + size;
+        if required > self.raw_stack.len() {
+            self.raw_stack
+                .resize(required.max(self.raw_stack.len() * 2), 0);
+        }
+    }
+
     pub fn pop_choice_point(&mut self) {
         let head_size = std::mem::size_of::<ChoicePointHead>();
         let head = unsafe {
@@ -75,6 +107,43 @@ impl ChoicePointStack {
         self.next_address -= head_size + head.num_arguments * std::mem::size_of::<Cell>();
     }
 
+    /// Address of the newest choice point.
+    pub fn get_current_address(&self) -> usize {
+        self.last_address
+    }
+
+    /// End of the newest choice point. Choice points created later all start at or above it.
+    pub fn get_top_address(&self) -> usize {
+        self.next_address
+    }
+
+    /// Removes all choice points created after the top was at `address`.
+    pub fn cut(&mut self, address: usize) {
+        while self.next_address > address {
+            self.pop_choice_point();
+        }
+    }
+
+    /// Removes the choice point at `address` but keeps the ones created after it. As they are
+    /// still needed, the choice point is only marked and skipped when backtracking reaches it.
+    pub fn soft_cut(&mut self, address: usize) {
+        if address == self.last_address {
+            self.pop_choice_point();
+            return;
+        }
+        let head_size = std::mem::size_of::<ChoicePointHead>();
+        let raw_ptr = self.raw_stack[address..address + head_size].as_mut_ptr();
+        let head = unsafe { &mut *raw_ptr.cast::<ChoicePointHead>() };
+        head.next_instruction_address = REMOVED_CHOICE_POINT;
+    }
+
+    /// Pops the newest choice points removed by [`Self::soft_cut`].
+    pub fn pop_removed(&mut self) {
+        while !self.is_empty() && self.get_head().next_instruction_address == REMOVED_CHOICE_POINT {
+            self.pop_choice_point();
+        }
+    }
+
     pub fn get_argument_mut(&mut self, index: usize) -> &mut Cell {
         let head_size = std::mem::size_of::<ChoicePointHead>();
         let variable_offset = head_size + index * std::mem::size_of::<Cell>();
@@ -109,6 +178,34 @@ impl ChoicePointStack {
         head
     }
 
+    pub fn get_num_arguments(&self) -> usize {
+        let head = self.get_head();
+        head.num_arguments
+    }
+
+    /// Environments below this address must not be overwritten, as backtracking to the newest
+    /// choice point may still return to them. Zero if there is no choice point.
+    pub fn get_environment_top(&self) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+        let head = self.get_head();
+        head.environment_top
+    }
+
+    /// The next clause and the generation of the newest choice point, which must belong to a
+    /// dynamic predicate.
+    pub fn get_clause(&self) -> (usize, usize) {
+        let head = self.get_head();
+        (head.clause, head.generation)
+    }
+
+    pub fn set_clause(&mut self, clause: usize, generation: usize) {
+        let head = self.get_head_mut();
+        head.clause = clause;
+        head.generation = generation;
+    }
+
     pub fn get_continuation(&self) -> usize {
         let head = self.get_head();
         head.continuation_address
@@ -0,0 +1,298 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    compiler::Compiler,
+    interpreter::{
+        Cell, CellAddress, Interpreter,
+        builtins::argument,
+        error::{ErrorCulprit, PrologError},
+        solutions::StoredTerm,
+    },
+    parsing::{AbstractFact, AbstractProgram, AbstractTerm, program_from_term},
+    writer::{WriteOptions, format_term},
+};
+
+/// A file being loaded by `consult/1`.
+#[derive(Clone, Debug)]
+pub(super) struct LoadContext {
+    /// The file the clauses belong to.
+    file: String,
+    /// The file the clauses are read from, which differs from `file` inside `include/1`.
+    source: String,
+    /// The source module when the load started, restored once it has finished.
+    source_module: String,
+    /// The goals of `initialization/1`, run once the file has been loaded.
+    initialization: Vec<StoredTerm>,
+}
+
+impl Interpreter {
+    /// Loads the Prolog source file `path` into `compiler` by running `consult/1`. The returned
+    /// interpreter holds the warnings of the load, and the exception if it was aborted.
+    pub fn consult(compiler: Compiler, path: &str) -> Self {
+        let mut compiler = compiler;
+        let query = AbstractProgram::Fact(AbstractFact {
+            term: AbstractTerm::Structure(
+                "consult".to_string(),
+                vec![AbstractTerm::Constant(path.to_string())],
+            ),
+        });
+        let artifact = compiler.compile(&query);
+        let mut interpreter = Interpreter::new(compiler, &artifact);
+        while interpreter.step() {}
+        interpreter
+    }
+
+    /// The compiler of the program, with everything loaded and asserted while running it.
+    pub fn into_compiler(self) -> Compiler {
+        self.compiler
+    }
+
+    /// `'$absolute_file_name'(Spec, File)`, the canonical path of the source file `Spec`.
+    /// Relative paths are resolved against the directory of the file being loaded, and the
+    /// extension `.pl` may be left out.
+    pub(super) fn absolute_file_name(&mut self) -> Result<bool, PrologError> {
+        let spec = argument(0);
+        if self.is_unbound(spec) {
+            return Err(PrologError::InstantiationError);
+        }
+        let name = self
+            .atom_name(spec)
+            .ok_or(PrologError::type_error("atom", spec))?;
+        let mut path = PathBuf::from(&name);
+        if path.is_relative()
+            && let Some(directory) = self
+                .load_contexts
+                .last()
+                .and_then(|context| Path::new(&context.source).parent())
+        {
+            path = directory.join(path);
+        }
+        let file = [path.clone(), path.with_added_extension("pl")]
+            .into_iter()
+            .find(|candidate| candidate.is_file())
+            .and_then(|file| file.canonicalize().ok())
+            .ok_or(PrologError::ExistenceError {
+                kind: "source_sink",
+                culprit: ErrorCulprit::Address(spec),
+            })?;
+        let file = self.atom(&file.to_string_lossy());
+        Ok(self.unify_cell(argument(1), file))
+    }
+
+    /// `'$start_consult'(File)`, starts loading `File` into `user`.
+    pub(super) fn start_consult(&mut self) -> Result<bool, PrologError> {
+        let file = self.file_argument(argument(0))?;
+        let source_module = self.compiler.start_file(&file);
+        self.load_contexts.push(LoadContext {
+            file: file.clone(),
+            source: file,
+            source_module,
+            initialization: Vec::new(),
+        });
+        Ok(true)
+    }
+
+    /// `'$end_consult'(Goals)`, finishes the innermost load and unifies `Goals` with the goals
+    /// of its `initialization/1` directives. Fails if no file is being loaded.
+    pub(super) fn end_consult(&mut self) -> Result<bool, PrologError> {
+        let Some(context) = self.load_contexts.pop() else {
+            return Ok(false);
+        };
+        self.compiler
+            .end_file(&context.file, &context.source_module);
+        let goals = context
+            .initialization
+            .iter()
+            .map(|goal| self.load_term(goal))
+            .collect::<Vec<_>>();
+        let tail = self.empty_list();
+        let list = self.build_list(&goals, tail);
+        Ok(self.unify_cell(argument(0), list))
+    }
+
+    /// `'$loaded_file'(File)`, whether `File` has been loaded.
+    pub(super) fn loaded_file(&mut self) -> Result<bool, PrologError> {
+        let file = self.file_argument(argument(0))?;
+        Ok(self.compiler.is_loaded(&file))
+    }
+
+    /// `'$file_module'(File, Module)`, the module defined by the loaded `File`.
+    pub(super) fn file_module(&mut self) -> Result<bool, PrologError> {
+        let file = self.file_argument(argument(0))?;
+        let Some(module) = self.compiler.file_module(&file).map(str::to_string) else {
+            return Err(PrologError::domain_error("module_file", argument(0)));
+        };
+        let module = self.atom(&module);
+        Ok(self.unify_cell(argument(1), module))
+    }
+
+    /// `'$load_source'(Old, New)`, unifies `Old` with the file clauses are read from and reads
+    /// them from `New` from now on. Fails if no file is being loaded.
+    pub(super) fn load_source(&mut self) -> Result<bool, PrologError> {
+        let source = self.file_argument(argument(1))?;
+        let Some(context) = self.load_contexts.last_mut() else {
+            return Ok(false);
+        };
+        let old = std::mem::replace(&mut context.source, source);
+        let old = self.atom(&old);
+        Ok(self.unify_cell(argument(0), old))
+    }
+
+    /// `'$add_clause'(Clause, Line)`, adds a clause of the file being loaded. Clauses which
+    /// cannot be added are reported with their location and skipped. Fails if no file is being
+    /// loaded.
+    pub(super) fn add_clause(&mut self) -> Result<bool, PrologError> {
+        let Some(context) = self.load_contexts.last() else {
+            return Ok(false);
+        };
+        let file = context.file.clone();
+        let clause = argument(0);
+        let result = if self.is_unbound(clause) {
+            Err(PrologError::InstantiationError)
+        } else if !self.is_acyclic(clause) {
+            Err(PrologError::type_error("acyclic_term", clause))
+        } else {
+            let term = self.abstract_term(clause, &mut Vec::new());
+            match program_from_term(term) {
+                Ok(program @ (AbstractProgram::Fact(_) | AbstractProgram::Rule(_))) => {
+                    self.compiler.add_file_clause(&program, &file)
+                }
+                _ => Err(PrologError::type_error("callable", clause)),
+            }
+        };
+
+        self.grow_registers(0);
+        match result {
+            Ok(None) => {}
+            Ok(Some(warning)) => self.report("Warning", argument(1), &warning)?,
+            Err(error) => {
+                let message = self.error_text(&error);
+                self.report("Error", argument(1), &message)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// `'$compiler_directive'(Directive)`, executes a declaration such as `dynamic/1` or
+    /// `module/2`. Fails if `Directive` is a goal to run instead.
+    pub(super) fn compiler_directive(&mut self) -> Result<bool, PrologError> {
+        if self.is_unbound(argument(0)) {
+            return Err(PrologError::InstantiationError);
+        }
+        self.must_be_acyclic(argument(0))?;
+        let directive = self.abstract_term(argument(0), &mut Vec::new());
+        self.compiler.directive(&directive)
+    }
+
+    /// `'$source_module'(Module)`, the module clauses are currently added to.
+    pub(super) fn source_module(&mut self) -> Result<bool, PrologError> {
+        let module = self.compiler.source_module().to_string();
+        let module = self.atom(&module);
+        Ok(self.unify_cell(argument(0), module))
+    }
+
+    /// `'$add_initialization'(Goal)`, runs `Goal` once the file being loaded has been loaded.
+    /// Fails if no file is being loaded.
+    pub(super) fn add_initialization(&mut self) -> Result<bool, PrologError> {
+        if self.load_contexts.is_empty() {
+            return Ok(false);
+        }
+        let goal = self.store_term(argument(0));
+        self.load_contexts
+            .last_mut()
+            .expect("a file to be loading")
+            .initialization
+            .push(goal);
+        Ok(true)
+    }
+
+    /// `'$directive_failed'(Goal, Line)`, warns that a directive failed. `Line` is unbound for
+    /// the goals of `initialization/1`.
+    pub(super) fn directive_failed(&mut self) -> Result<bool, PrologError> {
+        let goal = self.format(argument(0), &WriteOptions::writeq());
+        let kind = match self.is_unbound(argument(1)) {
+            true => "initialization",
+            false => "directive",
+        };
+        self.report(
+            "Warning",
+            argument(1),
+            &format!("Goal ({}) failed: {}", kind, goal),
+        )?;
+        Ok(true)
+    }
+
+    /// Writes `message` to the standard error, prefixed with the file being loaded and `line`,
+    /// unless it is unbound.
+    fn report(&mut self, level: &str, line: CellAddress, message: &str) -> Result<(), PrologError> {
+        let mut location = String::new();
+        if let Some(context) = self.load_contexts.last() {
+            location = format!("{}:", context.source);
+            if let Cell::Integer(line) = self.value(line) {
+                location += &format!("{}:", line);
+            }
+            location.push(' ');
+        }
+        self.emit_error(&format!("{}: {}{}\n", level, location, message))
+    }
+
+    /// The name of the file at `address`.
+    fn file_argument(&self, address: CellAddress) -> Result<String, PrologError> {
+        if self.is_unbound(address) {
+            return Err(PrologError::InstantiationError);
+        }
+        self.atom_name(address)
+            .ok_or(PrologError::type_error("atom", address))
+    }
+
+    /// `error` written as the formal term of its ISO error, e.g. `type_error(atom,1)`. Its
+    /// arguments are separated like the ones of terms written by `writeq/1`.
+    fn error_text(&self, error: &PrologError) -> String {
+        let culprit = |culprit: &ErrorCulprit| match culprit {
+            ErrorCulprit::Address(address) => self.format(*address, &WriteOptions::writeq()),
+            ErrorCulprit::Term(term) => {
+                format_term(term, &self.compiler.operators, &WriteOptions::writeq())
+            }
+            ErrorCulprit::Number(number) => number.to_string(),
+            ErrorCulprit::Indicator(functor) => self
+                .compiler
+                .descriptor_allocator
+                .get(*functor)
+                .pretty_name(),
+        };
+        match error {
+            PrologError::InstantiationError => "instantiation_error".to_string(),
+            PrologError::UninstantiationError(term) => {
+                format!("uninstantiation_error({})", culprit(term))
+            }
+            PrologError::TypeError {
+                expected,
+                culprit: term,
+            } => format!("type_error({},{})", expected, culprit(term)),
+            PrologError::DomainError {
+                domain,
+                culprit: term,
+            } => format!("domain_error({},{})", domain, culprit(term)),
+            PrologError::ExistenceError {
+                kind,
+                culprit: term,
+            } => format!("existence_error({},{})", kind, culprit(term)),
+            PrologError::PermissionError {
+                action,
+                kind,
+                culprit: term,
+            } => format!("permission_error({},{},{})", action, kind, culprit(term)),
+            PrologError::EvaluationError(error) => format!("evaluation_error({})", error),
+            PrologError::RepresentationError(error) => {
+                format!("representation_error({})", error)
+            }
+            PrologError::ResourceError(resource) => format!("resource_error({})", resource),
+            PrologError::FormatError(message) => format!("format({:?})", message),
+            PrologError::SyntaxError(message) => format!("syntax_error({:?})", message),
+            PrologError::SystemError(message) => format!("system_error({:?})", message),
+            PrologError::OccursCheck { variable, term } => {
+                format!("occurs_check({},{})", culprit(variable), culprit(term))
+            }
+        }
+    }
+}
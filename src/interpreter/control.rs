@@ -0,0 +1,392 @@
+use std::collections::HashMap;
+
+use crate::{
+    instructions::Builtin,
+    interpreter::{Cell, CellAddress, Interpreter, builtins::argument, error::PrologError},
+    parsing::{
+        AbstractTerm,
+        dcg::{translate_body, translate_rule},
+        program_from_term,
+    },
+};
+
+impl Interpreter {
+    /// `call(Closure, Arguments...)`, calls `Closure` with `arity - 1` extra arguments appended
+    /// to its own.
+    pub(super) fn call_closure(&mut self, arity: usize) -> Result<bool, PrologError> {
+        if arity == 1 {
+            return self.call_goal(argument(0));
+        }
+
+        // The arguments are added to the closure inside `Module:Closure`.
+        let mut closure = argument(0);
+        let mut module = None;
+        while let Some((qualifier, inner)) = self.qualified_goal(closure) {
+            module = Some(qualifier);
+            closure = inner;
+        }
+        let (name, mut arguments) = match self.value(closure) {
+            Cell::Reference(_) | Cell::AttributedVariable(_) => {
+                return Err(PrologError::InstantiationError);
+            }
+            Cell::Constant(functor) => (*functor, Vec::new()),
+            _ => match self.structure(closure) {
+                Some((functor, index)) => {
+                    let arity = self.compiler.descriptor_allocator.get(functor).arity();
+                    let arguments = (1..=arity)
+                        .map(|i| self.term_cell(CellAddress::GlobalStack { index: index + i }))
+                        .collect::<Vec<_>>();
+                    (functor, arguments)
+                }
+                None => return Err(PrologError::type_error("callable", closure)),
+            },
+        };
+        arguments.extend((1..arity).map(|i| self.term_cell(argument(i))));
+
+        let name = self.compiler.descriptor_allocator.get(name).name.clone();
+        let functor = self
+            .compiler
+            .descriptor_allocator
+            .get_or_set_functor(&name, arguments.len());
+        let mut goal = self.build_structure(functor, &arguments);
+        if let Some(module) = module {
+            let colon = self
+                .compiler
+                .descriptor_allocator
+                .get_or_set_functor(":", 2);
+            let module = self.term_cell(module);
+            goal = self.build_structure(colon, &[module, goal]);
+        }
+        self.global_stack.push(goal);
+        self.call_goal(CellAddress::GlobalStack {
+            index: self.global_stack.len() - 1,
+        })
+    }
+
+    /// Splits the term `Module:Goal` at `address` into the addresses of its arguments.
+    fn qualified_goal(&self, address: CellAddress) -> Option<(CellAddress, CellAddress)> {
+        let (functor, index) = self.structure(address)?;
+        let descriptor = self.compiler.descriptor_allocator.get(functor);
+        (descriptor.name == ":" && descriptor.arity() == 2).then_some((
+            CellAddress::GlobalStack { index: index + 1 },
+            CellAddress::GlobalStack { index: index + 2 },
+        ))
+    }
+
+    /// The cut barrier passed to `'$cut'/1` or `'$soft_cut'/1`, stored by the clause whose
+    /// control construct calls it.
+    pub(super) fn cut_level(&self, level: CellAddress) -> Result<usize, PrologError> {
+        match self.value(level) {
+            Cell::Reference(_) | Cell::AttributedVariable(_) => {
+                Err(PrologError::InstantiationError)
+            }
+            Cell::Integer(value) => usize::try_from(*value)
+                .map_err(|_| PrologError::domain_error("not_less_than_zero", level)),
+            _ => Err(PrologError::type_error("integer", level)),
+        }
+    }
+
+    /// `call(Goal)`. Predicates and builtins are called directly, anything else such as a
+    /// conjunction is compiled into an auxiliary predicate first.
+    pub(super) fn call_goal(&mut self, goal: CellAddress) -> Result<bool, PrologError> {
+        self.call_goal_in("user", goal)
+    }
+
+    /// Calls `goal` in the context of `module`: its predicate is looked up in `module`, and
+    /// its goal and closure arguments are qualified with `module` if it is a meta-predicate.
+    fn call_goal_in(&mut self, module: &str, goal: CellAddress) -> Result<bool, PrologError> {
+        if let Some((qualifier, inner)) = self.qualified_goal(goal) {
+            let module = match self.value(qualifier) {
+                Cell::Reference(_) | Cell::AttributedVariable(_) => {
+                    return Err(PrologError::InstantiationError);
+                }
+                Cell::Constant(id) => self.compiler.descriptor_allocator.get(*id).name.clone(),
+                _ => return Err(PrologError::type_error("module", qualifier)),
+            };
+            return self.call_goal_in(&module, inner);
+        }
+
+        let (functor, arguments) = match self.value(goal) {
+            Cell::Reference(_) | Cell::AttributedVariable(_) => {
+                return Err(PrologError::InstantiationError);
+            }
+            Cell::Constant(functor) => (*functor, Vec::new()),
+            _ => match self.structure(goal) {
+                Some((functor, index)) => {
+                    let arity = self.compiler.descriptor_allocator.get(functor).arity();
+                    let arguments = (1..=arity)
+                        .map(|i| CellAddress::GlobalStack { index: index + i })
+                        .collect::<Vec<_>>();
+                    (functor, arguments)
+                }
+                None => return Err(PrologError::type_error("callable", goal)),
+            },
+        };
+
+        let name = self
+            .compiler
+            .descriptor_allocator
+            .get(functor)
+            .name
+            .as_str();
+        match (name, arguments.as_slice()) {
+            // Existential quantification only matters to `bagof/3` and `setof/3`.
+            ("^", [_, goal]) => return self.call_goal_in(module, *goal),
+            // Cut is local to the called goal, so on its own it has nothing to cut.
+            ("!", []) => return Ok(true),
+            (",", [_, _]) => return self.call_compiled_goal(module, goal),
+            // Compiling them in `module` qualifies the goals inside.
+            (";" | "->", [_, _]) | ("\\+", [_]) if module != "user" => {
+                return self.call_compiled_goal(module, goal);
+            }
+            _ => {}
+        }
+
+        let name = name.to_string();
+        let mut arguments = arguments
+            .iter()
+            .map(|&argument| self.term_cell(argument))
+            .collect::<Vec<_>>();
+        if module != "user"
+            && let Some(meta) = self.compiler.meta_arguments(module, &name, arguments.len())
+        {
+            let colon = self
+                .compiler
+                .descriptor_allocator
+                .get_or_set_functor(":", 2);
+            let qualifier = self.atom(module);
+            for (argument, is_goal) in arguments.iter_mut().zip(meta) {
+                if is_goal {
+                    *argument = self.build_structure(colon, &[qualifier.clone(), argument.clone()]);
+                }
+            }
+        }
+        if let Some(builtin) = Builtin::lookup(&name, arguments.len()) {
+            self.load_arguments(&arguments);
+            return self.call_builtin(builtin);
+        }
+
+        let functor =
+            self.compiler
+                .descriptor_allocator
+                .get_or_set_predicate(module, &name, arguments.len());
+        let Some(address) = self.compiler.resolve_predicate(functor) else {
+            return self.unknown_procedure(functor);
+        };
+        self.load_arguments(&arguments);
+        self.call_predicate(address, functor);
+        Ok(true)
+    }
+
+    /// Calls `goal` through an auxiliary predicate `'$call_N'(Variables...) :- Goal.`, which is
+    /// compiled once for every distinct shape of goal.
+    fn call_compiled_goal(&mut self, module: &str, goal: CellAddress) -> Result<bool, PrologError> {
+        self.must_be_acyclic(goal)?;
+        let mut variables = Vec::new();
+        let body = self.abstract_term(goal, &mut variables);
+        let key = match module {
+            "user" => format!("{:?}", body),
+            _ => format!("{}:{:?}", module, body),
+        };
+
+        let functor = match self.goal_cache.get(&key) {
+            Some(functor) => *functor,
+            None => {
+                let name = format!("$call_{}", self.goal_cache.len());
+                let head = if variables.is_empty() {
+                    AbstractTerm::Constant(name)
+                } else {
+                    AbstractTerm::Structure(name, (0..variables.len()).map(variable_name).collect())
+                };
+                let functor = self.compiler.descriptor_allocator.get_or_set_predicate(
+                    module,
+                    head.name(),
+                    head.arity(),
+                );
+                let clause = AbstractTerm::Structure(":-".to_string(), vec![head, body]);
+                let clause = program_from_term(clause)
+                    .map_err(|_| PrologError::type_error("callable", goal))?;
+                let source_module = self.compiler.set_source_module(module);
+                let result = self.compiler.add_program(&clause);
+                self.compiler.set_source_module(&source_module);
+                result?;
+                self.goal_cache.insert(key, functor);
+                functor
+            }
+        };
+
+        let arguments = variables
+            .into_iter()
+            .map(Cell::Reference)
+            .collect::<Vec<_>>();
+        self.load_arguments(&arguments);
+        let address = self
+            .compiler
+            .predicate_address(functor)
+            .expect("compiled goals to have a clause");
+        self.call_predicate(address, functor);
+        Ok(true)
+    }
+
+    /// `'$dcg_body'(Body, S0, S, Goal)`, translates the grammar body `Body` into the goal which
+    /// describes the list `S0` up to `S`.
+    pub(super) fn dcg_body(&mut self) -> Result<bool, PrologError> {
+        let body = argument(0);
+        if self.is_unbound(body) {
+            return Err(PrologError::InstantiationError);
+        }
+        for i in 0..3 {
+            self.must_be_acyclic(argument(i))?;
+        }
+        let mut variables = Vec::new();
+        let [body_term, start, end] =
+            [0, 1, 2].map(|i| self.abstract_term(argument(i), &mut variables));
+        let goal = translate_body(&body_term, start, end)
+            .map_err(|_| PrologError::type_error("callable", body))?;
+        let goal = self.build_with_variables(&goal, variables);
+        Ok(self.unify_cell(argument(3), goal))
+    }
+
+    /// `'$dcg_translate_rule'(Rule, Clause)`, translates the grammar rule `Head --> Body` into
+    /// a clause. Fails if `Rule` is not a valid grammar rule.
+    pub(super) fn dcg_translate_rule(&mut self) -> Result<bool, PrologError> {
+        if !self.is_acyclic(argument(0)) {
+            return Ok(false);
+        }
+        let mut variables = Vec::new();
+        let rule = self.abstract_term(argument(0), &mut variables);
+        let AbstractTerm::Structure(name, arguments) = &rule else {
+            return Ok(false);
+        };
+        if name != "-->" || arguments.len() != 2 {
+            return Ok(false);
+        }
+        let Ok(clause) = translate_rule(&arguments[0], &arguments[1]) else {
+            return Ok(false);
+        };
+        let clause = self.build_with_variables(&clause, variables);
+        Ok(self.unify_cell(argument(1), clause))
+    }
+
+    /// Builds `term` read by [`Interpreter::abstract_term`], whose variables are still those
+    /// at the addresses collected in `variables`. Variables new to `term` are created.
+    fn build_with_variables(&mut self, term: &AbstractTerm, variables: Vec<usize>) -> Cell {
+        let mut bindings = HashMap::new();
+        for (position, index) in variables.into_iter().enumerate() {
+            if let AbstractTerm::Variable(name) = &variable_name(position) {
+                bindings.insert(name.clone(), Cell::Reference(index));
+            }
+        }
+        self.build_term(term, &mut bindings)
+    }
+
+    /// Reads the term at `address` back into its abstract form. Unbound variables are named by
+    /// their position in `variables`, which collects their global stack addresses.
+    ///
+    /// Arguments are read from an explicit work stack, and each structure is assembled once its
+    /// arguments are on the `terms` stack, so long lists are read without recursion.
+    pub(super) fn abstract_term(
+        &self,
+        address: CellAddress,
+        variables: &mut Vec<usize>,
+    ) -> AbstractTerm {
+        enum Work {
+            Read(CellAddress),
+            /// Replaces the topmost `arity` terms by the structure with these arguments.
+            Assemble(String, usize),
+        }
+
+        let mut work = vec![Work::Read(address)];
+        let mut terms = Vec::new();
+        while let Some(next) = work.pop() {
+            let address = match next {
+                Work::Read(address) => self.deref_cell(address),
+                Work::Assemble(name, arity) => {
+                    let arguments = terms.split_off(terms.len() - arity);
+                    terms.push(AbstractTerm::Structure(name, arguments));
+                    continue;
+                }
+            };
+            let term = match self.lookup_address(address) {
+                Cell::Reference(index) | Cell::AttributedVariable(index) => {
+                    let position = variables
+                        .iter()
+                        .position(|variable| variable == index)
+                        .unwrap_or_else(|| {
+                            variables.push(*index);
+                            variables.len() - 1
+                        });
+                    variable_name(position)
+                }
+                Cell::Constant(id) => {
+                    AbstractTerm::Constant(self.compiler.descriptor_allocator.get(*id).name.clone())
+                }
+                Cell::StructureRef(index) => {
+                    let index = *index;
+                    let Cell::Structure(functor) = self.global_stack[index] else {
+                        unreachable!("structure references always point to a structure");
+                    };
+                    let descriptor = self.compiler.descriptor_allocator.get(functor);
+                    let arity = descriptor.arity();
+                    work.push(Work::Assemble(descriptor.name.clone(), arity));
+                    // Pushed last to first, so the arguments are read from left to right.
+                    work.extend(
+                        (1..=arity)
+                            .rev()
+                            .map(|i| Work::Read(CellAddress::GlobalStack { index: index + i })),
+                    );
+                    continue;
+                }
+                cell @ Cell::StringRef(_) => {
+                    AbstractTerm::String(self.read_string(cell).expect("cell is a string"))
+                }
+                cell => AbstractTerm::Number(self.read_number(cell).expect("cell is a number")),
+            };
+            terms.push(term);
+        }
+        terms.pop().expect("the term to be read")
+    }
+
+    /// Builds `term` on the global stack. Variables with the same name in `variables` share one
+    /// heap variable.
+    pub(super) fn build_term(
+        &mut self,
+        term: &AbstractTerm,
+        variables: &mut HashMap<String, Cell>,
+    ) -> Cell {
+        match term {
+            AbstractTerm::Variable(name) => match variables.get(name) {
+                Some(variable) => variable.clone(),
+                None => {
+                    let variable = self.new_variable();
+                    variables.insert(name.clone(), variable.clone());
+                    variable
+                }
+            },
+            AbstractTerm::Constant(name) => self.atom(name),
+            AbstractTerm::Number(number) => self.allocate_number(number),
+            AbstractTerm::String(text) => self.allocate_string(text),
+            AbstractTerm::Structure(name, arguments) => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.build_term(argument, variables))
+                    .collect::<Vec<_>>();
+                let functor = self
+                    .compiler
+                    .descriptor_allocator
+                    .get_or_set_functor(name, arguments.len());
+                self.build_structure(functor, &arguments)
+            }
+        }
+    }
+
+    /// Puts `arguments` into the argument registers, as the caller of a predicate would.
+    pub(super) fn load_arguments(&mut self, arguments: &[Cell]) {
+        self.grow_registers(arguments.len());
+        self.registers[..arguments.len()].clone_from_slice(arguments);
+    }
+}
+
+fn variable_name(position: usize) -> AbstractTerm {
+    AbstractTerm::Variable(format!("_G{}", position))
+}
@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+
+use crate::{
+    compiler::ClausePosition,
+    instructions::{Builtin, DescriptorId},
+    interpreter::{Cell, CellAddress, Interpreter, builtins::argument, error::PrologError},
+    number::Number,
+    parsing::{AbstractProgram, program_from_term},
+};
+
+impl Interpreter {
+    /// Enters a dynamic predicate. Only the clauses existing right now are tried, even if the
+    /// predicate changes before the call has finished.
+    pub(super) fn try_clauses(&mut self, functor: DescriptorId) {
+        let generation = self.compiler.generation();
+        let Some(predicate) = self
+            .compiler
+            .dynamic_predicate(functor)
+            .filter(|predicate| predicate.defined)
+        else {
+            match self.unknown_procedure(functor) {
+                Ok(_) => self.backtrack(),
+                Err(error) => self.raise(error),
+            }
+            return;
+        };
+
+        let mut visible = predicate
+            .clauses
+            .iter()
+            .filter(|clause| clause.is_visible(generation));
+        let Some(first) = visible.next() else {
+            self.backtrack();
+            return;
+        };
+        let address = first.code.start;
+
+        if let Some(next) = visible.next() {
+            let next = next.id;
+            let arity = self.compiler.descriptor_allocator.get(functor).arity();
+            // The choice point resumes at the `RetryClauses` instruction following this one.
+            self.push_choice_point(arity, self.instruction_index);
+            self.choice_point_stack.set_clause(next, generation);
+        }
+        self.instruction_index = address;
+    }
+
+    /// Backtracks into the next clause of a dynamic predicate visible to the original call.
+    pub(super) fn retry_clauses(&mut self, functor: DescriptorId) {
+        self.cut_barrier = self.choice_point_stack.get_current_address();
+        self.restore_choice_point();
+
+        let (clause, generation) = self.choice_point_stack.get_clause();
+        let clauses = &self
+            .compiler
+            .dynamic_predicate(functor)
+            .expect("predicate to be dynamic")
+            .clauses;
+        let position = clauses
+            .iter()
+            .position(|candidate| candidate.id == clause)
+            .expect("clauses to be kept while a choice point may try them");
+        let address = clauses[position].code.start;
+
+        match clauses[position + 1..]
+            .iter()
+            .find(|candidate| candidate.is_visible(generation))
+        {
+            Some(next) => self.choice_point_stack.set_clause(next.id, generation),
+            None => self.choice_point_stack.pop_choice_point(),
+        }
+        self.instruction_index = address;
+    }
+
+    /// `asserta/1`, `assertz/1` and `assert/1`.
+    pub(super) fn assert(&mut self, position: ClausePosition) -> Result<bool, PrologError> {
+        let clause = argument(0);
+        if self.is_unbound(clause) {
+            return Err(PrologError::InstantiationError);
+        }
+        self.must_be_acyclic(clause)?;
+        let term = self.abstract_term(clause, &mut Vec::new());
+        let program = match program_from_term(term) {
+            Ok(program @ (AbstractProgram::Fact(_) | AbstractProgram::Rule(_))) => program,
+            _ => return Err(PrologError::type_error("callable", clause)),
+        };
+        self.compiler.assert_clause(&program, position)?;
+
+        // The new clause may use more temporary registers than any clause before.
+        self.grow_registers(0);
+        Ok(true)
+    }
+
+    /// `'$clause_refs'(Head, Refs)`, the ids of the clauses of the dynamic predicate of `Head`
+    /// which exist right now. Undefined predicates have no clauses.
+    pub(super) fn clause_refs(&mut self) -> Result<bool, PrologError> {
+        let functor = self.head_functor(argument(0))?;
+
+        let refs = match self.compiler.dynamic_predicate(functor) {
+            Some(predicate) if predicate.defined => {
+                let generation = self.compiler.generation();
+                predicate
+                    .clauses
+                    .iter()
+                    .filter(|clause| clause.is_visible(generation))
+                    .map(|clause| Cell::Integer(clause.id as i64))
+                    .collect()
+            }
+            _ if self.is_static(functor) => {
+                return Err(PrologError::permission_error(
+                    "modify",
+                    "static_procedure",
+                    functor,
+                ));
+            }
+            _ => Vec::new(),
+        };
+        let tail = self.empty_list();
+        let refs = self.build_list(&refs, tail);
+        Ok(self.unify_cell(argument(1), refs))
+    }
+
+    /// `'$clause'(Ref, Head, Body)`, unifies `Head` and `Body` with a copy of the clause `Ref`.
+    /// Fails if the clause has been reclaimed.
+    pub(super) fn clause_parts(&mut self) -> Result<bool, PrologError> {
+        let Some(id) = self.clause_ref(argument(0)) else {
+            return Ok(false);
+        };
+        let Some(clause) = self.compiler.clause_predicate(id).and_then(|functor| {
+            self.compiler
+                .dynamic_predicate(functor)?
+                .clauses
+                .iter()
+                .find(|clause| clause.id == id)
+        }) else {
+            return Ok(false);
+        };
+
+        let (head, body) = (clause.head.clone(), clause.body.clone());
+        let mut variables = HashMap::new();
+        let head = self.build_term(&head, &mut variables);
+        let body = self.build_term(&body, &mut variables);
+        Ok(self.unify_cell(argument(1), head) && self.unify_cell(argument(2), body))
+    }
+
+    /// `'$erase'(Ref)`, retracts the clause `Ref`. Fails if it has been retracted already.
+    pub(super) fn erase(&mut self) -> Result<bool, PrologError> {
+        let Some(id) = self.clause_ref(argument(0)) else {
+            return Ok(false);
+        };
+        let erased = self.compiler.erase_clause(id);
+        self.reclaim_clauses();
+        Ok(erased)
+    }
+
+    /// `abolish(Name/Arity)`.
+    pub(super) fn abolish(&mut self) -> Result<bool, PrologError> {
+        let functor = self.predicate_indicator(argument(0))?;
+        self.compiler.abolish(functor)?;
+        self.reclaim_clauses();
+        Ok(true)
+    }
+
+    /// `dynamic(Indicators)`, where `Indicators` is a single predicate indicator, a conjunction
+    /// or a list of them.
+    pub(super) fn dynamic(&mut self) -> Result<bool, PrologError> {
+        let mut pending = vec![argument(0)];
+        while let Some(specification) = pending.pop() {
+            if let Some((functor, index)) = self.structure(specification) {
+                let descriptor = self.compiler.descriptor_allocator.get(functor);
+                if matches!(descriptor.name.as_str(), "," | ".") && descriptor.arity() == 2 {
+                    pending.push(CellAddress::GlobalStack { index: index + 2 });
+                    pending.push(CellAddress::GlobalStack { index: index + 1 });
+                    continue;
+                }
+            }
+            if let Cell::Constant(id) = self.value(specification)
+                && self.compiler.descriptor_allocator.get(*id).name == "[]"
+            {
+                continue;
+            }
+            let functor = self.predicate_indicator(specification)?;
+            self.compiler.declare_dynamic(functor)?;
+        }
+        Ok(true)
+    }
+
+    /// Reads a `Name/Arity` predicate indicator.
+    pub(super) fn predicate_indicator(
+        &mut self,
+        address: CellAddress,
+    ) -> Result<DescriptorId, PrologError> {
+        if self.is_unbound(address) {
+            return Err(PrologError::InstantiationError);
+        }
+        let slash = self
+            .compiler
+            .descriptor_allocator
+            .get_or_set_functor("/", 2);
+        let Some((_, index)) = self
+            .structure(address)
+            .filter(|(functor, _)| *functor == slash)
+        else {
+            return Err(PrologError::type_error("predicate_indicator", address));
+        };
+        let (name, arity) = (
+            CellAddress::GlobalStack { index: index + 1 },
+            CellAddress::GlobalStack { index: index + 2 },
+        );
+
+        if self.is_unbound(name) || self.is_unbound(arity) {
+            return Err(PrologError::InstantiationError);
+        }
+        let Cell::Constant(name_id) = self.value(name) else {
+            return Err(PrologError::type_error("atom", name));
+        };
+        let name = self
+            .compiler
+            .descriptor_allocator
+            .get(*name_id)
+            .name
+            .clone();
+        let arity = match self.integer_argument(arity)? {
+            Number::Integer(value) if value < 0 => {
+                return Err(PrologError::domain_error("not_less_than_zero", arity));
+            }
+            Number::Integer(value) => value as usize,
+            _ => return Err(PrologError::RepresentationError("max_arity")),
+        };
+        Ok(self
+            .compiler
+            .descriptor_allocator
+            .get_or_set_functor(&name, arity))
+    }
+
+    /// The predicate a clause head or goal refers to.
+    pub(super) fn head_functor(&self, head: CellAddress) -> Result<DescriptorId, PrologError> {
+        match self.value(head) {
+            Cell::Reference(_) | Cell::AttributedVariable(_) => {
+                Err(PrologError::InstantiationError)
+            }
+            Cell::Constant(functor) => Ok(*functor),
+            _ => match self.structure(head) {
+                Some((functor, _)) => Ok(functor),
+                None => Err(PrologError::type_error("callable", head)),
+            },
+        }
+    }
+
+    /// Whether `functor` is a builtin or a predicate with static clauses.
+    fn is_static(&self, functor: DescriptorId) -> bool {
+        let descriptor = self.compiler.descriptor_allocator.get(functor);
+        Builtin::lookup(&descriptor.name, descriptor.arity()).is_some()
+            || (self.compiler.predicate_address(functor).is_some()
+                && !self.compiler.is_dynamic(functor))
+    }
+
+    fn clause_ref(&self, address: CellAddress) -> Option<usize> {
+        match self.value(address) {
+            Cell::Integer(id) => usize::try_from(*id).ok(),
+            _ => None,
+        }
+    }
+
+    /// Reclaims the instructions of retracted clauses which no running or suspended execution
+    /// can reach anymore: no instruction or continuation points into them, and no choice point
+    /// of their predicate was created while they were still visible.
+    fn reclaim_clauses(&mut self) {
+        let choice_points = self.choice_point_stack.inspect();
+        let mut live_addresses = vec![self.instruction_index, self.proceed_return_address];
+        live_addresses.extend(
+            self.environment_stack
+                .continuations(self.environment_stack.get_current_address()),
+        );
+        for choice_point in &choice_points {
+            live_addresses.push(choice_point.head.continuation_address);
+            live_addresses.push(choice_point.head.next_instruction_address);
+            live_addresses.extend(
+                self.environment_stack
+                    .continuations(choice_point.head.environment_address),
+            );
+        }
+
+        let reclaimable = self
+            .compiler
+            .erased_clauses()
+            .filter(|(functor, clause)| {
+                let retry_address = self.compiler.dynamic_predicate(*functor).unwrap().address + 1;
+                let erased = clause.erased.unwrap();
+                !live_addresses
+                    .iter()
+                    .any(|address| clause.code.contains(address))
+                    && !choice_points.iter().any(|choice_point| {
+                        choice_point.head.next_instruction_address == retry_address
+                            && choice_point.head.generation < erased
+                    })
+            })
+            .map(|(_, clause)| clause.id)
+            .collect::<Vec<_>>();
+        for id in reclaimable {
+            self.compiler.reclaim_clause(id);
+        }
+    }
+}
@@ -46,8 +46,26 @@ impl EnvironmentStack {
             + head.num_variables * std::mem::size_of::<Cell>();
     }
 
-    pub fn push_environment(&mut self, num_variables: usize, continuation_address: usize) {
+    /// End of the current environment, where the next one would be allocated.
+    pub fn get_top_address(&self) -> usize {
+        self.next_environment_address
+    }
+
+    /// Pushes a new environment above the current one, but never below `protected_address`.
+    pub fn push_environment(
+        &mut self,
+        num_variables: usize,
+        continuation_address: usize,
+        protected_address: usize,
+    ) {
         let head_size = std::mem::size_of::<EnvironmentHead>();
+        self.next_environment_address = self.next_environment_address.max(protected_address);
+        let required =
+            self.next_environment_address + head_size + num_variables * std::mem::size_of::<Cell>();
+        if required > self.raw_stack.len() {
+            self.raw_stack
+                .resize(required.max(self.raw_stack.len() * 2), 0);
+        }
         let next_head = unsafe {
             let raw_ptr = self.raw_stack
                 [self.next_environment_address..self.next_environment_address + head_size]
@@ -115,19 +133,42 @@ impl EnvironmentStack {
         head.continuation_address
     }
 
+    /// Continuation addresses of the environment at `address` and of all environments it returns
+    /// to.
+    pub fn continuations(&self, address: usize) -> Vec<usize> {
+        let mut continuations = Vec::new();
+        if self.last_environment_address == 0 && self.next_environment_address == 0 {
+            return continuations;
+        }
+
+        let mut current_offset = address;
+        loop {
+            let head_size = std::mem::size_of::<EnvironmentHead>();
+            let head = unsafe {
+                let raw_ptr = self.raw_stack[current_offset..current_offset + head_size].as_ptr();
+                &*raw_ptr.cast::<EnvironmentHead>()
+            };
+            continuations.push(head.continuation_address);
+
+            if current_offset == 0 || head.previous_environment_address == current_offset {
+                break;
+            }
+            current_offset = head.previous_environment_address;
+        }
+        continuations
+    }
+
+    /// Lists the environments reachable from the current one, oldest first. Environments are not
+    /// necessarily contiguous, as deallocated ones protected by a choice point are skipped.
     pub fn inspect(&self) -> Vec<InspectedEnvironment> {
         let mut environments = Vec::new();
-        let mut current_offset = 0;
+        let mut current_offset = self.last_environment_address;
 
         if self.last_environment_address == 0 && self.next_environment_address == 0 {
             return environments;
         }
 
         loop {
-            if current_offset > self.last_environment_address {
-                break;
-            }
-
             let head_size = std::mem::size_of::<EnvironmentHead>();
             let head = unsafe {
                 let raw_ptr = self.raw_stack[current_offset..current_offset + head_size].as_ptr();
@@ -151,9 +192,13 @@ impl EnvironmentStack {
                 variables: variables.to_vec(),
             });
 
-            current_offset += head_size + head.num_variables * std::mem::size_of::<Cell>();
+            if current_offset == 0 || head.previous_environment_address == current_offset {
+                break;
+            }
+            current_offset = head.previous_environment_address;
         }
 
+        environments.reverse();
         environments
     }
 }
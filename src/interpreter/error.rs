@@ -0,0 +1,107 @@
+use crate::{
+    instructions::DescriptorId, interpreter::CellAddress, number::Number, parsing::AbstractTerm,
+};
+
+/// The term an error is about, as it will appear in the second argument of the ISO error term.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorCulprit {
+    /// The term at this address, which refers to other terms once execution moves on.
+    Address(CellAddress),
+    /// A copy of the culprit, with unbound variables named `_G` followed by their address.
+    /// Raising an error replaces its other culprits by their copies.
+    Term(AbstractTerm),
+    Number(Number),
+    /// The predicate indicator `Name/Arity` of a predicate.
+    Indicator(DescriptorId),
+}
+
+/// Errors raised by builtins. They mirror the ISO error classes, e.g.
+/// `TypeError { expected: "evaluable", .. }` stands for `error(type_error(evaluable, Culprit), _)`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrologError {
+    InstantiationError,
+    /// `error(uninstantiation_error(Culprit), _)`, a term that should have been unbound.
+    UninstantiationError(ErrorCulprit),
+    TypeError {
+        expected: &'static str,
+        culprit: ErrorCulprit,
+    },
+    DomainError {
+        domain: &'static str,
+        culprit: ErrorCulprit,
+    },
+    ExistenceError {
+        kind: &'static str,
+        culprit: ErrorCulprit,
+    },
+    PermissionError {
+        action: &'static str,
+        kind: &'static str,
+        culprit: ErrorCulprit,
+    },
+    EvaluationError(&'static str),
+    RepresentationError(&'static str),
+    /// `error(resource_error(Resource), _)`, e.g. a result too large to fit in memory.
+    ResourceError(&'static str),
+    /// `error(format(Message), _)`, a `format/2` directive that does not fit its argument.
+    FormatError(String),
+    /// `error(syntax_error(Message), _)`, text read by `read_term/2` that is not a valid term.
+    SyntaxError(String),
+    /// `error(system_error(Message), _)`, a failure reported by the operating system.
+    SystemError(String),
+    /// `error(occurs_check(Variable, Term), _)`, binding `Variable` to `Term` would create a
+    /// cyclic term while the `occurs_check` flag is `error`. The culprits are boxed to keep
+    /// results with this error small.
+    OccursCheck {
+        variable: Box<ErrorCulprit>,
+        term: Box<ErrorCulprit>,
+    },
+}
+
+impl PrologError {
+    pub fn type_error(expected: &'static str, culprit: CellAddress) -> Self {
+        PrologError::TypeError {
+            expected,
+            culprit: ErrorCulprit::Address(culprit),
+        }
+    }
+
+    pub fn domain_error(domain: &'static str, culprit: CellAddress) -> Self {
+        PrologError::DomainError {
+            domain,
+            culprit: ErrorCulprit::Address(culprit),
+        }
+    }
+
+    pub fn existence_error(kind: &'static str, culprit: DescriptorId) -> Self {
+        PrologError::ExistenceError {
+            kind,
+            culprit: ErrorCulprit::Indicator(culprit),
+        }
+    }
+
+    pub fn permission_error(
+        action: &'static str,
+        kind: &'static str,
+        culprit: DescriptorId,
+    ) -> Self {
+        PrologError::PermissionError {
+            action,
+            kind,
+            culprit: ErrorCulprit::Indicator(culprit),
+        }
+    }
+
+    /// The culprits of the error, which are copied when it is raised.
+    pub(super) fn culprits_mut(&mut self) -> Vec<&mut ErrorCulprit> {
+        match self {
+            PrologError::UninstantiationError(culprit)
+            | PrologError::TypeError { culprit, .. }
+            | PrologError::DomainError { culprit, .. }
+            | PrologError::ExistenceError { culprit, .. }
+            | PrologError::PermissionError { culprit, .. } => vec![culprit],
+            PrologError::OccursCheck { variable, term } => vec![variable.as_mut(), term.as_mut()],
+            _ => Vec::new(),
+        }
+    }
+}
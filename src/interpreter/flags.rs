@@ -0,0 +1,239 @@
+use crate::{
+    instructions::DescriptorId,
+    interpreter::{
+        Cell, CellAddress, Interpreter,
+        builtins::argument,
+        error::{ErrorCulprit, PrologError},
+        terms::MAX_ARITY,
+    },
+    parsing::DoubleQuotes,
+};
+
+/// What happens when a procedure without definition is called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Unknown {
+    #[default]
+    Error,
+    Fail,
+    /// Print a warning to the standard error, then fail.
+    Warning,
+}
+
+/// Whether binding a variable checks that the variable does not occur in its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OccursCheck {
+    #[default]
+    False,
+    /// Unification fails instead of creating a cyclic term.
+    True,
+    /// Unification raises an `occurs_check` error instead of creating a cyclic term.
+    Error,
+}
+
+/// The Prolog flags of a machine. Flags which are not listed here are read-only.
+#[derive(Debug, Clone, Default)]
+pub struct Flags {
+    pub unknown: Unknown,
+    pub double_quotes: DoubleQuotes,
+    pub occurs_check: OccursCheck,
+    pub debug: bool,
+    /// Whether integer division with a remainder yields a rational instead of a float.
+    pub prefer_rationals: bool,
+}
+
+/// The value of a Prolog flag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FlagValue {
+    Atom(&'static str),
+    Integer(i64),
+}
+
+/// Why a flag cannot be set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlagError {
+    UnknownFlag,
+    ReadOnly,
+    InvalidValue,
+}
+
+impl Flags {
+    /// The names of all flags, in the order `current_prolog_flag/2` enumerates them.
+    pub const NAMES: &[&str] = &[
+        "bounded",
+        "max_integer",
+        "min_integer",
+        "max_arity",
+        "unknown",
+        "double_quotes",
+        "occurs_check",
+        "last_call_optimisation",
+        "debug",
+        "prefer_rationals",
+    ];
+
+    pub fn get(&self, name: &str) -> Option<FlagValue> {
+        let value = match name {
+            // Integers grow into big integers instead of overflowing.
+            "bounded" => FlagValue::Atom("false"),
+            "max_integer" => FlagValue::Integer(i64::MAX),
+            "min_integer" => FlagValue::Integer(i64::MIN),
+            "max_arity" => FlagValue::Integer(MAX_ARITY as i64),
+            "unknown" => FlagValue::Atom(match self.unknown {
+                Unknown::Error => "error",
+                Unknown::Fail => "fail",
+                Unknown::Warning => "warning",
+            }),
+            "double_quotes" => FlagValue::Atom(self.double_quotes.name()),
+            "occurs_check" => FlagValue::Atom(match self.occurs_check {
+                OccursCheck::False => "false",
+                OccursCheck::True => "true",
+                OccursCheck::Error => "error",
+            }),
+            // Every call keeps its environment until it returns.
+            "last_call_optimisation" => FlagValue::Atom("false"),
+            "debug" => FlagValue::Atom(if self.debug { "true" } else { "false" }),
+            "prefer_rationals" => FlagValue::Atom(if self.prefer_rationals {
+                "true"
+            } else {
+                "false"
+            }),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Sets the flag `name` to the atom `value`.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), FlagError> {
+        let invalid = FlagError::InvalidValue;
+        match name {
+            "unknown" => {
+                self.unknown = match value {
+                    "error" => Unknown::Error,
+                    "fail" => Unknown::Fail,
+                    "warning" => Unknown::Warning,
+                    _ => return Err(invalid),
+                }
+            }
+            "double_quotes" => {
+                self.double_quotes = DoubleQuotes::from_name(value).ok_or(invalid)?
+            }
+            "occurs_check" => {
+                self.occurs_check = match value {
+                    "false" => OccursCheck::False,
+                    "true" => OccursCheck::True,
+                    "error" => OccursCheck::Error,
+                    _ => return Err(invalid),
+                }
+            }
+            "debug" => {
+                self.debug = match value {
+                    "true" => true,
+                    "false" => false,
+                    _ => return Err(invalid),
+                }
+            }
+            "prefer_rationals" => {
+                self.prefer_rationals = match value {
+                    "true" => true,
+                    "false" => false,
+                    _ => return Err(invalid),
+                }
+            }
+            _ if Self::NAMES.contains(&name) => return Err(FlagError::ReadOnly),
+            _ => return Err(FlagError::UnknownFlag),
+        }
+        Ok(())
+    }
+}
+
+impl Interpreter {
+    /// `set_prolog_flag(Flag, Value)`.
+    pub(super) fn set_prolog_flag(&mut self) -> Result<bool, PrologError> {
+        let (flag, value) = (argument(0), argument(1));
+        if self.is_unbound(flag) || self.is_unbound(value) {
+            return Err(PrologError::InstantiationError);
+        }
+        let Cell::Constant(id) = *self.value(flag) else {
+            return Err(PrologError::type_error("atom", flag));
+        };
+        let name = self.compiler.descriptor_allocator.get(id).name.clone();
+        // No flag takes the empty atom, so a value which is not an atom is invalid as well.
+        let atom = self.atom_name(value).unwrap_or_default();
+        match self.flags.set(&name, &atom) {
+            Ok(()) => Ok(true),
+            Err(FlagError::UnknownFlag) => Err(PrologError::domain_error("prolog_flag", flag)),
+            Err(FlagError::ReadOnly) => Err(PrologError::PermissionError {
+                action: "modify",
+                kind: "flag",
+                culprit: ErrorCulprit::Address(flag),
+            }),
+            Err(FlagError::InvalidValue) => {
+                let plus = self
+                    .compiler
+                    .descriptor_allocator
+                    .get_or_set_functor("+", 2);
+                let arguments = [self.term_cell(flag), self.term_cell(value)];
+                let culprit = self.build_structure(plus, &arguments);
+                self.global_stack.push(culprit);
+                let culprit = CellAddress::GlobalStack {
+                    index: self.global_stack.len() - 1,
+                };
+                Err(PrologError::domain_error("flag_value", culprit))
+            }
+        }
+    }
+
+    /// `'$prolog_flags'(Flag, Pairs)`, the `Name-Value` pairs of all flags, or only the pair
+    /// of `Flag` if it is bound. `Flag` must be unbound or the name of a flag.
+    pub(super) fn prolog_flags(&mut self) -> Result<bool, PrologError> {
+        let flag = argument(0);
+        let names = if self.is_unbound(flag) {
+            Flags::NAMES
+        } else {
+            let Some(name) = self.atom_name(flag) else {
+                return Err(PrologError::type_error("atom", flag));
+            };
+            match Flags::NAMES.iter().position(|known| *known == name) {
+                Some(index) => &Flags::NAMES[index..=index],
+                None => return Err(PrologError::domain_error("prolog_flag", flag)),
+            }
+        };
+        let minus = self
+            .compiler
+            .descriptor_allocator
+            .get_or_set_functor("-", 2);
+        let pairs = names
+            .iter()
+            .map(|name| {
+                let value = match self.flags.get(name).expect("flag to exist") {
+                    FlagValue::Atom(value) => self.atom(value),
+                    FlagValue::Integer(value) => Cell::Integer(value),
+                };
+                let name = self.atom(name);
+                self.build_structure(minus, &[name, value])
+            })
+            .collect::<Vec<_>>();
+        let tail = self.empty_list();
+        let list = self.build_list(&pairs, tail);
+        Ok(self.unify_cell(argument(1), list))
+    }
+
+    /// Handles a call to `functor`, which has no definition, as the `unknown` flag says.
+    /// Returns false if the call fails instead of raising an existence error.
+    pub(super) fn unknown_procedure(&mut self, functor: DescriptorId) -> Result<bool, PrologError> {
+        let functor = self.compiler.imported_predicate(functor);
+        match self.flags.unknown {
+            Unknown::Error => Err(PrologError::existence_error("procedure", functor)),
+            Unknown::Fail => Ok(false),
+            Unknown::Warning => {
+                let name = self
+                    .compiler
+                    .descriptor_allocator
+                    .get(functor)
+                    .pretty_name();
+                self.emit_error(&format!("Warning: Unknown procedure: {}\n", name))?;
+                Ok(false)
+            }
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use num_bigint::BigInt;
+
+use crate::{
+    interpreter::{
+        Cell, CellAddress, Interpreter, builtins::argument, error::PrologError, terms::ListShape,
+    },
+    number::Number,
+    writer::WriteOptions,
+};
+
+/// Text produced by `format/2`, with the state of the column it is currently filling.
+struct FormatOutput {
+    text: String,
+    /// Byte offset where the current column segment starts.
+    segment_start: usize,
+    /// Column of the segment start.
+    segment_column: usize,
+    /// Byte offsets and characters of the `~t` fill points of the current segment.
+    fills: Vec<(usize, char)>,
+}
+
+impl FormatOutput {
+    fn new(column: usize) -> Self {
+        FormatOutput {
+            text: String::new(),
+            segment_start: 0,
+            segment_column: column,
+            fills: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, text: &str) {
+        self.text.push_str(text);
+        if let Some(newline) = self.text[self.segment_start..].rfind('\n') {
+            self.segment_start += newline + 1;
+            self.segment_column = 0;
+            self.fills.clear();
+        }
+    }
+
+    fn column(&self) -> usize {
+        self.segment_column + self.text[self.segment_start..].chars().count()
+    }
+
+    /// Ends the current column segment at `column`, padding it at its fill points. Without fill
+    /// points the text is left aligned.
+    fn column_stop(&mut self, column: usize) {
+        let padding = column.saturating_sub(self.column());
+        let mut fills = std::mem::take(&mut self.fills);
+        if fills.is_empty() {
+            fills.push((self.text.len(), ' '));
+        }
+        // Inserted back to front so the earlier offsets stay valid. Padding that does not
+        // divide evenly goes to the leftmost fill points.
+        let count = fills.len();
+        for (index, (offset, fill)) in fills.into_iter().enumerate().rev() {
+            let width = padding / count + usize::from(index < padding % count);
+            self.text
+                .insert_str(offset, &fill.to_string().repeat(width));
+        }
+        self.segment_column = self.column();
+        self.segment_start = self.text.len();
+    }
+}
+
+impl Interpreter {
+    /// `format(Format, Arguments)`.
+    pub(super) fn format_2(&mut self) -> Result<bool, PrologError> {
+        let stream = self.current_output();
+        let text = self.format_text(argument(0), argument(1), self.column(stream))?;
+        self.emit_to(stream, &text)?;
+        Ok(true)
+    }
+
+    /// `format(Output, Format, Arguments)`, where `Output` is a stream or a sink `atom(A)`,
+    /// `string(S)`, `codes(C)` or `chars(C)`.
+    pub(super) fn format_3(&mut self) -> Result<bool, PrologError> {
+        let output = argument(0);
+        if self.sink(output)?.is_some() {
+            let text = self.format_text(argument(1), argument(2), 0)?;
+            return self.unify_sink(output, &text);
+        }
+        let stream = self.checked_stream(output, "output", Some(false))?;
+        let text = self.format_text(argument(1), argument(2), self.column(stream))?;
+        self.emit_to(stream, &text)?;
+        Ok(true)
+    }
+
+    /// Runs the directives of the format at `format` on the arguments at `arguments`, a list or
+    /// a single term. `column` is the column the text starts in.
+    fn format_text(
+        &self,
+        format: CellAddress,
+        arguments: CellAddress,
+        column: usize,
+    ) -> Result<String, PrologError> {
+        if self.is_unbound(format) {
+            return Err(PrologError::InstantiationError);
+        }
+        let format = self
+            .read_text(format)
+            .ok_or(PrologError::type_error("text", format))?;
+        let mut arguments = match self.read_list(arguments) {
+            ListShape::Proper(elements) => elements,
+            _ => vec![arguments],
+        }
+        .into_iter();
+        let mut next_argument = |directive: char| {
+            arguments.next().ok_or_else(|| {
+                PrologError::FormatError(format!("not enough arguments for ~{}", directive))
+            })
+        };
+
+        let mut output = FormatOutput::new(column);
+        let mut last_stop = column;
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '~' {
+                output.push(c.encode_utf8(&mut [0; 4]));
+                continue;
+            }
+
+            // The numeric argument: digits, `*` taking it from the arguments, or a backquote
+            // followed by a character standing for its code.
+            let numeric = match chars.peek() {
+                Some('*') => {
+                    chars.next();
+                    let value = next_argument('*')?;
+                    match self.read_number(self.value(value)) {
+                        Some(Number::Integer(value)) if value >= 0 => Some(value as usize),
+                        _ => {
+                            return Err(PrologError::FormatError(
+                                "~* expects a non-negative integer argument".to_string(),
+                            ));
+                        }
+                    }
+                }
+                Some('`') => {
+                    chars.next();
+                    chars.next().map(|c| c as usize)
+                }
+                _ => {
+                    let mut digits = String::new();
+                    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                        digits.push(digit);
+                    }
+                    digits.parse().ok()
+                }
+            };
+
+            let Some(directive) = chars.next() else {
+                return Err(PrologError::FormatError(
+                    "truncated format directive".to_string(),
+                ));
+            };
+            match directive {
+                '~' => output.push("~"),
+                'w' | 'p' | 'q' => {
+                    let options = match directive {
+                        'w' => WriteOptions::write(),
+                        _ => WriteOptions::writeq(),
+                    };
+                    output.push(&self.format(next_argument(directive)?, &options));
+                }
+                'a' => {
+                    let value = next_argument(directive)?;
+                    match self.value(value) {
+                        Cell::Constant(id) => {
+                            output.push(&self.compiler.descriptor_allocator.get(*id).name)
+                        }
+                        cell => match self.read_number(cell) {
+                            Some(number) => output.push(&number.to_string()),
+                            None => return Err(argument_error(directive, "an atomic")),
+                        },
+                    }
+                }
+                'd' | 'D' => {
+                    let value = next_argument(directive)?;
+                    let value = self
+                        .read_number(self.value(value))
+                        .and_then(|number| number.to_bigint())
+                        .ok_or_else(|| argument_error(directive, "an integer"))?;
+                    output.push(&format_integer(
+                        &value,
+                        numeric.unwrap_or(0),
+                        directive == 'D',
+                    ));
+                }
+                'f' | 'e' => {
+                    let value = next_argument(directive)?;
+                    let number = self
+                        .read_number(self.value(value))
+                        .ok_or_else(|| argument_error(directive, "a numeric"))?;
+                    let digits = numeric.unwrap_or(6);
+                    let text = match (directive, number.to_bigint()) {
+                        ('f', Some(integer)) if number.is_integer() => match digits {
+                            0 => integer.to_string(),
+                            _ => format!("{}.{}", integer, "0".repeat(digits)),
+                        },
+                        ('f', _) => format!("{:.*}", digits, number.to_f64()),
+                        _ => format_exponential(number.to_f64(), digits),
+                    };
+                    output.push(&text);
+                }
+                's' => {
+                    let value = next_argument(directive)?;
+                    let text = match self.read_list(value) {
+                        ListShape::Proper(_) => self.read_text(value),
+                        _ => None,
+                    }
+                    .ok_or_else(|| argument_error(directive, "a string"))?;
+                    output.push(&text);
+                }
+                'c' => {
+                    let value = next_argument(directive)?;
+                    let c = match self.value(value) {
+                        Cell::Integer(code) => u32::try_from(*code).ok().and_then(char::from_u32),
+                        _ => None,
+                    }
+                    .ok_or_else(|| argument_error(directive, "a character code"))?;
+                    output.push(&c.to_string().repeat(numeric.unwrap_or(1)));
+                }
+                'r' | 'R' => {
+                    let value = next_argument(directive)?;
+                    let value = self
+                        .read_number(self.value(value))
+                        .and_then(|number| number.to_bigint())
+                        .ok_or_else(|| argument_error(directive, "an integer"))?;
+                    let radix = numeric
+                        .filter(|radix| (2..=36).contains(radix))
+                        .ok_or_else(|| {
+                            PrologError::FormatError(format!(
+                                "~{} expects a radix between 2 and 36",
+                                directive
+                            ))
+                        })?;
+                    let text = value.to_str_radix(radix as u32);
+                    output.push(&match directive {
+                        'R' => text.to_uppercase(),
+                        _ => text,
+                    });
+                }
+                'n' => output.push(&"\n".repeat(numeric.unwrap_or(1))),
+                'i' => {
+                    next_argument(directive)?;
+                }
+                't' => {
+                    let fill = numeric.and_then(|code| char::from_u32(code as u32));
+                    output.fills.push((output.text.len(), fill.unwrap_or(' ')));
+                }
+                '|' | '+' => {
+                    let column = match (directive, numeric) {
+                        ('|', Some(column)) => column,
+                        ('|', None) => output.column(),
+                        (_, width) => last_stop + width.unwrap_or(8),
+                    };
+                    output.column_stop(column);
+                    last_stop = column;
+                }
+                _ => {
+                    return Err(PrologError::FormatError(format!(
+                        "unknown directive ~{}",
+                        directive
+                    )));
+                }
+            }
+        }
+
+        if arguments.next().is_some() {
+            return Err(PrologError::FormatError("too many arguments".to_string()));
+        }
+        Ok(output.text)
+    }
+}
+
+fn argument_error(directive: char, expected: &str) -> PrologError {
+    PrologError::FormatError(format!("~{} expects {} argument", directive, expected))
+}
+
+/// Writes an integer for `~d` and `~D`, with a decimal point inserted `decimals` digits from
+/// the right and, for `~D`, the digits before it grouped by three.
+fn format_integer(value: &BigInt, decimals: usize, group: bool) -> String {
+    let digits = value.magnitude().to_string();
+    let digits = format!("{:0>width$}", digits, width = decimals + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - decimals);
+    let integer = if group {
+        let mut grouped = String::new();
+        for (index, digit) in integer.chars().enumerate() {
+            if index > 0 && (integer.len() - index) % 3 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(digit);
+        }
+        grouped
+    } else {
+        integer.to_string()
+    };
+    let sign = if value.sign() == num_bigint::Sign::Minus {
+        "-"
+    } else {
+        ""
+    };
+    match decimals {
+        0 => format!("{}{}", sign, integer),
+        _ => format!("{}{}.{}", sign, integer, fraction),
+    }
+}
+
+/// Writes a float like C's `%e`, e.g. `1.500000e+03`.
+fn format_exponential(value: f64, digits: usize) -> String {
+    let text = format!("{:.*e}", digits, value);
+    let (mantissa, exponent) = text.split_once('e').expect("exponential notation");
+    let (sign, exponent) = match exponent.strip_prefix('-') {
+        Some(exponent) => ('-', exponent),
+        None => ('+', exponent),
+    };
+    format!("{}e{}{:0>2}", mantissa, sign, exponent)
+}
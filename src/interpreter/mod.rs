@@ -1,25 +1,59 @@
 use std::{
-    collections::{HashMap, VecDeque},
+    cmp::Ordering,
+    collections::{HashMap, HashSet, VecDeque},
     ops::Range,
 };
 
-use pest::Stack;
+use num_bigint::{BigInt, Sign};
 
 use crate::{
-    descriptor::TermDescriptor,
-    instructions::{DescriptorId, Instruction, RegisterId},
-    interpreter::{choicepoint::ChoicePointStack, environment::EnvironmentStack},
+    compiler::{ClauseKey, CompileArtifact, Compiler},
+    descriptor::DescriptorAllocator,
+    instructions::{DescriptorId, Instruction, RegisterId, UNRESOLVED_ADDRESS},
+    interpreter::{
+        choicepoint::{ChoicePointStack, SavedState},
+        consult::LoadContext,
+        environment::EnvironmentStack,
+        solutions::StoredTerm,
+        streams::StreamTable,
+        terms::ListShape,
+    },
+    number::Number,
+    parsing::AbstractTerm,
+    writer::{WriteOptions, format_term},
 };
 
+pub use error::{ErrorCulprit, PrologError};
+pub use flags::{FlagError, FlagValue, Flags, OccursCheck, Unknown};
+pub use output::TermDisplay;
+
+mod arithmetic;
+mod attributes;
+mod builtins;
 mod choicepoint;
+mod consult;
+mod control;
+mod database;
 mod environment;
+mod error;
+mod flags;
+mod format;
+mod order;
+mod output;
+mod reader;
+mod reflection;
+mod solutions;
+mod streams;
+mod terms;
+mod text;
 
 #[derive(Clone, Debug)]
 pub struct Interpreter {
     pub global_stack: Vec<Cell>,
     pub registers: Vec<Cell>,
-    pub trail: Vec<CellAddress>,
-    instructions: Vec<Instruction>,
+    pub trail: Vec<TrailEntry>,
+    /// The compiler of the running program, used to look up and compile predicates at run time.
+    compiler: Compiler,
     pub instruction_index: usize,
     pub mode: Mode,
     pub next_sub_term_address: usize,
@@ -28,9 +62,33 @@ pub struct Interpreter {
     pub choice_point_stack: ChoicePointStack,
     pub proceed_return_address: usize,
     pub current_functor: DescriptorId,
-    inspection_watch: Vec<WatchCell>,
-    inspection_set: bool,
-    descriptors: Vec<TermDescriptor>,
+    /// Top of the choice point stack when the current predicate was called. A cut in its clauses
+    /// removes every choice point above it.
+    pub cut_barrier: usize,
+    /// Top of the choice point stack when the query started. The choice points above it are
+    /// the alternatives of the query.
+    query_choice_point: usize,
+    pub exception: Option<PrologError>,
+    /// The open streams, including the standard input and output.
+    streams: StreamTable,
+    /// The Prolog flags, set by `set_prolog_flag/2`.
+    pub flags: Flags,
+    /// Set while probing unifiability, so every binding is trailed and can be undone.
+    trail_all_bindings: bool,
+    /// The variable bound by the last `GetStructure` in write mode and the index of the
+    /// structure cell it was bound to.
+    built_structure: Option<(CellAddress, usize)>,
+    /// The attributes of the attributed variables bound since the last goal and the values
+    /// they were bound to. Their hooks run before the next goal.
+    wakeups: Vec<(Cell, Cell)>,
+    /// The named variables of the query, the n-th one living in the n-th global stack cell.
+    query_variables: Vec<DescriptorId>,
+    /// Solutions collected by the currently running `findall/4` calls, innermost last.
+    solutions: Vec<Vec<StoredTerm>>,
+    /// Predicates compiled for goals executed by `call/1`, keyed by the shape of the goal.
+    goal_cache: HashMap<String, DescriptorId>,
+    /// The files being loaded by `consult/1`, innermost last.
+    load_contexts: Vec<LoadContext>,
 }
 
 #[derive(Clone, Debug)]
@@ -43,6 +101,7 @@ pub enum Mode {
 pub enum ExecutionState {
     Normal,
     Failure,
+    Exception,
 }
 
 #[derive(Copy, Debug, Clone, PartialEq, Eq)]
@@ -52,13 +111,6 @@ pub enum CellAddress {
 }
 
 impl CellAddress {
-    fn is_register(&self) -> bool {
-        match self {
-            CellAddress::Register { .. } => true,
-            CellAddress::GlobalStack { .. } => false,
-        }
-    }
-
     fn index_num(&self) -> usize {
         match self {
             CellAddress::Register { index } => match index {
@@ -71,12 +123,35 @@ impl CellAddress {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Cell {
     StructureRef(usize),
     Structure(DescriptorId),
     Constant(DescriptorId),
     Reference(usize),
+    Integer(i64),
+    Float(f64),
+    /// Points to a boxed big integer on the global stack.
+    BigIntegerRef(usize),
+    /// Header of a boxed big integer, followed by `limbs` limb cells, least significant first.
+    BigInteger {
+        negative: bool,
+        limbs: usize,
+    },
+    Limb(u64),
+    /// Points to two consecutive integer cells on the global stack, numerator and denominator.
+    RationalRef(usize),
+    /// Points to a boxed string on the global stack.
+    StringRef(usize),
+    /// Header of a boxed string of `length` bytes of UTF-8, followed by limb cells holding eight
+    /// bytes each.
+    String {
+        length: usize,
+    },
+    /// An unbound variable with attributes, which points to itself like an unbound
+    /// [`Cell::Reference`]. The cell after it holds the attributes, a chain of
+    /// `att(Module, Value, More)` ending in `[]`.
+    AttributedVariable(usize),
     Undefined,
 }
 
@@ -84,53 +159,70 @@ impl Cell {
     pub fn heap_address(&self) -> CellAddress {
         match self {
             Cell::StructureRef(index) => CellAddress::GlobalStack { index: *index },
-            Cell::Reference(index) => CellAddress::GlobalStack { index: *index },
+            Cell::Reference(index) | Cell::AttributedVariable(index) => {
+                CellAddress::GlobalStack { index: *index }
+            }
             _ => panic!("Unexpected call on heap address"),
         }
     }
+
+    /// Whether the cell is a variable, with or without attributes. Only the cell a variable
+    /// dereferences to tells whether it is unbound.
+    pub fn is_variable(&self) -> bool {
+        matches!(self, Cell::Reference(_) | Cell::AttributedVariable(_))
+    }
 }
 
-#[derive(Clone, Debug)]
-struct WatchCell {
-    address: CellAddress,
-    descriptor_id: DescriptorId,
+/// A change to the global stack, undone when backtracking.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrailEntry {
+    pub index: usize,
+    /// The cell before it was changed.
+    pub value: Cell,
 }
 
 impl Interpreter {
-    pub fn new(
-        instructions: Vec<Instruction>,
-        start_instruction_index: usize,
-        registers: usize,
-        descriptors: Vec<TermDescriptor>,
-        variables_to_watch: &[InspectionVariable],
-    ) -> Self {
+    pub fn new(compiler: Compiler, artifact: &CompileArtifact) -> Self {
+        let start_instruction_index = artifact.start_instruction_index;
+        let choice_point_stack = ChoicePointStack::new();
         Self {
             global_stack: Vec::with_capacity(1024),
             environment_stack: EnvironmentStack::new(),
             trail: Vec::with_capacity(1024),
-            choice_point_stack: ChoicePointStack::new(),
-            registers: vec![Cell::Undefined; registers],
+            query_choice_point: choice_point_stack.get_top_address(),
+            choice_point_stack,
+            registers: vec![Cell::Undefined; artifact.max_registers],
             instruction_index: start_instruction_index,
             current_functor: DescriptorId(0),
+            cut_barrier: 0,
             proceed_return_address: start_instruction_index,
             execution_state: ExecutionState::Normal,
+            exception: None,
+            streams: StreamTable::default(),
+            flags: Flags::default(),
+            trail_all_bindings: false,
+            built_structure: None,
+            wakeups: Vec::new(),
             mode: Mode::Write,
             next_sub_term_address: 0,
-            inspection_watch: variables_to_watch
-                .iter()
-                .map(|var| WatchCell {
-                    descriptor_id: var.variable,
-                    address: CellAddress::Register {
-                        index: var.register,
-                    },
-                })
-                .collect(),
-            descriptors,
-            instructions,
-            inspection_set: false,
+            query_variables: artifact.query_variables.clone(),
+            compiler,
+            solutions: Vec::new(),
+            goal_cache: HashMap::new(),
+            load_contexts: Vec::new(),
         }
     }
 
+    /// The descriptors known to the program, including functors created while running it.
+    pub fn descriptors(&self) -> &DescriptorAllocator {
+        &self.compiler.descriptor_allocator
+    }
+
+    /// The instructions of the program, including the ones compiled while running it.
+    pub fn instructions(&self) -> &[Instruction] {
+        self.compiler.instructions()
+    }
+
     fn lookup_register(&self, register: &RegisterId) -> &Cell {
         match register {
             RegisterId::Argument(index) => &self.registers[*index],
@@ -182,89 +274,118 @@ impl Interpreter {
         }
     }
 
+    /// Records the cell at `address` before it is changed, if backtracking needs to restore it.
     fn try_trail(&mut self, address: CellAddress) {
-        if self.choice_point_stack.is_empty() {
+        let CellAddress::GlobalStack { index } = address else {
             return;
-        }
-        let choice_point_stack_address = self.choice_point_stack.get_stack_address();
-
-        match address {
-            CellAddress::GlobalStack { index } if index < choice_point_stack_address => {
-                self.trail.push(address.clone());
-            }
-            _ => {}
+        };
+        let older_than_choice_point = !self.choice_point_stack.is_empty()
+            && index < self.choice_point_stack.get_stack_address();
+        if self.trail_all_bindings || older_than_choice_point {
+            let value = self.global_stack[index].clone();
+            self.trail.push(TrailEntry { index, value });
         }
     }
 
+    /// Binds the unbound variable among `a` and `b` to the other term. Of two variables, one
+    /// without attributes is bound in preference, otherwise the older one.
     fn bind_address(&mut self, a: CellAddress, b: CellAddress) {
         let a_value = self.lookup_address(a);
         let b_value = self.lookup_address(b);
 
-        let mut target = a;
-        let mut value = Cell::Undefined;
-
-        match (a, b) {
-            (CellAddress::Register { .. }, _) => {
-                target = b;
-                value = a_value.clone();
-            }
-            (_, CellAddress::Register { .. }) => {
-                target = a;
-                value = b_value.clone();
-            }
+        let (target, value) = match (a, b) {
+            (CellAddress::Register { .. }, _) => (b, a_value.clone()),
+            (_, CellAddress::Register { .. }) => (a, b_value.clone()),
             _ => match (a_value, b_value) {
-                (Cell::Reference(_), Cell::Reference(_)) if a.index_num() > b.index_num() => {
-                    target = b;
-                    value = Cell::Reference(a.index_num());
-                }
-                (Cell::Reference(_), Cell::Reference(_)) => {
-                    target = a;
-                    value = Cell::Reference(b.index_num());
+                (Cell::Reference(_), Cell::Reference(_))
+                | (Cell::AttributedVariable(_), Cell::AttributedVariable(_))
+                    if a.index_num() > b.index_num() =>
+                {
+                    (b, Cell::Reference(a.index_num()))
                 }
-                (Cell::Reference(_), _) => {
-                    target = a;
-                    value = Cell::Reference(b.index_num());
-                }
-                (_, Cell::Reference(_)) => {
-                    target = b;
-                    value = Cell::Reference(a.index_num());
-                }
-                _ => {}
+                (Cell::Reference(_), _) => (a, Cell::Reference(b.index_num())),
+                (_, Cell::Reference(_)) => (b, Cell::Reference(a.index_num())),
+                (Cell::AttributedVariable(_), _) => (a, Cell::Reference(b.index_num())),
+                (_, Cell::AttributedVariable(_)) => (b, Cell::Reference(a.index_num())),
+                _ => (a, Cell::Undefined),
             },
-        }
+        };
+        self.bind_variable(target, value);
+    }
 
-        self.try_trail(target);
-        let target = self.lookup_address_mut(target);
-        *target = value;
+    /// Binds the unbound variable at `address` to `value`. Binding an attributed variable wakes
+    /// the hooks of its attributes, which run before the next goal.
+    fn bind_variable(&mut self, address: CellAddress, value: Cell) {
+        if let Cell::AttributedVariable(index) = *self.lookup_address(address) {
+            let attributes = self.global_stack[index + 1].clone();
+            self.wakeups.push((attributes, value.clone()));
+        }
+        self.try_trail(address);
+        *self.lookup_address_mut(address) = value;
     }
 
+    /// Follows the chain of references starting at `address` to the cell they lead to.
     fn deref_cell(&self, address: CellAddress) -> CellAddress {
-        let value = self.lookup_address(address);
-        let index = address.index_num();
-        match value {
-            Cell::Reference(child_address) if *child_address != index || address.is_register() => {
-                self.deref_cell(CellAddress::GlobalStack {
-                    index: *child_address,
-                })
-            }
-            _ => address,
-        }
+        self.deref_cell_safe(address)
+            .expect("references to point into the global stack")
     }
 
     fn deref_cell_safe(&self, address: CellAddress) -> Option<CellAddress> {
-        let value = self.lookup_address_safe(address)?;
-        let index = address.index_num();
-        match value {
-            Cell::Reference(child_address) if *child_address != index || address.is_register() => {
-                self.deref_cell_safe(CellAddress::GlobalStack {
-                    index: *child_address,
-                })
+        let mut address = address;
+        loop {
+            match (address, self.lookup_address_safe(address)?) {
+                (
+                    CellAddress::Register { .. },
+                    Cell::Reference(child_address) | Cell::AttributedVariable(child_address),
+                ) => {
+                    address = CellAddress::GlobalStack {
+                        index: *child_address,
+                    };
+                }
+                (
+                    CellAddress::GlobalStack { index },
+                    Cell::Reference(child_address) | Cell::AttributedVariable(child_address),
+                ) if *child_address != index => {
+                    address = CellAddress::GlobalStack {
+                        index: *child_address,
+                    };
+                }
+                _ => return Some(address),
             }
-            _ => Some(address),
         }
     }
 
-    fn unify(&mut self, a: CellAddress, b: CellAddress) {
+    /// Unifies `a` and `b`, checking for cyclic bindings as the `occurs_check` flag says.
+    fn unify(&mut self, a: CellAddress, b: CellAddress) -> bool {
+        self.unify_terms(a, b, self.flags.occurs_check)
+    }
+
+    /// Checks whether `a` and `b` unify, without leaving any bindings behind.
+    fn unifiable(&mut self, a: CellAddress, b: CellAddress, occurs_check: bool) -> bool {
+        let global_stack_top = self.global_stack.len();
+        let trail_top = self.trail.len();
+        let wakeups_top = self.wakeups.len();
+
+        self.trail_all_bindings = true;
+        let occurs_check = match occurs_check {
+            true => OccursCheck::True,
+            false => OccursCheck::False,
+        };
+        let unified = self.unify_terms(a, b, occurs_check);
+        self.trail_all_bindings = false;
+
+        self.unwind_trail(trail_top..self.trail.len());
+        self.trail.truncate(trail_top);
+        self.global_stack.truncate(global_stack_top);
+        self.wakeups.truncate(wakeups_top);
+        unified
+    }
+
+    /// Unifies `a` and `b`. Unless `occurs_check` is false, a variable is never bound to a term
+    /// containing it: unification fails, or raises an `occurs_check` error. Pairs of structures
+    /// are only unified once, so this terminates on cyclic terms.
+    fn unify_terms(&mut self, a: CellAddress, b: CellAddress, occurs_check: OccursCheck) -> bool {
+        let mut unified_structures = HashSet::new();
         let mut working_stack = VecDeque::new();
         working_stack.push_back(a);
         working_stack.push_back(b);
@@ -280,57 +401,288 @@ impl Interpreter {
             let b = self.lookup_address(b_address);
 
             match (a, b) {
-                (Cell::Reference(_), _) | (_, Cell::Reference(_)) => {
+                (a, b) if a.is_variable() || b.is_variable() => {
+                    if occurs_check != OccursCheck::False
+                        && self.occurs_in_binding(a_address, b_address)
+                    {
+                        if occurs_check == OccursCheck::Error {
+                            let (variable, term) = match self.lookup_address(a_address) {
+                                cell if cell.is_variable() => (a_address, b_address),
+                                _ => (b_address, a_address),
+                            };
+                            self.raise(PrologError::OccursCheck {
+                                variable: Box::new(ErrorCulprit::Address(variable)),
+                                term: Box::new(ErrorCulprit::Address(term)),
+                            });
+                        }
+                        return false;
+                    }
                     self.bind_address(a_address, b_address);
                 }
                 (Cell::Constant(a), Cell::Constant(b)) => {
                     if a != b {
-                        self.backtrack();
-                        break;
+                        return false;
                     }
                 }
                 (Cell::StructureRef(a_ref), Cell::StructureRef(b_ref)) => {
+                    if !unified_structures.insert((*a_ref, *b_ref)) {
+                        continue;
+                    }
                     let structure_a =
                         self.lookup_address(CellAddress::GlobalStack { index: *a_ref });
                     let structure_b =
                         self.lookup_address(CellAddress::GlobalStack { index: *b_ref });
 
                     match (structure_a, structure_b) {
-                        (Cell::Structure(structure_a), Cell::Structure(structure_b)) => {
-                            if *structure_a == *structure_b {
-                                let functor_description = &self.descriptors[structure_a.0];
-                                for i in 1..=functor_description.arity() {
-                                    working_stack
-                                        .push_back(CellAddress::GlobalStack { index: a_ref + i });
-                                    working_stack
-                                        .push_back(CellAddress::GlobalStack { index: b_ref + i });
-                                }
-                                continue;
+                        (Cell::Structure(structure_a), Cell::Structure(structure_b))
+                            if *structure_a == *structure_b =>
+                        {
+                            let functor_description =
+                                self.compiler.descriptor_allocator.get(*structure_a);
+                            for i in 1..=functor_description.arity() {
+                                working_stack
+                                    .push_back(CellAddress::GlobalStack { index: a_ref + i });
+                                working_stack
+                                    .push_back(CellAddress::GlobalStack { index: b_ref + i });
                             }
                         }
-                        _ => {}
+                        _ => return false,
                     }
-
-                    self.backtrack();
-                    break;
                 }
-                _ => {
-                    self.backtrack();
-                    break;
+                (Cell::StringRef(_), Cell::StringRef(_)) => {
+                    if self.read_string(a) != self.read_string(b) {
+                        return false;
+                    }
                 }
+                (a, b) => match (self.read_number(a), self.read_number(b)) {
+                    (Some(a), Some(b)) if a == b => {}
+                    _ => return false,
+                },
+            }
+        }
+
+        true
+    }
+
+    /// Whether binding the variable among `a` and `b` to the other term would create a cycle.
+    fn occurs_in_binding(&self, a: CellAddress, b: CellAddress) -> bool {
+        let (variable, term) = match self.lookup_address(a) {
+            cell if cell.is_variable() => (a, b),
+            _ => (b, a),
+        };
+        self.find_subterm(term, |address| address == variable)
+            .is_some_and(|address| address != term)
+    }
+
+    /// The first dereferenced subterm of `term` for which `matches` holds. Subterms of a match
+    /// are not visited, and shared or cyclic structures only once.
+    fn find_subterm(
+        &self,
+        term: CellAddress,
+        matches: impl Fn(CellAddress) -> bool,
+    ) -> Option<CellAddress> {
+        let mut visited = HashSet::new();
+        let mut pending = vec![term];
+        while let Some(address) = pending.pop() {
+            let address = self.deref_cell(address);
+            if matches(address) {
+                return Some(address);
+            }
+            if let Cell::StructureRef(index) = self.lookup_address(address)
+                && visited.insert(*index)
+            {
+                let Cell::Structure(functor) = self.global_stack[*index] else {
+                    unreachable!("structure references always point to a structure");
+                };
+                let arity = self.compiler.descriptor_allocator.get(functor).arity();
+                pending.extend((1..=arity).map(|i| CellAddress::GlobalStack { index: index + i }));
+            }
+        }
+        None
+    }
+
+    /// Whether the term in `register` can become an argument of the structure being built in
+    /// write mode. Head unification only checks this for repeated variables, since the first
+    /// occurrence of a variable is always fresh. The structure is already bound, so storing a
+    /// term which contains it would make it cyclic.
+    fn acyclic_argument(&mut self, register: RegisterId) -> bool {
+        let Some((variable, structure)) = self.built_structure else {
+            return true;
+        };
+        let term = CellAddress::Register { index: register };
+        let cyclic = self
+            .find_subterm(term, |address| {
+                self.lookup_address(address) == &Cell::StructureRef(structure)
+            })
+            .is_some();
+        if !cyclic {
+            return true;
+        }
+        if self.flags.occurs_check == OccursCheck::Error {
+            // Undo the binding and complete the structure, so the error shows the equation which
+            // has no finite solution.
+            *self.lookup_address_mut(variable) = Cell::Reference(variable.index_num());
+            let value = self.lookup_address(term).clone();
+            self.global_stack.push(value);
+            let Cell::Structure(functor) = self.global_stack[structure] else {
+                unreachable!("structure references always point to a structure");
+            };
+            let arity = self.compiler.descriptor_allocator.get(functor).arity();
+            while self.global_stack.len() <= structure + arity {
+                self.global_stack
+                    .push(Cell::Reference(self.global_stack.len()));
             }
+            self.raise(PrologError::OccursCheck {
+                variable: Box::new(ErrorCulprit::Address(variable)),
+                term: Box::new(ErrorCulprit::Address(CellAddress::GlobalStack {
+                    index: structure - 1,
+                })),
+            });
         }
+        false
     }
 
+    /// Reads the number stored in `cell`, following the box of big integers and rationals.
+    pub(crate) fn read_number(&self, cell: &Cell) -> Option<Number> {
+        match cell {
+            Cell::Integer(value) => Some(Number::Integer(*value)),
+            Cell::Float(value) => Some(Number::Float(*value)),
+            Cell::BigIntegerRef(address) => {
+                let Cell::BigInteger { negative, limbs } = self.global_stack[*address] else {
+                    unreachable!("big integer references always point to a big integer header");
+                };
+                let bytes = self.global_stack[address + 1..=address + limbs]
+                    .iter()
+                    .flat_map(|limb| match limb {
+                        Cell::Limb(limb) => limb.to_le_bytes(),
+                        _ => unreachable!("big integer headers are followed by limbs"),
+                    })
+                    .collect::<Vec<_>>();
+                let sign = if negative { Sign::Minus } else { Sign::Plus };
+                Some(Number::BigInteger(BigInt::from_bytes_le(sign, &bytes)))
+            }
+            Cell::RationalRef(address) => {
+                let numerator = self.read_number(&self.global_stack[*address])?;
+                let denominator = self.read_number(&self.global_stack[address + 1])?;
+                Some(Number::Rational(num_rational::BigRational::new_raw(
+                    numerator.to_bigint()?,
+                    denominator.to_bigint()?,
+                )))
+            }
+            _ => None,
+        }
+    }
+
+    /// Creates the cell representing `number`. Big integers and rationals are boxed on the global
+    /// stack, so the returned cell only points to them.
+    pub(crate) fn allocate_number(&mut self, number: &Number) -> Cell {
+        match number {
+            Number::Integer(value) => Cell::Integer(*value),
+            Number::Float(value) => Cell::Float(*value),
+            Number::BigInteger(value) => {
+                let (sign, digits) = value.to_u64_digits();
+                let address = self.global_stack.len();
+                self.global_stack.push(Cell::BigInteger {
+                    negative: sign == Sign::Minus,
+                    limbs: digits.len(),
+                });
+                self.global_stack.extend(digits.into_iter().map(Cell::Limb));
+                Cell::BigIntegerRef(address)
+            }
+            Number::Rational(value) => {
+                let numerator = self.allocate_number(&Number::from_bigint(value.numer().clone()));
+                let denominator = self.allocate_number(&Number::from_bigint(value.denom().clone()));
+                let address = self.global_stack.len();
+                self.global_stack.push(numerator);
+                self.global_stack.push(denominator);
+                Cell::RationalRef(address)
+            }
+        }
+    }
+
+    /// Reads the string `cell` points to, `None` if it is no string.
+    pub(crate) fn read_string(&self, cell: &Cell) -> Option<String> {
+        let Cell::StringRef(address) = cell else {
+            return None;
+        };
+        let Cell::String { length } = self.global_stack[*address] else {
+            unreachable!("string references always point to a string header");
+        };
+        let mut bytes = self.global_stack[address + 1..=address + length.div_ceil(8)]
+            .iter()
+            .flat_map(|limb| match limb {
+                Cell::Limb(limb) => limb.to_le_bytes(),
+                _ => unreachable!("string headers are followed by limbs"),
+            })
+            .collect::<Vec<_>>();
+        bytes.truncate(length);
+        Some(String::from_utf8(bytes).expect("strings to be valid UTF-8"))
+    }
+
+    /// Creates the cell representing the string `text`, which is boxed on the global stack.
+    pub(crate) fn allocate_string(&mut self, text: &str) -> Cell {
+        let address = self.global_stack.len();
+        self.global_stack.push(Cell::String { length: text.len() });
+        self.global_stack
+            .extend(text.as_bytes().chunks(8).map(|chunk| {
+                let mut limb = [0; 8];
+                limb[..chunk.len()].copy_from_slice(chunk);
+                Cell::Limb(u64::from_le_bytes(limb))
+            }));
+        Cell::StringRef(address)
+    }
+
+    /// Binds the term at `address` to the string `text`, or checks that it already is that
+    /// string.
+    fn unify_string(&mut self, address: CellAddress, text: &str) -> bool {
+        let address = self.deref_cell(address);
+        match self.lookup_address(address) {
+            cell if cell.is_variable() => {
+                let cell = self.allocate_string(text);
+                self.bind_variable(address, cell);
+                true
+            }
+            cell => self.read_string(cell).is_some_and(|string| string == text),
+        }
+    }
+
+    /// Pushes a number as the next structure argument. The compiler loads boxed numbers into a
+    /// register beforehand, so only numbers fitting into a single cell end up here.
+    fn push_number_argument(&mut self, number: &Number) {
+        debug_assert!(!number.is_boxed());
+        let cell = self.allocate_number(number);
+        self.global_stack.push(cell);
+    }
+
+    /// Binds the term at `address` to `number`, or checks that it already is that number.
+    fn unify_number(&mut self, address: CellAddress, number: &Number) -> bool {
+        let address = self.deref_cell(address);
+        match self.lookup_address(address) {
+            cell if cell.is_variable() => {
+                let cell = self.allocate_number(number);
+                self.bind_variable(address, cell);
+                true
+            }
+            cell => self.read_number(cell).as_ref() == Some(number),
+        }
+    }
+
+    /// Restores the cells changed by the trail entries in `range`, newest first.
     fn unwind_trail(&mut self, range: Range<usize>) {
-        for i in range {
-            let stack_address = self.trail[i];
-            let stack_address_raw = stack_address.index_num();
-            self.global_stack[stack_address_raw] = Cell::Reference(stack_address_raw);
+        for i in range.rev() {
+            let TrailEntry { index, value } = self.trail[i].clone();
+            self.global_stack[index] = value;
         }
     }
 
     fn backtrack(&mut self) {
+        // Unification may have raised an error before failing.
+        if self.execution_state == ExecutionState::Exception {
+            return;
+        }
+        // The bindings which woke them are undone.
+        self.wakeups.clear();
+        self.choice_point_stack.pop_removed();
         if self.choice_point_stack.is_empty() {
             self.execution_state = ExecutionState::Failure;
             return;
@@ -341,7 +693,7 @@ impl Interpreter {
     }
 
     pub fn try_backtrack(&mut self) -> bool {
-        if self.choice_point_stack.is_empty() || self.execution_state == ExecutionState::Failure {
+        if !self.has_alternatives() {
             return false;
         }
 
@@ -349,15 +701,40 @@ impl Interpreter {
         true
     }
 
+    /// Whether the query may have solutions after the current one, because choice points it
+    /// created remain. If not, the solution is deterministic and `try_backtrack` fails.
+    pub fn has_alternatives(&self) -> bool {
+        self.execution_state == ExecutionState::Normal
+            && self.choice_point_stack.get_top_address() > self.query_choice_point
+    }
+
+    fn raise(&mut self, mut error: PrologError) {
+        for culprit in error.culprits_mut() {
+            let term = match culprit {
+                ErrorCulprit::Address(address) => self
+                    .inspect_variable(*address)
+                    .to_term(&self.compiler.descriptor_allocator),
+                ErrorCulprit::Number(number) => AbstractTerm::Number(number.clone()),
+                ErrorCulprit::Indicator(functor) => {
+                    self.compiler.descriptor_allocator.get(*functor).indicator()
+                }
+                ErrorCulprit::Term(_) => continue,
+            };
+            *culprit = ErrorCulprit::Term(term);
+        }
+        self.exception = Some(error);
+        self.execution_state = ExecutionState::Exception;
+    }
+
     pub fn step(&mut self) -> bool {
-        if self.execution_state == ExecutionState::Failure {
+        if self.execution_state != ExecutionState::Normal {
             return false;
         }
-        if self.instruction_index == self.instructions.len() {
+        if self.instruction_index == self.compiler.instructions().len() {
             return false;
         }
         // TODO: Fix unneeded clone
-        let instruction = &self.instructions[self.instruction_index];
+        let instruction = &self.compiler.instructions()[self.instruction_index];
         self.instruction_index += 1;
 
         match instruction {
@@ -384,6 +761,28 @@ impl Interpreter {
             Instruction::SetConstant { constant } => {
                 self.global_stack.push(Cell::Constant(*constant));
             }
+            Instruction::SetNumber { number } => {
+                let number = number.clone();
+                self.push_number_argument(&number);
+            }
+            Instruction::PutNumber { number, register } => {
+                let register = *register;
+                let cell = self.allocate_number(&number.clone());
+                *Self::lookup_register_mut(
+                    &mut self.environment_stack,
+                    &mut self.registers,
+                    register,
+                ) = cell;
+            }
+            Instruction::PutString { string, register } => {
+                let register = *register;
+                let cell = self.allocate_string(&string.clone());
+                *Self::lookup_register_mut(
+                    &mut self.environment_stack,
+                    &mut self.registers,
+                    register,
+                ) = cell;
+            }
             Instruction::PutStructure {
                 structure,
                 register,
@@ -446,7 +845,7 @@ impl Interpreter {
                 let address = self.deref_cell(CellAddress::Register { index: *register });
                 let value = self.lookup_address(address);
                 match value {
-                    Cell::Reference(_) => {
+                    Cell::Reference(_) | Cell::AttributedVariable(_) => {
                         self.global_stack
                             .push(Cell::StructureRef(self.global_stack.len() + 1));
                         self.global_stack.push(Cell::Structure(*structure));
@@ -456,6 +855,7 @@ impl Interpreter {
                                 index: self.global_stack.len() - 2,
                             },
                         );
+                        self.built_structure = Some((address, self.global_stack.len() - 1));
                         self.mode = Mode::Write;
                     }
                     Cell::StructureRef(structure_addr) => {
@@ -490,7 +890,7 @@ impl Interpreter {
                 argument_register,
                 value_register,
             } => {
-                self.unify(
+                let unified = self.unify(
                     CellAddress::Register {
                         index: *value_register,
                     },
@@ -498,16 +898,29 @@ impl Interpreter {
                         index: *argument_register,
                     },
                 );
+                if !unified {
+                    self.backtrack();
+                }
+            }
+            Instruction::GetNumber { number, register } => {
+                let register = *register;
+                if !self.unify_number(CellAddress::Register { index: register }, &number.clone()) {
+                    self.backtrack();
+                }
+            }
+            Instruction::GetString { string, register } => {
+                let register = *register;
+                if !self.unify_string(CellAddress::Register { index: register }, &string.clone()) {
+                    self.backtrack();
+                }
             }
             Instruction::GetConstant { constant, register } => {
                 let address = self.deref_cell(CellAddress::Register { index: *register });
                 let cell = self.lookup_address(address);
                 match cell {
-                    Cell::Reference(_) => {
+                    Cell::Reference(_) | Cell::AttributedVariable(_) => {
                         let constant = *constant;
-                        let cell = self.lookup_address_mut(address);
-                        *cell = Cell::Constant(constant);
-                        self.try_trail(address);
+                        self.bind_variable(address, Cell::Constant(constant));
                     }
                     Cell::Constant(compare_constant) => {
                         if compare_constant != constant {
@@ -544,17 +957,27 @@ impl Interpreter {
             Instruction::UnifyValue { register } => {
                 match self.mode {
                     Mode::Read => {
-                        self.unify(
+                        let unified = self.unify(
                             CellAddress::Register { index: *register },
                             CellAddress::GlobalStack {
                                 index: self.next_sub_term_address,
                             },
                         );
+                        if !unified {
+                            self.backtrack();
+                        }
                     }
                     Mode::Write => {
-                        let register =
-                            self.lookup_address(CellAddress::Register { index: *register });
-                        self.global_stack.push(register.clone());
+                        let register = *register;
+                        if self.flags.occurs_check != OccursCheck::False
+                            && !self.acyclic_argument(register)
+                        {
+                            self.backtrack();
+                        } else {
+                            let value =
+                                self.lookup_address(CellAddress::Register { index: register });
+                            self.global_stack.push(value.clone());
+                        }
                     }
                 }
                 self.next_sub_term_address += 1;
@@ -566,11 +989,9 @@ impl Interpreter {
                     });
                     let cell = self.lookup_address(address);
                     match cell {
-                        Cell::Reference(_) => {
+                        Cell::Reference(_) | Cell::AttributedVariable(_) => {
                             let constant = *constant;
-                            let cell = self.lookup_address_mut(address);
-                            *cell = Cell::Constant(constant);
-                            self.try_trail(address);
+                            self.bind_variable(address, Cell::Constant(constant));
                         }
                         Cell::Constant(compare_constant) => {
                             if compare_constant != constant {
@@ -586,161 +1007,516 @@ impl Interpreter {
                     self.global_stack.push(Cell::Constant(*constant));
                 }
             },
+            Instruction::UnifyNumber { number } => {
+                let number = number.clone();
+                match self.mode {
+                    Mode::Read => {
+                        let address = CellAddress::GlobalStack {
+                            index: self.next_sub_term_address,
+                        };
+                        if !self.unify_number(address, &number) {
+                            self.backtrack();
+                        }
+                    }
+                    Mode::Write => self.push_number_argument(&number),
+                }
+                self.next_sub_term_address += 1;
+            }
             // Control flow
             Instruction::Proceed => {
                 self.instruction_index = self.proceed_return_address;
+                if !self.wakeups.is_empty() {
+                    self.wake_up(0);
+                }
+            }
+            // The hooks of attributed variables bound by the previous goal or the head run
+            // before the next goal, which is called again afterwards.
+            Instruction::Call { functor, .. } if !self.wakeups.is_empty() => {
+                let arity = self.compiler.descriptor_allocator.get(*functor).arity();
+                self.instruction_index -= 1;
+                self.wake_up(arity);
+            }
+            Instruction::CallBuiltin { builtin } if !self.wakeups.is_empty() => {
+                let arity = builtin.arity();
+                self.instruction_index -= 1;
+                self.wake_up(arity);
+            }
+            Instruction::Deallocate if !self.wakeups.is_empty() => {
+                self.instruction_index -= 1;
+                self.wake_up(0);
             }
             Instruction::Call { address, functor } => {
-                self.proceed_return_address = self.instruction_index;
-                self.instruction_index = *address;
-
-                self.current_functor = *functor;
-
-                // before executing the fact we collect the values of the watched registers.
-                if !self.inspection_set {
-                    for watch in &mut self.inspection_watch {
-                        let cell = self.registers[watch.address.index_num()].heap_address();
-                        watch.address = cell.clone();
+                let (address, functor) = (*address, *functor);
+                if address != UNRESOLVED_ADDRESS {
+                    self.call_predicate(address, functor);
+                } else if let Some(address) = self.compiler.resolve_predicate(functor) {
+                    // A library loaded on demand may use more registers than the program.
+                    self.grow_registers(0);
+                    self.call_predicate(address, functor);
+                } else {
+                    match self.unknown_procedure(functor) {
+                        Ok(_) => self.backtrack(),
+                        Err(error) => self.raise(error),
                     }
-                    self.inspection_set = true;
+                }
+            }
+            Instruction::Add {
+                left,
+                right,
+                target,
+            } => {
+                let (left, right, target) = (*left, *right, *target);
+                self.execute_inline_arithmetic("+", i64::checked_add, left, right, target);
+            }
+            Instruction::Subtract {
+                left,
+                right,
+                target,
+            } => {
+                let (left, right, target) = (*left, *right, *target);
+                self.execute_inline_arithmetic("-", i64::checked_sub, left, right, target);
+            }
+            Instruction::Multiply {
+                left,
+                right,
+                target,
+            } => {
+                let (left, right, target) = (*left, *right, *target);
+                self.execute_inline_arithmetic("*", i64::checked_mul, left, right, target);
+            }
+            Instruction::CompareEq { left, right } => {
+                let (left, right) = (*left, *right);
+                self.execute_inline_comparison(left, right, |o| o == Ordering::Equal);
+            }
+            Instruction::CompareNe { left, right } => {
+                let (left, right) = (*left, *right);
+                self.execute_inline_comparison(left, right, |o| o != Ordering::Equal);
+            }
+            Instruction::CompareLt { left, right } => {
+                let (left, right) = (*left, *right);
+                self.execute_inline_comparison(left, right, |o| o == Ordering::Less);
+            }
+            Instruction::CompareLe { left, right } => {
+                let (left, right) = (*left, *right);
+                self.execute_inline_comparison(left, right, |o| o != Ordering::Greater);
+            }
+            Instruction::CompareGt { left, right } => {
+                let (left, right) = (*left, *right);
+                self.execute_inline_comparison(left, right, |o| o == Ordering::Greater);
+            }
+            Instruction::CompareGe { left, right } => {
+                let (left, right) = (*left, *right);
+                self.execute_inline_comparison(left, right, |o| o != Ordering::Less);
+            }
+            Instruction::CallBuiltin { builtin } => {
+                let builtin = *builtin;
+                match self.call_builtin(builtin) {
+                    Ok(true) => {}
+                    Ok(false) => self.backtrack(),
+                    Err(error) => self.raise(error),
                 }
             }
             Instruction::Allocate { variables } => {
-                self.environment_stack
-                    .push_environment(*variables, self.proceed_return_address);
+                // Environments below the top of the newest choice point are still needed when
+                // backtracking, even if they have been deallocated since.
+                let protected_address = self.choice_point_stack.get_environment_top();
+                self.environment_stack.push_environment(
+                    *variables,
+                    self.proceed_return_address,
+                    protected_address,
+                );
             }
             Instruction::Deallocate => {
                 self.instruction_index = self.environment_stack.get_continuation();
                 self.environment_stack.pop_environment();
             }
             Instruction::TryMeElse { else_address } => {
-                let arity = self.descriptors[self.current_functor.0].arity();
-                self.choice_point_stack.push_choice_point(
-                    arity,
-                    self.proceed_return_address,
-                    self.environment_stack.get_current_address(),
-                    *else_address,
-                    self.trail.len(),
-                    self.global_stack.len(),
-                );
-                for i in 0..arity {
-                    let argument = self.registers[i].clone();
-                    *self.choice_point_stack.get_argument_mut(i) = argument.clone();
+                let arity = self
+                    .compiler
+                    .descriptor_allocator
+                    .get(self.current_functor)
+                    .arity();
+                // Without another clause the call can match, there is nothing to come back to.
+                let key = self.first_argument_key(arity);
+                if let Some(address) = self.compiler.matching_clause(*else_address, key) {
+                    self.push_choice_point(arity, address);
                 }
             }
             Instruction::RetryMeElse { else_address } => {
-                let arity = self.descriptors[self.current_functor.0].arity();
-                for i in 0..arity {
-                    let argument = &mut self.registers[i];
-                    *argument = self.choice_point_stack.get_argument(i).clone();
+                let else_address = *else_address;
+                self.cut_barrier = self.choice_point_stack.get_current_address();
+                self.restore_choice_point();
+                let key = self.first_argument_key(self.choice_point_stack.get_num_arguments());
+                match self.compiler.matching_clause(else_address, key) {
+                    Some(address) => *self.choice_point_stack.get_next_instruction_mut() = address,
+                    None => self.choice_point_stack.pop_choice_point(),
                 }
-                self.environment_stack
-                    .reset_to(self.choice_point_stack.get_environment_address());
-                self.proceed_return_address = self.choice_point_stack.get_continuation();
-                *self.choice_point_stack.get_next_instruction_mut() = *else_address;
-
-                let trail_address = self.choice_point_stack.get_trail_address();
-                self.unwind_trail(trail_address..self.trail.len());
-                unsafe { self.trail.set_len(trail_address) };
-
-                let stack_address = self.choice_point_stack.get_stack_address();
-                unsafe { self.global_stack.set_len(stack_address) };
             }
             Instruction::TrustMe => {
-                let arity = self.descriptors[self.current_functor.0].arity();
-                for i in 0..arity {
-                    let argument = &mut self.registers[i];
-                    *argument = self.choice_point_stack.get_argument(i).clone();
-                }
-
-                self.environment_stack
-                    .reset_to(self.choice_point_stack.get_environment_address());
-                self.proceed_return_address = self.choice_point_stack.get_continuation();
-
-                let trail_address = self.choice_point_stack.get_trail_address();
-                self.unwind_trail(trail_address..self.trail.len());
-                unsafe { self.trail.set_len(trail_address) };
-
-                let stack_address = self.choice_point_stack.get_stack_address();
-                unsafe { self.global_stack.set_len(stack_address) };
-
+                self.cut_barrier = self.choice_point_stack.get_current_address();
+                self.restore_choice_point();
                 self.choice_point_stack.pop_choice_point();
             }
+            Instruction::TryClauses { functor } => {
+                let functor = *functor;
+                self.try_clauses(functor);
+            }
+            Instruction::RetryClauses { functor } => {
+                let functor = *functor;
+                self.retry_clauses(functor);
+            }
+            Instruction::GetLevel { register } => {
+                *Self::lookup_register_mut(
+                    &mut self.environment_stack,
+                    &mut self.registers,
+                    *register,
+                ) = Cell::Integer(self.cut_barrier as i64);
+            }
+            Instruction::Cut { register } => {
+                let Cell::Integer(level) = self.lookup_register(register) else {
+                    unreachable!("cut levels are stored as integers");
+                };
+                let level = *level as usize;
+                self.choice_point_stack.cut(level);
+            }
             Instruction::NoOp => {}
+            Instruction::Halt => {
+                self.instruction_index -= 1;
+                return false;
+            }
         }
 
         true
     }
 
-    fn inspect_variable(&self, address: CellAddress) -> InspectionView {
-        let Some(deref_address) = self.deref_cell_safe(address) else {
-            return InspectionView::Undefined;
+    /// Makes room for every register the compiled code uses, which grows as clauses and
+    /// libraries are added, and for at least `count` registers.
+    pub(super) fn grow_registers(&mut self, count: usize) {
+        let registers = self.compiler.max_registers().max(count);
+        if self.registers.len() < registers {
+            self.registers.resize(registers, Cell::Undefined);
+        }
+    }
+
+    /// Pushes a choice point resuming at `next_instruction_address`, which saves the current
+    /// state and the first `arity` argument registers.
+    fn push_choice_point(&mut self, arity: usize, next_instruction_address: usize) {
+        let state = SavedState {
+            continuation_address: self.proceed_return_address,
+            environment_address: self.environment_stack.get_current_address(),
+            environment_top: self.environment_stack.get_top_address(),
+            trail_address: self.trail.len(),
+            stack_address: self.global_stack.len(),
         };
-        let Some(cell) = self.lookup_address_safe(deref_address) else {
-            return InspectionView::Undefined;
+        self.choice_point_stack
+            .push_choice_point(arity, next_instruction_address, state);
+        for i in 0..arity {
+            *self.choice_point_stack.get_argument_mut(i) = self.registers[i].clone();
+        }
+    }
+
+    /// Resets the machine to the state saved in the newest choice point, to try its next
+    /// alternative.
+    fn restore_choice_point(&mut self) {
+        let arity = self.choice_point_stack.get_num_arguments();
+        for i in 0..arity {
+            self.registers[i] = self.choice_point_stack.get_argument(i).clone();
+        }
+
+        self.environment_stack
+            .reset_to(self.choice_point_stack.get_environment_address());
+        self.proceed_return_address = self.choice_point_stack.get_continuation();
+
+        let trail_address = self.choice_point_stack.get_trail_address();
+        self.unwind_trail(trail_address..self.trail.len());
+        unsafe { self.trail.set_len(trail_address) };
+
+        let stack_address = self.choice_point_stack.get_stack_address();
+        unsafe { self.global_stack.set_len(stack_address) };
+    }
+
+    /// The key of the first argument of a call to a predicate of `arity`, which selects the
+    /// clauses the call can match. None if the argument is unbound or has no key.
+    fn first_argument_key(&self, arity: usize) -> Option<ClauseKey> {
+        if arity == 0 {
+            return None;
+        }
+        let argument = CellAddress::Register {
+            index: RegisterId::Argument(0),
         };
-        match cell {
-            Cell::Reference(reference_address_index)
-                if CellAddress::GlobalStack {
-                    index: *reference_address_index,
-                } == deref_address =>
-            {
-                InspectionView::UnboundVariable {
-                    index: *reference_address_index,
-                }
-            }
-            Cell::StructureRef(reference_index) => {
-                self.inspect_variable(CellAddress::GlobalStack {
-                    index: *reference_index,
-                })
-            }
-            Cell::Constant(descriptor_id) => InspectionView::Constant {
-                descriptor_id: *descriptor_id,
-            },
-            Cell::Structure(descriptor_id) => {
-                let arity = self.descriptors[descriptor_id.0].arity();
+        match self.value(argument) {
+            Cell::Constant(atom) => Some(ClauseKey::Functor(*atom)),
+            Cell::Integer(value) => Some(ClauseKey::Integer(*value)),
+            Cell::StructureRef(_) => self
+                .structure(argument)
+                .map(|(functor, _)| ClauseKey::Functor(functor)),
+            _ => None,
+        }
+    }
 
-                InspectionView::Structure {
+    /// Jumps to the predicate at `address`, returning to the current instruction afterwards.
+    fn call_predicate(&mut self, address: usize, functor: DescriptorId) {
+        self.proceed_return_address = self.instruction_index;
+        self.instruction_index = address;
+        self.current_functor = functor;
+        self.cut_barrier = self.choice_point_stack.get_top_address();
+    }
+
+    fn inspect_variable(&self, address: CellAddress) -> InspectionView {
+        self.inspect_term(address)
+    }
+
+    /// Inspects the term at `address`. A structure met again inside itself becomes a
+    /// [`InspectionView::Cycle`] inside the [`InspectionView::Cyclic`] structure.
+    ///
+    /// The term is walked with an explicit work stack, and each structure is assembled once the
+    /// views of its arguments are on the `views` stack, so long lists are inspected without
+    /// recursion.
+    fn inspect_term(&self, address: CellAddress) -> InspectionView {
+        enum Work {
+            Inspect(CellAddress),
+            /// Replaces the topmost `arity` views by the structure with these arguments.
+            Assemble(DescriptorId, usize),
+            /// Leaves the structure at `label`, wrapping its view if it contains itself.
+            Leave(usize),
+        }
+
+        // The structures on the path from the root to the current term, and the ones among them
+        // met again inside themselves.
+        let mut path = HashSet::new();
+        let mut cycles = HashSet::new();
+        let mut work = vec![Work::Inspect(address)];
+        let mut views = Vec::new();
+        while let Some(next) = work.pop() {
+            let address = match next {
+                Work::Inspect(address) => address,
+                Work::Assemble(descriptor_id, arity) => {
+                    let arguments = views.split_off(views.len() - arity);
+                    views.push(InspectionView::Structure {
+                        descriptor_id,
+                        arguments,
+                    });
+                    continue;
+                }
+                Work::Leave(label) => {
+                    path.remove(&label);
+                    if cycles.remove(&label) {
+                        let view = views.pop().expect("structure to be inspected");
+                        views.push(InspectionView::Cyclic {
+                            label,
+                            term: Box::new(view),
+                        });
+                    }
+                    continue;
+                }
+            };
+            let Some(deref_address) = self.deref_cell_safe(address) else {
+                views.push(InspectionView::Undefined);
+                continue;
+            };
+            let Some(cell) = self.lookup_address_safe(deref_address) else {
+                views.push(InspectionView::Undefined);
+                continue;
+            };
+            let view = match cell {
+                Cell::Reference(reference_address_index)
+                | Cell::AttributedVariable(reference_address_index)
+                    if CellAddress::GlobalStack {
+                        index: *reference_address_index,
+                    } == deref_address =>
+                {
+                    InspectionView::UnboundVariable {
+                        index: *reference_address_index,
+                    }
+                }
+                Cell::StructureRef(reference_index) => {
+                    let label = *reference_index;
+                    if !path.insert(label) {
+                        cycles.insert(label);
+                        views.push(InspectionView::Cycle { label });
+                        continue;
+                    }
+                    work.push(Work::Leave(label));
+                    work.push(Work::Inspect(CellAddress::GlobalStack { index: label }));
+                    continue;
+                }
+                Cell::Constant(descriptor_id) => InspectionView::Constant {
                     descriptor_id: *descriptor_id,
-                    arguments: (0..arity)
-                        .map(|i| {
-                            self.inspect_variable(CellAddress::GlobalStack {
-                                index: address.index_num() + i + 1,
-                            })
+                },
+                Cell::Structure(descriptor_id) => {
+                    let arity = self
+                        .compiler
+                        .descriptor_allocator
+                        .get(*descriptor_id)
+                        .arity();
+                    work.push(Work::Assemble(*descriptor_id, arity));
+                    // Pushed last to first, so the arguments are inspected from left to right.
+                    work.extend((1..=arity).rev().map(|i| {
+                        Work::Inspect(CellAddress::GlobalStack {
+                            index: address.index_num() + i,
                         })
-                        .collect(),
+                    }));
+                    continue;
                 }
-            }
-            Cell::Undefined => InspectionView::Undefined,
-            _ => {
-                todo!("Implement inspection for other cell types {:?}", cell)
-            }
+                Cell::Undefined => InspectionView::Undefined,
+                Cell::Integer(_)
+                | Cell::Float(_)
+                | Cell::BigIntegerRef(_)
+                | Cell::RationalRef(_) => InspectionView::Number(self.read_number(cell).unwrap()),
+                Cell::StringRef(_) => InspectionView::String(self.read_string(cell).unwrap()),
+                _ => {
+                    todo!("Implement inspection for other cell types {:?}", cell)
+                }
+            };
+            views.push(view);
         }
+        views.pop().expect("term to be inspected")
     }
 
+    /// The values of the query variables.
     pub fn inspect(&self) -> InspectionResult {
-        let mut result = InspectionResult {
-            variables: Vec::new(),
+        let addresses = (0..self.query_variables.len())
+            .map(|index| self.deref_cell_safe(CellAddress::GlobalStack { index }))
+            .collect::<Vec<_>>();
+        let variables = self
+            .query_variables
+            .iter()
+            .enumerate()
+            .map(|(index, variable)| {
+                let view = self.inspect_variable(CellAddress::GlobalStack { index });
+                (*variable, view)
+            })
+            .collect();
+        let aliases = addresses
+            .iter()
+            .enumerate()
+            .map(|(position, address)| match address {
+                Some(_) => addresses.iter().position(|other| other == address).unwrap(),
+                None => position,
+            })
+            .collect();
+        // The query clause puts the residual goals right after the query variables.
+        let residuals = CellAddress::GlobalStack {
+            index: self.query_variables.len(),
         };
-
-        for variable in &self.inspection_watch {
-            let view = self.inspect_variable(variable.address);
-            result.variables.push((variable.descriptor_id, view));
+        let goals = match self.read_list(residuals) {
+            ListShape::Proper(goals) => goals
+                .into_iter()
+                .map(|goal| self.inspect_variable(goal))
+                .collect(),
+            _ => Vec::new(),
+        };
+        InspectionResult {
+            variables,
+            aliases,
+            goals,
         }
-
-        result
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct InspectionVariable {
-    pub variable: DescriptorId,
-    pub register: RegisterId,
+    /// The answer to the query as the Prolog toplevel writes it, e.g. `X = Y, Y = f(_A,b)`,
+    /// followed by the residual goals of attributed variables such as `dif(X,a)`. It is
+    /// `true` if the query succeeded without binding any variable, and `false` if it failed or
+    /// raised an exception.
+    pub fn answer(&self) -> String {
+        if self.execution_state != ExecutionState::Normal {
+            return "false".to_string();
+        }
+        let (bindings, goals) = self
+            .inspect()
+            .answer_terms(&self.compiler.descriptor_allocator);
+        if bindings.is_empty() && goals.is_empty() {
+            return "true".to_string();
+        }
+        let options = WriteOptions {
+            priority: 699,
+            ..WriteOptions::writeq()
+        };
+        let goal_options = WriteOptions {
+            priority: 999,
+            ..WriteOptions::writeq()
+        };
+        bindings
+            .iter()
+            .map(|(name, term)| {
+                format!(
+                    "{} = {}",
+                    name,
+                    format_term(term, &self.compiler.operators, &options)
+                )
+            })
+            .chain(
+                goals
+                    .iter()
+                    .map(|goal| format_term(goal, &self.compiler.operators, &goal_options)),
+            )
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct InspectionResult {
     pub variables: Vec<(DescriptorId, InspectionView)>,
+    /// For every variable, the position of the first one sharing its value, which may be its
+    /// own.
+    pub aliases: Vec<usize>,
+    /// The residual goals describing the attributes left on the variables.
+    pub goals: Vec<InspectionView>,
+}
+
+impl InspectionResult {
+    /// The bindings of the answer, as the Prolog toplevel writes them: variables sharing their
+    /// value are chained as in `X = Y, Y = a`, unbound variables which share nothing are left
+    /// out, and other unbound variables are named `_A`, `_B`, ... Cyclic terms are followed by
+    /// the definitions `_S1 = ...` of their cycles.
+    pub fn bindings(&self, descriptors: &DescriptorAllocator) -> Vec<(String, AbstractTerm)> {
+        self.answer_terms(descriptors).0
+    }
+
+    /// The bindings of the answer and its residual goals, which share the names of their
+    /// variables.
+    pub fn answer_terms(
+        &self,
+        descriptors: &DescriptorAllocator,
+    ) -> (Vec<(String, AbstractTerm)>, Vec<AbstractTerm>) {
+        let name = |position: usize| descriptors.get(self.variables[position].0).name.clone();
+        let groups = (0..self.variables.len())
+            .filter(|&position| self.aliases[position] == position)
+            .map(|first| {
+                (first..self.variables.len())
+                    .filter(|&position| self.aliases[position] == first)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        // An unbound variable is written as the last query variable it stands for.
+        let mut names = TermNames::fresh();
+        for group in &groups {
+            let last = *group.last().expect("groups to have a variable");
+            if let InspectionView::UnboundVariable { index } = self.variables[last].1 {
+                names.variables.insert(index, name(last));
+            }
+        }
+
+        let mut bindings = Vec::new();
+        for group in &groups {
+            for pair in group.windows(2) {
+                bindings.push((name(pair[0]), AbstractTerm::Variable(name(pair[1]))));
+            }
+            let last = *group.last().expect("groups to have a variable");
+            let view = &self.variables[last].1;
+            if !matches!(view, InspectionView::UnboundVariable { .. }) {
+                bindings.push((name(last), view.to_rational_term(descriptors, &mut names)));
+            }
+        }
+        let goals = self
+            .goals
+            .iter()
+            .map(|goal| goal.to_rational_term(descriptors, &mut names))
+            .collect();
+        bindings.extend(names.definitions().map(|(name, term)| (name, term.clone())));
+        (bindings, goals)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -756,4 +1532,214 @@ pub enum InspectionView {
         descriptor_id: DescriptorId,
         arguments: Vec<InspectionView>,
     },
+    Number(Number),
+    String(String),
+    /// A structure which contains itself. Inside `term`, a [`InspectionView::Cycle`] with the
+    /// same `label` stands for the whole structure again.
+    Cyclic {
+        label: usize,
+        term: Box<InspectionView>,
+    },
+    /// The enclosing [`InspectionView::Cyclic`] structure with this `label`.
+    Cycle {
+        label: usize,
+    },
+}
+
+impl InspectionView {
+    /// The inspected term, with unbound variables named `_G` followed by their address. Cyclic
+    /// terms are written as `@(Term, [_S1 = ...])`, see [`InspectionView::to_rational_term`].
+    pub fn to_term(&self, descriptors: &DescriptorAllocator) -> AbstractTerm {
+        let mut names = TermNames::default();
+        let term = self.to_rational_term(descriptors, &mut names);
+        if names.structures.is_empty() {
+            return term;
+        }
+        let definitions = names
+            .definitions()
+            .map(|(name, term)| {
+                AbstractTerm::Structure(
+                    "=".to_string(),
+                    vec![AbstractTerm::Variable(name), term.clone()],
+                )
+            })
+            .collect();
+        AbstractTerm::Structure(
+            "@".to_string(),
+            vec![
+                term,
+                AbstractTerm::list(definitions, AbstractTerm::Constant("[]".to_string())),
+            ],
+        )
+    }
+
+    /// The inspected term, with each structure which contains itself replaced by a variable
+    /// `_S1`, `_S2`, ... defined in `names`. Terms sharing `names` share the names of the
+    /// variables and structures they have in common.
+    pub fn to_rational_term(
+        &self,
+        descriptors: &DescriptorAllocator,
+        names: &mut TermNames,
+    ) -> AbstractTerm {
+        enum Work<'a> {
+            Convert(&'a InspectionView),
+            /// Replaces the topmost `arity` terms by the structure with these arguments.
+            Assemble(DescriptorId, usize),
+            /// Replaces the topmost term by the name of the structure at `label` it defines.
+            Define(usize),
+        }
+
+        let mut work = vec![Work::Convert(self)];
+        let mut terms = Vec::new();
+        while let Some(next) = work.pop() {
+            let view = match next {
+                Work::Convert(view) => view,
+                Work::Assemble(descriptor_id, arity) => {
+                    let arguments = terms.split_off(terms.len() - arity);
+                    terms.push(AbstractTerm::Structure(
+                        descriptors.get(descriptor_id).name.clone(),
+                        arguments,
+                    ));
+                    continue;
+                }
+                Work::Define(label) => {
+                    let definition = terms.pop().expect("definition to be converted");
+                    names.define_structure(label, definition);
+                    terms.push(names.structure(label));
+                    continue;
+                }
+            };
+            let term = match view {
+                InspectionView::UnboundVariable { index } => {
+                    AbstractTerm::Variable(names.variable(*index))
+                }
+                InspectionView::Undefined => AbstractTerm::Constant("undefined".to_string()),
+                InspectionView::Constant { descriptor_id } => {
+                    AbstractTerm::Constant(descriptors.get(*descriptor_id).name.clone())
+                }
+                InspectionView::Structure {
+                    descriptor_id,
+                    arguments,
+                } => {
+                    work.push(Work::Assemble(*descriptor_id, arguments.len()));
+                    // Pushed last to first, so the arguments are converted from left to right.
+                    work.extend(arguments.iter().rev().map(Work::Convert));
+                    continue;
+                }
+                InspectionView::Number(number) => AbstractTerm::Number(number.clone()),
+                InspectionView::String(text) => AbstractTerm::String(text.clone()),
+                InspectionView::Cyclic { label, term } => {
+                    if !names.contains_structure(*label) {
+                        names.structures.push((*label, None));
+                        work.push(Work::Define(*label));
+                        work.push(Work::Convert(term));
+                        continue;
+                    }
+                    names.structure(*label)
+                }
+                InspectionView::Cycle { label } => names.structure(*label),
+            };
+            terms.push(term);
+        }
+        terms.pop().expect("term to be converted")
+    }
+}
+
+/// Views are dropped without recursion, so long lists don't overflow the stack.
+impl Drop for InspectionView {
+    fn drop(&mut self) {
+        let mut pending = match self {
+            InspectionView::Structure { arguments, .. } => std::mem::take(arguments),
+            InspectionView::Cyclic { term, .. } => {
+                vec![std::mem::replace(&mut **term, InspectionView::Undefined)]
+            }
+            _ => return,
+        };
+        while let Some(mut view) = pending.pop() {
+            match &mut view {
+                InspectionView::Structure { arguments, .. } => pending.append(arguments),
+                InspectionView::Cyclic { term, .. } => {
+                    pending.push(std::mem::replace(&mut **term, InspectionView::Undefined));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Names for the parts of inspected terms which have no name of their own: unbound variables
+/// and structures which contain themselves.
+#[derive(Debug, Clone, Default)]
+pub struct TermNames {
+    /// The names of unbound variables by global stack address.
+    variables: HashMap<usize, String>,
+    /// Whether variables without a name are named `_A`, `_B`, ... in the order they are met,
+    /// instead of `_G` followed by their address.
+    fresh_variables: bool,
+    /// The number of fresh names handed out.
+    fresh: usize,
+    /// The label of every structure which contains itself, and its definition once known.
+    structures: Vec<(usize, Option<AbstractTerm>)>,
+}
+
+impl TermNames {
+    /// Names which give unnamed variables the fresh names `_A`, `_B`, ...
+    pub fn fresh() -> Self {
+        TermNames {
+            fresh_variables: true,
+            ..Default::default()
+        }
+    }
+
+    /// The variables `_S1`, `_S2`, ... standing for structures and the terms they stand for.
+    pub fn definitions(&self) -> impl Iterator<Item = (String, &AbstractTerm)> {
+        self.structures
+            .iter()
+            .enumerate()
+            .map(|(position, (_, term))| {
+                let term = term.as_ref().expect("definitions to be complete");
+                (format!("_S{}", position + 1), term)
+            })
+    }
+
+    fn variable(&mut self, index: usize) -> String {
+        if let Some(name) = self.variables.get(&index) {
+            return name.clone();
+        }
+        if !self.fresh_variables {
+            return format!("_G{}", index);
+        }
+        let count = self.fresh;
+        self.fresh += 1;
+        let letter = char::from(b'A' + (count % 26) as u8);
+        let name = match count / 26 {
+            0 => format!("_{}", letter),
+            round => format!("_{}{}", letter, round),
+        };
+        self.variables.insert(index, name.clone());
+        name
+    }
+
+    fn contains_structure(&self, label: usize) -> bool {
+        self.structures.iter().any(|(entry, _)| *entry == label)
+    }
+
+    fn define_structure(&mut self, label: usize, definition: AbstractTerm) {
+        if let Some((_, term)) = self
+            .structures
+            .iter_mut()
+            .find(|(entry, _)| *entry == label)
+        {
+            *term = Some(definition);
+        }
+    }
+
+    fn structure(&self, label: usize) -> AbstractTerm {
+        let position = self
+            .structures
+            .iter()
+            .position(|(entry, _)| *entry == label)
+            .expect("cycles to be inside their structure");
+        AbstractTerm::Variable(format!("_S{}", position + 1))
+    }
 }
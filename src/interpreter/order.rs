@@ -0,0 +1,169 @@
+use std::{cmp::Ordering, collections::HashSet};
+
+use crate::{
+    instructions::DescriptorId,
+    interpreter::{Cell, CellAddress, Interpreter, builtins::argument, error::PrologError},
+    number::Number,
+};
+
+impl Interpreter {
+    /// Compares two terms in the standard order of terms:
+    /// `Var < Number < Atom < String < Compound`. Variables are ordered by age, numbers by value
+    /// with floats before integers of the same value, atoms and strings alphabetically and
+    /// compound terms by arity, name and then their arguments from left to right. Two cyclic terms are equal if no difference
+    /// shows up before their comparison loops back to a pair of structures compared earlier.
+    pub(super) fn compare_terms(&self, a: CellAddress, b: CellAddress) -> Ordering {
+        let mut compared = HashSet::new();
+        let mut pending = vec![(a, b)];
+        while let Some((a, b)) = pending.pop() {
+            let a = self.deref_cell(a);
+            let b = self.deref_cell(b);
+            if a == b {
+                continue;
+            }
+
+            let (a_cell, b_cell) = (self.lookup_address(a), self.lookup_address(b));
+            let ordering = self
+                .order_class(a_cell)
+                .cmp(&self.order_class(b_cell))
+                .then_with(|| match (a_cell, b_cell) {
+                    (
+                        Cell::Reference(a) | Cell::AttributedVariable(a),
+                        Cell::Reference(b) | Cell::AttributedVariable(b),
+                    ) => a.cmp(b),
+                    (Cell::Constant(a), Cell::Constant(b)) => {
+                        let a = &self.compiler.descriptor_allocator.get(*a).name;
+                        let b = &self.compiler.descriptor_allocator.get(*b).name;
+                        a.cmp(b)
+                    }
+                    (a @ Cell::StringRef(_), b @ Cell::StringRef(_)) => {
+                        self.read_string(a).cmp(&self.read_string(b))
+                    }
+                    (Cell::StructureRef(a), Cell::StructureRef(b)) => {
+                        let (a, b) = (*a, *b);
+                        let (Cell::Structure(a_functor), Cell::Structure(b_functor)) =
+                            (&self.global_stack[a], &self.global_stack[b])
+                        else {
+                            unreachable!("structure references always point to a structure");
+                        };
+                        let a_descriptor = self.compiler.descriptor_allocator.get(*a_functor);
+                        let b_descriptor = self.compiler.descriptor_allocator.get(*b_functor);
+                        let ordering = a_descriptor
+                            .arity()
+                            .cmp(&b_descriptor.arity())
+                            .then_with(|| a_descriptor.name.cmp(&b_descriptor.name));
+                        if ordering == Ordering::Equal && compared.insert((a, b)) {
+                            // Pushed in reverse so the leftmost argument is compared first.
+                            pending.extend((1..=a_descriptor.arity()).rev().map(|i| {
+                                (
+                                    CellAddress::GlobalStack { index: a + i },
+                                    CellAddress::GlobalStack { index: b + i },
+                                )
+                            }));
+                        }
+                        ordering
+                    }
+                    (a, b) => compare_numbers(
+                        &self.read_number(a).expect("cell is a number"),
+                        &self.read_number(b).expect("cell is a number"),
+                    ),
+                });
+
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    }
+
+    fn order_class(&self, cell: &Cell) -> u8 {
+        match cell {
+            Cell::Reference(_) | Cell::AttributedVariable(_) => 0,
+            Cell::Constant(_) => 2,
+            Cell::StringRef(_) => 3,
+            Cell::StructureRef(_) => 4,
+            _ => 1,
+        }
+    }
+
+    /// `compare(Order, A, B)`
+    pub(super) fn compare(&mut self) -> Result<bool, PrologError> {
+        match self.value(argument(0)) {
+            Cell::Reference(_) | Cell::AttributedVariable(_) => {}
+            Cell::Constant(id) => {
+                if !matches!(
+                    self.compiler.descriptor_allocator.get(*id).name.as_str(),
+                    "<" | "=" | ">"
+                ) {
+                    return Err(PrologError::domain_error("order", argument(0)));
+                }
+            }
+            _ => return Err(PrologError::type_error("atom", argument(0))),
+        }
+
+        let order = match self.compare_terms(argument(1), argument(2)) {
+            Ordering::Less => "<",
+            Ordering::Equal => "=",
+            Ordering::Greater => ">",
+        };
+        let order = self.atom(order);
+        Ok(self.unify_cell(argument(0), order))
+    }
+
+    /// `sort/2` and `msort/2`, removing duplicates if `deduplicate` is set.
+    pub(super) fn sort(&mut self, deduplicate: bool) -> Result<bool, PrologError> {
+        let mut elements = self.list_argument(argument(0))?;
+        elements.sort_by(|a, b| self.compare_terms(*a, *b));
+        if deduplicate {
+            elements.dedup_by(|a, b| self.compare_terms(*a, *b) == Ordering::Equal);
+        }
+        self.unify_sorted(&elements)
+    }
+
+    /// `keysort(Pairs, Sorted)`, a stable sort on the keys of `Key-Value` pairs.
+    pub(super) fn keysort(&mut self) -> Result<bool, PrologError> {
+        let elements = self.list_argument(argument(0))?;
+        let mut keyed = Vec::with_capacity(elements.len());
+        for element in elements {
+            match self.structure(element) {
+                Some((functor, index)) if self.is_pair_functor(functor) => {
+                    keyed.push((CellAddress::GlobalStack { index: index + 1 }, element));
+                }
+                _ if self.is_unbound(element) => return Err(PrologError::InstantiationError),
+                _ => return Err(PrologError::type_error("pair", element)),
+            }
+        }
+
+        keyed.sort_by(|(a, _), (b, _)| self.compare_terms(*a, *b));
+        let elements = keyed
+            .into_iter()
+            .map(|(_, element)| element)
+            .collect::<Vec<_>>();
+        self.unify_sorted(&elements)
+    }
+
+    fn is_pair_functor(&self, functor: DescriptorId) -> bool {
+        let descriptor = self.compiler.descriptor_allocator.get(functor);
+        descriptor.name == "-" && descriptor.arity() == 2
+    }
+
+    fn unify_sorted(&mut self, elements: &[CellAddress]) -> Result<bool, PrologError> {
+        let elements = elements
+            .iter()
+            .map(|&element| self.term_cell(element))
+            .collect::<Vec<_>>();
+        let tail = self.empty_list();
+        let list = self.build_list(&elements, tail);
+        Ok(self.unify_cell(argument(1), list))
+    }
+}
+
+/// Numbers are ordered by value, a float comes before an integer or rational of equal value.
+fn compare_numbers(a: &Number, b: &Number) -> Ordering {
+    a.compare(b).then_with(|| match (a, b) {
+        (Number::Float(_), Number::Float(_)) => Ordering::Equal,
+        (Number::Float(_), _) => Ordering::Less,
+        (_, Number::Float(_)) => Ordering::Greater,
+        _ => Ordering::Equal,
+    })
+}
@@ -0,0 +1,126 @@
+use std::fmt;
+
+use crate::{
+    interpreter::{Cell, CellAddress, Interpreter, builtins::argument, error::PrologError},
+    number::Number,
+    writer::{WriteOptions, format_term},
+};
+
+/// Shows a term on the global stack the way `writeq/1` writes it.
+pub struct TermDisplay<'a> {
+    interpreter: &'a Interpreter,
+    address: CellAddress,
+}
+
+impl fmt::Display for TermDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(
+            &self
+                .interpreter
+                .format(self.address, &WriteOptions::writeq()),
+        )
+    }
+}
+
+impl Interpreter {
+    pub fn display(&self, address: CellAddress) -> TermDisplay<'_> {
+        TermDisplay {
+            interpreter: self,
+            address,
+        }
+    }
+
+    /// The term at `address` written as text. Unbound variables are named `_G` followed by
+    /// their address.
+    pub(super) fn format(&self, address: CellAddress, options: &WriteOptions) -> String {
+        let term = self
+            .inspect_variable(address)
+            .to_term(&self.compiler.descriptor_allocator);
+        format_term(&term, &self.compiler.operators, options)
+    }
+
+    /// `write/1`, `print/1`, `writeq/1` and `write_canonical/1`.
+    pub(super) fn write(&mut self, options: WriteOptions) -> Result<bool, PrologError> {
+        let text = self.format(argument(0), &options);
+        self.emit(&text)?;
+        Ok(true)
+    }
+
+    /// `write_term(Term, Options)` and `write_term(Stream, Term, Options)`.
+    pub(super) fn write_term(&mut self, arity: usize) -> Result<bool, PrologError> {
+        let (stream, term, options_list) = match arity {
+            3 => (
+                self.checked_stream(argument(0), "output", Some(false))?,
+                argument(1),
+                argument(2),
+            ),
+            _ => (self.current_output(), argument(0), argument(1)),
+        };
+        let mut options = WriteOptions::default();
+        for option in self.list_argument(options_list)? {
+            if self.is_unbound(option) {
+                return Err(PrologError::InstantiationError);
+            }
+            let Some((functor, index)) = self.structure(option) else {
+                return Err(PrologError::domain_error("write_option", option));
+            };
+            let value = CellAddress::GlobalStack { index: index + 1 };
+            if self.is_unbound(value) {
+                return Err(PrologError::InstantiationError);
+            }
+            let descriptor = self.compiler.descriptor_allocator.get(functor);
+            if descriptor.arity() != 1 {
+                return Err(PrologError::domain_error("write_option", option));
+            }
+            match descriptor.name.as_str() {
+                "max_depth" => match self.read_number(self.value(value)) {
+                    Some(Number::Integer(depth)) if depth >= 0 => {
+                        options.max_depth = depth as usize;
+                    }
+                    _ => return Err(PrologError::domain_error("write_option", option)),
+                },
+                "priority" => match self.read_number(self.value(value)) {
+                    Some(Number::Integer(priority)) if (0..=1200).contains(&priority) => {
+                        options.priority = priority as usize;
+                    }
+                    _ => return Err(PrologError::domain_error("write_option", option)),
+                },
+                name => {
+                    let flag = match self.boolean(value) {
+                        Some(flag) => flag,
+                        None => return Err(PrologError::domain_error("write_option", option)),
+                    };
+                    match name {
+                        "quoted" => options.quoted = flag,
+                        "ignore_ops" => options.ignore_ops = flag,
+                        "numbervars" => options.numbervars = flag,
+                        "portray" => {}
+                        _ => return Err(PrologError::domain_error("write_option", option)),
+                    }
+                }
+            }
+        }
+
+        let text = self.format(term, &options);
+        self.emit_to(stream, &text)?;
+        Ok(true)
+    }
+
+    /// `nl/0`.
+    pub(super) fn nl(&mut self) -> Result<bool, PrologError> {
+        self.emit("\n")?;
+        Ok(true)
+    }
+
+    /// Reads `true` or `false`.
+    fn boolean(&self, address: CellAddress) -> Option<bool> {
+        let Cell::Constant(id) = self.value(address) else {
+            return None;
+        };
+        match self.compiler.descriptor_allocator.get(*id).name.as_str() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        }
+    }
+}
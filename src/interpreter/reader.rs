@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+
+use crate::{
+    interpreter::{
+        Cell, CellAddress, Interpreter, builtins::argument, error::PrologError,
+        reflection::count_variables,
+    },
+    parsing::{AbstractTerm, parse_term},
+};
+
+/// What `read_term/2` does with text that is not a valid term.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SyntaxErrors {
+    Error,
+    Fail,
+    Quiet,
+    /// Report the error on the standard error and read the next term.
+    Dec10,
+}
+
+impl Interpreter {
+    /// `read_term(Term, Options)` and `read_term(Stream, Term, Options)`. At the end of the
+    /// stream the term read is `end_of_file`.
+    pub(super) fn read_term(&mut self, arity: usize) -> Result<bool, PrologError> {
+        let (stream, term, options) = match arity {
+            3 => (argument(0), argument(1), argument(2)),
+            _ => (self.current_input(), argument(0), argument(1)),
+        };
+
+        let mut syntax_errors = SyntaxErrors::Error;
+        let mut requests = Vec::new();
+        for option in self.list_argument(options)? {
+            if self.is_unbound(option) {
+                return Err(PrologError::InstantiationError);
+            }
+            let Some((functor, index)) = self.structure(option) else {
+                return Err(PrologError::domain_error("read_option", option));
+            };
+            let value = CellAddress::GlobalStack { index: index + 1 };
+            let descriptor = self.compiler.descriptor_allocator.get(functor);
+            if descriptor.arity() != 1 {
+                return Err(PrologError::domain_error("read_option", option));
+            }
+            match descriptor.name.as_str() {
+                "syntax_errors" => {
+                    let Cell::Constant(id) = self.value(value) else {
+                        return Err(PrologError::domain_error("read_option", option));
+                    };
+                    syntax_errors = match self.compiler.descriptor_allocator.get(*id).name.as_str()
+                    {
+                        "error" => SyntaxErrors::Error,
+                        "fail" => SyntaxErrors::Fail,
+                        "quiet" => SyntaxErrors::Quiet,
+                        "dec10" => SyntaxErrors::Dec10,
+                        _ => return Err(PrologError::domain_error("read_option", option)),
+                    };
+                }
+                name @ ("variables" | "variable_names" | "singletons" | "term_position") => {
+                    requests.push((name.to_string(), value));
+                }
+                _ => return Err(PrologError::domain_error("read_option", option)),
+            }
+        }
+
+        let (read, position) = loop {
+            let Some((text, position)) = self.take_clause(stream)? else {
+                break (AbstractTerm::Constant("end_of_file".to_string()), None);
+            };
+            match parse_term(&text, &self.compiler.operators, self.flags.double_quotes) {
+                Ok(read) => break (read, Some(position)),
+                Err(error) => {
+                    let message = error.to_string();
+                    match syntax_errors {
+                        SyntaxErrors::Error => return Err(PrologError::SyntaxError(message)),
+                        SyntaxErrors::Fail | SyntaxErrors::Quiet => return Ok(false),
+                        SyntaxErrors::Dec10 => {
+                            let location = match self.stream_file_name(stream) {
+                                Some(file) => format!("{}:{}: ", file, position.lines + 1),
+                                None => String::new(),
+                            };
+                            self.emit_error(&format!("Syntax error: {}{}\n", location, message))?
+                        }
+                    }
+                }
+            }
+        };
+
+        let mut occurrences = Vec::new();
+        count_variables(&read, &mut occurrences);
+        let mut variables = HashMap::new();
+        let cell = self.build_term(&read, &mut variables);
+        if !self.unify_cell(term, cell) {
+            return Ok(false);
+        }
+
+        let equals = self
+            .compiler
+            .descriptor_allocator
+            .get_or_set_functor("=", 2);
+        for (request, value) in requests {
+            if request == "term_position" {
+                // `'$stream_position'(CharCount, LineCount, LinePosition, ByteCount)` of the
+                // first character of the term.
+                let position = position.unwrap_or_default();
+                let functor = self
+                    .compiler
+                    .descriptor_allocator
+                    .get_or_set_functor("$stream_position", 4);
+                let term = self.build_structure(
+                    functor,
+                    &[
+                        Cell::Integer(position.chars as i64),
+                        Cell::Integer(position.lines as i64 + 1),
+                        Cell::Integer(position.line_position as i64),
+                        Cell::Integer(position.bytes as i64),
+                    ],
+                );
+                if !self.unify_cell(value, term) {
+                    return Ok(false);
+                }
+                continue;
+            }
+            let elements = occurrences
+                .iter()
+                .filter(|(name, count)| match request.as_str() {
+                    "variables" => true,
+                    // Anonymous variables are read as `_#N`.
+                    "variable_names" => !name.starts_with("_#"),
+                    _ => !name.starts_with("_#") && *count == 1,
+                })
+                .map(|(name, _)| (name.to_string(), variables[*name].clone()))
+                .collect::<Vec<_>>();
+            let elements = elements
+                .into_iter()
+                .map(|(name, variable)| match request.as_str() {
+                    "variables" => variable,
+                    _ => {
+                        let name = self.atom(&name);
+                        self.build_structure(equals, &[name, variable])
+                    }
+                })
+                .collect::<Vec<_>>();
+            let tail = self.empty_list();
+            let list = self.build_list(&elements, tail);
+            if !self.unify_cell(value, list) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+
+use crate::{
+    instructions::{Builtin, DescriptorId},
+    interpreter::{Cell, CellAddress, Interpreter, builtins::argument, error::PrologError},
+    parsing::{AbstractTerm, operators::OperatorTable},
+    writer::{WriteOptions, format_term, variable_name},
+};
+
+impl Interpreter {
+    /// `'$clauses'(Head, Body, Clauses)`, unifies `Clauses` with a `Head-Body` copy of every
+    /// clause of the predicate of `Head`. Dynamic predicates only show the clauses existing
+    /// right now, undefined predicates have none.
+    pub(super) fn clauses(&mut self) -> Result<bool, PrologError> {
+        let functor = self.head_functor(argument(0))?;
+        let body = argument(1);
+        if !self.is_unbound(body) && !matches!(self.value(body), Cell::Constant(_)) {
+            self.structure(body)
+                .ok_or(PrologError::type_error("callable", body))?;
+        }
+        if self.is_private(functor) {
+            return Err(PrologError::permission_error(
+                "access",
+                "private_procedure",
+                functor,
+            ));
+        }
+
+        let sources = match self.compiler.dynamic_predicate(functor) {
+            Some(predicate) if predicate.defined => {
+                let generation = self.compiler.generation();
+                predicate
+                    .clauses
+                    .iter()
+                    .filter(|clause| clause.is_visible(generation))
+                    .map(|clause| (clause.head.clone(), clause.body.clone()))
+                    .collect()
+            }
+            _ => self.compiler.static_clauses(functor).to_vec(),
+        };
+        let pair = self
+            .compiler
+            .descriptor_allocator
+            .get_or_set_functor("-", 2);
+        let clauses = sources
+            .iter()
+            .map(|(head, body)| {
+                let mut variables = HashMap::new();
+                let head = self.build_term(head, &mut variables);
+                let body = self.build_term(body, &mut variables);
+                self.build_structure(pair, &[head, body])
+            })
+            .collect::<Vec<_>>();
+        let tail = self.empty_list();
+        let clauses = self.build_list(&clauses, tail);
+        Ok(self.unify_cell(argument(2), clauses))
+    }
+
+    /// `'$current_predicates'(Indicator, Indicators)`, checks that `Indicator` can be a
+    /// predicate indicator and unifies `Indicators` with the `Name/Arity` of every predicate of
+    /// the user program, in standard order.
+    pub(super) fn current_predicates(&mut self) -> Result<bool, PrologError> {
+        let indicator = argument(0);
+        if !self.is_unbound(indicator) {
+            let slash = self
+                .compiler
+                .descriptor_allocator
+                .get_or_set_functor("/", 2);
+            let valid = self
+                .structure(indicator)
+                .filter(|(functor, _)| *functor == slash)
+                .is_some_and(|(_, index)| {
+                    let name = CellAddress::GlobalStack { index: index + 1 };
+                    let arity = CellAddress::GlobalStack { index: index + 2 };
+                    (self.is_unbound(name) || matches!(self.value(name), Cell::Constant(_)))
+                        && (self.is_unbound(arity) || self.is_integer(arity))
+                });
+            if !valid {
+                return Err(PrologError::type_error("predicate_indicator", indicator));
+            }
+        }
+
+        let mut predicates = self
+            .compiler
+            .predicates()
+            .filter(|functor| !self.compiler.is_library(*functor))
+            .map(|functor| {
+                let descriptor = self.compiler.descriptor_allocator.get(functor);
+                (descriptor.name.clone(), descriptor.arity())
+            })
+            .collect::<Vec<_>>();
+        predicates.sort();
+        let slash = self
+            .compiler
+            .descriptor_allocator
+            .get_or_set_functor("/", 2);
+        let indicators = predicates
+            .iter()
+            .map(|(name, arity)| {
+                let name = self.atom(name);
+                self.build_structure(slash, &[name, Cell::Integer(*arity as i64)])
+            })
+            .collect::<Vec<_>>();
+        let tail = self.empty_list();
+        let indicators = self.build_list(&indicators, tail);
+        Ok(self.unify_cell(argument(1), indicators))
+    }
+
+    /// `'$predicate_properties'(Head, Properties)`, the properties of the predicate of `Head`.
+    /// Undefined predicates have none. A predicate of a bundled library is loaded first, the
+    /// way calling it would.
+    pub(super) fn predicate_properties(&mut self) -> Result<bool, PrologError> {
+        let functor = self.head_functor(argument(0))?;
+        if self.compiler.resolve_predicate(functor).is_some() {
+            self.grow_registers(0);
+        }
+        let clauses = match self.compiler.dynamic_predicate(functor) {
+            Some(predicate) if predicate.defined => {
+                let generation = self.compiler.generation();
+                Some(
+                    predicate
+                        .clauses
+                        .iter()
+                        .filter(|clause| clause.is_visible(generation))
+                        .count(),
+                )
+            }
+            _ => None,
+        };
+
+        let mut properties = Vec::new();
+        if self.is_private(functor) {
+            properties.extend(["built_in", "defined", "static"].map(|name| self.atom(name)));
+        } else if self.compiler.predicate_address(functor).is_some() {
+            let kind = if clauses.is_some() {
+                "dynamic"
+            } else {
+                "static"
+            };
+            properties.extend(["defined", kind].map(|name| self.atom(name)));
+            let clauses = clauses.unwrap_or_else(|| self.compiler.static_clauses(functor).len());
+            let number_of_clauses = self
+                .compiler
+                .descriptor_allocator
+                .get_or_set_functor("number_of_clauses", 1);
+            properties
+                .push(self.build_structure(number_of_clauses, &[Cell::Integer(clauses as i64)]));
+        }
+        let tail = self.empty_list();
+        let properties = self.build_list(&properties, tail);
+        Ok(self.unify_cell(argument(1), properties))
+    }
+
+    /// `listing(Spec)`, prints the clauses of the predicates named `Spec`, which is either a
+    /// name or a `Name/Arity` predicate indicator.
+    pub(super) fn listing(&mut self) -> Result<bool, PrologError> {
+        let specification = argument(0);
+        let mut functors = match self.value(specification) {
+            Cell::Reference(_) | Cell::AttributedVariable(_) => {
+                return Err(PrologError::InstantiationError);
+            }
+            Cell::Constant(name) => {
+                let name = self.compiler.descriptor_allocator.get(*name).name.clone();
+                self.compiler
+                    .predicates()
+                    .filter(|functor| self.compiler.descriptor_allocator.get(*functor).name == name)
+                    .collect()
+            }
+            _ => {
+                let functor = self.predicate_indicator(specification)?;
+                vec![functor]
+            }
+        };
+        functors.sort_by_key(|functor| self.compiler.descriptor_allocator.get(*functor).arity());
+
+        for functor in functors {
+            let clauses = match self.compiler.dynamic_predicate(functor) {
+                Some(predicate) if predicate.defined => {
+                    let descriptor = self.compiler.descriptor_allocator.get(functor);
+                    let declaration =
+                        format!(":- dynamic {}/{}.\n\n", descriptor.name, descriptor.arity());
+                    let generation = self.compiler.generation();
+                    let clauses = predicate
+                        .clauses
+                        .iter()
+                        .filter(|clause| clause.is_visible(generation))
+                        .map(|clause| (clause.head.clone(), clause.body.clone()))
+                        .collect();
+                    self.emit(&declaration)?;
+                    clauses
+                }
+                _ => self.compiler.static_clauses(functor).to_vec(),
+            };
+            let mut text = String::new();
+            for (head, body) in &clauses {
+                text.push_str(&format_clause(head, body, &self.compiler.operators));
+            }
+            text.push('\n');
+            self.emit(&text)?;
+        }
+        Ok(true)
+    }
+
+    /// Builtins and prelude predicates, whose clauses are not accessible.
+    fn is_private(&self, functor: DescriptorId) -> bool {
+        let descriptor = self.compiler.descriptor_allocator.get(functor);
+        Builtin::lookup(&descriptor.name, descriptor.arity()).is_some()
+            || self.compiler.is_library(functor)
+    }
+}
+
+/// Lays out a clause the way `listing/1` shows it: one goal per line, variables named `A`, `B`,
+/// ... in order of appearance and variables occurring once written as `_`.
+fn format_clause(head: &AbstractTerm, body: &AbstractTerm, operators: &OperatorTable) -> String {
+    let mut occurrences = Vec::<(&str, usize)>::new();
+    count_variables(head, &mut occurrences);
+    count_variables(body, &mut occurrences);
+    let mut names = HashMap::new();
+    let mut letters = 0;
+    for (variable, count) in occurrences {
+        let name = if count == 1 {
+            "_".to_string()
+        } else {
+            letters += 1;
+            variable_name(letters - 1)
+        };
+        names.insert(variable.to_string(), name);
+    }
+
+    let options = WriteOptions {
+        priority: 999,
+        ..WriteOptions::writeq()
+    };
+    let head = format_term(&rename_variables(head, &names), operators, &options);
+    if matches!(body, AbstractTerm::Constant(name) if name == "true") {
+        return format!("{}.\n", head);
+    }
+    let mut goals = Vec::new();
+    let mut conjunction = body;
+    while let AbstractTerm::Structure(name, arguments) = conjunction
+        && name == ","
+        && arguments.len() == 2
+    {
+        goals.push(&arguments[0]);
+        conjunction = &arguments[1];
+    }
+    goals.push(conjunction);
+    let goals = goals
+        .into_iter()
+        .map(|goal| format_term(&rename_variables(goal, &names), operators, &options))
+        .collect::<Vec<_>>();
+    format!("{} :-\n    {}.\n", head, goals.join(",\n    "))
+}
+
+/// Counts the occurrences of the variables of `term`, in order of first appearance.
+pub(super) fn count_variables<'a>(term: &'a AbstractTerm, occurrences: &mut Vec<(&'a str, usize)>) {
+    match term {
+        AbstractTerm::Variable(name) => {
+            match occurrences
+                .iter_mut()
+                .find(|(variable, _)| variable == name)
+            {
+                Some((_, count)) => *count += 1,
+                None => occurrences.push((name, 1)),
+            }
+        }
+        AbstractTerm::Structure(_, arguments) => {
+            for argument in arguments {
+                count_variables(argument, occurrences);
+            }
+        }
+        AbstractTerm::Constant(_) | AbstractTerm::Number(_) | AbstractTerm::String(_) => {}
+    }
+}
+
+fn rename_variables(term: &AbstractTerm, names: &HashMap<String, String>) -> AbstractTerm {
+    match term {
+        AbstractTerm::Variable(name) => AbstractTerm::Variable(names[name].clone()),
+        AbstractTerm::Structure(name, arguments) => AbstractTerm::Structure(
+            name.clone(),
+            arguments
+                .iter()
+                .map(|argument| rename_variables(argument, names))
+                .collect(),
+        ),
+        term => term.clone(),
+    }
+}
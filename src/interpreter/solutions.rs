@@ -0,0 +1,219 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+};
+
+use crate::interpreter::{Cell, CellAddress, Interpreter, builtins::argument, error::PrologError};
+
+/// A term copied off the global stack, so it survives backtracking. Addresses inside the cells
+/// are relative to the start of `cells`.
+#[derive(Clone, Debug)]
+pub(super) struct StoredTerm {
+    root: Cell,
+    cells: Vec<Cell>,
+}
+
+impl Interpreter {
+    /// Copies the term at `address` off the global stack. The copy is made by
+    /// [`Interpreter::copy_term_cell`], so long lists are stored without recursion.
+    pub(super) fn store_term(&mut self, address: CellAddress) -> StoredTerm {
+        let base = self.global_stack.len();
+        let root = self.copy_term_cell(address, &mut HashMap::new());
+        let cells = self
+            .global_stack
+            .split_off(base)
+            .iter()
+            .map(|cell| relocate(cell, base, 0))
+            .collect();
+        StoredTerm {
+            root: relocate(&root, base, 0),
+            cells,
+        }
+    }
+
+    /// Copies a stored term back onto the global stack.
+    pub(super) fn load_term(&mut self, term: &StoredTerm) -> Cell {
+        let base = self.global_stack.len();
+        self.global_stack
+            .extend(term.cells.iter().map(|cell| relocate(cell, 0, base)));
+        relocate(&term.root, 0, base)
+    }
+
+    /// `'$findall_start'`, opens a new solution buffer.
+    pub(super) fn findall_start(&mut self) -> Result<bool, PrologError> {
+        self.solutions.push(Vec::new());
+        Ok(true)
+    }
+
+    /// `'$findall_add'(Template)`, adds a copy of `Template` to the innermost buffer. Fails if
+    /// no buffer is open.
+    pub(super) fn findall_add(&mut self) -> Result<bool, PrologError> {
+        if self.solutions.is_empty() {
+            return Ok(false);
+        }
+        let solution = self.store_term(argument(0));
+        self.solutions
+            .last_mut()
+            .expect("findall to be started")
+            .push(solution);
+        Ok(true)
+    }
+
+    /// `'$findall_collect'(Bag, Tail)`, closes the innermost buffer and unifies `Bag` with its
+    /// solutions followed by `Tail`. Fails if no buffer is open.
+    pub(super) fn findall_collect(&mut self) -> Result<bool, PrologError> {
+        let Some(solutions) = self.solutions.pop() else {
+            return Ok(false);
+        };
+        let elements = solutions
+            .iter()
+            .map(|solution| self.load_term(solution))
+            .collect::<Vec<_>>();
+        let tail = self.term_cell(argument(1));
+        let bag = self.build_list(&elements, tail);
+        Ok(self.unify_cell(argument(0), bag))
+    }
+
+    /// `'$free_variables'(Template, Goal, Witness, Inner)`, strips the `^` quantifiers off `Goal`
+    /// and collects the variables of the remaining goal which neither occur in `Template` nor
+    /// are quantified.
+    pub(super) fn free_variables(&mut self) -> Result<bool, PrologError> {
+        let mut bound = self
+            .variables(argument(0))
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let mut goal = argument(1);
+        while let Some((functor, index)) = self.structure(goal)
+            && self.compiler.descriptor_allocator.get(functor).name == "^"
+            && self.compiler.descriptor_allocator.get(functor).arity() == 2
+        {
+            bound.extend(self.variables(CellAddress::GlobalStack { index: index + 1 }));
+            goal = CellAddress::GlobalStack { index: index + 2 };
+        }
+
+        let witness = self
+            .variables(goal)
+            .into_iter()
+            .filter(|variable| !bound.contains(variable))
+            .map(Cell::Reference)
+            .collect::<Vec<_>>();
+        let tail = self.empty_list();
+        let witness = self.build_list(&witness, tail);
+        let inner = self.term_cell(goal);
+        Ok(self.unify_cell(argument(2), witness) && self.unify_cell(argument(3), inner))
+    }
+
+    /// `'$bagof_groups'(Pairs, Groups)`, groups the `Witness-Template` pairs found by `bagof/3`
+    /// into `Witness-Templates` pairs, one for each distinct witness in standard order.
+    pub(super) fn bagof_groups(&mut self) -> Result<bool, PrologError> {
+        let mut pairs = self
+            .list_argument(argument(0))?
+            .into_iter()
+            .map(|pair| match self.structure(pair) {
+                Some((functor, index))
+                    if self.compiler.descriptor_allocator.get(functor).arity() == 2 =>
+                {
+                    Ok((
+                        CellAddress::GlobalStack { index: index + 1 },
+                        CellAddress::GlobalStack { index: index + 2 },
+                    ))
+                }
+                _ => Err(PrologError::type_error("pair", pair)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        pairs.sort_by(|(a, _), (b, _)| self.compare_terms(*a, *b));
+
+        let mut groups: Vec<(CellAddress, Vec<CellAddress>)> = Vec::new();
+        for (witness, template) in pairs {
+            match groups
+                .iter_mut()
+                .find(|(group, _)| self.is_variant(*group, witness))
+            {
+                Some((group, templates)) => {
+                    // Variants only differ in their variables, so this binding always succeeds.
+                    self.unify(*group, witness);
+                    templates.push(template);
+                }
+                None => groups.push((witness, vec![template])),
+            }
+        }
+
+        let pair = self
+            .compiler
+            .descriptor_allocator
+            .get_or_set_functor("-", 2);
+        let groups = groups
+            .into_iter()
+            .map(|(witness, templates)| {
+                let templates = templates
+                    .into_iter()
+                    .map(|template| self.term_cell(template))
+                    .collect::<Vec<_>>();
+                let tail = self.empty_list();
+                let templates = self.build_list(&templates, tail);
+                let witness = self.term_cell(witness);
+                self.build_structure(pair, &[witness, templates])
+            })
+            .collect::<Vec<_>>();
+        let tail = self.empty_list();
+        let groups = self.build_list(&groups, tail);
+        Ok(self.unify_cell(argument(1), groups))
+    }
+
+    /// Whether `a` and `b` are equal up to renaming of their variables.
+    fn is_variant(&self, a: CellAddress, b: CellAddress) -> bool {
+        let mut renaming = HashMap::new();
+        let mut renamed = HashSet::new();
+        let mut compared = HashSet::new();
+        let mut pending = vec![(a, b)];
+        while let Some((a, b)) = pending.pop() {
+            let (a, b) = (self.deref_cell(a), self.deref_cell(b));
+            match (self.lookup_address(a), self.lookup_address(b)) {
+                (
+                    Cell::Reference(a) | Cell::AttributedVariable(a),
+                    Cell::Reference(b) | Cell::AttributedVariable(b),
+                ) => match renaming.get(a) {
+                    Some(renamed_to) if renamed_to != b => return false,
+                    Some(_) => {}
+                    None if !renamed.insert(*b) => return false,
+                    None => {
+                        renaming.insert(*a, *b);
+                    }
+                },
+                (a, b) if a.is_variable() || b.is_variable() => return false,
+                _ => match (self.structure(a), self.structure(b)) {
+                    (Some((a_functor, a_index)), Some((b_functor, b_index))) => {
+                        if a_functor != b_functor {
+                            return false;
+                        }
+                        if !compared.insert((a_index, b_index)) {
+                            continue;
+                        }
+                        let arity = self.compiler.descriptor_allocator.get(a_functor).arity();
+                        pending.extend((1..=arity).map(|i| {
+                            (
+                                CellAddress::GlobalStack { index: a_index + i },
+                                CellAddress::GlobalStack { index: b_index + i },
+                            )
+                        }));
+                    }
+                    (None, None) if self.compare_terms(a, b) == Ordering::Equal => {}
+                    _ => return false,
+                },
+            }
+        }
+        true
+    }
+}
+
+fn relocate(cell: &Cell, from: usize, to: usize) -> Cell {
+    match cell {
+        Cell::Reference(index) => Cell::Reference(index - from + to),
+        Cell::AttributedVariable(index) => Cell::AttributedVariable(index - from + to),
+        Cell::StructureRef(index) => Cell::StructureRef(index - from + to),
+        Cell::BigIntegerRef(index) => Cell::BigIntegerRef(index - from + to),
+        Cell::RationalRef(index) => Cell::RationalRef(index - from + to),
+        Cell::StringRef(index) => Cell::StringRef(index - from + to),
+        cell => cell.clone(),
+    }
+}
@@ -0,0 +1,869 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, ErrorKind, Write},
+    rc::Rc,
+};
+
+use crate::{
+    interpreter::{
+        Cell, CellAddress, Interpreter,
+        builtins::argument,
+        error::{ErrorCulprit, PrologError},
+    },
+    parsing::{ClauseEnd, clause_end},
+};
+
+const USER_INPUT: usize = 0;
+const USER_OUTPUT: usize = 1;
+const USER_ERROR: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StreamMode {
+    Read,
+    Write,
+    Append,
+}
+
+/// What reading from a stream that is past its end does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EofAction {
+    /// Raise a permission error.
+    Error,
+    /// Return the end of file marker again.
+    EofCode,
+    /// Try reading again, for streams that can receive more input such as the standard input.
+    Reset,
+}
+
+/// The unit read or written by a character I/O builtin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum IoUnit {
+    Char,
+    Code,
+    Byte,
+}
+
+#[derive(Debug, Clone)]
+enum Backend {
+    /// Input served from `data`, the whole content of a file or the text given to the standard
+    /// input so far.
+    Input {
+        data: Vec<u8>,
+        offset: usize,
+    },
+    File(Rc<File>),
+    /// Output kept in memory: the standard output and error and `with_output_to/2` buffers.
+    Memory(String),
+}
+
+/// How far a stream has been read or written.
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct Position {
+    pub(super) chars: usize,
+    pub(super) bytes: usize,
+    /// Completed lines, so the current line is `lines + 1`.
+    pub(super) lines: usize,
+    pub(super) line_position: usize,
+}
+
+impl Position {
+    fn advance(&mut self, c: char) {
+        self.chars += 1;
+        self.bytes += c.len_utf8();
+        if c == '\n' {
+            self.lines += 1;
+            self.line_position = 0;
+        } else {
+            self.line_position += 1;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Stream {
+    file_name: Option<String>,
+    alias: Option<String>,
+    mode: StreamMode,
+    binary: bool,
+    eof_action: EofAction,
+    backend: Backend,
+    position: Position,
+    /// Set once a read returned the end of file marker.
+    past_end: bool,
+}
+
+impl Stream {
+    fn new(mode: StreamMode, backend: Backend) -> Self {
+        Stream {
+            file_name: None,
+            alias: None,
+            mode,
+            binary: false,
+            eof_action: EofAction::Error,
+            backend,
+            position: Position::default(),
+            past_end: false,
+        }
+    }
+
+    fn standard(alias: &str, mode: StreamMode, backend: Backend) -> Self {
+        Stream {
+            alias: Some(alias.to_string()),
+            eof_action: EofAction::Reset,
+            ..Stream::new(mode, backend)
+        }
+    }
+
+    fn is_input(&self) -> bool {
+        self.mode == StreamMode::Read
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        match &mut self.backend {
+            Backend::File(file) => file.as_ref().write_all(text.as_bytes())?,
+            Backend::Memory(buffer) => buffer.push_str(text),
+            Backend::Input { .. } => unreachable!("input streams are not written to"),
+        }
+        text.chars().for_each(|c| self.position.advance(c));
+        Ok(())
+    }
+
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        match &mut self.backend {
+            Backend::File(file) => file.as_ref().write_all(&[byte])?,
+            Backend::Memory(buffer) => buffer.push(char::from(byte)),
+            Backend::Input { .. } => unreachable!("input streams are not written to"),
+        }
+        self.position.bytes += 1;
+        Ok(())
+    }
+
+    /// The next character or byte and its length in bytes, `None` at the end of the stream.
+    fn next(&self, unit: IoUnit) -> Option<(u32, usize)> {
+        let Backend::Input { data, offset } = &self.backend else {
+            return None;
+        };
+        let rest = &data[*offset..];
+        let first = *rest.first()?;
+        if unit == IoUnit::Byte {
+            return Some((u32::from(first), 1));
+        }
+        let length = match first {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => 1,
+        }
+        .min(rest.len());
+        let c = std::str::from_utf8(&rest[..length])
+            .ok()
+            .and_then(|text| text.chars().next())
+            .unwrap_or(char::REPLACEMENT_CHARACTER);
+        Some((c as u32, length))
+    }
+
+    /// Reads the next character or byte, leaving it in the stream if `peek` is set. At the end
+    /// of the stream the stream is marked as past its end, unless it can receive more input.
+    fn read(&mut self, unit: IoUnit, peek: bool) -> Option<u32> {
+        let Some((value, length)) = self.next(unit) else {
+            if !peek && self.eof_action != EofAction::Reset {
+                self.past_end = true;
+            }
+            return None;
+        };
+        if !peek {
+            if let Backend::Input { offset, .. } = &mut self.backend {
+                *offset += length;
+            }
+            match char::from_u32(value) {
+                Some(c) if unit != IoUnit::Byte => self.position.advance(c),
+                _ => self.position.bytes += 1,
+            }
+        }
+        Some(value)
+    }
+
+    fn end_of_stream(&self) -> &'static str {
+        match &self.backend {
+            _ if self.past_end => "past",
+            Backend::Input { data, offset } if *offset < data.len() => "not",
+            _ => "at",
+        }
+    }
+}
+
+/// The open streams, with the current input and output.
+#[derive(Debug, Clone)]
+pub(super) struct StreamTable {
+    /// Streams by number, `None` once closed. The first three are the standard streams, which
+    /// cannot be closed.
+    streams: Vec<Option<Stream>>,
+    input: usize,
+    output: usize,
+}
+
+impl Default for StreamTable {
+    fn default() -> Self {
+        let input = Backend::Input {
+            data: Vec::new(),
+            offset: 0,
+        };
+        StreamTable {
+            streams: vec![
+                Some(Stream::standard("user_input", StreamMode::Read, input)),
+                Some(Stream::standard(
+                    "user_output",
+                    StreamMode::Append,
+                    Backend::Memory(String::new()),
+                )),
+                Some(Stream::standard(
+                    "user_error",
+                    StreamMode::Append,
+                    Backend::Memory(String::new()),
+                )),
+            ],
+            input: USER_INPUT,
+            output: USER_OUTPUT,
+        }
+    }
+}
+
+impl StreamTable {
+    fn get(&self, stream: usize) -> &Stream {
+        self.streams[stream].as_ref().expect("stream to be open")
+    }
+
+    fn get_mut(&mut self, stream: usize) -> &mut Stream {
+        self.streams[stream].as_mut().expect("stream to be open")
+    }
+
+    fn add(&mut self, stream: Stream) -> usize {
+        self.streams.push(Some(stream));
+        self.streams.len() - 1
+    }
+
+    fn alias(&self, alias: &str) -> Option<usize> {
+        self.streams.iter().position(|stream| {
+            stream
+                .as_ref()
+                .is_some_and(|stream| stream.alias.as_deref() == Some(alias))
+        })
+    }
+
+    fn close(&mut self, stream: usize) -> Option<Stream> {
+        if stream <= USER_ERROR {
+            return None;
+        }
+        if self.input == stream {
+            self.input = USER_INPUT;
+        }
+        if self.output == stream {
+            self.output = USER_OUTPUT;
+        }
+        self.streams[stream].take()
+    }
+
+    fn memory(&self, stream: usize) -> &str {
+        match &self.get(stream).backend {
+            Backend::Memory(text) => text,
+            _ => unreachable!("standard output streams are kept in memory"),
+        }
+    }
+}
+
+impl Interpreter {
+    /// Text written to the standard output.
+    pub fn output(&self) -> &str {
+        self.streams.memory(USER_OUTPUT)
+    }
+
+    /// Text written to the standard error.
+    pub fn error_output(&self) -> &str {
+        self.streams.memory(USER_ERROR)
+    }
+
+    /// Makes `text` available to reads from the standard input.
+    pub fn provide_input(&mut self, text: &str) {
+        if let Backend::Input { data, .. } = &mut self.streams.get_mut(USER_INPUT).backend {
+            data.extend_from_slice(text.as_bytes());
+        }
+    }
+
+    /// Writes `text` to the current output.
+    pub(super) fn emit(&mut self, text: &str) -> Result<(), PrologError> {
+        self.emit_to(self.streams.output, text)
+    }
+
+    pub(super) fn emit_to(&mut self, stream: usize, text: &str) -> Result<(), PrologError> {
+        self.streams
+            .get_mut(stream)
+            .write(text)
+            .map_err(|error| PrologError::SystemError(error.to_string()))
+    }
+
+    /// The column the next character written to `stream` goes to.
+    pub(super) fn column(&self, stream: usize) -> usize {
+        self.streams.get(stream).position.line_position
+    }
+
+    pub(super) fn current_output(&self) -> usize {
+        self.streams.output
+    }
+
+    /// The current input as a stream term on the global stack.
+    pub(super) fn current_input(&mut self) -> CellAddress {
+        let term = self.stream_term(self.streams.input);
+        self.global_stack.push(term);
+        CellAddress::GlobalStack {
+            index: self.global_stack.len() - 1,
+        }
+    }
+
+    /// Writes `text` to the standard error.
+    pub(super) fn emit_error(&mut self, text: &str) -> Result<(), PrologError> {
+        self.emit_to(USER_ERROR, text)
+    }
+
+    /// Resolves the stream or alias at `address` to an open stream.
+    fn stream_argument(&self, address: CellAddress) -> Result<usize, PrologError> {
+        let existence_error = PrologError::ExistenceError {
+            kind: "stream",
+            culprit: ErrorCulprit::Address(address),
+        };
+        match self.value(address) {
+            Cell::Reference(_) | Cell::AttributedVariable(_) => {
+                Err(PrologError::InstantiationError)
+            }
+            Cell::Constant(id) => {
+                let name = &self.compiler.descriptor_allocator.get(*id).name;
+                self.streams.alias(name).ok_or(existence_error)
+            }
+            _ => {
+                let stream = self
+                    .structure(address)
+                    .filter(|(functor, _)| {
+                        let descriptor = self.compiler.descriptor_allocator.get(*functor);
+                        descriptor.name == "$stream" && descriptor.arity() == 1
+                    })
+                    .and_then(|(_, index)| {
+                        match self.value(CellAddress::GlobalStack { index: index + 1 }) {
+                            Cell::Integer(stream) => usize::try_from(*stream).ok(),
+                            _ => None,
+                        }
+                    })
+                    .ok_or(PrologError::domain_error("stream_or_alias", address))?;
+                match self.streams.streams.get(stream) {
+                    Some(Some(_)) => Ok(stream),
+                    _ => Err(existence_error),
+                }
+            }
+        }
+    }
+
+    /// Resolves the stream at `address` and checks it can be used for `action`, `input` or
+    /// `output`, and if given, that it is a binary or a text stream.
+    pub(super) fn checked_stream(
+        &self,
+        address: CellAddress,
+        action: &'static str,
+        binary: Option<bool>,
+    ) -> Result<usize, PrologError> {
+        let stream = self.stream_argument(address)?;
+        let permission_error = |kind| PrologError::PermissionError {
+            action,
+            kind,
+            culprit: ErrorCulprit::Address(address),
+        };
+        let entry = self.streams.get(stream);
+        if entry.is_input() != (action == "input") {
+            return Err(permission_error("stream"));
+        }
+        match binary {
+            Some(binary) if binary != entry.binary => Err(permission_error(if entry.binary {
+                "binary_stream"
+            } else {
+                "text_stream"
+            })),
+            _ => Ok(stream),
+        }
+    }
+
+    fn stream_term(&mut self, stream: usize) -> Cell {
+        let functor = self
+            .compiler
+            .descriptor_allocator
+            .get_or_set_functor("$stream", 1);
+        self.build_structure(functor, &[Cell::Integer(stream as i64)])
+    }
+
+    pub(super) fn atom_name(&self, address: CellAddress) -> Option<String> {
+        match self.value(address) {
+            Cell::Constant(id) => Some(self.compiler.descriptor_allocator.get(*id).name.clone()),
+            _ => None,
+        }
+    }
+
+    /// `open(Source, Mode, Stream, Options)`.
+    pub(super) fn open(&mut self) -> Result<bool, PrologError> {
+        let (source, mode, stream) = (argument(0), argument(1), argument(2));
+        if self.is_unbound(source) || self.is_unbound(mode) {
+            return Err(PrologError::InstantiationError);
+        }
+        let file_name = self
+            .atom_name(source)
+            .ok_or(PrologError::domain_error("source_sink", source))?;
+        let mode = match self
+            .atom_name(mode)
+            .ok_or(PrologError::type_error("atom", mode))?
+            .as_str()
+        {
+            "read" => StreamMode::Read,
+            "write" => StreamMode::Write,
+            "append" => StreamMode::Append,
+            _ => return Err(PrologError::domain_error("io_mode", mode)),
+        };
+        if !self.is_unbound(stream) {
+            return Err(PrologError::type_error("variable", stream));
+        }
+
+        let mut entry = Stream::new(
+            mode,
+            Backend::Memory(String::new()), // Replaced once the options are valid.
+        );
+        for option in self.list_argument(argument(3))? {
+            if self.is_unbound(option) {
+                return Err(PrologError::InstantiationError);
+            }
+            let Some((functor, index)) = self.structure(option) else {
+                return Err(PrologError::domain_error("stream_option", option));
+            };
+            let value = CellAddress::GlobalStack { index: index + 1 };
+            if self.is_unbound(value) {
+                return Err(PrologError::InstantiationError);
+            }
+            let descriptor = self.compiler.descriptor_allocator.get(functor);
+            let name = (descriptor.arity() == 1).then(|| descriptor.name.clone());
+            let value_name = self.atom_name(value);
+            match (name.as_deref(), value_name.as_deref()) {
+                (Some("type"), Some("text")) => entry.binary = false,
+                (Some("type"), Some("binary")) => entry.binary = true,
+                (Some("eof_action"), Some("error")) => entry.eof_action = EofAction::Error,
+                (Some("eof_action"), Some("eof_code")) => entry.eof_action = EofAction::EofCode,
+                (Some("eof_action"), Some("reset")) => entry.eof_action = EofAction::Reset,
+                (Some("reposition"), Some("true" | "false")) | (Some("encoding"), Some(_)) => {}
+                (Some("alias"), Some(alias)) => {
+                    if self.streams.alias(alias).is_some() {
+                        return Err(PrologError::PermissionError {
+                            action: "open",
+                            kind: "source_sink",
+                            culprit: ErrorCulprit::Address(option),
+                        });
+                    }
+                    entry.alias = Some(alias.to_string());
+                }
+                _ => return Err(PrologError::domain_error("stream_option", option)),
+            }
+        }
+
+        let backend = match mode {
+            StreamMode::Read => {
+                std::fs::read(&file_name).map(|data| Backend::Input { data, offset: 0 })
+            }
+            StreamMode::Write => File::create(&file_name).map(|file| Backend::File(Rc::new(file))),
+            StreamMode::Append => OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(&file_name)
+                .map(|file| Backend::File(Rc::new(file))),
+        };
+        entry.backend = backend.map_err(|error| match error.kind() {
+            ErrorKind::NotFound => PrologError::ExistenceError {
+                kind: "source_sink",
+                culprit: ErrorCulprit::Address(source),
+            },
+            _ => PrologError::PermissionError {
+                action: "open",
+                kind: "source_sink",
+                culprit: ErrorCulprit::Address(source),
+            },
+        })?;
+        entry.file_name = Some(file_name);
+
+        let stream_id = self.streams.add(entry);
+        let term = self.stream_term(stream_id);
+        Ok(self.unify_cell(stream, term))
+    }
+
+    /// `close(Stream, Options)`. Closing a standard stream does nothing.
+    pub(super) fn close(&mut self) -> Result<bool, PrologError> {
+        let stream = self.stream_argument(argument(0))?;
+        for option in self.list_argument(argument(1))? {
+            if self.is_unbound(option) {
+                return Err(PrologError::InstantiationError);
+            }
+            let valid = self.structure(option).is_some_and(|(functor, index)| {
+                let descriptor = self.compiler.descriptor_allocator.get(functor);
+                let value = self.atom_name(CellAddress::GlobalStack { index: index + 1 });
+                descriptor.name == "force"
+                    && descriptor.arity() == 1
+                    && matches!(value.as_deref(), Some("true" | "false"))
+            });
+            if !valid {
+                return Err(PrologError::domain_error("close_option", option));
+            }
+        }
+        self.streams.close(stream);
+        Ok(true)
+    }
+
+    /// `set_input(Stream)` and `set_output(Stream)`.
+    pub(super) fn set_stream(&mut self, action: &'static str) -> Result<bool, PrologError> {
+        let stream = self.checked_stream(argument(0), action, None)?;
+        match action {
+            "input" => self.streams.input = stream,
+            _ => self.streams.output = stream,
+        }
+        Ok(true)
+    }
+
+    /// `current_input(Stream)` and `current_output(Stream)`.
+    pub(super) fn current_stream(&mut self, action: &'static str) -> Result<bool, PrologError> {
+        let address = argument(0);
+        if !self.is_unbound(address) && self.structure(address).is_none() {
+            return Err(PrologError::domain_error("stream", address));
+        }
+        let stream = match action {
+            "input" => self.streams.input,
+            _ => self.streams.output,
+        };
+        let term = self.stream_term(stream);
+        Ok(self.unify_cell(address, term))
+    }
+
+    /// `get_char/2`, `get_code/2`, `get_byte/2` and their `peek_` counterparts. The end of the
+    /// stream reads as `end_of_file` or -1.
+    pub(super) fn get(&mut self, unit: IoUnit, peek: bool) -> Result<bool, PrologError> {
+        let (address, result) = (argument(0), argument(1));
+        if !self.is_unbound(result) {
+            match (unit, self.value(result)) {
+                (IoUnit::Char, Cell::Constant(id)) => {
+                    let mut chars = self.compiler.descriptor_allocator.get(*id).name.chars();
+                    if chars.next().is_none() || chars.next().is_some() {
+                        return Err(PrologError::type_error("in_character", result));
+                    }
+                }
+                (IoUnit::Char, _) => return Err(PrologError::type_error("in_character", result)),
+                (IoUnit::Code, Cell::Integer(code)) => {
+                    if *code != -1 && u32::try_from(*code).ok().and_then(char::from_u32).is_none() {
+                        return Err(PrologError::RepresentationError("in_character_code"));
+                    }
+                }
+                (IoUnit::Code, _) => return Err(PrologError::type_error("integer", result)),
+                (IoUnit::Byte, Cell::Integer(-1..=255)) => {}
+                (IoUnit::Byte, _) => return Err(PrologError::type_error("in_byte", result)),
+            }
+        }
+
+        let stream = self.checked_stream(address, "input", Some(unit == IoUnit::Byte))?;
+        let value = match self.is_past_end(stream, address)? {
+            true => None,
+            false => self.streams.get_mut(stream).read(unit, peek),
+        };
+        let cell = match (unit, value.and_then(char::from_u32)) {
+            (IoUnit::Char, Some(c)) => self.atom(c.encode_utf8(&mut [0; 4])),
+            (IoUnit::Char, None) => self.atom("end_of_file"),
+            _ => Cell::Integer(value.map_or(-1, i64::from)),
+        };
+        Ok(self.unify_cell(result, cell))
+    }
+
+    /// Applies the end of file action of `stream`, the stream at `address`, if it is past its
+    /// end. Returns whether reading should give the end of file marker without trying.
+    fn is_past_end(&mut self, stream: usize, address: CellAddress) -> Result<bool, PrologError> {
+        let entry = self.streams.get_mut(stream);
+        if !entry.past_end {
+            return Ok(false);
+        }
+        match entry.eof_action {
+            EofAction::Error => Err(PrologError::PermissionError {
+                action: "input",
+                kind: "past_end_of_stream",
+                culprit: ErrorCulprit::Address(address),
+            }),
+            EofAction::EofCode => Ok(true),
+            EofAction::Reset => {
+                entry.past_end = false;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Takes the text of the next clause from the text input stream at `address`, up to its
+    /// end token and the layout character following it. Text without an end token is taken
+    /// whole for the parser to report. Returns `None` at the end of the stream.
+    pub(super) fn take_clause(
+        &mut self,
+        address: CellAddress,
+    ) -> Result<Option<(String, Position)>, PrologError> {
+        let stream = self.checked_stream(address, "input", Some(false))?;
+        if self.is_past_end(stream, address)? {
+            return Ok(None);
+        }
+        let entry = self.streams.get_mut(stream);
+        let Backend::Input { data, offset } = &mut entry.backend else {
+            unreachable!("input streams read from memory");
+        };
+        let rest = &data[*offset..];
+        let rest = match std::str::from_utf8(rest) {
+            Ok(text) => text,
+            Err(error) => std::str::from_utf8(&rest[..error.valid_up_to()]).expect("valid prefix"),
+        };
+        let (length, empty) = match clause_end(rest) {
+            ClauseEnd::At(end) => {
+                let layout = rest[end..]
+                    .chars()
+                    .next()
+                    .filter(|c| c.is_whitespace())
+                    .map_or(0, char::len_utf8);
+                (end + layout, false)
+            }
+            ClauseEnd::Missing => (rest.len(), false),
+            ClauseEnd::Empty => (rest.len(), true),
+        };
+        let text = rest[..length].to_string();
+        *offset += length;
+        let mut start = entry.position;
+        text[..layout_length(&text)]
+            .chars()
+            .for_each(|c| start.advance(c));
+        text.chars().for_each(|c| entry.position.advance(c));
+        if empty {
+            if entry.eof_action != EofAction::Reset {
+                entry.past_end = true;
+            }
+            return Ok(None);
+        }
+        Ok(Some((text, start)))
+    }
+
+    /// The name of the file `address` reads from or writes to, if it is a file stream.
+    pub(super) fn stream_file_name(&self, address: CellAddress) -> Option<String> {
+        let stream = self.stream_argument(address).ok()?;
+        self.streams.get(stream).file_name.clone()
+    }
+
+    /// `put_char/2`, `put_code/2` and `put_byte/2`.
+    pub(super) fn put(&mut self, unit: IoUnit) -> Result<bool, PrologError> {
+        let stream = self.checked_stream(argument(0), "output", Some(unit == IoUnit::Byte))?;
+        let value = argument(1);
+        if self.is_unbound(value) {
+            return Err(PrologError::InstantiationError);
+        }
+        let c = match (unit, self.value(value)) {
+            (IoUnit::Char, Cell::Constant(id)) => {
+                let mut chars = self.compiler.descriptor_allocator.get(*id).name.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => c,
+                    _ => return Err(PrologError::type_error("character", value)),
+                }
+            }
+            (IoUnit::Char, _) => return Err(PrologError::type_error("character", value)),
+            (IoUnit::Code, Cell::Integer(code)) => u32::try_from(*code)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or(PrologError::RepresentationError("character_code"))?,
+            (IoUnit::Code, _) => return Err(PrologError::type_error("integer", value)),
+            (IoUnit::Byte, Cell::Integer(byte @ 0..=255)) => {
+                let byte = *byte as u8;
+                return self
+                    .streams
+                    .get_mut(stream)
+                    .write_byte(byte)
+                    .map(|_| true)
+                    .map_err(|error| PrologError::SystemError(error.to_string()));
+            }
+            (IoUnit::Byte, _) => return Err(PrologError::type_error("byte", value)),
+        };
+        self.emit_to(stream, c.encode_utf8(&mut [0; 4]))?;
+        Ok(true)
+    }
+
+    /// `flush_output(Stream)`.
+    pub(super) fn flush_output(&mut self) -> Result<bool, PrologError> {
+        let stream = self.checked_stream(argument(0), "output", None)?;
+        if let Backend::File(file) = &self.streams.get(stream).backend {
+            file.as_ref()
+                .flush()
+                .map_err(|error| PrologError::SystemError(error.to_string()))?;
+        }
+        Ok(true)
+    }
+
+    /// `at_end_of_stream(Stream)`.
+    pub(super) fn at_end_of_stream(&mut self) -> Result<bool, PrologError> {
+        let stream = self.checked_stream(argument(0), "input", None)?;
+        Ok(self.streams.get(stream).end_of_stream() != "not")
+    }
+
+    /// `'$streams'(Stream, Streams)`, unifies `Streams` with `[Stream]` if `Stream` is bound to
+    /// an open stream, or with every open stream otherwise.
+    pub(super) fn stream_list(&mut self) -> Result<bool, PrologError> {
+        let address = argument(0);
+        let streams = if self.is_unbound(address) {
+            let open = (0..self.streams.streams.len())
+                .filter(|stream| self.streams.streams[*stream].is_some())
+                .collect::<Vec<_>>();
+            open.into_iter()
+                .map(|stream| self.stream_term(stream))
+                .collect()
+        } else {
+            self.stream_argument(address)?;
+            vec![self.term_cell(address)]
+        };
+        let tail = self.empty_list();
+        let streams = self.build_list(&streams, tail);
+        Ok(self.unify_cell(argument(1), streams))
+    }
+
+    /// `'$stream_properties'(Stream, Properties)`.
+    pub(super) fn stream_properties(&mut self) -> Result<bool, PrologError> {
+        let entry = self.streams.get(self.stream_argument(argument(0))?).clone();
+        let direction = self.atom(if entry.is_input() { "input" } else { "output" });
+        let mut properties = vec![direction];
+        let mut property = |interpreter: &mut Self, name: &str, value: Cell| {
+            let functor = interpreter
+                .compiler
+                .descriptor_allocator
+                .get_or_set_functor(name, 1);
+            properties.push(interpreter.build_structure(functor, &[value]));
+        };
+
+        if let Some(file_name) = &entry.file_name {
+            let value = self.atom(file_name);
+            property(self, "file_name", value);
+        }
+        let mode = self.atom(match entry.mode {
+            StreamMode::Read => "read",
+            StreamMode::Write => "write",
+            StreamMode::Append => "append",
+        });
+        property(self, "mode", mode);
+        if let Some(alias) = &entry.alias {
+            let value = self.atom(alias);
+            property(self, "alias", value);
+        }
+        let position = self
+            .compiler
+            .descriptor_allocator
+            .get_or_set_functor("$stream_position", 4);
+        let position = self.build_structure(
+            position,
+            &[
+                entry.position.chars,
+                entry.position.lines + 1,
+                entry.position.line_position,
+                entry.position.bytes,
+            ]
+            .map(|count| Cell::Integer(count as i64)),
+        );
+        property(self, "position", position);
+        if entry.is_input() {
+            let end = self.atom(entry.end_of_stream());
+            property(self, "end_of_stream", end);
+        }
+        let eof_action = self.atom(match entry.eof_action {
+            EofAction::Error => "error",
+            EofAction::EofCode => "eof_code",
+            EofAction::Reset => "reset",
+        });
+        property(self, "eof_action", eof_action);
+        let kind = self.atom(if entry.binary { "binary" } else { "text" });
+        property(self, "type", kind);
+        let reposition = self.atom("false");
+        property(self, "reposition", reposition);
+
+        let tail = self.empty_list();
+        let properties = self.build_list(&properties, tail);
+        Ok(self.unify_cell(argument(1), properties))
+    }
+
+    /// `'$memory_output'(Sink, Stream)`, opens a stream collecting output in memory for
+    /// `with_output_to/2`, after checking `Sink`.
+    pub(super) fn memory_output(&mut self) -> Result<bool, PrologError> {
+        self.sink(argument(0))?
+            .ok_or(PrologError::domain_error("output_sink", argument(0)))?;
+        let stream = self.streams.add(Stream::new(
+            StreamMode::Write,
+            Backend::Memory(String::new()),
+        ));
+        let term = self.stream_term(stream);
+        Ok(self.unify_cell(argument(1), term))
+    }
+
+    /// `'$memory_output_text'(Stream, Sink)`, closes a stream opened by `'$memory_output'/2`
+    /// and unifies the sink with the text written to it.
+    pub(super) fn memory_output_text(&mut self) -> Result<bool, PrologError> {
+        let stream = self.stream_argument(argument(0))?;
+        let text = self.streams.memory(stream).to_string();
+        self.streams.close(stream);
+        self.unify_sink(argument(1), &text)
+    }
+
+    /// Reads `atom(A)`, `string(S)`, `codes(C)` or `chars(C)`, returning the kind of text and
+    /// the address of its argument. Anything else is `None`.
+    pub(super) fn sink(
+        &self,
+        address: CellAddress,
+    ) -> Result<Option<(String, CellAddress)>, PrologError> {
+        if self.is_unbound(address) {
+            return Err(PrologError::InstantiationError);
+        }
+        Ok(self.structure(address).and_then(|(functor, index)| {
+            let descriptor = self.compiler.descriptor_allocator.get(functor);
+            (descriptor.arity() == 1
+                && matches!(
+                    descriptor.name.as_str(),
+                    "atom" | "string" | "codes" | "chars"
+                ))
+            .then(|| {
+                (
+                    descriptor.name.clone(),
+                    CellAddress::GlobalStack { index: index + 1 },
+                )
+            })
+        }))
+    }
+
+    /// Unifies the sink at `address` with `text`, see [`Interpreter::sink`].
+    pub(super) fn unify_sink(
+        &mut self,
+        address: CellAddress,
+        text: &str,
+    ) -> Result<bool, PrologError> {
+        let (kind, value) = self
+            .sink(address)?
+            .ok_or(PrologError::domain_error("output_sink", address))?;
+        let result = match kind.as_str() {
+            "atom" => self.atom(text),
+            "chars" => self.build_chars(text),
+            "codes" => self.build_codes(text),
+            _ => self.allocate_string(text),
+        };
+        Ok(self.unify_cell(value, result))
+    }
+}
+
+/// Length of the layout and comments `text` starts with.
+fn layout_length(text: &str) -> usize {
+    let mut rest = text;
+    loop {
+        let trimmed = rest.trim_start();
+        rest = if let Some(comment) = trimmed.strip_prefix('%') {
+            comment.find('\n').map_or("", |end| &comment[end..])
+        } else if let Some(comment) = trimmed.strip_prefix("/*") {
+            comment.find("*/").map_or("", |end| &comment[end + 2..])
+        } else {
+            return text.len() - trimmed.len();
+        };
+    }
+}
@@ -0,0 +1,655 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    instructions::DescriptorId,
+    interpreter::{
+        Cell, CellAddress, Interpreter,
+        builtins::argument,
+        error::{ErrorCulprit, PrologError},
+    },
+    number::Number,
+};
+
+/// The largest arity of a compound term, reported by the `max_arity` flag.
+pub(super) const MAX_ARITY: usize = 1 << 20;
+
+/// The shape of a term read as a list.
+pub(super) enum ListShape {
+    /// A proper list, holding the addresses of its elements.
+    Proper(Vec<CellAddress>),
+    /// A list whose tail is an unbound variable.
+    Partial,
+    /// Anything else.
+    Invalid,
+}
+
+impl Interpreter {
+    /// The cell the term at `address` dereferences to.
+    pub(super) fn value(&self, address: CellAddress) -> &Cell {
+        self.lookup_address(self.deref_cell(address))
+    }
+
+    pub(super) fn is_unbound(&self, address: CellAddress) -> bool {
+        self.value(address).is_variable()
+    }
+
+    /// Returns the functor and the global stack address of the structure at `address`.
+    pub(super) fn structure(&self, address: CellAddress) -> Option<(DescriptorId, usize)> {
+        let Cell::StructureRef(index) = self.value(address) else {
+            return None;
+        };
+        let Cell::Structure(functor) = self.global_stack[*index] else {
+            unreachable!("structure references always point to a structure");
+        };
+        Some((functor, *index))
+    }
+
+    /// Returns a cell referring to the term at `address`, suitable to be stored inside another
+    /// term.
+    pub(super) fn term_cell(&self, address: CellAddress) -> Cell {
+        let address = self.deref_cell(address);
+        match (address, self.lookup_address(address)) {
+            (CellAddress::GlobalStack { index }, cell) if cell.is_variable() => {
+                Cell::Reference(index)
+            }
+            (_, cell) => cell.clone(),
+        }
+    }
+
+    /// Pushes a fresh unbound variable on the global stack.
+    pub(super) fn new_variable(&mut self) -> Cell {
+        let index = self.global_stack.len();
+        self.global_stack.push(Cell::Reference(index));
+        Cell::Reference(index)
+    }
+
+    /// Builds `functor(arguments...)` on the global stack.
+    pub(super) fn build_structure(&mut self, functor: DescriptorId, arguments: &[Cell]) -> Cell {
+        let index = self.global_stack.len();
+        self.global_stack.push(Cell::Structure(functor));
+        self.global_stack.extend_from_slice(arguments);
+        Cell::StructureRef(index)
+    }
+
+    /// Builds the list of `elements` ending in `tail` on the global stack.
+    pub(super) fn build_list(&mut self, elements: &[Cell], tail: Cell) -> Cell {
+        let dot = self
+            .compiler
+            .descriptor_allocator
+            .get_or_set_functor(".", 2);
+        elements.iter().rev().fold(tail, |tail, element| {
+            self.build_structure(dot, &[element.clone(), tail])
+        })
+    }
+
+    pub(super) fn atom(&mut self, name: &str) -> Cell {
+        Cell::Constant(
+            self.compiler
+                .descriptor_allocator
+                .get_or_set_functor(name, 0),
+        )
+    }
+
+    pub(super) fn empty_list(&mut self) -> Cell {
+        self.atom("[]")
+    }
+
+    /// Unifies the term at `address` with `cell`.
+    pub(super) fn unify_cell(&mut self, address: CellAddress, cell: Cell) -> bool {
+        self.global_stack.push(cell);
+        let index = self.global_stack.len() - 1;
+        self.unify(address, CellAddress::GlobalStack { index })
+    }
+
+    pub(super) fn read_list(&self, address: CellAddress) -> ListShape {
+        let Some((elements, tail)) = self.walk_list(address) else {
+            return ListShape::Invalid;
+        };
+        match self.value(tail) {
+            Cell::Reference(_) | Cell::AttributedVariable(_) => ListShape::Partial,
+            Cell::Constant(id) if self.compiler.descriptor_allocator.get(*id).name == "[]" => {
+                ListShape::Proper(elements)
+            }
+            _ => ListShape::Invalid,
+        }
+    }
+
+    /// Follows the list cells starting at `address`, returning the addresses of their elements
+    /// and of the first tail which is no list cell. Returns `None` for a cyclic list.
+    fn walk_list(&self, address: CellAddress) -> Option<(Vec<CellAddress>, CellAddress)> {
+        let mut elements = Vec::new();
+        let mut current = address;
+        // Brent's algorithm: a cyclic list returns to the cell marked at the last power of two.
+        let (mut mark, mut steps, mut lap) = (None, 0, 1);
+        while let Some((functor, index)) = self.structure(current)
+            && self.is_list_functor(functor)
+        {
+            if mark == Some(index) {
+                return None;
+            }
+            steps += 1;
+            if steps == lap {
+                (mark, steps, lap) = (Some(index), 0, lap * 2);
+            }
+            elements.push(CellAddress::GlobalStack { index: index + 1 });
+            current = CellAddress::GlobalStack { index: index + 2 };
+        }
+        Some((elements, current))
+    }
+
+    /// `'$skip_list'(List, Length, Tail)`, the number of elements of the proper or partial
+    /// list `List` and its tail, `[]` or a variable. Raises `type_error(list, List)` for
+    /// cyclic lists and lists ending in anything else.
+    pub(super) fn skip_list(&mut self) -> Result<bool, PrologError> {
+        let list = argument(0);
+        let Some((elements, tail)) = self.walk_list(list) else {
+            return Err(PrologError::type_error("list", list));
+        };
+        let tail = self.term_cell(tail);
+        if !tail.is_variable() && tail != self.empty_list() {
+            return Err(PrologError::type_error("list", list));
+        }
+        Ok(
+            self.unify_cell(argument(1), Cell::Integer(elements.len() as i64))
+                && self.unify_cell(argument(2), tail),
+        )
+    }
+
+    /// Reads the proper list at `address`, raising an error for partial lists and non-lists.
+    pub(super) fn list_argument(
+        &self,
+        address: CellAddress,
+    ) -> Result<Vec<CellAddress>, PrologError> {
+        match self.read_list(address) {
+            ListShape::Proper(elements) => Ok(elements),
+            ListShape::Partial => Err(PrologError::InstantiationError),
+            ListShape::Invalid => Err(PrologError::type_error("list", address)),
+        }
+    }
+
+    /// Reads text given as an atom, a number or a list of character codes or characters. The
+    /// empty list is the empty text.
+    pub(super) fn read_text(&self, address: CellAddress) -> Option<String> {
+        if let ListShape::Proper(elements) = self.read_list(address) {
+            return elements
+                .into_iter()
+                .map(|element| match self.value(element) {
+                    Cell::Integer(code) => u32::try_from(*code).ok().and_then(char::from_u32),
+                    Cell::Constant(id) => {
+                        let mut chars = self.compiler.descriptor_allocator.get(*id).name.chars();
+                        chars.next().filter(|_| chars.next().is_none())
+                    }
+                    _ => None,
+                })
+                .collect();
+        }
+        match self.value(address) {
+            Cell::Constant(id) => Some(self.compiler.descriptor_allocator.get(*id).name.clone()),
+            cell @ Cell::StringRef(_) => self.read_string(cell),
+            cell => self.read_number(cell).map(|number| number.to_string()),
+        }
+    }
+
+    /// Builds the list of the character codes of `text`.
+    pub(super) fn build_codes(&mut self, text: &str) -> Cell {
+        let codes = text
+            .chars()
+            .map(|c| Cell::Integer(c as i64))
+            .collect::<Vec<_>>();
+        let tail = self.empty_list();
+        self.build_list(&codes, tail)
+    }
+
+    /// Builds the list of the characters of `text`, as one-character atoms.
+    pub(super) fn build_chars(&mut self, text: &str) -> Cell {
+        let chars = text
+            .chars()
+            .map(|c| self.atom(c.encode_utf8(&mut [0; 4])))
+            .collect::<Vec<_>>();
+        let tail = self.empty_list();
+        self.build_list(&chars, tail)
+    }
+
+    fn is_list_functor(&self, functor: DescriptorId) -> bool {
+        let descriptor = self.compiler.descriptor_allocator.get(functor);
+        descriptor.name == "." && descriptor.arity() == 2
+    }
+
+    pub(super) fn is_atomic(&self, address: CellAddress) -> bool {
+        !matches!(
+            self.value(address),
+            Cell::Reference(_) | Cell::AttributedVariable(_) | Cell::StructureRef(_)
+        )
+    }
+
+    pub(super) fn is_integer(&self, address: CellAddress) -> bool {
+        self.read_number(self.value(address))
+            .is_some_and(|number| number.is_integer())
+    }
+
+    pub(super) fn is_ground(&self, address: CellAddress) -> bool {
+        let mut visited = HashSet::new();
+        let mut pending = vec![address];
+        while let Some(address) = pending.pop() {
+            if self.is_unbound(address) {
+                return false;
+            }
+            if let Some((functor, index)) = self.structure(address)
+                && visited.insert(index)
+            {
+                let arity = self.compiler.descriptor_allocator.get(functor).arity();
+                pending.extend((1..=arity).map(|i| CellAddress::GlobalStack { index: index + i }));
+            }
+        }
+        true
+    }
+
+    /// Whether the term at `address` is finite, i.e. no structure contains itself.
+    pub(super) fn is_acyclic(&self, address: CellAddress) -> bool {
+        // The structures being visited are those on the path from the root to the current one.
+        let (mut visiting, mut visited) = (HashSet::new(), HashSet::new());
+        let mut pending = vec![(address, false)];
+        while let Some((address, leaving)) = pending.pop() {
+            let Some((functor, index)) = self.structure(address) else {
+                continue;
+            };
+            if leaving {
+                visiting.remove(&index);
+                visited.insert(index);
+                continue;
+            }
+            if visited.contains(&index) {
+                continue;
+            }
+            if !visiting.insert(index) {
+                return false;
+            }
+            pending.push((address, true));
+            let arity = self.compiler.descriptor_allocator.get(functor).arity();
+            pending.extend(
+                (1..=arity).map(|i| (CellAddress::GlobalStack { index: index + i }, false)),
+            );
+        }
+        true
+    }
+
+    /// Raises a type error if the term at `address` is cyclic. Terms are checked before being
+    /// read into their abstract form, which cannot represent cycles.
+    pub(super) fn must_be_acyclic(&self, address: CellAddress) -> Result<(), PrologError> {
+        match self.is_acyclic(address) {
+            true => Ok(()),
+            false => Err(PrologError::type_error("acyclic_term", address)),
+        }
+    }
+
+    /// The unbound variables of the term at `address` by global stack address, in depth-first
+    /// order and without duplicates.
+    pub(super) fn variables(&self, address: CellAddress) -> Vec<usize> {
+        let mut variables = Vec::new();
+        let mut visited = HashSet::new();
+        let mut pending = vec![address];
+        while let Some(address) = pending.pop() {
+            match (self.deref_cell(address), self.value(address)) {
+                (CellAddress::GlobalStack { index }, cell) if cell.is_variable() => {
+                    if !variables.contains(&index) {
+                        variables.push(index);
+                    }
+                }
+                _ => {
+                    if let Some((functor, index)) = self.structure(address)
+                        && visited.insert(index)
+                    {
+                        let arity = self.compiler.descriptor_allocator.get(functor).arity();
+                        // Pushed in reverse so the leftmost argument is visited first.
+                        pending.extend(
+                            (1..=arity)
+                                .rev()
+                                .map(|i| CellAddress::GlobalStack { index: index + i }),
+                        );
+                    }
+                }
+            }
+        }
+        variables
+    }
+
+    /// Reads the integer argument at `address`, raising the usual errors if it isn't one.
+    pub(super) fn integer_argument(&self, address: CellAddress) -> Result<Number, PrologError> {
+        match self.read_number(self.value(address)) {
+            Some(number) if number.is_integer() => Ok(number),
+            _ if self.is_unbound(address) => Err(PrologError::InstantiationError),
+            _ => Err(PrologError::type_error("integer", address)),
+        }
+    }
+
+    /// `must_be(Type, Term)`: succeeds if `Term` is of `Type`, raises the error a builtin
+    /// expecting `Type` would raise otherwise. Used by the library to check its arguments.
+    pub(super) fn must_be(&self) -> Result<bool, PrologError> {
+        let (kind, term) = (argument(0), argument(1));
+        let name = match self.value(kind) {
+            Cell::Reference(_) | Cell::AttributedVariable(_) => {
+                return Err(PrologError::InstantiationError);
+            }
+            Cell::Constant(id) => self.compiler.descriptor_allocator.get(*id).name.as_str(),
+            _ => return Err(PrologError::type_error("atom", kind)),
+        };
+        if name == "var" {
+            return match self.is_unbound(term) {
+                true => Ok(true),
+                false => Err(PrologError::UninstantiationError(ErrorCulprit::Address(
+                    term,
+                ))),
+            };
+        }
+        if self.is_unbound(term) {
+            return match name {
+                "integer" | "nonneg" | "positive_integer" | "atom" | "atomic" | "callable"
+                | "boolean" | "list" => Err(PrologError::InstantiationError),
+                _ => Err(PrologError::domain_error("type", kind)),
+            };
+        }
+        match name {
+            "integer" => {
+                self.integer_argument(term)?;
+            }
+            "nonneg" | "positive_integer" => {
+                let number = self.integer_argument(term)?;
+                if number.is_negative() || (name == "positive_integer" && number.is_zero()) {
+                    return Err(PrologError::type_error(
+                        match name {
+                            "nonneg" => "nonneg",
+                            _ => "positive_integer",
+                        },
+                        term,
+                    ));
+                }
+            }
+            "atom" if !matches!(self.value(term), Cell::Constant(_)) => {
+                return Err(PrologError::type_error("atom", term));
+            }
+            "atomic" if !self.is_atomic(term) => {
+                return Err(PrologError::type_error("atomic", term));
+            }
+            "callable"
+                if !matches!(self.value(term), Cell::Constant(_) | Cell::StructureRef(_)) =>
+            {
+                return Err(PrologError::type_error("callable", term));
+            }
+            "boolean" => {
+                let is_boolean = match self.value(term) {
+                    Cell::Constant(id) => {
+                        matches!(
+                            self.compiler.descriptor_allocator.get(*id).name.as_str(),
+                            "true" | "false"
+                        )
+                    }
+                    _ => false,
+                };
+                if !is_boolean {
+                    return Err(PrologError::type_error("boolean", term));
+                }
+            }
+            "list" => match self.read_list(term) {
+                ListShape::Proper(_) => {}
+                ListShape::Partial => return Err(PrologError::InstantiationError),
+                ListShape::Invalid => return Err(PrologError::type_error("list", term)),
+            },
+            "atom" | "atomic" | "callable" => {}
+            _ => return Err(PrologError::domain_error("type", kind)),
+        }
+        Ok(true)
+    }
+
+    /// `'$domain_error'(Domain, Culprit)`, raises `domain_error(Domain, Culprit)` for one of
+    /// the domains the bundled libraries check.
+    pub(super) fn raise_domain_error(&self) -> Result<bool, PrologError> {
+        const DOMAINS: &[&str] = &["labeling_option", "not_less_than_zero"];
+        let domain = argument(0);
+        if self.is_unbound(domain) {
+            return Err(PrologError::InstantiationError);
+        }
+        let name = self.atom_name(domain);
+        match DOMAINS
+            .iter()
+            .find(|known| Some(**known) == name.as_deref())
+        {
+            Some(known) => Err(PrologError::domain_error(known, argument(1))),
+            None => Err(PrologError::domain_error("domain", domain)),
+        }
+    }
+
+    /// `functor(Term, Name, Arity)`
+    pub(super) fn functor(&mut self) -> Result<bool, PrologError> {
+        let term = argument(0);
+
+        if let Some((functor, _)) = self.structure(term) {
+            let descriptor = self.compiler.descriptor_allocator.get(functor);
+            let (name, arity) = (descriptor.name.clone(), descriptor.arity());
+            let name = self.atom(&name);
+            return Ok(self.unify_cell(argument(1), name)
+                && self.unify_cell(argument(2), Cell::Integer(arity as i64)));
+        }
+        if !self.is_unbound(term) {
+            return Ok(
+                self.unify(argument(1), term) && self.unify_cell(argument(2), Cell::Integer(0))
+            );
+        }
+
+        if self.is_unbound(argument(1)) {
+            return Err(PrologError::InstantiationError);
+        }
+        let arity = match self.integer_argument(argument(2))? {
+            Number::Integer(arity) if arity < 0 => {
+                return Err(PrologError::domain_error("not_less_than_zero", argument(2)));
+            }
+            Number::Integer(arity) if arity as u64 <= MAX_ARITY as u64 => arity as usize,
+            _ => return Err(PrologError::RepresentationError("max_arity")),
+        };
+
+        if self.structure(argument(1)).is_some() {
+            return Err(PrologError::type_error("atomic", argument(1)));
+        }
+        if arity == 0 {
+            return Ok(self.unify(term, argument(1)));
+        }
+        let Cell::Constant(name) = self.value(argument(1)) else {
+            return Err(PrologError::type_error("atom", argument(1)));
+        };
+
+        let name = self.compiler.descriptor_allocator.get(*name).name.clone();
+        let functor = self
+            .compiler
+            .descriptor_allocator
+            .get_or_set_functor(&name, arity);
+        let index = self.global_stack.len();
+        self.global_stack.push(Cell::Structure(functor));
+        self.global_stack
+            .extend((1..=arity).map(|i| Cell::Reference(index + i)));
+        Ok(self.unify_cell(term, Cell::StructureRef(index)))
+    }
+
+    /// `arg(N, Term, Argument)`
+    pub(super) fn arg(&mut self) -> Result<bool, PrologError> {
+        let index = self.integer_argument(argument(0))?;
+        let Some((functor, structure)) = self.structure(argument(1)) else {
+            if self.is_unbound(argument(1)) {
+                return Err(PrologError::InstantiationError);
+            }
+            return Err(PrologError::type_error("compound", argument(1)));
+        };
+
+        let arity = self.compiler.descriptor_allocator.get(functor).arity();
+        match index {
+            Number::Integer(index) if index >= 1 && index as usize <= arity => {
+                let address = CellAddress::GlobalStack {
+                    index: structure + index as usize,
+                };
+                Ok(self.unify(argument(2), address))
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// `Term =.. List`
+    pub(super) fn univ(&mut self) -> Result<bool, PrologError> {
+        let term = argument(0);
+
+        if let Some((functor, structure)) = self.structure(term) {
+            let descriptor = self.compiler.descriptor_allocator.get(functor);
+            let (name, arity) = (descriptor.name.clone(), descriptor.arity());
+            let mut elements = vec![self.atom(&name)];
+            elements.extend_from_slice(&self.global_stack[structure + 1..=structure + arity]);
+            let tail = self.empty_list();
+            let list = self.build_list(&elements, tail);
+            return Ok(self.unify_cell(argument(1), list));
+        }
+        if !self.is_unbound(term) {
+            let elements = [self.term_cell(term)];
+            let tail = self.empty_list();
+            let list = self.build_list(&elements, tail);
+            return Ok(self.unify_cell(argument(1), list));
+        }
+
+        let elements = self.list_argument(argument(1))?;
+        let Some((&name, arguments)) = elements.split_first() else {
+            return Err(PrologError::domain_error("non_empty_list", argument(1)));
+        };
+
+        if self.is_unbound(name) {
+            return Err(PrologError::InstantiationError);
+        }
+        if self.structure(name).is_some() {
+            return Err(PrologError::type_error("atomic", name));
+        }
+        if arguments.is_empty() {
+            return Ok(self.unify(term, name));
+        }
+        let Cell::Constant(name) = self.value(name) else {
+            return Err(PrologError::type_error("atom", name));
+        };
+
+        let name = self.compiler.descriptor_allocator.get(*name).name.clone();
+        let functor = self
+            .compiler
+            .descriptor_allocator
+            .get_or_set_functor(&name, arguments.len());
+        let arguments = arguments
+            .iter()
+            .map(|&address| self.term_cell(address))
+            .collect::<Vec<_>>();
+        let structure = self.build_structure(functor, &arguments);
+        Ok(self.unify_cell(term, structure))
+    }
+
+    /// `copy_term(Term, Copy)`
+    pub(super) fn copy_term(&mut self) -> Result<bool, PrologError> {
+        let copy = self.copy_term_cell(argument(0), &mut HashMap::new());
+        Ok(self.unify_cell(argument(1), copy))
+    }
+
+    /// `term_variables(Term, Variables)`
+    pub(super) fn term_variables(&mut self) -> Result<bool, PrologError> {
+        let variables = self
+            .variables(argument(0))
+            .into_iter()
+            .map(Cell::Reference)
+            .collect::<Vec<_>>();
+        let tail = self.empty_list();
+        let list = self.build_list(&variables, tail);
+        Ok(self.unify_cell(argument(1), list))
+    }
+
+    /// Copies the term at `address` with fresh variables. `copies` maps the variables and
+    /// structures of the original term to their copies, so sharing and cycles are preserved.
+    ///
+    /// The copy is built without recursion: a structure is allocated before its arguments are
+    /// copied, and the arguments are filled in from an explicit work stack of
+    /// (original, copy slot) pairs. This keeps long lists and deep terms off the native stack.
+    pub(super) fn copy_term_cell(
+        &mut self,
+        address: CellAddress,
+        copies: &mut HashMap<usize, Cell>,
+    ) -> Cell {
+        let mut pending = Vec::new();
+        let root = self.copy_cell(address, copies, &mut pending);
+        while let Some((original, slot)) = pending.pop() {
+            self.global_stack[slot] = self.copy_cell(original, copies, &mut pending);
+        }
+        root
+    }
+
+    /// Copies the outermost cell of the term at `address`. The arguments of a copied structure
+    /// and the attributes of a copied attributed variable are left as placeholders and pushed
+    /// onto `pending`, to be copied by [`Interpreter::copy_term_cell`].
+    fn copy_cell(
+        &mut self,
+        address: CellAddress,
+        copies: &mut HashMap<usize, Cell>,
+        pending: &mut Vec<(CellAddress, usize)>,
+    ) -> Cell {
+        let address = self.deref_cell(address);
+        match self.lookup_address(address).clone() {
+            Cell::Reference(_) => {
+                let CellAddress::GlobalStack { index } = address else {
+                    unreachable!("unbound variables live on the global stack");
+                };
+                if let Some(copy) = copies.get(&index) {
+                    return copy.clone();
+                }
+                let copy = self.new_variable();
+                copies.insert(index, copy.clone());
+                copy
+            }
+            // The copy gets copies of the attributes.
+            Cell::AttributedVariable(index) => {
+                if let Some(copy) = copies.get(&index) {
+                    return copy.clone();
+                }
+                let copy = self.global_stack.len();
+                self.global_stack
+                    .extend([Cell::AttributedVariable(copy), Cell::Undefined]);
+                copies.insert(index, Cell::Reference(copy));
+                pending.push((CellAddress::GlobalStack { index: index + 1 }, copy + 1));
+                Cell::Reference(copy)
+            }
+            Cell::StructureRef(structure) => {
+                // The copy is registered before its arguments are copied, so a cycle back to
+                // this structure refers to the copy.
+                if let Some(copy) = copies.get(&structure) {
+                    return copy.clone();
+                }
+                let Cell::Structure(functor) = self.global_stack[structure] else {
+                    unreachable!("structure references always point to a structure");
+                };
+                let arity = self.compiler.descriptor_allocator.get(functor).arity();
+                let copy = self.global_stack.len();
+                self.global_stack.push(Cell::Structure(functor));
+                self.global_stack
+                    .extend(std::iter::repeat_n(Cell::Undefined, arity));
+                copies.insert(structure, Cell::StructureRef(copy));
+                // Pushed last to first, so the first argument is copied first and the stack
+                // stays small for lists, whose tail is the last argument.
+                pending.extend((1..=arity).rev().map(|i| {
+                    (
+                        CellAddress::GlobalStack {
+                            index: structure + i,
+                        },
+                        copy + i,
+                    )
+                }));
+                Cell::StructureRef(copy)
+            }
+            // Boxed numbers and strings are copied as well, so the copy is independent of the
+            // original.
+            cell @ (Cell::BigIntegerRef(_) | Cell::RationalRef(_)) => {
+                let number = self.read_number(&cell).expect("cell is a number");
+                self.allocate_number(&number)
+            }
+            cell @ Cell::StringRef(_) => {
+                let text = self.read_string(&cell).expect("cell is a string");
+                self.allocate_string(&text)
+            }
+            cell => cell,
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use crate::{
+    interpreter::{
+        Cell, CellAddress, Interpreter, builtins::argument, error::PrologError, terms::ListShape,
+    },
+    number::Number,
+    parsing::{AbstractTerm, DoubleQuotes, parse_term},
+};
+
+/// The kind of text a builtin works on. String builtins accept any text and produce strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum TextKind {
+    Atom,
+    String,
+}
+
+impl Interpreter {
+    /// Reads the text argument at `address`, `None` if it is unbound. Atom builtins only
+    /// accept atoms, string builtins any text.
+    fn text_argument(
+        &self,
+        address: CellAddress,
+        kind: TextKind,
+    ) -> Result<Option<String>, PrologError> {
+        if self.is_unbound(address) {
+            return Ok(None);
+        }
+        let text = match (kind, self.value(address)) {
+            (TextKind::Atom, Cell::Constant(id)) => {
+                Some(self.compiler.descriptor_allocator.get(*id).name.clone())
+            }
+            (TextKind::Atom, _) => None,
+            (TextKind::String, _) => self.read_text(address),
+        };
+        match (kind, text) {
+            (_, Some(text)) => Ok(Some(text)),
+            (TextKind::Atom, None) => Err(PrologError::type_error("atom", address)),
+            (TextKind::String, None) => Err(PrologError::type_error("string", address)),
+        }
+    }
+
+    fn build_text(&mut self, kind: TextKind, text: &str) -> Cell {
+        match kind {
+            TextKind::Atom => self.atom(text),
+            TextKind::String => self.allocate_string(text),
+        }
+    }
+
+    /// Reads the proper list of character codes, or characters if `codes` is not set, at
+    /// `address`.
+    fn list_text(&self, address: CellAddress, codes: bool) -> Result<String, PrologError> {
+        self.list_argument(address)?
+            .into_iter()
+            .map(|element| match self.value(element) {
+                Cell::Reference(_) | Cell::AttributedVariable(_) => {
+                    Err(PrologError::InstantiationError)
+                }
+                Cell::Integer(code) if codes => u32::try_from(*code)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or(PrologError::RepresentationError("character_code")),
+                _ if codes => Err(PrologError::type_error("integer", element)),
+                Cell::Constant(id) => {
+                    let mut chars = self.compiler.descriptor_allocator.get(*id).name.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) => Ok(c),
+                        _ => Err(PrologError::type_error("character", element)),
+                    }
+                }
+                _ => Err(PrologError::type_error("character", element)),
+            })
+            .collect()
+    }
+
+    /// Reads the integer argument at `address`, `None` if it is unbound.
+    fn optional_integer(&self, address: CellAddress) -> Result<Option<i64>, PrologError> {
+        match self.value(address) {
+            Cell::Reference(_) | Cell::AttributedVariable(_) => Ok(None),
+            Cell::Integer(value) => Ok(Some(*value)),
+            _ if self.is_integer(address) => Err(PrologError::RepresentationError("max_integer")),
+            _ => Err(PrologError::type_error("integer", address)),
+        }
+    }
+
+    /// `atom_length/2` and `string_length/2`.
+    pub(super) fn text_length(&mut self, kind: TextKind) -> Result<bool, PrologError> {
+        let text = self
+            .text_argument(argument(0), kind)?
+            .ok_or(PrologError::InstantiationError)?;
+        let length = argument(1);
+        match self.optional_integer(length)? {
+            Some(value) if value < 0 => {
+                Err(PrologError::domain_error("not_less_than_zero", length))
+            }
+            _ => Ok(self.unify_cell(length, Cell::Integer(text.chars().count() as i64))),
+        }
+    }
+
+    /// `atom_chars/2`, `atom_codes/2`, `string_chars/2` and `string_codes/2`.
+    pub(super) fn text_list(&mut self, kind: TextKind, codes: bool) -> Result<bool, PrologError> {
+        let (text, list) = (argument(0), argument(1));
+        match self.text_argument(text, kind)? {
+            Some(value) => {
+                let cell = match codes {
+                    true => self.build_codes(&value),
+                    false => self.build_chars(&value),
+                };
+                Ok(self.unify_cell(list, cell))
+            }
+            None => {
+                let value = self.list_text(list, codes)?;
+                let cell = self.build_text(kind, &value);
+                Ok(self.unify_cell(text, cell))
+            }
+        }
+    }
+
+    /// `char_code(Char, Code)`.
+    pub(super) fn char_code(&mut self) -> Result<bool, PrologError> {
+        let (char, code) = (argument(0), argument(1));
+        if !self.is_unbound(char) {
+            let c = match self.value(char) {
+                Cell::Constant(id) => {
+                    let mut chars = self.compiler.descriptor_allocator.get(*id).name.chars();
+                    chars.next().filter(|_| chars.next().is_none())
+                }
+                _ => None,
+            }
+            .ok_or(PrologError::type_error("character", char))?;
+            return Ok(self.unify_cell(code, Cell::Integer(c as i64)));
+        }
+        let value = self
+            .optional_integer(code)?
+            .ok_or(PrologError::InstantiationError)?;
+        let c = u32::try_from(value)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or(PrologError::RepresentationError("character_code"))?;
+        let cell = self.atom(c.encode_utf8(&mut [0; 4]));
+        Ok(self.unify_cell(char, cell))
+    }
+
+    /// `number_chars/2` and `number_codes/2`. The list is parsed if it is a proper list,
+    /// otherwise the number is written.
+    pub(super) fn number_text(&mut self, codes: bool) -> Result<bool, PrologError> {
+        let (number, list) = (argument(0), argument(1));
+        let ground_list = matches!(self.read_list(list), ListShape::Proper(_))
+            .then(|| self.list_text(list, codes))
+            .transpose();
+        match ground_list {
+            // A list with unbound elements is read from the number instead.
+            Ok(Some(text)) => {
+                let value = self.parse_number(&text)?;
+                let cell = self.allocate_number(&value);
+                Ok(self.unify_cell(number, cell))
+            }
+            Err(PrologError::InstantiationError) | Ok(None) => {
+                if self.is_unbound(number) {
+                    return Err(PrologError::InstantiationError);
+                }
+                let value = self
+                    .read_number(self.value(number))
+                    .ok_or(PrologError::type_error("number", number))?;
+                let cell = match codes {
+                    true => self.build_codes(&value.to_string()),
+                    false => self.build_chars(&value.to_string()),
+                };
+                Ok(self.unify_cell(list, cell))
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// `upcase_atom/2`, `downcase_atom/2`, `string_upper/2` and `string_lower/2`.
+    pub(super) fn change_case(&mut self, kind: TextKind, upper: bool) -> Result<bool, PrologError> {
+        let text = self
+            .text_argument(argument(0), kind)?
+            .ok_or(PrologError::InstantiationError)?;
+        let text = match upper {
+            true => text.to_uppercase(),
+            false => text.to_lowercase(),
+        };
+        let cell = self.build_text(kind, &text);
+        Ok(self.unify_cell(argument(1), cell))
+    }
+
+    /// `string_to_atom(String, Atom)`.
+    pub(super) fn string_to_atom(&mut self) -> Result<bool, PrologError> {
+        let (string, atom) = (argument(0), argument(1));
+        match self.text_argument(string, TextKind::String)? {
+            Some(text) => {
+                let cell = self.atom(&text);
+                Ok(self.unify_cell(atom, cell))
+            }
+            None => {
+                let text = self
+                    .text_argument(atom, TextKind::String)?
+                    .ok_or(PrologError::InstantiationError)?;
+                let cell = self.allocate_string(&text);
+                Ok(self.unify_cell(string, cell))
+            }
+        }
+    }
+
+    /// `'$atom_concat'(Prefix, Suffix, Whole, Splits)` and `'$string_concat'/4`, unify `Splits`
+    /// with the `Prefix-Suffix` pairs that can make up `Whole`. If `Prefix` and `Suffix` are
+    /// both known they are joined into `Whole` instead.
+    pub(super) fn text_concat(&mut self, kind: TextKind) -> Result<bool, PrologError> {
+        let (prefix, suffix, whole) = (argument(0), argument(1), argument(2));
+        let pair = self
+            .compiler
+            .descriptor_allocator
+            .get_or_set_functor("-", 2);
+        let known = (
+            self.text_argument(prefix, kind)?,
+            self.text_argument(suffix, kind)?,
+        );
+        let whole_text = self.text_argument(whole, kind)?;
+        let splits = match (known.clone(), whole_text) {
+            ((Some(left), Some(right)), whole_text) => {
+                let text = left + &right;
+                // A known whole may be written as any text, so only its text is compared.
+                let joined = match whole_text {
+                    Some(whole_text) => whole_text == text,
+                    None => {
+                        let cell = self.build_text(kind, &text);
+                        self.unify_cell(whole, cell)
+                    }
+                };
+                if !joined {
+                    return Ok(false);
+                }
+                let (prefix, suffix) = (self.term_cell(prefix), self.term_cell(suffix));
+                vec![self.build_structure(pair, &[prefix, suffix])]
+            }
+            (_, None) => return Err(PrologError::InstantiationError),
+            (_, Some(text)) => {
+                // Known parts are passed on as given, as a string can be written as any text.
+                let boundaries = text
+                    .char_indices()
+                    .map(|(index, _)| index)
+                    .chain([text.len()])
+                    .filter(|index| {
+                        known.0.as_ref().is_none_or(|left| *left == text[..*index])
+                            && known
+                                .1
+                                .as_ref()
+                                .is_none_or(|right| *right == text[*index..])
+                    })
+                    .collect::<Vec<_>>();
+                boundaries
+                    .into_iter()
+                    .map(|index| {
+                        let prefix = match known.0 {
+                            Some(_) => self.term_cell(prefix),
+                            None => self.build_text(kind, &text[..index]),
+                        };
+                        let suffix = match known.1 {
+                            Some(_) => self.term_cell(suffix),
+                            None => self.build_text(kind, &text[index..]),
+                        };
+                        self.build_structure(pair, &[prefix, suffix])
+                    })
+                    .collect()
+            }
+        };
+        let tail = self.empty_list();
+        let splits = self.build_list(&splits, tail);
+        Ok(self.unify_cell(argument(3), splits))
+    }
+
+    /// `'$sub_atom'(Atom, Before, Length, After, Sub, Solutions)` and `'$sub_string'/6`,
+    /// unify `Solutions` with a `s(Before, Length, After, Sub)` term for every part of the
+    /// text that agrees with the arguments already known.
+    pub(super) fn sub_text(&mut self, kind: TextKind) -> Result<bool, PrologError> {
+        let chars = self
+            .text_argument(argument(0), kind)?
+            .ok_or(PrologError::InstantiationError)?
+            .chars()
+            .collect::<Vec<_>>();
+        let before = self.optional_integer(argument(1))?;
+        let length = self.optional_integer(argument(2))?;
+        let after = self.optional_integer(argument(3))?;
+        let sub = self
+            .text_argument(argument(4), kind)?
+            .map(|sub| sub.chars().collect::<Vec<_>>());
+
+        let total = chars.len() as i64;
+        let matches = |value: Option<i64>, actual: i64| value.is_none_or(|value| value == actual);
+        let mut solutions = Vec::new();
+        for start in 0..=total {
+            for size in 0..=total - start {
+                let range = start as usize..(start + size) as usize;
+                if matches(before, start)
+                    && matches(length, size)
+                    && matches(after, total - start - size)
+                    && sub
+                        .as_ref()
+                        .is_none_or(|sub| sub[..] == chars[range.clone()])
+                {
+                    solutions.push((start, size, chars[range].iter().collect::<String>()));
+                }
+            }
+        }
+
+        let functor = self
+            .compiler
+            .descriptor_allocator
+            .get_or_set_functor("s", 4);
+        let solutions = solutions
+            .into_iter()
+            .map(|(start, size, text)| {
+                let sub = match sub {
+                    Some(_) => self.term_cell(argument(4)),
+                    None => self.build_text(kind, &text),
+                };
+                self.build_structure(
+                    functor,
+                    &[
+                        Cell::Integer(start),
+                        Cell::Integer(size),
+                        Cell::Integer(total - start - size),
+                        sub,
+                    ],
+                )
+            })
+            .collect::<Vec<_>>();
+        let tail = self.empty_list();
+        let solutions = self.build_list(&solutions, tail);
+        Ok(self.unify_cell(argument(5), solutions))
+    }
+
+    /// `string_code(Index, String, Code)`, the code of the character at the 1-based `Index`.
+    /// Fails if `Index` is out of range.
+    pub(super) fn string_code(&mut self) -> Result<bool, PrologError> {
+        let index = self
+            .optional_integer(argument(0))?
+            .ok_or(PrologError::InstantiationError)?;
+        let text = self
+            .text_argument(argument(1), TextKind::String)?
+            .ok_or(PrologError::InstantiationError)?;
+        match usize::try_from(index - 1)
+            .ok()
+            .and_then(|index| text.chars().nth(index))
+        {
+            Some(c) => Ok(self.unify_cell(argument(2), Cell::Integer(c as i64))),
+            None => Ok(false),
+        }
+    }
+
+    /// `split_string(String, Separators, Pad, Parts)`, splits `String` at every character in
+    /// `Separators` and strips the characters in `Pad` from both ends of the parts.
+    pub(super) fn split_string(&mut self) -> Result<bool, PrologError> {
+        let [text, separators, pad] = [0, 1, 2].map(|index| {
+            self.text_argument(argument(index), TextKind::String)?
+                .ok_or(PrologError::InstantiationError)
+        });
+        let (text, separators, pad) = (text?, separators?, pad?);
+        let parts = text
+            .split(|c| separators.contains(c))
+            .map(|part| part.trim_matches(|c| pad.contains(c)).to_string())
+            .collect::<Vec<_>>();
+        let parts = parts
+            .iter()
+            .map(|part| self.allocate_string(part))
+            .collect::<Vec<_>>();
+        let tail = self.empty_list();
+        let parts = self.build_list(&parts, tail);
+        Ok(self.unify_cell(argument(3), parts))
+    }
+
+    /// Reads `text` as a number, allowing leading layout and a minus sign.
+    fn parse_number(&self, text: &str) -> Result<Number, PrologError> {
+        let illegal = || PrologError::SyntaxError("illegal_number".to_string());
+        if text.trim().is_empty() {
+            return Err(illegal());
+        }
+        match parse_term(
+            &format!("{}\n.", text),
+            &self.compiler.operators,
+            DoubleQuotes::Codes,
+        ) {
+            Ok(AbstractTerm::Number(ref number)) => Ok(number.clone()),
+            _ => Err(illegal()),
+        }
+    }
+}
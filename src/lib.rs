@@ -2,6 +2,8 @@ pub mod compiler;
 pub mod descriptor;
 pub mod instructions;
 pub mod interpreter;
+pub mod number;
 pub mod parsing;
 pub mod traversal;
 pub mod ui;
+pub mod writer;
@@ -0,0 +1,61 @@
+% library(arithmetic): integer helpers, loaded the first time one of them is called.
+
+% `High` may be `inf` or `infinite` for an unbounded range.
+between(Low, High, X) :-
+    must_be(integer, Low),
+    (   '$infinite'(High)
+    ->  true
+    ;   must_be(integer, High)
+    ),
+    (   nonvar(X)
+    ->  must_be(integer, X),
+        X >= Low,
+        (   '$infinite'(High)
+        ->  true
+        ;   X =< High
+        )
+    ;   (   '$infinite'(High)
+        ->  true
+        ;   Low =< High
+        ),
+        '$between'(Low, High, X)
+    ).
+
+'$infinite'(High) :-
+    High == inf.
+'$infinite'(High) :-
+    High == infinite.
+
+% The last solution leaves no choice point behind.
+'$between'(Low, Low, Low) :-
+    !.
+'$between'(Low, _, Low).
+'$between'(Low, High, X) :-
+    Next is Low + 1,
+    '$between'(Next, High, X).
+
+succ(X, Y) :-
+    nonvar(X),
+    !,
+    must_be(nonneg, X),
+    (   nonvar(Y)
+    ->  must_be(nonneg, Y)
+    ;   true
+    ),
+    Y is X + 1.
+succ(X, Y) :-
+    must_be(nonneg, Y),
+    Y > 0,
+    X is Y - 1.
+
+plus(X, Y, Z) :-
+    nonvar(X),
+    nonvar(Y),
+    !,
+    Z is X + Y.
+plus(X, Y, Z) :-
+    nonvar(X),
+    !,
+    Y is Z - X.
+plus(X, Y, Z) :-
+    X is Z - Y.
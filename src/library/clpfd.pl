@@ -0,0 +1,972 @@
+% library(clpfd): constraints over finite domains of integers, loaded the first time one of
+% them is called.
+%
+% A constrained variable carries the attribute `clpfd(Domain, Propagators, Auxiliary)`.
+% `Domain` is a sorted list of disjoint intervals `Low-High`, whose bounds may be `inf` and
+% `sup`. Each propagator `p(Constraint, Goal, State)` narrows the domains of the variables of
+% `Constraint` and is run again whenever one of them changes, until nothing changes anymore.
+% `Goal` is the constraint as it was posted, and `State` becomes `dead` once it is entailed.
+% Auxiliary variables stand for the non-linear parts of expressions.
+
+X #= Y :-
+    '$fd_relation'(X - Y, eq, X #= Y).
+X #\= Y :-
+    '$fd_relation'(X - Y, ne, X #\= Y).
+X #=< Y :-
+    '$fd_relation'(X - Y, le, X #=< Y).
+X #< Y :-
+    '$fd_relation'(X - Y + 1, le, X #< Y).
+X #>= Y :-
+    '$fd_relation'(Y - X, le, X #>= Y).
+X #> Y :-
+    '$fd_relation'(Y - X + 1, le, X #> Y).
+
+X in Spec :-
+    '$fd_spec'(Spec, Domain),
+    '$fd_in'(X, Domain).
+
+Xs ins Spec :-
+    must_be(list, Xs),
+    '$fd_spec'(Spec, Domain),
+    '$fd_ins'(Xs, Domain).
+
+'$fd_ins'([], _) :-
+    !.
+'$fd_ins'([X|Xs], Domain) :-
+    '$fd_in'(X, Domain),
+    '$fd_ins'(Xs, Domain).
+
+'$fd_in'(X, Domain) :-
+    (   var(X)
+    ->  '$fd_restrict'(X, Domain)
+    ;   must_be(integer, X),
+        '$fd_contains'(Domain, X)
+    ).
+
+% Only values already fixed are removed from the domains of the other variables.
+all_different(Xs) :-
+    '$fd_variables'(Xs),
+    '$fd_post'(all_different(Xs), all_different(Xs)).
+
+% In addition, fails as soon as the remaining variables have fewer values left than there are
+% variables.
+all_distinct(Xs) :-
+    '$fd_variables'(Xs),
+    '$fd_post'(all_distinct(Xs), all_distinct(Xs)).
+
+sum(Xs, Relation, Value) :-
+    '$fd_variables'(Xs),
+    must_be(callable, Relation),
+    '$fd_relational'(Relation),
+    '$fd_sum_expression'(Xs, Sum),
+    Goal =.. [Relation, Sum, Value],
+    call(Goal).
+
+'$fd_relational'(#=).
+'$fd_relational'(#\=).
+'$fd_relational'(#<).
+'$fd_relational'(#>).
+'$fd_relational'(#=<).
+'$fd_relational'(#>=).
+
+'$fd_sum_expression'([], 0).
+'$fd_sum_expression'([X|Xs], Sum) :-
+    '$fd_sum_expression'(Xs, X, Sum).
+
+'$fd_sum_expression'([], Sum, Sum).
+'$fd_sum_expression'([X|Xs], Sum0, Sum) :-
+    '$fd_sum_expression'(Xs, Sum0 + X, Sum).
+
+'$fd_variables'(Xs) :-
+    must_be(list, Xs),
+    '$fd_variables_'(Xs).
+
+'$fd_variables_'([]) :-
+    !.
+'$fd_variables_'([X|Xs]) :-
+    (   var(X)
+    ->  true
+    ;   must_be(integer, X)
+    ),
+    '$fd_variables_'(Xs).
+
+% Labeling ---------------------------------------------------------------------------------
+
+label(Vars) :-
+    labeling([], Vars).
+
+% Options select the next variable (leftmost, ff, ffc, min, max) and the order in which its
+% values are tried (up, down). The branching options step, enum and bisect all try one value
+% after the other. With min(Expr) and max(Expr), the solutions come in increasing or
+% decreasing order of Expr, the first of them optimal. Unknown options raise
+% domain_error(labeling_option, Option).
+labeling(Options, Vars) :-
+    must_be(list, Options),
+    '$fd_variables'(Vars),
+    '$fd_label_options'(Options, leftmost, Selection, up, Order, Objectives),
+    '$fd_finite'(Vars),
+    '$fd_label'(Objectives, Vars, Selection, Order).
+
+'$fd_label_options'([], Selection, Selection, Order, Order, []) :-
+    !.
+'$fd_label_options'([Option|Options], Selection0, Selection, Order0, Order, Objectives) :-
+    (   nonvar(Option),
+        '$fd_objective'(Option)
+    ->  Selection1 = Selection0,
+        Order1 = Order0,
+        Objectives = [Option|Objectives1]
+    ;   Objectives = Objectives1,
+        (   var(Option)
+        ->  must_be(atom, Option)
+        ;   '$fd_selection'(Option)
+        ->  Selection1 = Option,
+            Order1 = Order0
+        ;   '$fd_order'(Option)
+        ->  Selection1 = Selection0,
+            Order1 = Option
+        ;   '$fd_branching'(Option)
+        ->  Selection1 = Selection0,
+            Order1 = Order0
+        ;   '$domain_error'(labeling_option, Option)
+        )
+    ),
+    '$fd_label_options'(Options, Selection1, Selection, Order1, Order, Objectives1).
+
+'$fd_objective'(min(_)).
+'$fd_objective'(max(_)).
+
+'$fd_selection'(leftmost).
+'$fd_selection'(ff).
+'$fd_selection'(ffc).
+'$fd_selection'(min).
+'$fd_selection'(max).
+
+'$fd_order'(up).
+'$fd_order'(down).
+
+'$fd_branching'(step).
+'$fd_branching'(enum).
+'$fd_branching'(bisect).
+
+% Labeling a variable whose domain is infinite raises an instantiation error.
+'$fd_finite'([]) :-
+    !.
+'$fd_finite'([X|Xs]) :-
+    '$fd_bounds'(X, Low, High),
+    (   integer(Low),
+        integer(High)
+    ->  true
+    ;   must_be(integer, X)
+    ),
+    '$fd_finite'(Xs).
+
+'$fd_label'([], Vars, Selection, Order) :-
+    !,
+    '$fd_label'(Vars, Selection, Order).
+'$fd_label'(Objectives, Vars, Selection, Order) :-
+    findall(Keys-Vars,
+            (   '$fd_label'(Vars, Selection, Order),
+                '$fd_objective_keys'(Objectives, Keys)
+            ),
+            Solutions),
+    keysort(Solutions, Sorted),
+    '$member'(_-Vars, Sorted).
+
+% The values of the objectives of a solution, negated for max(Expr) so they sort in the
+% order the solutions are wanted.
+'$fd_objective_keys'([], []) :-
+    !.
+'$fd_objective_keys'([min(Expression)|Objectives], [Key|Keys]) :-
+    !,
+    Key is Expression,
+    '$fd_objective_keys'(Objectives, Keys).
+'$fd_objective_keys'([max(Expression)|Objectives], [Key|Keys]) :-
+    Key is -Expression,
+    '$fd_objective_keys'(Objectives, Keys).
+
+'$fd_label'(Vars, Selection, Order) :-
+    '$fd_unfixed'(Vars, Unfixed),
+    (   Unfixed == []
+    ->  true
+    ;   '$fd_select'(Selection, Unfixed, Var),
+        '$fd_domain'(Var, Domain),
+        '$fd_value'(Order, Domain, Var),
+        '$fd_label'(Unfixed, Selection, Order)
+    ).
+
+'$fd_unfixed'([], []) :-
+    !.
+'$fd_unfixed'([X|Xs], Unfixed) :-
+    (   var(X)
+    ->  Unfixed = [X|Unfixed1]
+    ;   Unfixed = Unfixed1
+    ),
+    '$fd_unfixed'(Xs, Unfixed1).
+
+% The variable with the smallest key comes first, the leftmost one among equals.
+'$fd_select'(leftmost, [Var|_], Var) :-
+    !.
+'$fd_select'(Selection, [X|Xs], Var) :-
+    '$fd_key'(Selection, X, Key),
+    '$fd_select'(Xs, Selection, X, Key, Var).
+
+'$fd_select'([], _, Var, _, Var) :-
+    !.
+'$fd_select'([X|Xs], Selection, Best0, Key0, Best) :-
+    '$fd_key'(Selection, X, Key),
+    (   Key @< Key0
+    ->  '$fd_select'(Xs, Selection, X, Key, Best)
+    ;   '$fd_select'(Xs, Selection, Best0, Key0, Best)
+    ).
+
+'$fd_key'(ff, X, Size) :-
+    '$fd_domain'(X, Domain),
+    '$fd_size'(Domain, 0, Size).
+'$fd_key'(ffc, X, Size-Count) :-
+    '$fd_key'(ff, X, Size),
+    '$fd_get'(X, _, Propagators, _),
+    length(Propagators, Length),
+    Count is -Length.
+'$fd_key'(min, X, Low) :-
+    '$fd_bounds'(X, Low, _).
+'$fd_key'(max, X, Key) :-
+    '$fd_bounds'(X, _, High),
+    Key is -High.
+
+'$fd_value'(up, Domain, Value) :-
+    !,
+    '$member'(Low-High, Domain),
+    between(Low, High, Value).
+'$fd_value'(down, Domain, Value) :-
+    reverse(Domain, Intervals),
+    '$member'(Low-High, Intervals),
+    '$fd_down'(High, Low, Value).
+
+'$fd_down'(High, Low, Value) :-
+    High >= Low,
+    (   Value = High
+    ;   High1 is High - 1,
+        '$fd_down'(High1, Low, Value)
+    ).
+
+% Posting constraints -----------------------------------------------------------------------
+
+% `Expression Relation 0` as a linear constraint `Pairs + Constant Relation 0`, where `Pairs`
+% is a list of `Coefficient*Var`.
+'$fd_relation'(Expression, Relation, Goal) :-
+    '$fd_linear'(Expression, 1, Goal, [], Pairs0, 0, Constant),
+    '$fd_merge'(Pairs0, Pairs),
+    Constraint =.. [Relation, Pairs, Constant],
+    '$fd_post'(Constraint, Goal).
+
+% Attaches the propagator of `Constraint` to its variables and runs it.
+'$fd_post'(Constraint, Goal) :-
+    term_variables(Constraint, Vars),
+    Propagator = p(Constraint, Goal, State),
+    '$fd_attach'(Vars, Propagator),
+    '$fd_propagate'(Constraint, State).
+
+'$fd_attach'([], _) :-
+    !.
+'$fd_attach'([Var|Vars], Propagator) :-
+    '$fd_get'(Var, Domain, Propagators, Auxiliary),
+    put_attr(Var, clpfd, clpfd(Domain, [Propagator|Propagators], Auxiliary)),
+    '$fd_attach'(Vars, Propagator).
+
+% Adds `Multiplier * Expression` to the pairs and the constant. Products of two non-constant
+% expressions, absolute values and the other functions become auxiliary variables.
+'$fd_linear'(X, Multiplier, _, Pairs, [Multiplier*X|Pairs], Constant, Constant) :-
+    var(X),
+    !.
+'$fd_linear'(N, Multiplier, _, Pairs, Pairs, Constant0, Constant) :-
+    integer(N),
+    !,
+    Constant is Constant0 + Multiplier * N.
+'$fd_linear'(A + B, Multiplier, Goal, Pairs0, Pairs, Constant0, Constant) :-
+    !,
+    '$fd_linear'(A, Multiplier, Goal, Pairs0, Pairs1, Constant0, Constant1),
+    '$fd_linear'(B, Multiplier, Goal, Pairs1, Pairs, Constant1, Constant).
+'$fd_linear'(A - B, Multiplier, Goal, Pairs0, Pairs, Constant0, Constant) :-
+    !,
+    '$fd_linear'(A, Multiplier, Goal, Pairs0, Pairs1, Constant0, Constant1),
+    Negated is -Multiplier,
+    '$fd_linear'(B, Negated, Goal, Pairs1, Pairs, Constant1, Constant).
+'$fd_linear'(-A, Multiplier, Goal, Pairs0, Pairs, Constant0, Constant) :-
+    !,
+    Negated is -Multiplier,
+    '$fd_linear'(A, Negated, Goal, Pairs0, Pairs, Constant0, Constant).
+'$fd_linear'(A * B, Multiplier, Goal, Pairs0, Pairs, Constant0, Constant) :-
+    !,
+    '$fd_linear'(A, 1, Goal, [], PairsA, 0, ConstantA),
+    '$fd_linear'(B, 1, Goal, [], PairsB, 0, ConstantB),
+    (   PairsA == []
+    ->  Factor is Multiplier * ConstantA,
+        '$fd_scale'(PairsB, ConstantB, Factor, Pairs0, Pairs, Constant0, Constant)
+    ;   PairsB == []
+    ->  Factor is Multiplier * ConstantB,
+        '$fd_scale'(PairsA, ConstantA, Factor, Pairs0, Pairs, Constant0, Constant)
+    ;   '$fd_variable'(PairsA, ConstantA, Goal, X),
+        '$fd_variable'(PairsB, ConstantB, Goal, Y),
+        '$fd_auxiliary'(Z),
+        '$fd_post'(times(X, Y, Z), Goal),
+        Pairs = [Multiplier*Z|Pairs0],
+        Constant = Constant0
+    ).
+'$fd_linear'(abs(A), Multiplier, Goal, Pairs0, [Multiplier*Z|Pairs0], Constant, Constant) :-
+    !,
+    '$fd_linear'(A, 1, Goal, [], PairsA, 0, ConstantA),
+    '$fd_variable'(PairsA, ConstantA, Goal, X),
+    '$fd_auxiliary'(Z),
+    '$fd_post'(absolute(X, Z), Goal).
+'$fd_linear'(Expression, Multiplier, Goal, Pairs0, Pairs, Constant0, Constant) :-
+    '$fd_function'(Expression, Function, A, B),
+    !,
+    '$fd_linear'(A, 1, Goal, [], PairsA, 0, ConstantA),
+    '$fd_linear'(B, 1, Goal, [], PairsB, 0, ConstantB),
+    '$fd_variable'(PairsA, ConstantA, Goal, X),
+    '$fd_variable'(PairsB, ConstantB, Goal, Y),
+    (   integer(X),
+        integer(Y)
+    ->  '$fd_apply'(Function, X, Y, Value),
+        Pairs = Pairs0,
+        Constant is Constant0 + Multiplier * Value
+    ;   '$fd_auxiliary'(Z),
+        '$fd_post'(function(Function, X, Y, Z), Goal),
+        Pairs = [Multiplier*Z|Pairs0],
+        Constant = Constant0
+    ).
+'$fd_linear'(Expression, _, _, _, _, _, _) :-
+    must_be(integer, Expression).
+
+'$fd_function'(A // B, //, A, B).
+'$fd_function'(A mod B, mod, A, B).
+'$fd_function'(A rem B, rem, A, B).
+'$fd_function'(min(A, B), min, A, B).
+'$fd_function'(max(A, B), max, A, B).
+
+% The value of the function for fixed arguments. Dividing by zero has none.
+'$fd_apply'(min, X, Y, Value) :-
+    !,
+    Value is min(X, Y).
+'$fd_apply'(max, X, Y, Value) :-
+    !,
+    Value is max(X, Y).
+'$fd_apply'(Function, X, Y, Value) :-
+    Y =\= 0,
+    Application =.. [Function, X, Y],
+    Value is Application.
+
+'$fd_scale'([], Constant, Factor, Pairs, Pairs, Constant0, Constant1) :-
+    !,
+    Constant1 is Constant0 + Factor * Constant.
+'$fd_scale'([A*X|Pairs], Constant, Factor, Pairs0, [B*X|Pairs1], Constant0, Constant1) :-
+    B is A * Factor,
+    '$fd_scale'(Pairs, Constant, Factor, Pairs0, Pairs1, Constant0, Constant1).
+
+% A variable equal to the linear expression `Pairs + Constant`.
+'$fd_variable'([], Constant, _, Constant) :-
+    !.
+'$fd_variable'([1*X], 0, _, X) :-
+    !.
+'$fd_variable'(Pairs, Constant, Goal, X) :-
+    '$fd_auxiliary'(X),
+    '$fd_post'(eq([-1*X|Pairs], Constant), Goal).
+
+'$fd_auxiliary'(X) :-
+    put_attr(X, clpfd, clpfd([inf-sup], [], true)).
+
+% Adds up the coefficients of the same variable and drops those which cancel out.
+'$fd_merge'([], []) :-
+    !.
+'$fd_merge'([A*X|Pairs0], Pairs) :-
+    '$fd_collect'(Pairs0, X, A, Coefficient, Rest),
+    '$fd_merge'(Rest, Pairs1),
+    (   Coefficient =:= 0
+    ->  Pairs = Pairs1
+    ;   Pairs = [Coefficient*X|Pairs1]
+    ).
+
+'$fd_collect'([], _, Coefficient, Coefficient, []) :-
+    !.
+'$fd_collect'([A*Y|Pairs], X, Coefficient0, Coefficient, Rest) :-
+    (   Y == X
+    ->  Coefficient1 is Coefficient0 + A,
+        Rest = Rest1
+    ;   Coefficient1 = Coefficient0,
+        Rest = [A*Y|Rest1]
+    ),
+    '$fd_collect'(Pairs, X, Coefficient1, Coefficient, Rest1).
+
+% Propagation ------------------------------------------------------------------------------
+
+'$fd_run'([]) :-
+    !.
+'$fd_run'([p(Constraint, _, State)|Propagators]) :-
+    (   State == dead
+    ->  true
+    ;   '$fd_propagate'(Constraint, State)
+    ),
+    '$fd_run'(Propagators).
+
+% Narrows the domains of the variables of a constraint and marks it dead once it is entailed.
+'$fd_propagate'(le(Pairs, Constant), State) :-
+    !,
+    '$fd_less_equal'(Pairs, Constant),
+    '$fd_maximum'(Pairs, 0, Maximum, 0, Unbounded),
+    (   Unbounded =:= 0,
+        Maximum + Constant =< 0
+    ->  State = dead
+    ;   '$fd_linear_entailed'(Pairs, State)
+    ).
+'$fd_propagate'(eq(Pairs, Constant), State) :-
+    !,
+    '$fd_less_equal'(Pairs, Constant),
+    '$fd_negate'(Pairs, Negated),
+    NegatedConstant is -Constant,
+    '$fd_less_equal'(Negated, NegatedConstant),
+    '$fd_linear_entailed'(Pairs, State).
+'$fd_propagate'(ne(Pairs, Constant), State) :-
+    !,
+    '$fd_fixed_sum'(Pairs, Constant, Unfixed, Sum),
+    (   Unfixed == []
+    ->  Sum =\= 0
+    ;   Unfixed = [A*X]
+    ->  (   Sum mod A =:= 0
+        ->  Value is -Sum // A,
+            '$fd_exclude'(X, Value)
+        ;   true
+        )
+    ;   true
+    ),
+    '$fd_linear_entailed'(Pairs, State).
+'$fd_propagate'(times(X, Y, Z), State) :-
+    !,
+    '$fd_bounds'(X, LowX, HighX),
+    '$fd_bounds'(Y, LowY, HighY),
+    (   '$fd_integers'([LowX, HighX, LowY, HighY])
+    ->  '$fd_products'(LowX, HighX, LowY, HighY, Low, High),
+        '$fd_restrict'(Z, [Low-High])
+    ;   true
+    ),
+    '$fd_divide'(Z, Y, X),
+    '$fd_divide'(Z, X, Y),
+    (   integer(X),
+        integer(Y)
+    ->  State = dead
+    ;   true
+    ).
+'$fd_propagate'(absolute(X, Z), State) :-
+    !,
+    '$fd_restrict'(Z, [0-sup]),
+    '$fd_bounds'(X, LowX, HighX),
+    (   integer(LowX),
+        LowX >= 0
+    ->  '$fd_restrict'(Z, [LowX-HighX])
+    ;   integer(HighX),
+        HighX =< 0
+    ->  '$fd_negate_bound'(HighX, Low),
+        '$fd_negate_bound'(LowX, High),
+        '$fd_restrict'(Z, [Low-High])
+    ;   '$fd_negate_bound'(LowX, High0),
+        '$fd_max_bound'(High0, HighX, High),
+        '$fd_restrict'(Z, [0-High])
+    ),
+    '$fd_bounds'(Z, LowZ, HighZ),
+    '$fd_negate_bound'(HighZ, LowZ1),
+    '$fd_restrict'(X, [LowZ1-HighZ]),
+    (   LowZ > 0
+    ->  Below is -LowZ,
+        '$fd_restrict'(X, [inf-Below, LowZ-sup])
+    ;   true
+    ),
+    (   integer(X)
+    ->  State = dead
+    ;   true
+    ).
+'$fd_propagate'(function(Function, X, Y, Z), State) :-
+    !,
+    (   integer(X),
+        integer(Y)
+    ->  '$fd_apply'(Function, X, Y, Value),
+        '$fd_restrict'(Z, [Value-Value]),
+        State = dead
+    ;   '$fd_function_bounds'(Function, X, Y, Z)
+    ).
+'$fd_propagate'(all_different(Xs), State) :-
+    !,
+    '$fd_all_different'(Xs, State).
+'$fd_propagate'(all_distinct(Xs), State) :-
+    '$fd_all_different'(Xs, State),
+    '$fd_unfixed'(Xs, Unfixed),
+    '$fd_union'(Unfixed, [], Union),
+    '$fd_size'(Union, 0, Size),
+    length(Unfixed, Length),
+    (   Size == sup
+    ->  true
+    ;   Size >= Length
+    ).
+
+% Narrows the result of a function whose arguments are not both fixed yet.
+'$fd_function_bounds'(min, X, Y, Z) :-
+    !,
+    '$fd_bounds'(X, LowX, HighX),
+    '$fd_bounds'(Y, LowY, HighY),
+    '$fd_min_bound'(LowX, LowY, Low),
+    '$fd_min_bound'(HighX, HighY, High),
+    '$fd_restrict'(Z, [Low-High]).
+'$fd_function_bounds'(max, X, Y, Z) :-
+    !,
+    '$fd_bounds'(X, LowX, HighX),
+    '$fd_bounds'(Y, LowY, HighY),
+    '$fd_max_bound'(LowX, LowY, Low),
+    '$fd_max_bound'(HighX, HighY, High),
+    '$fd_restrict'(Z, [Low-High]).
+'$fd_function_bounds'(mod, _, Y, Z) :-
+    integer(Y),
+    !,
+    (   Y > 0
+    ->  High is Y - 1,
+        '$fd_restrict'(Z, [0-High])
+    ;   Y < 0
+    ->  Low is Y + 1,
+        '$fd_restrict'(Z, [Low-0])
+    ;   false
+    ).
+'$fd_function_bounds'(_, _, _, _).
+
+% Bounds of `Pairs + Constant =< 0`: every term is at most `-Constant` minus the smallest
+% value the others can take.
+'$fd_less_equal'(Pairs, Constant) :-
+    '$fd_minimum'(Pairs, 0, Minimum, 0, Unbounded),
+    '$fd_less_equal'(Pairs, Constant, Minimum, Unbounded).
+
+'$fd_less_equal'([], Constant, Minimum, Unbounded) :-
+    !,
+    (   Unbounded =:= 0
+    ->  Minimum + Constant =< 0
+    ;   true
+    ).
+'$fd_less_equal'([A*X|Pairs], Constant, Minimum, Unbounded) :-
+    '$fd_term_bounds'(A, X, Low, _),
+    (   Low == inf
+    ->  Others is Unbounded - 1,
+        Rest = Minimum
+    ;   Others = Unbounded,
+        Rest is Minimum - Low
+    ),
+    (   Others =:= 0
+    ->  Bound is -Constant - Rest,
+        (   A > 0
+        ->  High is Bound div A,
+            '$fd_restrict'(X, [inf-High])
+        ;   Divisor is -A,
+            LowX is -(Bound div Divisor),
+            '$fd_restrict'(X, [LowX-sup])
+        )
+    ;   true
+    ),
+    '$fd_less_equal'(Pairs, Constant, Minimum, Unbounded).
+
+% The sum of the finite smallest values of the terms, and the number of unbounded ones.
+'$fd_minimum'([], Minimum, Minimum, Unbounded, Unbounded) :-
+    !.
+'$fd_minimum'([A*X|Pairs], Minimum0, Minimum, Unbounded0, Unbounded) :-
+    '$fd_term_bounds'(A, X, Low, _),
+    (   Low == inf
+    ->  Minimum1 = Minimum0,
+        Unbounded1 is Unbounded0 + 1
+    ;   Minimum1 is Minimum0 + Low,
+        Unbounded1 = Unbounded0
+    ),
+    '$fd_minimum'(Pairs, Minimum1, Minimum, Unbounded1, Unbounded).
+
+'$fd_maximum'([], Maximum, Maximum, Unbounded, Unbounded) :-
+    !.
+'$fd_maximum'([A*X|Pairs], Maximum0, Maximum, Unbounded0, Unbounded) :-
+    '$fd_term_bounds'(A, X, _, High),
+    (   High == sup
+    ->  Maximum1 = Maximum0,
+        Unbounded1 is Unbounded0 + 1
+    ;   Maximum1 is Maximum0 + High,
+        Unbounded1 = Unbounded0
+    ),
+    '$fd_maximum'(Pairs, Maximum1, Maximum, Unbounded1, Unbounded).
+
+'$fd_term_bounds'(A, X, Low, High) :-
+    '$fd_bounds'(X, LowX, HighX),
+    (   A > 0
+    ->  '$fd_multiply'(A, LowX, Low),
+        '$fd_multiply'(A, HighX, High)
+    ;   '$fd_multiply'(A, HighX, Low),
+        '$fd_multiply'(A, LowX, High)
+    ).
+
+'$fd_multiply'(A, Bound, Product) :-
+    (   integer(Bound)
+    ->  Product is A * Bound
+    ;   A > 0
+    ->  Product = Bound
+    ;   '$fd_negate_bound'(Bound, Product)
+    ).
+
+'$fd_negate'([], []) :-
+    !.
+'$fd_negate'([A*X|Pairs], [B*X|Negated]) :-
+    B is -A,
+    '$fd_negate'(Pairs, Negated).
+
+% A linear constraint with at most one variable left holds once it has been propagated.
+'$fd_linear_entailed'(Pairs, State) :-
+    term_variables(Pairs, Vars),
+    (   Vars = [_, _|_]
+    ->  true
+    ;   State = dead
+    ).
+
+% The terms whose variable is not fixed yet, and the constant plus the fixed terms.
+'$fd_fixed_sum'([], Sum, [], Sum) :-
+    !.
+'$fd_fixed_sum'([A*X|Pairs], Sum0, Unfixed, Sum) :-
+    (   integer(X)
+    ->  Sum1 is Sum0 + A * X,
+        Unfixed = Unfixed1
+    ;   Sum1 = Sum0,
+        Unfixed = [A*X|Unfixed1]
+    ),
+    '$fd_fixed_sum'(Pairs, Sum1, Unfixed1, Sum).
+
+'$fd_products'(LowX, HighX, LowY, HighY, Low, High) :-
+    P1 is LowX * LowY,
+    P2 is LowX * HighY,
+    P3 is HighX * LowY,
+    P4 is HighX * HighY,
+    Low is min(min(P1, P2), min(P3, P4)),
+    High is max(max(P1, P2), max(P3, P4)).
+
+% Narrows `X` to the quotients `Z / Y` if the bounds of `Z` are finite and `Y` cannot be zero.
+% An infinite bound of `Y` contributes quotients tending to zero.
+'$fd_divide'(Z, Y, X) :-
+    '$fd_bounds'(Z, LowZ, HighZ),
+    '$fd_bounds'(Y, LowY, HighY),
+    (   integer(LowZ),
+        integer(HighZ),
+        (   integer(LowY),
+            LowY > 0
+        ;   integer(HighY),
+            HighY < 0
+        )
+    ->  '$fd_ceiling'(LowZ, LowY, Q1),
+        '$fd_ceiling'(LowZ, HighY, Q2),
+        '$fd_ceiling'(HighZ, LowY, Q3),
+        '$fd_ceiling'(HighZ, HighY, Q4),
+        Low is min(min(Q1, Q2), min(Q3, Q4)),
+        '$fd_floor'(LowZ, LowY, R1),
+        '$fd_floor'(LowZ, HighY, R2),
+        '$fd_floor'(HighZ, LowY, R3),
+        '$fd_floor'(HighZ, HighY, R4),
+        High is max(max(R1, R2), max(R3, R4)),
+        '$fd_restrict'(X, [Low-High])
+    ;   true
+    ).
+
+'$fd_ceiling'(Z, Y, Quotient) :-
+    (   integer(Y)
+    ->  Quotient is -((-Z) div Y)
+    ;   Quotient = 0
+    ).
+
+'$fd_floor'(Z, Y, Quotient) :-
+    (   integer(Y)
+    ->  Quotient is Z div Y
+    ;   Quotient = 0
+    ).
+
+'$fd_integers'([]) :-
+    !.
+'$fd_integers'([X|Xs]) :-
+    integer(X),
+    '$fd_integers'(Xs).
+
+'$fd_all_different'(Xs, State) :-
+    '$fd_fixed_values'(Xs, Values),
+    msort(Values, Sorted),
+    sort(Values, Sorted),
+    '$fd_exclude_all'(Xs, Values),
+    '$fd_unfixed'(Xs, Unfixed),
+    (   Unfixed = [_, _|_]
+    ->  true
+    ;   State = dead
+    ).
+
+'$fd_fixed_values'([], []) :-
+    !.
+'$fd_fixed_values'([X|Xs], Values) :-
+    (   integer(X)
+    ->  Values = [X|Values1]
+    ;   Values = Values1
+    ),
+    '$fd_fixed_values'(Xs, Values1).
+
+'$fd_exclude_all'([], _) :-
+    !.
+'$fd_exclude_all'([X|Xs], Values) :-
+    (   var(X)
+    ->  '$fd_exclude_values'(Values, X)
+    ;   true
+    ),
+    '$fd_exclude_all'(Xs, Values).
+
+'$fd_exclude_values'([], _) :-
+    !.
+'$fd_exclude_values'([Value|Values], X) :-
+    '$fd_exclude'(X, Value),
+    '$fd_exclude_values'(Values, X).
+
+'$fd_exclude'(X, Value) :-
+    (   integer(X)
+    ->  X =\= Value
+    ;   Below is Value - 1,
+        Above is Value + 1,
+        '$fd_restrict'(X, [inf-Below, Above-sup])
+    ).
+
+% Domains ----------------------------------------------------------------------------------
+
+'$fd_get'(X, Domain, Propagators, Auxiliary) :-
+    (   get_attr(X, clpfd, clpfd(Domain0, Propagators0, Auxiliary0))
+    ->  Domain = Domain0,
+        Propagators = Propagators0,
+        Auxiliary = Auxiliary0
+    ;   Domain = [inf-sup],
+        Propagators = [],
+        Auxiliary = false
+    ).
+
+'$fd_domain'(X, Domain) :-
+    (   integer(X)
+    ->  Domain = [X-X]
+    ;   '$fd_get'(X, Domain, _, _)
+    ).
+
+'$fd_bounds'(X, Low, High) :-
+    (   integer(X)
+    ->  Low = X,
+        High = X
+    ;   '$fd_get'(X, Domain, _, _),
+        Domain = [Low-_|_],
+        last(Domain, _-High)
+    ).
+
+% Narrows the domain of `X` to its intersection with `Domain`. The propagators of `X` run if
+% it changed, and `X` is bound once a single value is left.
+'$fd_restrict'(X, Domain) :-
+    (   integer(X)
+    ->  '$fd_contains'(Domain, X)
+    ;   '$fd_get'(X, Domain0, Propagators, Auxiliary),
+        '$fd_intersect'(Domain0, Domain, Domain1),
+        (   Domain1 == Domain0
+        ->  true
+        ;   Domain1 = [Value-Value],
+            integer(Value)
+        ->  X = Value
+        ;   Domain1 \== [],
+            put_attr(X, clpfd, clpfd(Domain1, Propagators, Auxiliary)),
+            '$fd_run'(Propagators)
+        )
+    ).
+
+'$fd_contains'([Low-High|Intervals], Value) :-
+    (   '$fd_le'(Low, Value),
+        '$fd_le'(Value, High)
+    ->  true
+    ;   '$fd_contains'(Intervals, Value)
+    ).
+
+'$fd_intersect'([], _, []) :-
+    !.
+'$fd_intersect'(_, [], []) :-
+    !.
+'$fd_intersect'([Low1-High1|Intervals1], [Low2-High2|Intervals2], Domain) :-
+    '$fd_max_bound'(Low1, Low2, Low),
+    '$fd_min_bound'(High1, High2, High),
+    (   '$fd_le'(Low, High)
+    ->  Domain = [Low-High|Domain1]
+    ;   Domain = Domain1
+    ),
+    (   '$fd_le'(High1, High2)
+    ->  '$fd_intersect'(Intervals1, [Low2-High2|Intervals2], Domain1)
+    ;   '$fd_intersect'([Low1-High1|Intervals1], Intervals2, Domain1)
+    ).
+
+% Adds the domains of `Xs` to `Domain0`.
+'$fd_union'([], Domain, Domain) :-
+    !.
+'$fd_union'([X|Xs], Domain0, Domain) :-
+    '$fd_domain'(X, Intervals),
+    '$fd_insert_all'(Intervals, Domain0, Domain1),
+    '$fd_union'(Xs, Domain1, Domain).
+
+'$fd_insert_all'([], Domain, Domain) :-
+    !.
+'$fd_insert_all'([Interval|Intervals], Domain0, Domain) :-
+    '$fd_insert'(Interval, Domain0, Domain1),
+    '$fd_insert_all'(Intervals, Domain1, Domain).
+
+% Inserts an interval, merging it with the ones it overlaps or touches.
+'$fd_insert'(Interval, [], [Interval]) :-
+    !.
+'$fd_insert'(Low-High, [Low1-High1|Intervals], Domain) :-
+    (   '$fd_before'(High, Low1)
+    ->  Domain = [Low-High, Low1-High1|Intervals]
+    ;   '$fd_before'(High1, Low)
+    ->  Domain = [Low1-High1|Domain1],
+        '$fd_insert'(Low-High, Intervals, Domain1)
+    ;   '$fd_min_bound'(Low, Low1, Low2),
+        '$fd_max_bound'(High, High1, High2),
+        '$fd_insert'(Low2-High2, Intervals, Domain)
+    ).
+
+'$fd_before'(High, Low) :-
+    integer(High),
+    integer(Low),
+    High + 1 < Low.
+
+'$fd_size'([], Size, Size) :-
+    !.
+'$fd_size'([Low-High|Intervals], Size0, Size) :-
+    (   integer(Low),
+        integer(High),
+        Size0 \== sup
+    ->  Size1 is Size0 + High - Low + 1,
+        '$fd_size'(Intervals, Size1, Size)
+    ;   Size = sup
+    ).
+
+% Bounds are integers, `inf` below all of them and `sup` above.
+'$fd_le'(A, B) :-
+    (   A == inf
+    ->  true
+    ;   B == sup
+    ->  true
+    ;   A == sup
+    ->  false
+    ;   B == inf
+    ->  false
+    ;   A =< B
+    ).
+
+'$fd_min_bound'(A, B, Min) :-
+    (   '$fd_le'(A, B)
+    ->  Min = A
+    ;   Min = B
+    ).
+
+'$fd_max_bound'(A, B, Max) :-
+    (   '$fd_le'(A, B)
+    ->  Max = B
+    ;   Max = A
+    ).
+
+'$fd_negate_bound'(inf, sup) :-
+    !.
+'$fd_negate_bound'(sup, inf) :-
+    !.
+'$fd_negate_bound'(Bound, Negated) :-
+    Negated is -Bound.
+
+% Domains are written as `Low..High`, joined with `\/`.
+'$fd_spec'(Spec, _) :-
+    var(Spec),
+    !,
+    must_be(integer, Spec).
+'$fd_spec'(N, [N-N]) :-
+    integer(N),
+    !.
+'$fd_spec'(Low..High, Domain) :-
+    !,
+    '$fd_spec_bound'(Low, inf, Low1),
+    '$fd_spec_bound'(High, sup, High1),
+    (   '$fd_le'(Low1, High1)
+    ->  Domain = [Low1-High1]
+    ;   Domain = []
+    ).
+'$fd_spec'(Spec1 \/ Spec2, Domain) :-
+    !,
+    '$fd_spec'(Spec1, Domain1),
+    '$fd_spec'(Spec2, Domain2),
+    '$fd_insert_all'(Domain2, Domain1, Domain).
+'$fd_spec'(Spec, _) :-
+    must_be(integer, Spec).
+
+'$fd_spec_bound'(Bound, Infinite, Bound) :-
+    Bound == Infinite,
+    !.
+'$fd_spec_bound'(Bound, _, Bound) :-
+    must_be(integer, Bound).
+
+'$fd_domain_spec'([Interval|Intervals], Spec) :-
+    '$fd_interval_spec'(Interval, Spec0),
+    '$fd_domain_spec'(Intervals, Spec0, Spec).
+
+'$fd_domain_spec'([], Spec, Spec) :-
+    !.
+'$fd_domain_spec'([Interval|Intervals], Spec0, Spec) :-
+    '$fd_interval_spec'(Interval, Spec1),
+    '$fd_domain_spec'(Intervals, Spec0 \/ Spec1, Spec).
+
+'$fd_interval_spec'(Low-High, Spec) :-
+    (   Low == High
+    ->  Spec = Low
+    ;   Spec = Low..High
+    ).
+
+% Attribute hooks ----------------------------------------------------------------------------
+
+clpfd:attr_unify_hook(clpfd(Domain, Propagators, Auxiliary), Value) :-
+    '$fd_unify'(Value, Domain, Propagators, Auxiliary).
+clpfd:attribute_goals(X, Goals0, Goals) :-
+    '$fd_goals'(X, Goals0, Goals).
+
+'$fd_unify'(Value, Domain, Propagators, Auxiliary) :-
+    (   integer(Value)
+    ->  '$fd_contains'(Domain, Value),
+        '$fd_run'(Propagators)
+    ;   var(Value)
+    ->  '$fd_get'(Value, Domain1, Propagators1, Auxiliary1),
+        '$fd_intersect'(Domain, Domain1, Domain2),
+        Domain2 \== [],
+        append(Propagators, Propagators1, Propagators2),
+        (   Auxiliary == true,
+            Auxiliary1 == true
+        ->  Auxiliary2 = true
+        ;   Auxiliary2 = false
+        ),
+        put_attr(Value, clpfd, clpfd(Domain2, Propagators2, Auxiliary2)),
+        (   Domain2 = [N-N],
+            integer(N)
+        ->  Value = N
+        ;   '$fd_run'(Propagators2)
+        )
+    ;   must_be(integer, Value)
+    ).
+
+% The domain of a variable, unless it is auxiliary or unrestricted, and the constraints on it
+% which are not entailed yet.
+'$fd_goals'(X, Goals0, Goals) :-
+    get_attr(X, clpfd, clpfd(Domain, Propagators, Auxiliary)),
+    (   Auxiliary == false,
+        Domain \== [inf-sup]
+    ->  '$fd_domain_spec'(Domain, Spec),
+        Goals0 = [X in Spec|Goals1]
+    ;   Goals0 = Goals1
+    ),
+    '$fd_propagator_goals'(Propagators, Goals1, Goals).
+
+'$fd_propagator_goals'([], Goals, Goals) :-
+    !.
+'$fd_propagator_goals'([p(_, Goal, State)|Propagators], Goals0, Goals) :-
+    (   State == dead
+    ->  Goals0 = Goals1
+    ;   Goals0 = [Goal|Goals1]
+    ),
+    '$fd_propagator_goals'(Propagators, Goals1, Goals).
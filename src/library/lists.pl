@@ -0,0 +1,133 @@
+% library(lists): list predicates, loaded the first time one of them is called.
+
+append([], Ys, Ys).
+append([X|Xs], Ys, [X|Zs]) :-
+    append(Xs, Ys, Zs).
+
+member(X, [Y|Ys]) :-
+    '$member'(Ys, X, Y).
+
+memberchk(X, Xs) :-
+    member(X, Xs),
+    !.
+
+select(X, [X|Xs], Xs).
+select(X, [Y|Xs], [Y|Ys]) :-
+    select(X, Xs, Ys).
+
+% With an unbound length, enumerates lists of increasing length. Cyclic lists and lists
+% ending in anything but `[]` or a variable raise type_error(list, List), a negative length
+% raises domain_error(not_less_than_zero, Length).
+length(List, Length) :-
+    '$skip_list'(List, Skipped, Tail),
+    (   var(Length)
+    ->  '$length'(Tail, Skipped, Length)
+    ;   must_be(integer, Length),
+        (   Length < 0
+        ->  '$domain_error'(not_less_than_zero, Length)
+        ;   true
+        ),
+        Rest is Length - Skipped,
+        '$length_make'(Tail, Rest)
+    ).
+
+'$length'([], Length, Length).
+'$length'([_|Xs], Length0, Length) :-
+    Length1 is Length0 + 1,
+    '$length'(Xs, Length1, Length).
+
+'$length_make'([], 0) :-
+    !.
+'$length_make'([_|Xs], Length) :-
+    Length > 0,
+    Length1 is Length - 1,
+    '$length_make'(Xs, Length1).
+
+reverse(Xs, Ys) :-
+    '$reverse'(Xs, [], Ys).
+
+'$reverse'([], Ys, Ys).
+'$reverse'([X|Xs], Acc, Ys) :-
+    '$reverse'(Xs, [X|Acc], Ys).
+
+nth0(Index, List, Elem) :-
+    integer(Index),
+    !,
+    Index >= 0,
+    '$nth'(Index, List, Elem).
+nth0(Index, List, Elem) :-
+    var(Index),
+    !,
+    '$nth_enumerate'(List, Elem, 0, Index).
+nth0(Index, _, _) :-
+    must_be(integer, Index).
+
+nth1(Index, List, Elem) :-
+    integer(Index),
+    !,
+    Index >= 1,
+    Index0 is Index - 1,
+    '$nth'(Index0, List, Elem).
+nth1(Index, List, Elem) :-
+    var(Index),
+    !,
+    '$nth_enumerate'(List, Elem, 1, Index).
+nth1(Index, _, _) :-
+    must_be(integer, Index).
+
+'$nth'(0, [Elem|_], Elem) :-
+    !.
+'$nth'(Index, [_|Xs], Elem) :-
+    Index > 0,
+    Index1 is Index - 1,
+    '$nth'(Index1, Xs, Elem).
+
+'$nth_enumerate'([Elem|_], Elem, Index, Index).
+'$nth_enumerate'([_|Xs], Elem, Index0, Index) :-
+    Index1 is Index0 + 1,
+    '$nth_enumerate'(Xs, Elem, Index1, Index).
+
+last([X|Xs], Last) :-
+    '$last'(Xs, X, Last).
+
+'$last'([], Last, Last).
+'$last'([X|Xs], _, Last) :-
+    '$last'(Xs, X, Last).
+
+% Keeps the first occurrence of every element, comparing with ==/2. Sorting the elements
+% tagged with their position brings the duplicates together in O(n log n).
+list_to_set(List, Set) :-
+    must_be(list, List),
+    '$number_elements'(List, 1, Numbered),
+    msort(Numbered, Sorted),
+    '$first_occurrences'(Sorted, Firsts),
+    msort(Firsts, Ordered),
+    '$pairs_values'(Ordered, Set).
+
+'$number_elements'([], _, []).
+'$number_elements'([X|Xs], Index, [X-Index|Pairs]) :-
+    Next is Index + 1,
+    '$number_elements'(Xs, Next, Pairs).
+
+'$first_occurrences'([], []).
+'$first_occurrences'([X-Index|Pairs], [Index-X|Firsts]) :-
+    '$skip_occurrences'(Pairs, X, Rest),
+    '$first_occurrences'(Rest, Firsts).
+
+'$skip_occurrences'([Y-_|Pairs], X, Rest) :-
+    Y == X,
+    !,
+    '$skip_occurrences'(Pairs, X, Rest).
+'$skip_occurrences'(Rest, _, Rest).
+
+'$pairs_values'([], []).
+'$pairs_values'([_-Value|Pairs], [Value|Values]) :-
+    '$pairs_values'(Pairs, Values).
+
+sum_list(Xs, Sum) :-
+    '$sum_list'(Xs, 0, Sum).
+
+'$sum_list'([], Sum, Sum).
+'$sum_list'([X|Xs], Sum0, Sum) :-
+    Sum1 is Sum0 + X,
+    '$sum_list'(Xs, Sum1, Sum).
@@ -0,0 +1,205 @@
+use std::{cmp::Ordering, fmt};
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+/// A Prolog number as seen by the compiler and by arithmetic evaluation.
+///
+/// Integers are kept in their small representation whenever they fit into an `i64` and only spill
+/// into a `BigInteger` once they overflow. Rationals with a denominator of one are always
+/// normalized back into integers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Number {
+    Integer(i64),
+    BigInteger(BigInt),
+    Rational(BigRational),
+    Float(f64),
+}
+
+impl Number {
+    pub fn from_bigint(value: BigInt) -> Self {
+        match value.to_i64() {
+            Some(value) => Number::Integer(value),
+            None => Number::BigInteger(value),
+        }
+    }
+
+    pub fn from_rational(value: BigRational) -> Self {
+        if value.denom().is_one() {
+            Number::from_bigint(value.numer().clone())
+        } else {
+            Number::Rational(value)
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Number::Integer(_) | Number::BigInteger(_))
+    }
+
+    /// Whether the number does not fit into a single cell and is stored as a box on the heap.
+    pub fn is_boxed(&self) -> bool {
+        matches!(self, Number::BigInteger(_) | Number::Rational(_))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Number::Integer(value) => *value == 0,
+            Number::BigInteger(value) => value.is_zero(),
+            Number::Rational(value) => value.is_zero(),
+            Number::Float(value) => *value == 0.0,
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        match self {
+            Number::Integer(value) => *value < 0,
+            Number::BigInteger(value) => value.is_negative(),
+            Number::Rational(value) => value.is_negative(),
+            Number::Float(value) => *value < 0.0,
+        }
+    }
+
+    pub fn to_bigint(&self) -> Option<BigInt> {
+        match self {
+            Number::Integer(value) => Some(BigInt::from(*value)),
+            Number::BigInteger(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn to_rational(&self) -> Option<BigRational> {
+        match self {
+            Number::Integer(value) => Some(BigRational::from_integer(BigInt::from(*value))),
+            Number::BigInteger(value) => Some(BigRational::from_integer(value.clone())),
+            Number::Rational(value) => Some(value.clone()),
+            Number::Float(_) => None,
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Number::Integer(value) => *value as f64,
+            Number::BigInteger(value) => value.to_f64().unwrap_or(f64::NAN),
+            Number::Rational(value) => value.to_f64().unwrap_or(f64::NAN),
+            Number::Float(value) => *value,
+        }
+    }
+
+    /// Compares two numbers by value, the way `=:=` and `<` do. Mixed integer and float
+    /// comparisons are done exactly rather than by converting the integer to a float.
+    pub fn compare(&self, other: &Number) -> Ordering {
+        match (self, other) {
+            (Number::Integer(a), Number::Integer(b)) => a.cmp(b),
+            (Number::Float(a), Number::Float(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+            (Number::Float(a), b) => compare_float_exact(*a, b),
+            (a, Number::Float(b)) => compare_float_exact(*b, a).reverse(),
+            (a, b) => a.to_rational().unwrap().cmp(&b.to_rational().unwrap()),
+        }
+    }
+
+    /// Parses the text of a number token as produced by the reader.
+    pub fn parse(text: &str) -> Option<Number> {
+        if let Some(code) = text.strip_prefix("0'") {
+            return parse_character_code(code).map(|code| Number::Integer(code as i64));
+        }
+        for (prefix, radix) in [("0x", 16), ("0o", 8), ("0b", 2)] {
+            if let Some(digits) = text.strip_prefix(prefix) {
+                return BigInt::parse_bytes(digits.as_bytes(), radix).map(Number::from_bigint);
+            }
+        }
+        if let Some((numerator, denominator)) = text.split_once('r') {
+            let numerator = BigInt::parse_bytes(numerator.as_bytes(), 10)?;
+            let denominator = BigInt::parse_bytes(denominator.as_bytes(), 10)?;
+            if denominator.is_zero() {
+                return None;
+            }
+            return Some(Number::from_rational(BigRational::new(
+                numerator,
+                denominator,
+            )));
+        }
+        if text.contains(['.', 'e', 'E']) {
+            return text.parse::<f64>().ok().map(Number::Float);
+        }
+        BigInt::parse_bytes(text.as_bytes(), 10).map(Number::from_bigint)
+    }
+}
+
+fn compare_float_exact(float: f64, other: &Number) -> Ordering {
+    if float.is_nan() {
+        return Ordering::Less;
+    }
+    if float.is_infinite() {
+        return if float > 0.0 {
+            Ordering::Greater
+        } else {
+            Ordering::Less
+        };
+    }
+    let float = BigRational::from_float(float).unwrap();
+    float.cmp(&other.to_rational().unwrap())
+}
+
+fn parse_character_code(text: &str) -> Option<u32> {
+    let mut chars = text.chars();
+    match chars.next()? {
+        '\\' => {
+            let escaped = chars.next()?;
+            let code = match escaped {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                'a' => '\x07',
+                'b' => '\x08',
+                'f' => '\x0c',
+                'v' => '\x0b',
+                '0' => '\0',
+                'e' => '\x1b',
+                's' => ' ',
+                other => other,
+            };
+            Some(code as u32)
+        }
+        '\'' => {
+            // Both `0''` and the ISO form `0'''` denote the quote character.
+            Some('\'' as u32)
+        }
+        c => Some(c as u32),
+    }
+}
+
+impl From<i64> for Number {
+    fn from(value: i64) -> Self {
+        Number::Integer(value)
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Number::Integer(value) => write!(f, "{}", value),
+            Number::BigInteger(value) => write!(f, "{}", value),
+            Number::Rational(value) => write!(f, "{}r{}", value.numer(), value.denom()),
+            Number::Float(value) => write!(f, "{}", format_float(*value)),
+        }
+    }
+}
+
+/// Formats a float the way Prolog prints it: the shortest representation that reads back to the
+/// same value, always with a fractional part so it cannot be mistaken for an integer.
+pub fn format_float(value: f64) -> String {
+    if value.is_nan() {
+        return "nan".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    let text = format!("{:?}", value);
+    match text.split_once('e') {
+        Some((mantissa, exponent)) if !mantissa.contains('.') => {
+            format!("{}.0e{}", mantissa, exponent)
+        }
+        _ => text,
+    }
+}
@@ -2,6 +2,11 @@ use anyhow::Result;
 use pest::{Parser, iterators::Pair};
 use pest_derive::Parser;
 
+use crate::{number::Number, parsing::operators::OperatorTable};
+
+pub mod dcg;
+pub mod operators;
+
 #[derive(Parser)]
 #[grammar = "syntax.pest"]
 pub struct PrologParser;
@@ -11,76 +16,615 @@ pub fn parse(input: &str) -> Result<AbstractProgram> {
         .into_iter()
         .next()
         .ok_or_else(|| anyhow::anyhow!("No term found"))?;
-    let term = parse_program(pair)?;
+    let operators = OperatorTable::default();
+    let mut reader = TermReader::new(&operators);
+    let term = parse_program(pair, &mut reader)?;
     Ok(term)
 }
 
-fn parse_program(pair: Pair<'_, Rule>) -> Result<AbstractProgram> {
+/// Parses a sequence of clauses, e.g. the contents of a source file.
+pub fn parse_clauses(input: &str) -> Result<Vec<AbstractProgram>> {
+    let pair = PrologParser::parse(Rule::program_text, input)?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("No program found"))?;
+    let operators = OperatorTable::default();
+    let mut reader = TermReader::new(&operators);
+    pair.into_inner()
+        .filter(|pair| pair.as_rule() == Rule::clause)
+        .map(|pair| parse_program(pair, &mut reader))
+        .collect()
+}
+
+/// Parses the text of one clause, a term followed by an end token, as a term. Unlike
+/// [`parse`] the term is not turned into a program and may be of any kind.
+pub fn parse_term(
+    input: &str,
+    operators: &OperatorTable,
+    double_quotes: DoubleQuotes,
+) -> Result<AbstractTerm> {
+    let clause = PrologParser::parse(Rule::program, input)?
+        .next()
+        .and_then(|program| program.into_inner().next())
+        .ok_or_else(|| anyhow::anyhow!("No term found"))?;
+    let term = clause.into_inner().next().unwrap();
+    let mut reader = TermReader::new(operators);
+    reader.double_quotes = double_quotes;
+    reader.read_term(term, 1200)
+}
+
+/// What text in double quotes is read as, set by the `double_quotes` flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DoubleQuotes {
+    #[default]
+    Codes,
+    Chars,
+    Atom,
+    String,
+}
+
+impl DoubleQuotes {
+    pub fn name(self) -> &'static str {
+        match self {
+            DoubleQuotes::Codes => "codes",
+            DoubleQuotes::Chars => "chars",
+            DoubleQuotes::Atom => "atom",
+            DoubleQuotes::String => "string",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "codes" => Some(DoubleQuotes::Codes),
+            "chars" => Some(DoubleQuotes::Chars),
+            "atom" => Some(DoubleQuotes::Atom),
+            "string" => Some(DoubleQuotes::String),
+            _ => None,
+        }
+    }
+}
+
+/// Where the first clause of some source text ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClauseEnd {
+    /// The byte offset just past the end token.
+    At(usize),
+    /// The text starts a clause but has no end token.
+    Missing,
+    /// The text holds nothing but layout and comments.
+    Empty,
+}
+
+/// Finds the end token of the first clause in `text`, skipping quoted text, character code
+/// literals and comments.
+pub fn clause_end(text: &str) -> ClauseEnd {
+    let is_symbol_char = |c: char| "+-*/\\^<>=~:.?@#&$".contains(c);
+    let mut empty = true;
+    let mut previous = ' ';
+    let mut chars = text.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '%' => while chars.next_if(|(_, c)| *c != '\n').is_some() {},
+            '/' if chars.peek().is_some_and(|(_, c)| *c == '*') => {
+                chars.next();
+                let mut star = false;
+                for (_, c) in chars.by_ref() {
+                    if star && c == '/' {
+                        break;
+                    }
+                    star = c == '*';
+                }
+            }
+            '\'' | '"' | '`' => {
+                empty = false;
+                while let Some((_, next)) = chars.next() {
+                    if next == '\\' {
+                        chars.next();
+                    } else if next == c && chars.next_if(|(_, c2)| *c2 == c).is_none() {
+                        break;
+                    }
+                }
+            }
+            '0' if !previous.is_alphanumeric() && chars.peek().is_some_and(|(_, c)| *c == '\'') => {
+                empty = false;
+                chars.next();
+                match chars.next() {
+                    Some((_, '\\')) | Some((_, '\'')) => {
+                        chars.next();
+                    }
+                    _ => {}
+                }
+            }
+            c if is_symbol_char(c) => {
+                empty = false;
+                let mut end = index + c.len_utf8();
+                while let Some((next, symbol)) = chars.next_if(|(_, c)| is_symbol_char(*c)) {
+                    end = next + symbol.len_utf8();
+                }
+                if &text[index..end] == "."
+                    && chars
+                        .peek()
+                        .is_none_or(|(_, c)| c.is_whitespace() || *c == '%')
+                {
+                    return ClauseEnd::At(end);
+                }
+            }
+            _ => empty = false,
+        }
+        previous = c;
+    }
+    if empty {
+        ClauseEnd::Empty
+    } else {
+        ClauseEnd::Missing
+    }
+}
+
+fn parse_program(pair: Pair<'_, Rule>, reader: &mut TermReader) -> Result<AbstractProgram> {
     match pair.as_rule() {
         Rule::program => {
             let mut inner = pair.into_inner();
             let pair = inner.next().unwrap();
-            parse_program(pair)
-        }
-        Rule::fact => {
-            let mut inner_pairs = pair.into_inner();
-            let pair = inner_pairs.next().unwrap();
-            Ok(AbstractProgram::Fact(AbstractFact {
-                term: parse_term(pair)?,
-            }))
-        }
-        Rule::rule => {
-            let mut inner_pairs = pair.into_inner();
-            let head = parse_term(inner_pairs.next().unwrap())?;
-            let goals = inner_pairs
-                .map(|pair| parse_term(pair))
-                .collect::<Result<Vec<_>>>()?;
-            Ok(AbstractProgram::Rule(AbstractRule { head, goals }))
+            parse_program(pair, reader)
+        }
+        Rule::clause => {
+            let pair = pair.into_inner().next().unwrap();
+            let term = reader.read_term(pair, 1200)?;
+            program_from_term(term)
         }
         _ => panic!("Unexpected rule: {:?}", pair.as_rule()),
     }
 }
 
-fn parse_term(pair: Pair<'_, Rule>) -> Result<AbstractTerm> {
+/// Turns a term read as a clause into a fact or a rule.
+pub fn program_from_term(mut term: AbstractTerm) -> Result<AbstractProgram> {
+    match &mut term {
+        AbstractTerm::Structure(name, args) if name == "-->" && args.len() == 2 => {
+            program_from_term(dcg::translate_rule(&args[0], &args[1])?)
+        }
+        AbstractTerm::Structure(name, args) if name == ":-" && args.len() == 2 => {
+            let body = args.pop().unwrap();
+            let head = args.pop().unwrap();
+            ensure_callable(&head)?;
+            let mut goals = Vec::new();
+            flatten_conjunction(body, &mut goals)?;
+            Ok(AbstractProgram::Rule(AbstractRule { head, goals }))
+        }
+        AbstractTerm::Structure(name, args) if name == ":-" && args.len() == 1 => {
+            match args.pop().unwrap() {
+                goal @ AbstractTerm::Structure(_, _)
+                    if matches!(
+                        goal.name(),
+                        "dynamic" | "module" | "use_module" | "ensure_loaded" | "meta_predicate"
+                    ) =>
+                {
+                    Ok(AbstractProgram::Directive(goal))
+                }
+                _ => Err(anyhow::anyhow!("Directives are not supported")),
+            }
+        }
+        _ => {
+            ensure_callable(&term)?;
+            Ok(AbstractProgram::Fact(AbstractFact { term }))
+        }
+    }
+}
+
+fn ensure_callable(term: &AbstractTerm) -> Result<()> {
+    match term {
+        AbstractTerm::Constant(_) | AbstractTerm::Structure(_, _) => Ok(()),
+        _ => Err(anyhow::anyhow!("Clause head is not callable")),
+    }
+}
+
+fn flatten_conjunction(mut term: AbstractTerm, goals: &mut Vec<AbstractTerm>) -> Result<()> {
+    match &mut term {
+        AbstractTerm::Structure(name, args) if name == "," && args.len() == 2 => {
+            let right = args.pop().unwrap();
+            let left = args.pop().unwrap();
+            flatten_conjunction(left, goals)?;
+            flatten_conjunction(right, goals)
+        }
+        AbstractTerm::Variable(_) => {
+            goals.push(AbstractTerm::Structure("call".to_string(), vec![term]));
+            Ok(())
+        }
+        AbstractTerm::Number(_) | AbstractTerm::String(_) => {
+            Err(anyhow::anyhow!("Goal is not callable"))
+        }
+        _ => {
+            goals.push(term);
+            Ok(())
+        }
+    }
+}
+
+/// A single token of a term before operators have been resolved.
+#[derive(Debug)]
+enum Token {
+    Operand(AbstractTerm),
+    Name(String),
+}
+
+#[derive(Debug)]
+struct Item {
+    token: Token,
+    /// Whether the token was written in functional notation, i.e. `name(arguments...)`.
+    functional: bool,
+    start: usize,
+    end: usize,
+}
+
+/// Turns the flat token sequences produced by the grammar into terms, resolving operators with
+/// the help of an operator table.
+struct TermReader<'a> {
+    operators: &'a OperatorTable,
+    anonymous_variables: usize,
+    double_quotes: DoubleQuotes,
+}
+
+impl<'a> TermReader<'a> {
+    fn new(operators: &'a OperatorTable) -> Self {
+        TermReader {
+            operators,
+            anonymous_variables: 0,
+            double_quotes: DoubleQuotes::Codes,
+        }
+    }
+
+    fn read_term(&mut self, pair: Pair<'_, Rule>, max_priority: usize) -> Result<AbstractTerm> {
+        let items = pair
+            .into_inner()
+            .map(|pair| self.read_item(pair))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut position = 0;
+        let (term, _) = self.resolve(&items, &mut position, max_priority)?;
+        if position < items.len() {
+            return Err(anyhow::anyhow!(
+                "Operator expected at position {}",
+                items[position].start
+            ));
+        }
+        Ok(term)
+    }
+
+    fn read_item(&mut self, pair: Pair<'_, Rule>) -> Result<Item> {
+        let span = pair.as_span();
+        let functional = pair.as_rule() == Rule::term_structure;
+        let token = match pair.as_rule() {
+            Rule::term_constant => Token::Name(read_name(pair)?),
+            Rule::term_comma => Token::Name(",".to_string()),
+            Rule::term_bar => Token::Name("|".to_string()),
+            _ => Token::Operand(self.read_primary(pair)?),
+        };
+        Ok(Item {
+            token,
+            functional,
+            start: span.start(),
+            end: span.end(),
+        })
+    }
+
+    fn read_primary(&mut self, pair: Pair<'_, Rule>) -> Result<AbstractTerm> {
+        match pair.as_rule() {
+            Rule::term_variable => {
+                let variable = pair.as_str();
+                if variable == "_" {
+                    self.anonymous_variables += 1;
+                    Ok(AbstractTerm::Variable(format!(
+                        "_#{}",
+                        self.anonymous_variables
+                    )))
+                } else {
+                    Ok(AbstractTerm::Variable(variable.to_string()))
+                }
+            }
+            Rule::term_number => Number::parse(pair.as_str())
+                .map(AbstractTerm::Number)
+                .ok_or_else(|| anyhow::anyhow!("Invalid number: {}", pair.as_str())),
+            Rule::term_string => {
+                let text = pair.as_str();
+                let text = unescape(&text[1..text.len() - 1], '"')?;
+                let elements = match self.double_quotes {
+                    DoubleQuotes::Atom => return Ok(AbstractTerm::Constant(text)),
+                    DoubleQuotes::String => return Ok(AbstractTerm::String(text)),
+                    DoubleQuotes::Codes => text
+                        .chars()
+                        .map(|c| AbstractTerm::Number(Number::Integer(c as i64)))
+                        .collect(),
+                    DoubleQuotes::Chars => text
+                        .chars()
+                        .map(|c| AbstractTerm::Constant(c.to_string()))
+                        .collect(),
+                };
+                Ok(AbstractTerm::list(
+                    elements,
+                    AbstractTerm::Constant("[]".to_string()),
+                ))
+            }
+            Rule::term_structure => {
+                let mut inner_pairs = pair.into_inner();
+                let functor = inner_pairs
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("No functor found"))?;
+                let functor = read_name(functor.into_inner().next().unwrap())?;
+                let args = inner_pairs
+                    .map(|p| self.read_term(p, 999))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(AbstractTerm::Structure(functor, args))
+            }
+            Rule::term_parenthesized => self.read_term(pair.into_inner().next().unwrap(), 1200),
+            Rule::term_list => {
+                let mut elements = Vec::new();
+                let mut tail = AbstractTerm::Constant("[]".to_string());
+                for pair in pair.into_inner() {
+                    match pair.as_rule() {
+                        Rule::term_list_tail => {
+                            tail = self.read_term(pair.into_inner().next().unwrap(), 999)?;
+                        }
+                        _ => elements.push(self.read_term(pair, 999)?),
+                    }
+                }
+                Ok(AbstractTerm::list(elements, tail))
+            }
+            Rule::term_empty_list => Ok(AbstractTerm::Constant("[]".to_string())),
+            Rule::term_curly => {
+                let inner = self.read_term(pair.into_inner().next().unwrap(), 1200)?;
+                Ok(AbstractTerm::Structure("{}".to_string(), vec![inner]))
+            }
+            Rule::term_empty_curly => Ok(AbstractTerm::Constant("{}".to_string())),
+            _ => Err(anyhow::anyhow!("Unexpected rule: {:?}", pair.as_rule())),
+        }
+    }
+
+    fn resolve(
+        &self,
+        items: &[Item],
+        position: &mut usize,
+        max_priority: usize,
+    ) -> Result<(AbstractTerm, usize)> {
+        let (mut left, mut left_priority) = self.resolve_primary(items, position, max_priority)?;
+
+        while let Some(item) = items.get(*position) {
+            let name = match &item.token {
+                Token::Name(name) if name == "|" => ";",
+                Token::Name(name) => name.as_str(),
+                // An infix operator directly followed by a parenthesis, as in `X^(a, b)`, reads
+                // like a structure but still is an operator in this position.
+                Token::Operand(AbstractTerm::Structure(name, arguments)) if item.functional => {
+                    match self.operators.infix(name) {
+                        Some(operator)
+                            if operator.priority <= max_priority
+                                && left_priority
+                                    <= operator
+                                        .operator_type
+                                        .argument_priorities(operator.priority)
+                                        .0 =>
+                        {
+                            *position += 1;
+                            let right = arguments
+                                .iter()
+                                .rev()
+                                .cloned()
+                                .reduce(|right, left| {
+                                    AbstractTerm::Structure(",".to_string(), vec![left, right])
+                                })
+                                .expect("structures to have arguments");
+                            left = AbstractTerm::Structure(name.clone(), vec![left, right]);
+                            left_priority = operator.priority;
+                            continue;
+                        }
+                        _ => break,
+                    }
+                }
+                Token::Operand(_) => break,
+            };
+
+            if let Some(operator) = self.operators.infix(name) {
+                let (left_max, right_max) = operator
+                    .operator_type
+                    .argument_priorities(operator.priority);
+                if operator.priority <= max_priority && left_priority <= left_max {
+                    *position += 1;
+                    let (right, _) = self.resolve(items, position, right_max)?;
+                    left = AbstractTerm::Structure(name.to_string(), vec![left, right]);
+                    left_priority = operator.priority;
+                    continue;
+                }
+            }
+
+            if let Some(operator) = self.operators.postfix(name) {
+                let (left_max, _) = operator
+                    .operator_type
+                    .argument_priorities(operator.priority);
+                if operator.priority <= max_priority && left_priority <= left_max {
+                    *position += 1;
+                    left = AbstractTerm::Structure(name.to_string(), vec![left]);
+                    left_priority = operator.priority;
+                    continue;
+                }
+            }
+
+            break;
+        }
+
+        Ok((left, left_priority))
+    }
+
+    fn resolve_primary(
+        &self,
+        items: &[Item],
+        position: &mut usize,
+        max_priority: usize,
+    ) -> Result<(AbstractTerm, usize)> {
+        let item = items
+            .get(*position)
+            .ok_or_else(|| anyhow::anyhow!("Unexpected end of term"))?;
+        *position += 1;
+
+        let name = match &item.token {
+            Token::Operand(term) => return Ok((term.clone(), 0)),
+            Token::Name(name) => name,
+        };
+
+        // A minus sign directly in front of a number literal is part of the number.
+        if name == "-"
+            && let Some(Item {
+                token: Token::Operand(AbstractTerm::Number(number)),
+                start,
+                ..
+            }) = items.get(*position)
+            && *start == item.end
+        {
+            *position += 1;
+            return Ok((AbstractTerm::Number(negate(number)), 0));
+        }
+
+        if let Some(operator) = self.operators.prefix(name)
+            && self.can_start_operand(items, *position)
+        {
+            let priority = operator.priority.min(max_priority);
+            let (_, argument_max) = operator.operator_type.argument_priorities(priority);
+            let (argument, _) = self.resolve(items, position, argument_max)?;
+            return Ok((
+                AbstractTerm::Structure(name.clone(), vec![argument]),
+                priority,
+            ));
+        }
+
+        Ok((AbstractTerm::Constant(name.clone()), 0))
+    }
+
+    /// Whether the item at `position` can begin the argument of a prefix operator. If it cannot,
+    /// the prefix operator is read as a plain atom instead, as in `X = (-)` or `- = X`.
+    fn can_start_operand(&self, items: &[Item], position: usize) -> bool {
+        match items.get(position) {
+            None => false,
+            Some(Item {
+                token: Token::Operand(_),
+                ..
+            }) => true,
+            Some(Item {
+                token: Token::Name(name),
+                ..
+            }) => {
+                self.operators.prefix(name).is_some()
+                    || (self.operators.infix(name).is_none()
+                        && self.operators.postfix(name).is_none())
+            }
+        }
+    }
+}
+
+fn negate(number: &Number) -> Number {
+    match number {
+        Number::Integer(value) => match value.checked_neg() {
+            Some(value) => Number::Integer(value),
+            None => Number::from_bigint(-num_bigint::BigInt::from(*value)),
+        },
+        Number::BigInteger(value) => Number::from_bigint(-value),
+        Number::Rational(value) => Number::Rational(-value),
+        Number::Float(value) => Number::Float(-value),
+    }
+}
+
+fn read_name(pair: Pair<'_, Rule>) -> Result<String> {
     let pair = pair
         .into_inner()
         .next()
-        .ok_or_else(|| anyhow::anyhow!("No term found"))?;
-
+        .ok_or_else(|| anyhow::anyhow!("No name found"))?;
+    let text = pair.as_str();
     match pair.as_rule() {
-        Rule::term_variable => {
-            let variable = pair.as_str().to_string();
-            Ok(AbstractTerm::Variable(variable))
+        Rule::term_quoted => unescape(&text[1..text.len() - 1], '\''),
+        _ => Ok(text.to_string()),
+    }
+}
+
+fn unescape(text: &str, quote: char) -> Result<String> {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == quote {
+            // Doubled quotes inside quoted text stand for a single quote.
+            chars.next();
+            result.push(quote);
+            continue;
         }
-        Rule::term_constant => {
-            let constant = pair.as_str().to_string();
-            Ok(AbstractTerm::Constant(constant))
+        if c != '\\' {
+            result.push(c);
+            continue;
         }
-        Rule::term_structure => {
-            let mut inner_pairs = pair.into_inner();
-            let functor = inner_pairs
-                .next()
-                .ok_or_else(|| anyhow::anyhow!("No functor found"))?
-                .as_str()
-                .to_string();
-            let args: Result<Vec<AbstractTerm>> = inner_pairs.map(|p| parse_term(p)).collect();
-            Ok(AbstractTerm::Structure(functor, args?))
+        let escaped = chars
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Unterminated escape sequence"))?;
+        match escaped {
+            'n' => result.push('\n'),
+            't' => result.push('\t'),
+            'r' => result.push('\r'),
+            'a' => result.push('\x07'),
+            'b' => result.push('\x08'),
+            'f' => result.push('\x0c'),
+            'v' => result.push('\x0b'),
+            'e' => result.push('\x1b'),
+            's' => result.push(' '),
+            '0'..='7' | 'x' => {
+                let radix = if escaped == 'x' { 16 } else { 8 };
+                let mut digits = String::new();
+                if escaped != 'x' {
+                    digits.push(escaped);
+                }
+                while let Some(&digit) = chars.peek() {
+                    chars.next();
+                    if digit == '\\' {
+                        break;
+                    }
+                    digits.push(digit);
+                }
+                let code = u32::from_str_radix(&digits, radix)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid character code: {}", digits))?;
+                result.push(code);
+            }
+            '\n' => {}
+            other => result.push(other),
         }
-        _ => Err(anyhow::anyhow!("Unexpected rule: {:?}", pair.as_rule())),
     }
+    Ok(result)
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum AbstractTerm {
     Variable(String),
     Constant(String),
+    Number(Number),
+    /// A string, read from text in double quotes if the `double_quotes` flag is `string`.
+    String(String),
     Structure(String, Vec<AbstractTerm>),
 }
 
+/// Terms are dropped without recursion, so long lists don't overflow the stack.
+impl Drop for AbstractTerm {
+    fn drop(&mut self) {
+        let AbstractTerm::Structure(_, arguments) = self else {
+            return;
+        };
+        let mut pending = std::mem::take(arguments);
+        while let Some(mut term) = pending.pop() {
+            if let AbstractTerm::Structure(_, arguments) = &mut term {
+                pending.append(arguments);
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum AbstractProgram {
     Fact(AbstractFact),
     Rule(AbstractRule),
+    /// A `:- Goal` declaration, handled while the program is compiled.
+    Directive(AbstractTerm),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -94,6 +638,18 @@ pub struct AbstractFact {
     pub term: AbstractTerm,
 }
 
+impl AbstractRule {
+    /// The goals of the body as one conjunction.
+    pub fn body(&self) -> AbstractTerm {
+        self.goals
+            .iter()
+            .cloned()
+            .rev()
+            .reduce(|right, left| AbstractTerm::Structure(",".to_string(), vec![left, right]))
+            .expect("rules to have a body")
+    }
+}
+
 impl AbstractFact {
     pub fn arity(&self) -> usize {
         self.term.arity()
@@ -105,10 +661,18 @@ impl AbstractFact {
 }
 
 impl AbstractTerm {
+    /// Builds a list from its elements, ending in `tail`.
+    pub fn list(elements: Vec<AbstractTerm>, tail: AbstractTerm) -> AbstractTerm {
+        elements.into_iter().rev().fold(tail, |tail, element| {
+            AbstractTerm::Structure(".".to_string(), vec![element, tail])
+        })
+    }
+
     pub fn arity(&self) -> usize {
         match self {
             AbstractTerm::Variable(_) => 0,
             AbstractTerm::Constant(_) => 0,
+            AbstractTerm::Number(_) | AbstractTerm::String(_) => 0,
             AbstractTerm::Structure(_, args) => args.len(),
         }
     }
@@ -117,6 +681,7 @@ impl AbstractTerm {
         match self {
             AbstractTerm::Variable(name) => name,
             AbstractTerm::Constant(name) => name,
+            AbstractTerm::Number(_) | AbstractTerm::String(_) => "",
             AbstractTerm::Structure(name, _) => name,
         }
     }
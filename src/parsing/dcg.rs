@@ -0,0 +1,162 @@
+use anyhow::{Result, anyhow};
+
+use crate::{number::Number, parsing::AbstractTerm};
+
+/// Translates the grammar rule `Head --> Body` into the clause `Head(S0, S) :- Body(S0, S)`.
+/// Every non-terminal gets two extra arguments, the list before and after the text it
+/// describes. A head of the form `Head, PushBack` puts the terminals `PushBack` back in front of `S`.
+pub fn translate_rule(head: &AbstractTerm, body: &AbstractTerm) -> Result<AbstractTerm> {
+    let mut translation = Translation::default();
+    let start = translation.fresh();
+    let end = translation.fresh();
+    let (head, body) = match head {
+        AbstractTerm::Structure(name, arguments) if name == "," && arguments.len() == 2 => {
+            let rest = translation.fresh();
+            let head = non_terminal(&arguments[0], start.clone(), end.clone())?;
+            let body = translation.body(body, start, rest.clone())?;
+            let push_back = terminals(&arguments[1], end, rest)?;
+            (head, conjunction(body, push_back))
+        }
+        _ => {
+            let head = non_terminal(head, start.clone(), end.clone())?;
+            (head, translation.body(body, start, end)?)
+        }
+    };
+    Ok(structure(":-", vec![head, body]))
+}
+
+/// Translates the grammar body `body` into a goal describing the text between `start` and
+/// `end`, as `phrase/3` runs it.
+pub fn translate_body(
+    body: &AbstractTerm,
+    start: AbstractTerm,
+    end: AbstractTerm,
+) -> Result<AbstractTerm> {
+    Translation::default().body(body, start, end)
+}
+
+/// Names the variables introduced by a translation. `#` cannot occur in the name of a variable
+/// read from source text, so they never clash with the variables of the rule.
+#[derive(Default)]
+struct Translation {
+    next_variable: usize,
+}
+
+impl Translation {
+    fn fresh(&mut self) -> AbstractTerm {
+        self.next_variable += 1;
+        AbstractTerm::Variable(format!("S#{}", self.next_variable))
+    }
+
+    fn body(
+        &mut self,
+        body: &AbstractTerm,
+        start: AbstractTerm,
+        end: AbstractTerm,
+    ) -> Result<AbstractTerm> {
+        let goal = match body {
+            AbstractTerm::Variable(_) => structure("phrase", vec![body.clone(), start, end]),
+            AbstractTerm::Structure(name, arguments) if arguments.len() == 2 => {
+                match name.as_str() {
+                    "," => {
+                        let middle = self.fresh();
+                        let first = self.body(&arguments[0], start, middle.clone())?;
+                        conjunction(first, self.body(&arguments[1], middle, end)?)
+                    }
+                    "->" => {
+                        let middle = self.fresh();
+                        let condition = self.body(&arguments[0], start, middle.clone())?;
+                        let action = self.body(&arguments[1], middle, end)?;
+                        structure("->", vec![condition, action])
+                    }
+                    ";" | "|" => {
+                        let either = self.body(&arguments[0], start.clone(), end.clone())?;
+                        let or = self.body(&arguments[1], start, end)?;
+                        structure(";", vec![either, or])
+                    }
+                    ":" => {
+                        let goal = self.body(&arguments[1], start, end)?;
+                        structure(":", vec![arguments[0].clone(), goal])
+                    }
+                    "." => terminals(body, start, end)?,
+                    _ => non_terminal(body, start, end)?,
+                }
+            }
+            AbstractTerm::Structure(name, arguments) if arguments.len() == 1 => {
+                match name.as_str() {
+                    "\\+" => {
+                        let skipped = self.fresh();
+                        let goal = self.body(&arguments[0], start.clone(), skipped)?;
+                        conjunction(structure("\\+", vec![goal]), unify(start, end))
+                    }
+                    "{}" => conjunction(arguments[0].clone(), unify(start, end)),
+                    _ => non_terminal(body, start, end)?,
+                }
+            }
+            AbstractTerm::Constant(name) if name == "!" => {
+                conjunction(body.clone(), unify(start, end))
+            }
+            AbstractTerm::Constant(name) if name == "[]" => unify(start, end),
+            // A string stands for the list of its codes.
+            AbstractTerm::String(text) => {
+                let codes = text
+                    .chars()
+                    .map(|c| AbstractTerm::Number(Number::Integer(c as i64)))
+                    .collect();
+                let list = AbstractTerm::list(codes, AbstractTerm::Constant("[]".to_string()));
+                terminals(&list, start, end)?
+            }
+            _ => non_terminal(body, start, end)?,
+        };
+        Ok(goal)
+    }
+}
+
+/// Adds the list arguments to the non-terminal `term`. `call//N` passes them on to its closure.
+fn non_terminal(
+    term: &AbstractTerm,
+    start: AbstractTerm,
+    end: AbstractTerm,
+) -> Result<AbstractTerm> {
+    match term {
+        AbstractTerm::Constant(name) => Ok(structure(name, vec![start, end])),
+        AbstractTerm::Structure(name, arguments) => {
+            let mut arguments = arguments.clone();
+            arguments.extend([start, end]);
+            Ok(structure(name, arguments))
+        }
+        AbstractTerm::Variable(_) => Err(anyhow!("Grammar rule head is a variable")),
+        AbstractTerm::Number(_) | AbstractTerm::String(_) => {
+            Err(anyhow!("Non-terminal is not callable"))
+        }
+    }
+}
+
+/// `start = [T1, ..., Tn|end]` for the list of terminals `list`.
+fn terminals(list: &AbstractTerm, start: AbstractTerm, end: AbstractTerm) -> Result<AbstractTerm> {
+    let mut elements = Vec::new();
+    let mut rest = list;
+    loop {
+        match rest {
+            AbstractTerm::Structure(name, arguments) if name == "." && arguments.len() == 2 => {
+                elements.push(arguments[0].clone());
+                rest = &arguments[1];
+            }
+            AbstractTerm::Constant(name) if name == "[]" => break,
+            _ => return Err(anyhow!("Terminals are not a proper list")),
+        }
+    }
+    Ok(unify(start, AbstractTerm::list(elements, end)))
+}
+
+fn conjunction(first: AbstractTerm, second: AbstractTerm) -> AbstractTerm {
+    structure(",", vec![first, second])
+}
+
+fn unify(left: AbstractTerm, right: AbstractTerm) -> AbstractTerm {
+    structure("=", vec![left, right])
+}
+
+fn structure(name: &str, arguments: Vec<AbstractTerm>) -> AbstractTerm {
+    AbstractTerm::Structure(name.to_string(), arguments)
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OperatorType {
+    Xfx,
+    Xfy,
+    Yfx,
+    Fy,
+    Fx,
+    Xf,
+    Yf,
+}
+
+impl OperatorType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "xfx" => Some(OperatorType::Xfx),
+            "xfy" => Some(OperatorType::Xfy),
+            "yfx" => Some(OperatorType::Yfx),
+            "fy" => Some(OperatorType::Fy),
+            "fx" => Some(OperatorType::Fx),
+            "xf" => Some(OperatorType::Xf),
+            "yf" => Some(OperatorType::Yf),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OperatorType::Xfx => "xfx",
+            OperatorType::Xfy => "xfy",
+            OperatorType::Yfx => "yfx",
+            OperatorType::Fy => "fy",
+            OperatorType::Fx => "fx",
+            OperatorType::Xf => "xf",
+            OperatorType::Yf => "yf",
+        }
+    }
+
+    /// Maximum priorities of the left and right argument for an operator of the given priority.
+    /// Prefix operators only have a right and postfix operators only a left argument.
+    pub fn argument_priorities(&self, priority: usize) -> (usize, usize) {
+        let below = priority.saturating_sub(1);
+        match self {
+            OperatorType::Xfx => (below, below),
+            OperatorType::Xfy => (below, priority),
+            OperatorType::Yfx => (priority, below),
+            OperatorType::Fy => (0, priority),
+            OperatorType::Fx => (0, below),
+            OperatorType::Xf => (below, 0),
+            OperatorType::Yf => (priority, 0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OperatorDefinition {
+    pub priority: usize,
+    pub operator_type: OperatorType,
+}
+
+#[derive(Debug, Clone)]
+pub struct OperatorTable {
+    prefix: HashMap<String, OperatorDefinition>,
+    infix: HashMap<String, OperatorDefinition>,
+    postfix: HashMap<String, OperatorDefinition>,
+}
+
+impl Default for OperatorTable {
+    fn default() -> Self {
+        let mut table = OperatorTable {
+            prefix: HashMap::new(),
+            infix: HashMap::new(),
+            postfix: HashMap::new(),
+        };
+
+        let defaults: &[(usize, OperatorType, &[&str])] = &[
+            (1200, OperatorType::Xfx, &[":-", "-->"]),
+            (1200, OperatorType::Fx, &[":-", "?-"]),
+            (
+                1150,
+                OperatorType::Fx,
+                &[
+                    "dynamic",
+                    "discontiguous",
+                    "initialization",
+                    "meta_predicate",
+                    "module_transparent",
+                    "multifile",
+                    "public",
+                    "table",
+                ],
+            ),
+            (1100, OperatorType::Xfy, &[";", "|"]),
+            (1050, OperatorType::Xfy, &["->", "*->"]),
+            (1000, OperatorType::Xfy, &[","]),
+            (990, OperatorType::Xfx, &[":="]),
+            (900, OperatorType::Fy, &["\\+"]),
+            (
+                700,
+                OperatorType::Xfx,
+                &[
+                    "=", "\\=", "==", "\\==", "@<", "@>", "@=<", "@>=", "=..", "is", "=:=", "=\\=",
+                    "<", ">", "=<", ">=", ">:<", ":<", "as", "#=", "#\\=", "#<", "#>", "#=<",
+                    "#>=", "in", "ins",
+                ],
+            ),
+            (450, OperatorType::Xfx, &[".."]),
+            (600, OperatorType::Xfy, &[":"]),
+            (500, OperatorType::Yfx, &["+", "-", "/\\", "\\/", "xor"]),
+            (500, OperatorType::Fx, &["?"]),
+            (
+                400,
+                OperatorType::Yfx,
+                &[
+                    "*", "/", "//", "rdiv", "<<", ">>", "mod", "rem", "div", "divmod",
+                ],
+            ),
+            (200, OperatorType::Xfx, &["**"]),
+            (200, OperatorType::Xfy, &["^"]),
+            (200, OperatorType::Fy, &["-", "+", "\\"]),
+            (100, OperatorType::Yfx, &["."]),
+            (1, OperatorType::Fx, &["$"]),
+        ];
+
+        for (priority, operator_type, names) in defaults {
+            for name in names.iter() {
+                table.add(*priority, *operator_type, name);
+            }
+        }
+
+        table
+    }
+}
+
+impl OperatorTable {
+    /// Adds or replaces an operator definition. A priority of zero removes the operator.
+    pub fn add(&mut self, priority: usize, operator_type: OperatorType, name: &str) {
+        let map = match operator_type {
+            OperatorType::Xfx | OperatorType::Xfy | OperatorType::Yfx => &mut self.infix,
+            OperatorType::Fy | OperatorType::Fx => &mut self.prefix,
+            OperatorType::Xf | OperatorType::Yf => &mut self.postfix,
+        };
+
+        if priority == 0 {
+            map.remove(name);
+        } else {
+            map.insert(
+                name.to_string(),
+                OperatorDefinition {
+                    priority,
+                    operator_type,
+                },
+            );
+        }
+    }
+
+    pub fn prefix(&self, name: &str) -> Option<OperatorDefinition> {
+        self.prefix.get(name).copied()
+    }
+
+    pub fn infix(&self, name: &str) -> Option<OperatorDefinition> {
+        self.infix.get(name).copied()
+    }
+
+    pub fn postfix(&self, name: &str) -> Option<OperatorDefinition> {
+        self.postfix.get(name).copied()
+    }
+
+    pub fn is_operator(&self, name: &str) -> bool {
+        self.prefix.contains_key(name)
+            || self.infix.contains_key(name)
+            || self.postfix.contains_key(name)
+    }
+}
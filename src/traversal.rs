@@ -10,8 +10,10 @@ pub struct AbstractTermItem<'a> {
     pub id: usize,
 }
 
-pub fn generate_term_id(parent: usize, argument_index: usize) -> usize {
-    parent << 4 | argument_index
+/// Identifies a node of a term by its address, which is unique among the nodes of a term as
+/// long as it is borrowed, however deep or wide the term is.
+pub fn term_id(term: &AbstractTerm) -> usize {
+    term as *const AbstractTerm as usize
 }
 
 // Breadth-first iterator without root node
@@ -26,7 +28,7 @@ impl<'a> FactIterator<'a> {
             term: root,
             level: 0,
             argument_index: 0,
-            id: 1,
+            id: term_id(root),
         });
         Self { queue }
     }
@@ -43,7 +45,7 @@ impl<'a> Iterator for FactIterator<'a> {
                     self.queue.push_back(AbstractTermItem {
                         term: sub_term,
                         level: term.level + 1,
-                        id: generate_term_id(term.id, argument_index),
+                        id: term_id(sub_term),
                         argument_index,
                     });
                 }
@@ -70,7 +72,7 @@ impl<'a> QueryIterator<'a> {
         queue.push_back(AbstractTermItem {
             term: root,
             level: 0,
-            id: 1,
+            id: term_id(root),
             argument_index: 0,
         });
         Self { queue, declared }
@@ -84,9 +86,9 @@ impl<'a> Iterator for QueryIterator<'a> {
         while let Some(term) = self.queue.pop_front() {
             match term.term {
                 AbstractTerm::Structure(_, sub_terms) => {
-                    let has_declared_all = sub_terms.iter().enumerate().all(|(index, sub_term)| {
-                        self.declared.contains(&generate_term_id(term.id, index))
-                    });
+                    let has_declared_all = sub_terms
+                        .iter()
+                        .all(|sub_term| self.declared.contains(&term_id(sub_term)));
                     if has_declared_all {
                         self.declared.insert(term.id);
                         if term.level > 0 {
@@ -98,7 +100,7 @@ impl<'a> Iterator for QueryIterator<'a> {
                             self.queue.push_front(AbstractTermItem {
                                 term: sub_term,
                                 level: term.level + 1,
-                                id: generate_term_id(term.id, argument_index),
+                                id: term_id(sub_term),
                                 argument_index,
                             });
                         }
@@ -125,7 +127,7 @@ impl<'a> DepthFirstIterator<'a> {
         stack.push(AbstractTermItem {
             term: root,
             level: 0,
-            id: 1,
+            id: term_id(root),
             argument_index: 0,
         });
         Self { stack }
@@ -144,7 +146,7 @@ impl<'a> Iterator for DepthFirstIterator<'a> {
                     self.stack.push(AbstractTermItem {
                         term: sub_term,
                         level: term.level + 1,
-                        id: generate_term_id(term.id, argument_index),
+                        id: term_id(sub_term),
                         argument_index,
                     });
                 }
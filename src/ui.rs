@@ -11,13 +11,13 @@ use ratatui::{
 };
 
 use crate::{
-    compiler::{CompileArtifact, Compiler, ProgramTarget, QueryTarget},
-    descriptor::{self, DescriptorAllocator},
-    instructions::{DescriptorId, RegisterId},
-    interpreter::{Cell, CellAddress, InspectionResult, InspectionView, Interpreter},
-    parsing::{AbstractProgram, AbstractTerm, parse},
+    compiler::{CompileArtifact, Compiler},
+    descriptor::DescriptorAllocator,
+    interpreter::{Cell, Interpreter},
+    number::format_float,
+    parsing::{AbstractProgram, parse},
     ui::{
-        instructionview::{InstructionView, InstructionViewState, format_register},
+        instructionview::{InstructionView, InstructionViewState},
         textview::{TextView, TextViewState},
     },
 };
@@ -31,7 +31,6 @@ pub struct App {
     ast: AbstractProgram,
     program: Vec<String>,
     program_ast: Vec<AbstractProgram>,
-    instructions: Vec<crate::instructions::Instruction>,
     interpreter: Interpreter,
     compiler: Compiler,
     compile_artifact_query: CompileArtifact,
@@ -57,21 +56,15 @@ impl App {
             .collect::<Result<Vec<AbstractProgram>>>()?;
 
         for abstract_program in &program_ast {
-            compiler.add_program(abstract_program);
+            compiler
+                .add_program(abstract_program)
+                .map_err(|error| anyhow::anyhow!("Cannot load program: {:?}", error))?;
         }
 
         let query = parse(&query_str)?;
         let compile_artifact_query = compiler.compile(&query);
 
-        let instructions = compile_artifact_query.instructions.clone();
-
-        let interpreter = Interpreter::new(
-            instructions.clone(),
-            compile_artifact_query.start_instruction_index,
-            compile_artifact_query.max_registers,
-            compiler.descriptor_allocator.descriptors.clone(),
-            &compile_artifact_query.inspection_variables,
-        );
+        let interpreter = Interpreter::new(compiler.clone(), &compile_artifact_query);
 
         Ok(Self {
             query: query_str,
@@ -79,7 +72,6 @@ impl App {
             program,
             program_ast,
             interpreter,
-            instructions,
             compile_artifact_query,
             compiler,
             ast_state: TextViewState::default(),
@@ -158,13 +150,8 @@ impl App {
                 self.interpreter.try_backtrack();
             }
             KeyCode::Char('r') => {
-                self.interpreter = Interpreter::new(
-                    self.instructions.clone(),
-                    self.compile_artifact_query.start_instruction_index,
-                    self.interpreter.registers.len(),
-                    self.compiler.descriptor_allocator.descriptors.clone(),
-                    &self.compile_artifact_query.inspection_variables,
-                );
+                self.interpreter =
+                    Interpreter::new(self.compiler.clone(), &self.compile_artifact_query);
             }
             KeyCode::Left => self.decrement_counter(),
             KeyCode::Right => self.increment_counter(),
@@ -270,9 +257,9 @@ impl Widget for &mut App {
             .padding(ratatui::widgets::Padding::proportional(1));
         block.clone().render(main_layout[0], buf);
         InstructionView {
-            instructions: &self.instructions,
+            instructions: self.interpreter.instructions(),
             interpreter: &self.interpreter,
-            descriptors: &self.compiler.descriptor_allocator,
+            descriptors: self.interpreter.descriptors(),
         }
         .render(
             block.inner(main_layout[0]),
@@ -283,7 +270,7 @@ impl Widget for &mut App {
         // Rigth side global stack
         let global_stack_text = format_cells(
             &self.interpreter.global_stack,
-            &self.compiler.descriptor_allocator,
+            self.interpreter.descriptors(),
         );
         let block = Block::bordered()
             .title(" Global Stack ")
@@ -303,10 +290,8 @@ impl Widget for &mut App {
         );
 
         // Rigth side registers
-        let registers_text = format_cells(
-            &self.interpreter.registers,
-            &self.compiler.descriptor_allocator,
-        );
+        let registers_text =
+            format_cells(&self.interpreter.registers, self.interpreter.descriptors());
         let block = Block::bordered()
             .title(" Registers ")
             .padding(ratatui::widgets::Padding::proportional(1));
@@ -333,10 +318,7 @@ impl Widget for &mut App {
             self.interpreter
                 .trail
                 .iter()
-                .map(|i| match i {
-                    CellAddress::GlobalStack { index } => format!("Stack({})", index),
-                    CellAddress::Register { index } => format_register(index).content.to_string(),
-                })
+                .map(|entry| format!("Stack({})", entry.index))
                 .collect::<Vec<String>>()
                 .join(", "),
             self.interpreter.proceed_return_address + 1,
@@ -397,10 +379,11 @@ impl Widget for &mut App {
         );
 
         // Rigth right side solution
-        let globals_text = format_inspection(
-            self.interpreter.inspect(),
-            &self.compiler.descriptor_allocator,
-        );
+        let terminator = match self.interpreter.has_alternatives() {
+            true => " ;",
+            false => ".",
+        };
+        let globals_text = self.interpreter.answer() + terminator;
         let block = Block::bordered()
             .title(" Solutions ")
             .padding(ratatui::widgets::Padding::proportional(1));
@@ -447,40 +430,6 @@ fn popup_area(area: Rect, percent_x: u16, percent_y: u16) -> Rect {
     area
 }
 
-fn format_inspection_view(view: &InspectionView, descriptors: &DescriptorAllocator) -> String {
-    match view {
-        InspectionView::Undefined => "undefined".to_string(),
-        InspectionView::Constant { descriptor_id } => {
-            format!("{}", descriptors.get(*descriptor_id).name)
-        }
-        InspectionView::UnboundVariable { index } => format!("_{}", index),
-        InspectionView::Structure {
-            descriptor_id,
-            arguments,
-        } => {
-            let inner_name = descriptors.get(*descriptor_id).pretty_name();
-            let args = arguments
-                .iter()
-                .map(|i| format_inspection_view(i, descriptors))
-                .collect::<Vec<_>>()
-                .join(", ");
-            format!("{}({})", inner_name, args)
-        }
-    }
-}
-
-fn format_inspection(result: InspectionResult, descriptors: &DescriptorAllocator) -> String {
-    let mut output = String::new();
-
-    for (id, variable) in result.variables {
-        let name = descriptors.get(id).pretty_name();
-        let value = format_inspection_view(&variable, descriptors);
-        output += &format!("{} = {}\n", name, value);
-    }
-
-    output
-}
-
 fn format_cells(cells: &[Cell], descriptors: &DescriptorAllocator) -> String {
     let formatted_cells = cells
         .iter()
@@ -496,9 +445,24 @@ fn format_cell(cell: &Cell, descriptors: &DescriptorAllocator) -> String {
             format!("CON({})", descriptors.get(*struc).name)
         }
         Cell::Reference(re) => format!("REF({})", re),
+        Cell::AttributedVariable(re) => format!("ATT({})", re),
         Cell::StructureRef(struc) => format!("STR({})", struc),
         Cell::Structure(struc) => {
             format!("{}", descriptors.get(*struc).pretty_name())
         }
+        Cell::Integer(value) => format!("INT({})", value),
+        Cell::Float(value) => format!("FLT({})", format_float(*value)),
+        Cell::BigIntegerRef(address) => format!("BIG({})", address),
+        Cell::BigInteger { negative, limbs } => {
+            format!(
+                "BIG_HEADER({}, {})",
+                if *negative { "-" } else { "+" },
+                limbs
+            )
+        }
+        Cell::Limb(limb) => format!("LIMB({:#x})", limb),
+        Cell::RationalRef(address) => format!("RAT({})", address),
+        Cell::StringRef(address) => format!("STRING({})", address),
+        Cell::String { length } => format!("STRING_HEADER({})", length),
     }
 }
@@ -40,6 +40,17 @@ pub fn format_register(register: &RegisterId) -> Span<'static> {
     }
 }
 
+fn format_arithmetic(name: &'static str, registers: &[&RegisterId]) -> Line<'static> {
+    let mut spans = vec![Span::raw(format!("{name} "))];
+    for (index, register) in registers.iter().enumerate() {
+        if index > 0 {
+            spans.push(Span::raw(", "));
+        }
+        spans.push(format_register(register));
+    }
+    Line::from(spans)
+}
+
 impl<'a> StatefulWidget for InstructionView<'a> {
     type State = InstructionViewState;
 
@@ -106,6 +117,25 @@ impl<'a> StatefulWidget for InstructionView<'a> {
                         Style::default().fg(Color::LightRed),
                     ),
                 ]),
+                Instruction::PutNumber { number, register } => Line::from(vec![
+                    Span::raw("put_number "),
+                    Span::styled(number.to_string(), Style::default().fg(Color::LightRed)),
+                    Span::raw(", "),
+                    format_register(register),
+                ]),
+                Instruction::SetNumber { number } => Line::from(vec![
+                    Span::raw("set_number "),
+                    Span::styled(number.to_string(), Style::default().fg(Color::LightRed)),
+                ]),
+                Instruction::PutString { string, register } => Line::from(vec![
+                    Span::raw("put_string "),
+                    Span::styled(
+                        format!("{:?}", string),
+                        Style::default().fg(Color::LightRed),
+                    ),
+                    Span::raw(", "),
+                    format_register(register),
+                ]),
                 Instruction::DebugComment { message } => Line::from(vec![Span::styled(
                     format!(";; {}", message),
                     Style::default().fg(Color::DarkGray),
@@ -164,14 +194,40 @@ impl<'a> StatefulWidget for InstructionView<'a> {
                         Style::default().fg(Color::LightRed),
                     ),
                 ]),
+                Instruction::GetNumber { number, register } => Line::from(vec![
+                    Span::raw("get_number "),
+                    Span::styled(number.to_string(), Style::default().fg(Color::LightRed)),
+                    Span::raw(", "),
+                    format_register(register),
+                ]),
+                Instruction::UnifyNumber { number } => Line::from(vec![
+                    Span::raw("unify_number "),
+                    Span::styled(number.to_string(), Style::default().fg(Color::LightRed)),
+                ]),
+                Instruction::GetString { string, register } => Line::from(vec![
+                    Span::raw("get_string "),
+                    Span::styled(
+                        format!("{:?}", string),
+                        Style::default().fg(Color::LightRed),
+                    ),
+                    Span::raw(", "),
+                    format_register(register),
+                ]),
                 Instruction::Proceed => Line::from(vec![Span::raw("proceed")]),
-                Instruction::Call { address, .. } => Line::from(vec![
+                Instruction::Call { address, functor } => Line::from(vec![
                     Span::raw("call "),
-                    match &self.instructions[*address] {
-                        Instruction::DebugComment { message } => {
+                    match self.instructions.get(*address) {
+                        Some(Instruction::DebugComment { message }) => {
                             Span::styled(message.to_string(), Style::default().fg(Color::LightRed))
                         }
-                        _ => Span::raw((address + 1).to_string()),
+                        Some(_) => Span::raw((address + 1).to_string()),
+                        None => Span::styled(
+                            format!(
+                                "{} (unresolved)",
+                                self.descriptors.get(*functor).pretty_name()
+                            ),
+                            Style::default().fg(Color::LightRed),
+                        ),
                     },
                 ]),
                 Instruction::TryMeElse { else_address } => Line::from(vec![
@@ -192,7 +248,67 @@ impl<'a> StatefulWidget for InstructionView<'a> {
                         _ => Span::raw((else_address + 1).to_string()),
                     },
                 ]),
+                Instruction::Add {
+                    left,
+                    right,
+                    target,
+                } => format_arithmetic("add", &[left, right, target]),
+                Instruction::Subtract {
+                    left,
+                    right,
+                    target,
+                } => format_arithmetic("subtract", &[left, right, target]),
+                Instruction::Multiply {
+                    left,
+                    right,
+                    target,
+                } => format_arithmetic("multiply", &[left, right, target]),
+                Instruction::CompareEq { left, right } => {
+                    format_arithmetic("compare_eq", &[left, right])
+                }
+                Instruction::CompareNe { left, right } => {
+                    format_arithmetic("compare_ne", &[left, right])
+                }
+                Instruction::CompareLt { left, right } => {
+                    format_arithmetic("compare_lt", &[left, right])
+                }
+                Instruction::CompareLe { left, right } => {
+                    format_arithmetic("compare_le", &[left, right])
+                }
+                Instruction::CompareGt { left, right } => {
+                    format_arithmetic("compare_gt", &[left, right])
+                }
+                Instruction::CompareGe { left, right } => {
+                    format_arithmetic("compare_ge", &[left, right])
+                }
+                Instruction::CallBuiltin { builtin } => Line::from(vec![
+                    Span::raw("call_builtin "),
+                    Span::styled(
+                        format!("{}/{}", builtin.name(), builtin.arity()),
+                        Style::default().fg(Color::LightRed),
+                    ),
+                ]),
                 Instruction::TrustMe => Line::from(vec![Span::raw("trust_me")]),
+                Instruction::TryClauses { functor } => Line::from(vec![
+                    Span::raw("try_clauses "),
+                    Span::styled(
+                        self.descriptors.get(*functor).pretty_name(),
+                        Style::default().fg(Color::LightRed),
+                    ),
+                ]),
+                Instruction::RetryClauses { functor } => Line::from(vec![
+                    Span::raw("retry_clauses "),
+                    Span::styled(
+                        self.descriptors.get(*functor).pretty_name(),
+                        Style::default().fg(Color::LightRed),
+                    ),
+                ]),
+                Instruction::GetLevel { register } => {
+                    Line::from(vec![Span::raw("get_level "), format_register(register)])
+                }
+                Instruction::Cut { register } => {
+                    Line::from(vec![Span::raw("cut "), format_register(register)])
+                }
                 Instruction::NoOp => Line::from(vec![Span::raw("no_op")]),
                 Instruction::Allocate { variables } => Line::from(vec![
                     Span::raw("allocate "),
@@ -202,6 +318,7 @@ impl<'a> StatefulWidget for InstructionView<'a> {
                     ),
                 ]),
                 Instruction::Deallocate => Line::from(vec![Span::raw("deallocate")]),
+                Instruction::Halt => Line::from(vec![Span::raw("halt")]),
             })
             .collect::<Vec<_>>();
 
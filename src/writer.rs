@@ -0,0 +1,315 @@
+use crate::{
+    number::Number,
+    parsing::{AbstractTerm, operators::OperatorTable},
+};
+
+/// Options of `write_term/2`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteOptions {
+    /// Quote atoms where needed so the output can be read back.
+    pub quoted: bool,
+    /// Write operator terms in functional notation.
+    pub ignore_ops: bool,
+    /// Write `'$VAR'(N)` as a variable name, `A` for 0, `B` for 1 and so on.
+    pub numbervars: bool,
+    /// Nesting depth beyond which terms are abbreviated as `...`, zero for no limit.
+    pub max_depth: usize,
+    /// Operator terms above this priority are bracketed.
+    pub priority: usize,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            quoted: false,
+            ignore_ops: false,
+            numbervars: false,
+            max_depth: 0,
+            priority: 1200,
+        }
+    }
+}
+
+impl WriteOptions {
+    /// The options of `write/1`.
+    pub fn write() -> Self {
+        WriteOptions {
+            numbervars: true,
+            ..Default::default()
+        }
+    }
+
+    /// The options of `writeq/1` and `print/1`.
+    pub fn writeq() -> Self {
+        WriteOptions {
+            quoted: true,
+            numbervars: true,
+            ..Default::default()
+        }
+    }
+
+    /// The options of `write_canonical/1`.
+    pub fn canonical() -> Self {
+        WriteOptions {
+            quoted: true,
+            ignore_ops: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// Writes `term` as text, using `operators` for terms in operator notation.
+pub fn format_term(
+    term: &AbstractTerm,
+    operators: &OperatorTable,
+    options: &WriteOptions,
+) -> String {
+    let mut writer = Writer {
+        operators,
+        options,
+        output: String::new(),
+    };
+    writer.write(term, options.priority, 1);
+    writer.output
+}
+
+/// Writes an atom, quoted if `quoted` is set and it could not be read back otherwise.
+pub fn format_atom(name: &str, quoted: bool) -> String {
+    if !quoted || !needs_quotes(name) {
+        return name.to_string();
+    }
+    quote(name, '\'')
+}
+
+/// Writes a string, in double quotes if `quoted` is set.
+fn format_string(text: &str, quoted: bool) -> String {
+    match quoted {
+        true => quote(text, '"'),
+        false => text.to_string(),
+    }
+}
+
+/// Encloses `text` in `delimiter`, escaping the characters which could not be read back.
+fn quote(text: &str, delimiter: char) -> String {
+    let mut quoted = String::from(delimiter);
+    for c in text.chars() {
+        match c {
+            c if c == delimiter => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\x{:x}\\", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push(delimiter);
+    quoted
+}
+
+fn needs_quotes(name: &str) -> bool {
+    let mut chars = name.chars();
+    let Some(first) = chars.next() else {
+        return true;
+    };
+    if matches!(name, "[]" | "{}" | "!" | ";") {
+        return false;
+    }
+    if first.is_ascii_lowercase() {
+        return !name.chars().all(is_alphanumeric);
+    }
+    // A lone dot would end the clause.
+    name == "." || !name.chars().all(is_symbol_char)
+}
+
+fn is_alphanumeric(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn is_symbol_char(c: char) -> bool {
+    "+-*/\\^<>=~:.?@#&$".contains(c)
+}
+
+struct Writer<'a> {
+    operators: &'a OperatorTable,
+    options: &'a WriteOptions,
+    output: String,
+}
+
+impl Writer<'_> {
+    /// Writes `term` in a context that accepts terms up to `max_priority`. `depth` is the
+    /// nesting depth of the term, starting at 1.
+    fn write(&mut self, term: &AbstractTerm, max_priority: usize, depth: usize) {
+        if self.options.max_depth > 0 && depth > self.options.max_depth {
+            self.emit("...");
+            return;
+        }
+        match term {
+            AbstractTerm::Variable(name) => self.emit(name),
+            AbstractTerm::Number(number) => self.emit(&number.to_string()),
+            AbstractTerm::String(text) => self.emit(&format_string(text, self.options.quoted)),
+            AbstractTerm::Constant(name) => {
+                let atom = format_atom(name, self.options.quoted);
+                // An operator on its own is bracketed where it could be mistaken for one.
+                if max_priority < 999 && self.operators.is_operator(name) {
+                    self.emit("(");
+                    self.emit(&atom);
+                    self.emit(")");
+                } else {
+                    self.emit(&atom);
+                }
+            }
+            AbstractTerm::Structure(name, arguments) => {
+                self.write_structure(name, arguments, max_priority, depth)
+            }
+        }
+    }
+
+    fn write_structure(
+        &mut self,
+        name: &str,
+        arguments: &[AbstractTerm],
+        max_priority: usize,
+        depth: usize,
+    ) {
+        match (name, arguments) {
+            (".", [head, tail]) => return self.write_list(head, tail, depth),
+            ("{}", [argument]) if !self.options.ignore_ops => {
+                self.emit("{");
+                self.write(argument, 1200, depth + 1);
+                self.emit("}");
+                return;
+            }
+            ("$VAR", [AbstractTerm::Number(Number::Integer(index))])
+                if self.options.numbervars && *index >= 0 =>
+            {
+                self.emit(&variable_name(*index as usize));
+                return;
+            }
+            _ => {}
+        }
+
+        if !self.options.ignore_ops {
+            let operator = match arguments {
+                [_, _] => self.operators.infix(name),
+                [_] => self.operators.prefix(name).or(self.operators.postfix(name)),
+                _ => None,
+            };
+            if let Some(operator) = operator {
+                let bracketed = operator.priority > max_priority;
+                if bracketed {
+                    self.emit("(");
+                }
+                let (left_max, right_max) = operator
+                    .operator_type
+                    .argument_priorities(operator.priority);
+                let atom = format_atom(name, self.options.quoted);
+                let alphanumeric = atom.chars().all(is_alphanumeric);
+                match arguments {
+                    [left, right] => {
+                        self.write(left, left_max, depth + 1);
+                        if name == "," {
+                            self.emit(",");
+                        } else if alphanumeric {
+                            self.output.push_str(&format!(" {} ", atom));
+                        } else {
+                            self.emit(&atom);
+                        }
+                        self.write(right, right_max, depth + 1);
+                    }
+                    [argument] if self.operators.prefix(name).is_some() => {
+                        self.emit(&atom);
+                        // `- 1` is a compound term, `-1` would be a number.
+                        if alphanumeric
+                            || (matches!(name, "-" | "+")
+                                && matches!(argument, AbstractTerm::Number(_)))
+                        {
+                            self.output.push(' ');
+                        }
+                        let start = self.output.len();
+                        self.write(argument, right_max, depth + 1);
+                        // `- (a, b)` has one argument, `-(a, b)` two.
+                        if self.output[start..].starts_with('(')
+                            && !self.output[..start].ends_with(' ')
+                        {
+                            self.output.insert(start, ' ');
+                        }
+                    }
+                    [argument] => {
+                        self.write(argument, left_max, depth + 1);
+                        self.emit(&atom);
+                    }
+                    _ => unreachable!("operators have one or two arguments"),
+                }
+                if bracketed {
+                    self.emit(")");
+                }
+                return;
+            }
+        }
+
+        self.emit(&format_atom(name, self.options.quoted));
+        self.output.push('(');
+        for (index, argument) in arguments.iter().enumerate() {
+            if index > 0 {
+                self.output.push(',');
+            }
+            self.write(argument, 999, depth + 1);
+        }
+        self.output.push(')');
+    }
+
+    fn write_list(&mut self, head: &AbstractTerm, tail: &AbstractTerm, depth: usize) {
+        self.emit("[");
+        self.write(head, 999, depth + 1);
+        let mut tail = tail;
+        let mut elements = 1;
+        loop {
+            match tail {
+                AbstractTerm::Structure(name, arguments) if name == "." && arguments.len() == 2 => {
+                    elements += 1;
+                    if self.options.max_depth > 0 && depth + elements > self.options.max_depth {
+                        self.output.push_str("|...");
+                        break;
+                    }
+                    self.output.push(',');
+                    self.write(&arguments[0], 999, depth + elements);
+                    tail = &arguments[1];
+                }
+                AbstractTerm::Constant(name) if name == "[]" => break,
+                tail => {
+                    self.output.push('|');
+                    self.write(tail, 999, depth + 1);
+                    break;
+                }
+            }
+        }
+        self.output.push(']');
+    }
+
+    /// Appends `text`, separated by a space from the output so far if they would otherwise read
+    /// as a single token.
+    fn emit(&mut self, text: &str) {
+        let (Some(last), Some(first)) = (self.output.chars().last(), text.chars().next()) else {
+            self.output.push_str(text);
+            return;
+        };
+        if (is_alphanumeric(last) && is_alphanumeric(first))
+            || (is_symbol_char(last) && is_symbol_char(first))
+        {
+            self.output.push(' ');
+        }
+        self.output.push_str(text);
+    }
+}
+
+/// The name `numbervars` gives to `'$VAR'(index)`: `A` to `Z`, then `A1` to `Z1` and so on.
+pub fn variable_name(index: usize) -> String {
+    let letter = char::from(b'A' + (index % 26) as u8);
+    match index / 26 {
+        0 => letter.to_string(),
+        round => format!("{}{}", letter, round),
+    }
+}
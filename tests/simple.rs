@@ -1,115 +1,55 @@
 use prolog_wan::{
     compiler::Compiler,
-    descriptor::DescriptorAllocator,
-    interpreter::{ExecutionState, InspectionResult, InspectionView, Interpreter},
-    parsing::parse,
+    instructions::Instruction,
+    interpreter::{
+        ErrorCulprit, ExecutionState, FlagError, FlagValue, Flags, Interpreter, PrologError,
+    },
+    parsing::{AbstractTerm, DoubleQuotes, operators::OperatorTable, parse, parse_term},
 };
 
-struct Output {
-    success: bool,
-    output: String,
-}
-
-fn helper_inspection_format(view: &InspectionView, descriptors: &DescriptorAllocator) -> String {
-    match view {
-        InspectionView::Undefined => "undefined".to_string(),
-        InspectionView::UnboundVariable { index } => format!("_{}", index),
-        InspectionView::Constant { descriptor_id } => {
-            let constant_name = descriptors.get(*descriptor_id).name.clone();
-            format!("{}", constant_name)
-        }
-        InspectionView::Structure {
-            descriptor_id,
-            arguments,
-        } => {
-            let inner_name = descriptors.get(*descriptor_id).name.clone();
-            format!(
-                "{}{}",
-                inner_name,
-                if arguments.is_empty() {
-                    "".to_string()
-                } else {
-                    format!(
-                        "({})",
-                        arguments
-                            .iter()
-                            .map(|arg| helper_inspection_format(arg, descriptors))
-                            .collect::<Vec<String>>()
-                            .join(", ")
-                    )
-                }
-            )
-        }
-    }
-}
-
-fn helper_inspection(result: InspectionResult, descriptors: &DescriptorAllocator) -> String {
-    let mut output = String::new();
-
-    for (index, (id, variable)) in result.variables.iter().enumerate() {
-        let name = descriptors.get(*id).name.clone();
-        output += &format!(
-            "{} = {}{}",
-            name,
-            helper_inspection_format(variable, descriptors),
-            if index == result.variables.len() - 1 {
-                ""
-            } else {
-                ", "
-            }
-        );
-    }
-
-    output
-}
-
-fn helper_execute_multi(program: &[&str], query: &str) -> Output {
+/// Compiles `program` and runs `query` to its first solution with `input` on the standard
+/// input.
+fn run(program: &[&str], query: &str, input: &str) -> Interpreter {
     let query = parse(query).unwrap();
-
     let mut compiler = Compiler::new();
     for program in program {
-        let program = parse(program).unwrap();
-        compiler.add_program(&program);
+        compiler.add_program(&parse(program).unwrap()).unwrap();
     }
     let artifact = compiler.compile(&query);
+    let mut interpreter = Interpreter::new(compiler, &artifact);
+    interpreter.provide_input(input);
+    while interpreter.step() {}
+    interpreter
+}
 
-    let mut interpreter = Interpreter::new(
-        artifact.instructions,
-        artifact.start_instruction_index,
-        artifact.max_registers,
-        compiler.descriptor_allocator.descriptors.clone(),
-        &artifact.inspection_variables,
-    );
-
-    let mut suceeded_once = false;
-    let mut output = String::new();
-    let mut back_track_count = 0;
+struct Output {
+    success: bool,
+    output: String,
+    /// Whether the first solution left no choice points behind.
+    deterministic: bool,
+}
 
+/// Runs `query` through all of its solutions.
+fn helper_execute_multi(program: &[&str], query: &str) -> Output {
+    let mut interpreter = run(program, query, "");
+    let mut answers = Vec::new();
+    let mut deterministic = false;
     loop {
-        while interpreter.step() {}
-
         if interpreter.execution_state == ExecutionState::Normal {
-            suceeded_once = true;
-
-            if back_track_count > 0 {
-                output.push_str("\n");
+            if answers.is_empty() {
+                deterministic = !interpreter.has_alternatives();
             }
-
-            output.push_str(&helper_inspection(
-                interpreter.inspect(),
-                &compiler.descriptor_allocator,
-            ));
+            answers.push(interpreter.answer());
         }
-
         if !interpreter.try_backtrack() {
             break;
         }
-
-        back_track_count += 1;
+        while interpreter.step() {}
     }
     Output {
-        success: suceeded_once,
-        output,
+        success: !answers.is_empty(),
+        output: answers.join("\n"),
+        deterministic,
     }
 }
 
@@ -117,6 +57,48 @@ fn helper_execute(program: &str, query: &str) -> Output {
     helper_execute_multi(&[program], query)
 }
 
+/// What running a query is expected to do.
+enum Outcome<'a> {
+    /// Succeed with these answers, one line per solution.
+    Answers(&'a str),
+    Succeeds,
+    Fails,
+    /// Raise exactly this error.
+    Raises(PrologError),
+    /// Print this text before the first solution.
+    Prints(&'a str),
+}
+use Outcome::*;
+
+/// Runs `query` against `program` and asserts that it has the `expected` outcome.
+#[track_caller]
+fn check(program: &[&str], query: &str, expected: Outcome) {
+    match expected {
+        Answers(answers) => {
+            assert_eq!(
+                helper_execute_multi(program, query).output,
+                answers,
+                "{}",
+                query
+            )
+        }
+        Succeeds => assert!(helper_execute_multi(program, query).success, "{}", query),
+        Fails => assert!(!helper_execute_multi(program, query).success, "{}", query),
+        Raises(error) => assert_eq!(run(program, query, "").exception, Some(error), "{}", query),
+        Prints(text) => assert_eq!(run(program, query, "").output(), text, "{}", query),
+    }
+}
+
+/// The culprit of an error about the term written as `text`.
+fn culprit(text: &str) -> ErrorCulprit {
+    let term = parse_term(
+        &format!("{}\n.", text),
+        &OperatorTable::default(),
+        DoubleQuotes::Codes,
+    );
+    ErrorCulprit::Term(term.unwrap())
+}
+
 #[test]
 fn test_execute() {
     assert_eq!(helper_execute("p(Z, Z).", "p(Z, Z).").success, true);
@@ -233,7 +215,7 @@ fn test_rules() {
             "p(X, Y)."
         )
         .output,
-        "X = _0, Y = _1"
+        "true"
     );
     assert_eq!(
         helper_execute_multi(
@@ -241,7 +223,7 @@ fn test_rules() {
             "p(f(X, Y, Z), g(b), h)."
         )
         .output,
-        "X = f(a), Y = g(b), Z = _3"
+        "X = f(a), Y = g(b)"
     );
     assert_eq!(
         helper_execute_multi(
@@ -265,7 +247,7 @@ fn test_rules() {
             "p(Z, Y, X)."
         )
         .output,
-        "Z = f(f(a), g(b), _7), Y = g(b), X = h"
+        "Z = f(f(a),g(b),_A), Y = g(b), X = h"
     );
     assert_eq!(
         helper_execute_multi(
@@ -273,7 +255,7 @@ fn test_rules() {
             "p(f(X, Y, Z), Y, h)."
         )
         .output,
-        "X = f(a), Y = g(b), Z = _3"
+        "X = f(a), Y = g(b)"
     );
 }
 
@@ -316,3 +298,2548 @@ fn test_movies() {
     );
 }
 */
+
+#[test]
+fn test_arithmetic() {
+    check(&["p."], "X is 1 + 2 * 3.", Answers("X = 7"));
+    check(&["p."], "X is 7 / 2.", Answers("X = 3.5"));
+    check(&["p."], "X is 8 / 2.", Answers("X = 4"));
+    check(&["p."], "X is -7 // 2.", Answers("X = -3"));
+    check(&["p."], "X is -7 mod 2.", Answers("X = 1"));
+    check(&["p."], "X is 2.0 * 3.", Answers("X = 6.0"));
+    check(&["p."], "X is 1.0e20 * 10.", Answers("X = 1.0e21"));
+    check(&["p."], "X is max(3, 4.0).", Answers("X = 4.0"));
+    check(&["p."], "X is 0'a.", Answers("X = 97"));
+    check(&["p."], "X is 0xff.", Answers("X = 255"));
+    check(&["p."], "1 < 2.", Succeeds);
+    check(&["p."], "2 =< 1.", Fails);
+    check(&["p."], "1 =:= 1.0.", Succeeds);
+    check(&["p(1)."], "p(1.0).", Fails);
+    check(&["p(-1)."], "p(-1).", Succeeds);
+    check(&["p(X, Y) :- Y is X - 1."], "p(3, Y).", Answers("Y = 2"));
+}
+
+#[test]
+fn test_arithmetic_errors() {
+    let interpreter = run(&["p."], "X is foo + 1.", "");
+    assert_eq!(interpreter.execution_state, ExecutionState::Exception);
+    assert_eq!(
+        interpreter.exception,
+        Some(PrologError::TypeError {
+            expected: "evaluable",
+            culprit: culprit("foo/0"),
+        })
+    );
+
+    check(
+        &["p."],
+        "X is Y + 1.",
+        Raises(PrologError::InstantiationError),
+    );
+    check(
+        &["p."],
+        "X is 1 // 0.",
+        Raises(PrologError::EvaluationError("zero_divisor")),
+    );
+}
+
+#[test]
+fn test_big_integers() {
+    let factorial = [
+        "factorial(0, 1).",
+        "factorial(N, F) :- N > 0, N1 is N - 1, factorial(N1, F1), F is N * F1.",
+    ];
+    check(
+        &factorial,
+        "factorial(50, F).",
+        Answers("F = 30414093201713378043612608166064768844377641568960512000000000000"),
+    );
+    check(
+        &factorial,
+        "factorial(25, 15511210043330985984000000).",
+        Succeeds,
+    );
+    check(
+        &factorial,
+        "factorial(25, 15511210043330985984000001).",
+        Fails,
+    );
+
+    check(
+        &["p."],
+        "X is 9223372036854775807 + 1.",
+        Answers("X = 9223372036854775808"),
+    );
+    check(
+        &["p."],
+        "X is -(-9223372036854775808).",
+        Answers("X = 9223372036854775808"),
+    );
+    check(&["p."], "X is 2 ** 100 - 2 ** 100 + 1.", Answers("X = 1"));
+    check(
+        &["big(f(123456789012345678901234567890, x))."],
+        "big(f(X, Y)).",
+        Answers("X = 123456789012345678901234567890, Y = x"),
+    );
+    check(&["p."], "2 ** 64 > 2 ** 63.", Succeeds);
+
+    check(
+        &["p."],
+        "X is 1 << 100.",
+        Answers("X = 1267650600228229401496703205376"),
+    );
+    check(&["p."], "X is -5 >> 100000000000.", Answers("X = -1"));
+    check(
+        &["p."],
+        "X is 1 >> -9223372036854775808.",
+        Raises(PrologError::RepresentationError("shift")),
+    );
+    check(
+        &["p."],
+        "X is 1 << 100000000000.",
+        Raises(PrologError::ResourceError("memory")),
+    );
+    // Powers are refused by their estimated width before they are computed.
+    check(
+        &["p."],
+        "X is 3 ^ 4000000000.",
+        Raises(PrologError::ResourceError("memory")),
+    );
+    check(
+        &["p."],
+        "X is 3 ^ 100000000.",
+        Raises(PrologError::ResourceError("memory")),
+    );
+    check(
+        &["p."],
+        "X is 2r3 ^ 100000000.",
+        Raises(PrologError::ResourceError("memory")),
+    );
+    check(&["p."], "X is 1 ^ 4000000000.", Answers("X = 1"));
+    check(&["p."], "X is -1 ^ 100000000001.", Answers("X = -1"));
+    check(&["p."], "X is 0 ^ 100000000000.", Answers("X = 0"));
+}
+
+#[test]
+fn test_rationals() {
+    check(&["p."], "X is 1r3 + 1r6.", Answers("X = 1r2"));
+    check(&["p."], "X is 1r3 * 3.", Answers("X = 1"));
+    check(&["p."], "X is 1 rdiv 3.", Answers("X = 1r3"));
+    check(
+        &["p."],
+        "X is 1r3 + 0.5.",
+        Answers("X = 0.8333333333333333"),
+    );
+    check(&["p(1r3)."], "p(X).", Answers("X = 1r3"));
+    check(&["p(f(1r3, 2r3))."], "p(f(X, 2r3)).", Answers("X = 1r3"));
+    check(&["p."], "1r3 < 1r2.", Succeeds);
+
+    check(
+        &["p."],
+        "set_prolog_flag(prefer_rationals, true), X is 1 / 3.",
+        Answers("X = 1r3"),
+    );
+}
+
+#[test]
+fn test_inline_arithmetic() {
+    let count = [
+        "count(N, N, []).",
+        "count(I, N, [I | T]) :- I < N, J is I + 1, count(J, N, T).",
+    ];
+    check(&count, "count(0, 3, L).", Answers("L = [0,1,2]"));
+
+    let sum = [
+        "sum([], 0).",
+        "sum([X | Xs], S) :- sum(Xs, S0), S is S0 + X * 2 - 1.",
+    ];
+    check(&sum, "sum([1, 2, 3], S).", Answers("S = 9"));
+    check(&sum, "sum([1.5, 2], S).", Answers("S = 5.0"));
+    check(
+        &sum,
+        "sum([9223372036854775807], S).",
+        Answers("S = 18446744073709551613"),
+    );
+    check(&sum, "sum([1, 2, 3], 9).", Succeeds);
+    check(&sum, "sum([1, 2, 3], 8).", Fails);
+
+    // Bound expressions and unbound variables are handled like in `is/2`.
+    check(
+        &["p(X, Y) :- Y is X * 3."],
+        "p(1 + 1, Y).",
+        Answers("Y = 6"),
+    );
+    check(&["p(X) :- X > 2 - 1."], "p(1).", Fails);
+    check(&["p(X) :- X >= 2 - 1."], "p(1).", Succeeds);
+    check(&["p(X, Y) :- X =:= Y."], "p(1, 1.0).", Succeeds);
+
+    let query = parse("p(Y).").unwrap();
+    let mut compiler = Compiler::new();
+    compiler
+        .add_program(&parse("q(X, Y) :- X < 3, Y is X + 1.").unwrap())
+        .unwrap();
+    let artifact = compiler.compile(&parse("q(1, Y).").unwrap());
+    let q = compiler.descriptor_allocator.get_or_set_functor("q", 2);
+    let clause = &artifact.instructions
+        [compiler.predicate_address(q).unwrap()..artifact.start_instruction_index];
+    assert!(
+        clause
+            .iter()
+            .any(|instruction| matches!(instruction, Instruction::CompareLt { .. }))
+    );
+    assert!(
+        clause
+            .iter()
+            .all(|instruction| !matches!(instruction, Instruction::CallBuiltin { .. }))
+    );
+
+    let mut compiler = Compiler::new();
+    compiler
+        .add_program(&parse("p(Y) :- Y is Z + 1.").unwrap())
+        .unwrap();
+    let artifact = compiler.compile(&query);
+    let p = compiler.descriptor_allocator.get_or_set_functor("p", 1);
+    let clause = &artifact.instructions
+        [compiler.predicate_address(p).unwrap()..artifact.start_instruction_index];
+    assert!(
+        !clause
+            .iter()
+            .any(|instruction| matches!(instruction, Instruction::Add { .. }))
+    );
+    let mut interpreter = Interpreter::new(compiler, &artifact);
+    while interpreter.step() {}
+    assert_eq!(interpreter.execution_state, ExecutionState::Exception);
+    assert_eq!(interpreter.exception, Some(PrologError::InstantiationError));
+}
+
+#[test]
+fn test_type_checks() {
+    check(&["p."], "var(X).", Succeeds);
+    check(&["p."], "var(a).", Fails);
+    check(&["p."], "nonvar(f(X)).", Succeeds);
+    check(&["p."], "atom(abc).", Succeeds);
+    check(&["p."], "atom([]).", Succeeds);
+    check(&["p."], "atom(1).", Fails);
+    check(&["p."], "atom(f(x)).", Fails);
+    check(&["p."], "number(1.5).", Succeeds);
+    check(&["p."], "number(1r3).", Succeeds);
+    check(
+        &["p."],
+        "integer(123456789012345678901234567890).",
+        Succeeds,
+    );
+    check(&["p."], "integer(1.0).", Fails);
+    check(&["p."], "float(1.0).", Succeeds);
+    check(&["p."], "atomic(a).", Succeeds);
+    check(&["p."], "atomic(1).", Succeeds);
+    check(&["p."], "atomic(f(a)).", Fails);
+    check(&["p."], "compound(f(a)).", Succeeds);
+    check(&["p."], "compound(a).", Fails);
+    check(&["p."], "callable(a).", Succeeds);
+    check(&["p."], "callable(f(X)).", Succeeds);
+    check(&["p."], "callable(1).", Fails);
+    check(&["p."], "is_list([a, b]).", Succeeds);
+    check(&["p."], "is_list([]).", Succeeds);
+    check(&["p."], "is_list([a | T]).", Fails);
+    check(&["p."], "ground(f(a, [b])).", Succeeds);
+    check(&["p."], "ground(f(a, [X])).", Fails);
+
+    let program = ["kind(X, var) :- var(X).", "kind(X, atom) :- atom(X)."];
+    check(&program, "kind(a, K).", Answers("K = atom"));
+}
+
+#[test]
+fn test_term_construction() {
+    check(&["p."], "functor(f(a, b), N, A).", Answers("N = f, A = 2"));
+    check(&["p."], "functor(abc, N, A).", Answers("N = abc, A = 0"));
+    check(&["p."], "functor(T, foo, 3).", Answers("T = foo(_A,_B,_C)"));
+    check(&["p."], "functor(T, 7, 0).", Answers("T = 7"));
+    check(&["p."], "arg(2, f(a, b), X).", Answers("X = b"));
+    check(&["p."], "arg(3, f(a, b), X).", Fails);
+    check(&["p."], "f(a, B) =.. L.", Answers("L = [f,a,B]"));
+    check(&["p."], "T =.. [bar, x, 1].", Answers("T = bar(x,1)"));
+    check(&["p."], "T =.. [1.5].", Answers("T = 1.5"));
+    check(
+        &["p."],
+        "copy_term(f(X, Y, X), C).",
+        Answers("C = f(_A,_B,_A)"),
+    );
+    check(
+        &["p."],
+        "length(_L, 100000), copy_term(f(_L, _L), f(_C, _D)), _C == _D, length(_C, N).",
+        Answers("N = 100000"),
+    );
+
+    check(
+        &["p."],
+        "functor(T, N, 1).",
+        Raises(PrologError::InstantiationError),
+    );
+    check(
+        &["p."],
+        "functor(T, f, 100000000000).",
+        Raises(PrologError::RepresentationError("max_arity")),
+    );
+    check(
+        &["p."],
+        "arg(x, f(a), A).",
+        Raises(PrologError::TypeError {
+            expected: "integer",
+            culprit: culprit("x"),
+        }),
+    );
+    check(
+        &["p."],
+        "arg(1, a, A).",
+        Raises(PrologError::TypeError {
+            expected: "compound",
+            culprit: culprit("a"),
+        }),
+    );
+    check(
+        &["p."],
+        "T =.. [].",
+        Raises(PrologError::DomainError {
+            domain: "non_empty_list",
+            culprit: culprit("[]"),
+        }),
+    );
+    check(
+        &["p."],
+        "T =.. [f(a), b].",
+        Raises(PrologError::TypeError {
+            expected: "atomic",
+            culprit: culprit("f(a)"),
+        }),
+    );
+}
+
+#[test]
+fn test_standard_order() {
+    check(&["p."], "compare(O, 1, a).", Answers("O = (<)"));
+    check(&["p."], "compare(O, f(b), f(a)).", Answers("O = (>)"));
+    check(&["p."], "compare(O, g(a), f(a, b)).", Answers("O = (<)"));
+    check(&["p."], "compare(O, f(X), f(X)).", Answers("O = (=)"));
+    check(&["p."], "compare(<, X, 1).", Succeeds);
+    check(&["p."], "1.0 @< 1.", Succeeds);
+    check(&["p."], "1 @< 1.5.", Succeeds);
+    check(&["p."], "abc @< abd.", Succeeds);
+    check(&["p."], "z @< f(a).", Succeeds);
+    check(&["p."], "f(a, b) @>= f(a, b).", Succeeds);
+    check(&["p."], "f(X, a) == f(X, a).", Succeeds);
+    check(&["p."], "f(X, a) == f(Y, a).", Fails);
+    check(&["p."], "f(X) \\== f(Y).", Succeeds);
+    check(
+        &["p."],
+        "compare(foo, 1, 2).",
+        Raises(PrologError::DomainError {
+            domain: "order",
+            culprit: culprit("foo"),
+        }),
+    );
+
+    check(
+        &["p."],
+        "sort([c, a, b, a, 1, f(x)], L).",
+        Answers("L = [1,a,b,c,f(x)]"),
+    );
+    check(&["p."], "msort([b, a, b], L).", Answers("L = [a,b,b]"));
+    check(
+        &["p."],
+        "keysort([b-1, a-2, b-0, a-1], L).",
+        Answers("L = [a-2,a-1,b-1,b-0]"),
+    );
+    check(
+        &["p."],
+        "sort([a | T], L).",
+        Raises(PrologError::InstantiationError),
+    );
+    check(
+        &["p."],
+        "keysort([a], L).",
+        Raises(PrologError::TypeError {
+            expected: "pair",
+            culprit: culprit("a"),
+        }),
+    );
+}
+
+#[test]
+fn test_unification_builtins() {
+    check(&["p."], "f(X, b) = f(a, Y).", Answers("X = a, Y = b"));
+    check(&["p."], "f(X, b) = f(a, a).", Fails);
+    check(&["p."], "f(X, b) \\= f(a, a).", Succeeds);
+    check(&["p."], "f(X, b) \\= f(a, Y).", Fails);
+    check(
+        &["q(X, Y) :- f(X, b) \\= f(a, c), Y = X."],
+        "q(Z, W).",
+        Answers("Z = W"),
+    );
+    check(&["p."], "unify_with_occurs_check(X, f(X)).", Fails);
+    check(&["p."], "unify_with_occurs_check(X, f(Y)).", Succeeds);
+    check(
+        &["p."],
+        "unify_with_occurs_check(f(X, Y), f(Y, X)).",
+        Succeeds,
+    );
+}
+
+#[test]
+fn test_answers() {
+    check(&["p."], "p.", Answers("true"));
+    assert_eq!(run(&["p."], "q = r.", "").answer(), "false");
+    check(&["p."], "X = Y.", Answers("X = Y"));
+    check(&["p."], "X = f(Y, _).", Answers("X = f(Y,_A)"));
+    check(
+        &["p."],
+        "X = Y, Y = Z, Z = a.",
+        Answers("X = Y, Y = Z, Z = a"),
+    );
+    check(
+        &["p."],
+        "X = f(A, B, A), A = Y.",
+        Answers("X = f(Y,B,Y), A = Y"),
+    );
+    check(
+        &["p."],
+        "length(L, 2), X = (a :- b).",
+        Answers("L = [_A,_B], X = (a:-b)"),
+    );
+    check(&["p."], "X = f(X).", Answers("X = _S1, _S1 = f(_S1)"));
+    // Long lists are inspected and written without recursing over their elements.
+    let numbers = (1..=100000).map(|i| i.to_string()).collect::<Vec<_>>();
+    check(
+        &[
+            "numlist(H, H, [H]) :- !.",
+            "numlist(L, H, [L|T]) :- M is L + 1, numlist(M, H, T).",
+        ],
+        "numlist(1, 100000, L).",
+        Answers(&format!("L = [{}]", numbers.join(","))),
+    );
+}
+
+#[test]
+fn test_determinism() {
+    let program = [
+        "colour(red).",
+        "colour(green).",
+        "first(X) :- colour(X), !.",
+        ":- dynamic(shade/1).",
+        "shade(dark).",
+        "shade(light).",
+    ];
+    assert!(helper_execute_multi(&program, "X = a.").deterministic);
+    assert!(helper_execute_multi(&program, "colour(green).").deterministic);
+    assert!(!helper_execute_multi(&program, "colour(X).").deterministic);
+    // Clauses whose first argument cannot match are no alternatives.
+    assert!(helper_execute_multi(&program, "colour(red).").deterministic);
+    assert!(helper_execute_multi(&program, "first(X).").deterministic);
+    assert!(helper_execute_multi(&program, "once(colour(X)).").deterministic);
+    assert!(helper_execute_multi(&program, "findall(X, colour(X), L).").deterministic);
+    for query in [
+        "sum_list([1, 2, 3], S).",
+        "last([1, 2, 3], X).",
+        "length([a, b], N).",
+        "append([1], [2], L).",
+        "member(b, [a, b]).",
+        "maplist(succ, [1, 2], L).",
+        "exclude(integer, [a, 1], L).",
+        "atom_concat(a, b, A).",
+        "aggregate_all(count, colour(_), N).",
+        "aggregate_all(max(X), member(X, [1, 3, 2]), M).",
+        "retractall(shade(_)).",
+        "setof(X, colour(X), L).",
+        "X #= 1 + 2.",
+        "X #> 3.",
+    ] {
+        let output = helper_execute_multi(&program, query);
+        assert!(output.success, "{}", query);
+        assert!(output.deterministic, "{}", query);
+    }
+
+    let mut interpreter = run(&program, "colour(X).", "");
+    assert!(interpreter.has_alternatives());
+    assert!(interpreter.try_backtrack());
+    while interpreter.step() {}
+    assert_eq!(interpreter.answer(), "X = green");
+    assert!(!interpreter.has_alternatives());
+    assert!(!interpreter.try_backtrack());
+    assert_eq!(interpreter.answer(), "X = green");
+}
+
+#[test]
+fn test_all_solutions() {
+    let program = [
+        "age(peter, 7).",
+        "age(ann, 11).",
+        "age(pat, 8).",
+        "age(tom, 5).",
+        "age(mike, 11).",
+        "class(peter, a).",
+        "class(ann, b).",
+        "class(pat, a).",
+        "class(tom, b).",
+        "class(mike, a).",
+        "older(X, Y) :- age(X, A), A > Y.",
+        "range(L, H, L) :- L =< H.",
+        "range(L, H, X) :- L < H, L1 is L + 1, range(L1, H, X).",
+    ];
+
+    check(
+        &program,
+        "findall(X, older(X, 6), L).",
+        Answers("L = [peter,ann,pat,mike]"),
+    );
+    check(
+        &program,
+        "findall(X-A, (age(X, A), A > 7), L).",
+        Answers("L = [ann-11,pat-8,mike-11]"),
+    );
+    check(&program, "findall(X, age(X, 100), L).", Answers("L = []"));
+    // Long solutions are stored without recursing over them.
+    check(
+        &program,
+        "length(_L, 100000), findall(_L, true, [_S]), length(_S, N).",
+        Answers("N = 100000"),
+    );
+    check(
+        &program,
+        "findall(X, age(X, 11), L, [end]).",
+        Answers("L = [ann,mike,end]"),
+    );
+    // Variables of the solutions are fresh, but shared within each solution.
+    check(
+        &program,
+        "findall(f(X, Y, X), age(Y, 7), L).",
+        Answers("L = [f(_A,peter,_A)]"),
+    );
+    check(
+        &program,
+        "findall(X, call(X = 1r3), L).",
+        Answers("L = [1r3]"),
+    );
+
+    check(
+        &program,
+        "bagof(X, age(X, A), L).",
+        Answers(
+            "A = 5, L = [tom]\n\
+         A = 7, L = [peter]\n\
+         A = 8, L = [pat]\n\
+         A = 11, L = [ann,mike]",
+        ),
+    );
+    check(
+        &program,
+        "bagof(X, A^age(X, A), L).",
+        Answers("L = [peter,ann,pat,tom,mike]"),
+    );
+    check(&program, "bagof(X, age(X, 100), L).", Fails);
+    check(
+        &program,
+        "setof(X, A^(class(X, C), age(X, A)), L).",
+        Answers(
+            "C = a, L = [mike,pat,peter]\n\
+         C = b, L = [ann,tom]",
+        ),
+    );
+    check(
+        &program,
+        "setof(X, C^A^(class(X, C), age(X, A)), L).",
+        Answers("L = [ann,mike,pat,peter,tom]"),
+    );
+
+    check(
+        &program,
+        "aggregate_all(count, class(_, a), N).",
+        Answers("N = 3"),
+    );
+    check(&program, "aggregate_all(count, fail, N).", Answers("N = 0"));
+    check(
+        &program,
+        "aggregate_all(max(A), age(_, A), M).",
+        Answers("M = 11"),
+    );
+    check(
+        &program,
+        "aggregate_all(bag(X), class(X, b), B).",
+        Answers("B = [ann,tom]"),
+    );
+    // Deep recursion with many open choice points.
+    check(
+        &program,
+        "aggregate_all(sum(X), range(1, 3000, X), S).",
+        Answers("S = 4501500"),
+    );
+
+    check(
+        &["p."],
+        "findall(X, q(X), L).",
+        Raises(PrologError::ExistenceError {
+            kind: "procedure",
+            culprit: culprit("q/1"),
+        }),
+    );
+    // The internal builtins fail or raise an error when called out of place.
+    check(&["p."], "'$findall_add'(x).", Fails);
+    check(&["p."], "'$findall_collect'(B, []).", Fails);
+    check(
+        &["p."],
+        "'$bagof_groups'([a], G).",
+        Raises(PrologError::TypeError {
+            expected: "pair",
+            culprit: culprit("a"),
+        }),
+    );
+}
+
+#[test]
+fn test_cut() {
+    let program = [
+        "b(1).",
+        "b(2).",
+        "b(3).",
+        "first(X) :- b(X), !.",
+        "second(X) :- b(X), X > 1, !.",
+        "sign(X, S) :- X < 0, !, S = negative.",
+        "sign(0, S) :- !, S = zero.",
+        "sign(_, positive).",
+        "opaque(X) :- call((b(X), !)).",
+        "opaque(9).",
+        "t(X) :- (X = 1, ! ; X = 2).",
+        "t(3).",
+        "u(X) :- (b(X) -> ! ; true).",
+        "u(4).",
+        "v(X) :- b(Y), (Y > 1 -> X = Y, ! ; fail).",
+        "v(5).",
+        "w(X) :- (b(X) *-> true ; X = none).",
+        "w(X) :- (fail *-> true ; X = none).",
+    ];
+
+    check(&program, "first(X).", Answers("X = 1"));
+    check(&program, "second(X).", Answers("X = 2"));
+    check(&program, "sign(-3, S).", Answers("S = negative"));
+    check(&program, "sign(0, S).", Answers("S = zero"));
+    check(&program, "sign(5, S).", Answers("S = positive"));
+    // A cut inside call/1 only cuts the choice points of the called goal.
+    check(&program, "opaque(X).", Answers("X = 1\nX = 9"));
+    check(&program, "call(!).", Succeeds);
+    // A cut inside a control construct cuts the whole clause.
+    check(&program, "t(X).", Answers("X = 1"));
+    check(&program, "u(X).", Answers("X = 1"));
+    check(&program, "v(X).", Answers("X = 2"));
+    check(&program, "(b(X), ! ; X = 0).", Answers("X = 1"));
+    // `*->` keeps the solutions of its condition, the else branch only runs without any.
+    check(&program, "w(X).", Answers("X = 1\nX = 2\nX = 3\nX = none"));
+    check(
+        &program,
+        "call((b(X) *-> true ; X = none)).",
+        Answers("X = 1\nX = 2\nX = 3"),
+    );
+    check(
+        &program,
+        "assertz((d(X) :- (X = 1, ! ; X = 2))), d(X).",
+        Answers("X = 1"),
+    );
+    // Cut levels passed from user code are checked.
+    for (query, level) in [("'$cut'(foo).", "foo"), ("'$soft_cut'(a).", "a")] {
+        check(
+            &["p."],
+            query,
+            Raises(PrologError::TypeError {
+                expected: "integer",
+                culprit: culprit(level),
+            }),
+        );
+    }
+    check(
+        &["p."],
+        "'$cut'(L).",
+        Raises(PrologError::InstantiationError),
+    );
+}
+
+#[test]
+fn test_meta_call() {
+    let program = [
+        "b(1).",
+        "b(2).",
+        "b(3).",
+        "add(X, Y, Z) :- Z is X + Y.",
+        "odd(X) :- 1 =:= X mod 2.",
+        "pair(X, Y, X-Y).",
+    ];
+
+    check(&program, "call(add(1), 2, X).", Answers("X = 3"));
+    check(&program, "call(add, 1, 2, X).", Answers("X = 3"));
+    check(&program, "once(b(X)).", Answers("X = 1"));
+    check(&program, "ignore(b(X)).", Answers("X = 1"));
+    check(&program, "ignore(fail).", Succeeds);
+    check(&program, "\\+ b(4).", Succeeds);
+    check(&program, "\\+ b(1).", Fails);
+    check(&program, "forall(b(X), X > 0).", Succeeds);
+    check(&program, "forall(b(X), X > 1).", Fails);
+    check(
+        &program,
+        "(b(X), X > 1 -> Y = yes ; Y = no).",
+        Answers("X = 2, Y = yes"),
+    );
+    check(
+        &program,
+        "(b(X) ; X = 4).",
+        Answers("X = 1\nX = 2\nX = 3\nX = 4"),
+    );
+
+    check(
+        &program,
+        "maplist(add(1), [1, 2, 3], L).",
+        Answers("L = [2,3,4]"),
+    );
+    check(&program, "maplist(b, [1, 2]).", Succeeds);
+    check(&program, "maplist(b, [1, 4]).", Fails);
+    check(
+        &program,
+        "maplist(pair, [a, b], [1, 2], L).",
+        Answers("L = [a-1,b-2]"),
+    );
+    check(&program, "foldl(add, [1, 2, 3], 0, S).", Answers("S = 6"));
+    check(
+        &program,
+        "include(odd, [1, 2, 3, 4, 5], L).",
+        Answers("L = [1,3,5]"),
+    );
+    check(
+        &program,
+        "exclude(odd, [1, 2, 3, 4, 5], L).",
+        Answers("L = [2,4]"),
+    );
+
+    check(&["p."], "call(G).", Raises(PrologError::InstantiationError));
+    check(
+        &["p."],
+        "call(1, a).",
+        Raises(PrologError::TypeError {
+            expected: "callable",
+            culprit: culprit("1"),
+        }),
+    );
+    check(
+        &["p."],
+        "call(foo, 1).",
+        Raises(PrologError::ExistenceError {
+            kind: "procedure",
+            culprit: culprit("foo/1"),
+        }),
+    );
+}
+
+#[test]
+fn test_dynamic_database() {
+    let program = [
+        ":- dynamic counter/1.",
+        "counter(0).",
+        "increment :- retract(counter(N)), N1 is N + 1, assertz(counter(N1)).",
+        "twice(C) :- increment, increment, counter(C).",
+        "order(L) :- assertz(f(1)), assertz(f(2)), asserta(f(0)), findall(X, f(X), L).",
+        "remove(L) :- assertz(h(1)), assertz(h(2)), assertz(h(3)), retract(h(2)), findall(X, h(X), L).",
+        "rule(X, B) :- assertz((r(Y) :- Y > 1, Y < 5)), r(X), retract((r(_) :- B)).",
+        "choices(X) :- assertz(m(1)), assertz(m(2)), retract(m(X)).",
+        "abolished :- assertz(n(1)), abolish(n/1), n(_).",
+        "cleared(L) :- retractall(o(_)), findall(X, o(X), L).",
+    ];
+
+    check(&program, "counter(X).", Answers("X = 0"));
+    check(&program, "twice(C).", Answers("C = 2"));
+    check(&program, "order(L).", Answers("L = [0,1,2]"));
+    check(&program, "remove(L).", Answers("L = [1,3]"));
+    check(&program, "rule(1, B).", Fails);
+    check(&program, "rule(3, B).", Answers("B = (_A>1,_A<5)"));
+    check(&program, "choices(X).", Answers("X = 1\nX = 2"));
+    check(&program, "cleared(L).", Answers("L = []"));
+    check(&program, "retract(undefined(1)).", Fails);
+
+    check(
+        &["p."],
+        "assertz(p).",
+        Raises(PrologError::PermissionError {
+            action: "modify",
+            kind: "static_procedure",
+            culprit: culprit("p/0"),
+        }),
+    );
+    check(
+        &["p."],
+        "retract(p).",
+        Raises(PrologError::PermissionError {
+            action: "modify",
+            kind: "static_procedure",
+            culprit: culprit("p/0"),
+        }),
+    );
+    check(
+        &["p."],
+        "assertz((q :- 1)).",
+        Raises(PrologError::TypeError {
+            expected: "callable",
+            culprit: culprit("(q :- 1)"),
+        }),
+    );
+    check(
+        &["p."],
+        "assertz(X).",
+        Raises(PrologError::InstantiationError),
+    );
+    check(
+        &["p."],
+        "abolish(q/a).",
+        Raises(PrologError::TypeError {
+            expected: "integer",
+            culprit: culprit("a"),
+        }),
+    );
+    check(
+        &["abolished :- assertz(n(1)), abolish(n/1), n(_)."],
+        "abolished.",
+        Raises(PrologError::ExistenceError {
+            kind: "procedure",
+            culprit: culprit("n/1"),
+        }),
+    );
+
+    // Declarations which cannot be executed are reported instead of aborting the host.
+    let load = |clauses: &[&str]| {
+        let mut compiler = Compiler::new();
+        clauses
+            .iter()
+            .try_for_each(|clause| compiler.add_program(&parse(clause).unwrap()))
+    };
+    assert!(matches!(
+        load(&[":- dynamic(foo)."]),
+        Err(PrologError::DomainError {
+            domain: "directive",
+            ..
+        })
+    ));
+    assert!(matches!(
+        load(&[":- use_module(library(nope))."]),
+        Err(PrologError::ExistenceError {
+            kind: "library",
+            ..
+        })
+    ));
+    assert!(matches!(
+        load(&["p(1).", ":- dynamic(p/1)."]),
+        Err(PrologError::PermissionError {
+            action: "modify",
+            ..
+        })
+    ));
+    assert!(matches!(
+        load(&[":- module(a, [f/1]).", "f(1).", ":- module(b, [f/1])."]),
+        Err(PrologError::PermissionError {
+            action: "import_into",
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_logical_update_view() {
+    let program = [
+        "grow(L, M) :- assertz(g(1)), findall(X, (g(X), Y is X + 1, assertz(g(Y))), L), findall(X, g(X), M).",
+        "shrink(L, M) :- assertz(k(1)), assertz(k(2)), findall(X, (k(X), retractall(k(_))), L), findall(X, k(X), M).",
+    ];
+
+    // Clauses added while `g/1` runs are not seen by that call.
+    check(&program, "grow(L, M).", Answers("L = [1], M = [1,2]"));
+    // Clauses removed while `k/1` runs are still seen by that call.
+    check(&program, "shrink(L, M).", Answers("L = [1,2], M = []"));
+}
+
+#[test]
+fn test_retracted_clauses_are_reclaimed() {
+    let query = parse("churn(300).").unwrap();
+    let mut compiler = Compiler::new();
+    compiler
+        .add_program(&parse("churn(0) :- !.").unwrap())
+        .unwrap();
+    compiler
+        .add_program(
+            &parse("churn(N) :- assertz(tmp(N)), retract(tmp(N)), N1 is N - 1, churn(N1).")
+                .unwrap(),
+        )
+        .unwrap();
+    let artifact = compiler.compile(&query);
+    let mut interpreter = Interpreter::new(compiler, &artifact);
+    while interpreter.step() {}
+
+    assert_eq!(interpreter.execution_state, ExecutionState::Normal);
+    // Every new clause reuses the instructions of the one retracted before.
+    assert!(interpreter.instructions().len() < artifact.instructions.len() + 20);
+
+    // The auxiliary predicates of a clause's control constructs are reclaimed with the clause,
+    // and move along with it into reclaimed instructions.
+    for remove in [
+        "retract((tmp(_) :- _))",
+        "retractall(tmp(_))",
+        "abolish(tmp/1)",
+    ] {
+        let query = parse("churn(300).").unwrap();
+        let mut compiler = Compiler::new();
+        compiler
+            .add_program(&parse("churn(0) :- !.").unwrap())
+            .unwrap();
+        let churn = format!(
+            "churn(N) :- assertz((tmp(X) :- (N < 0, !, X = neg ; X = pos))), tmp(pos), {}, \
+             N1 is N - 1, churn(N1).",
+            remove
+        );
+        compiler.add_program(&parse(&churn).unwrap()).unwrap();
+        let artifact = compiler.compile(&query);
+        let mut interpreter = Interpreter::new(compiler, &artifact);
+        while interpreter.step() {}
+
+        assert_eq!(
+            interpreter.execution_state,
+            ExecutionState::Normal,
+            "{}",
+            remove
+        );
+        assert!(
+            interpreter.instructions().len() < artifact.instructions.len() + 100,
+            "{}",
+            remove
+        );
+    }
+}
+
+#[test]
+fn test_reflection() {
+    let program = [
+        "edge(a, b).",
+        "edge(b, c).",
+        "path(X, Y) :- edge(X, Y).",
+        "path(X, Y) :- edge(X, Z), path(Z, Y).",
+        ":- dynamic(counter/1).",
+        "counter(0).",
+    ];
+    check(
+        &program,
+        "findall(B, clause(path(a, c), B), L).",
+        Answers("L = [edge(a,c),(edge(a,_A),path(_A,c))]"),
+    );
+    check(&program, "clause(counter(X), true).", Answers("X = 0"));
+    check(&program, "clause(undefined(_), _).", Fails);
+    check(
+        &program,
+        "findall(P, current_predicate(P), L).",
+        Answers("L = [counter/1,edge/2,path/2]"),
+    );
+    check(&program, "current_predicate(path/A).", Answers("A = 2"));
+    check(&program, "current_predicate(append/3).", Fails);
+    check(
+        &program,
+        "findall(P, predicate_property(path(a, b), P), L).",
+        Answers("L = [defined,static,number_of_clauses(2)]"),
+    );
+    check(
+        &program,
+        "findall(P, (assertz(counter(1)), predicate_property(counter(x), P)), L).",
+        Answers("L = [defined,dynamic,number_of_clauses(2)]"),
+    );
+    check(&program, "predicate_property(atom(_), built_in).", Succeeds);
+    check(
+        &program,
+        "predicate_property(maplist(_, _), built_in).",
+        Succeeds,
+    );
+    // Library predicates which are not loaded yet are loaded, as calling them would.
+    check(
+        &program,
+        "findall(P, predicate_property(append(_, _, _), P), L).",
+        Answers("L = [built_in,defined,static]"),
+    );
+    check(
+        &program,
+        "findall(H, predicate_property(H, dynamic), L).",
+        Answers("L = [counter(_A)]"),
+    );
+
+    check(
+        &["p."],
+        "clause(atom(_), _).",
+        Raises(PrologError::PermissionError {
+            action: "access",
+            kind: "private_procedure",
+            culprit: culprit("atom/1"),
+        }),
+    );
+    assert!(matches!(
+        run(&["p."], "clause(_, true).", "").exception,
+        Some(PrologError::InstantiationError)
+    ));
+    check(
+        &["p."],
+        "clause(p, 4).",
+        Raises(PrologError::TypeError {
+            expected: "callable",
+            culprit: culprit("4"),
+        }),
+    );
+    check(
+        &["p."],
+        "current_predicate(4).",
+        Raises(PrologError::TypeError {
+            expected: "predicate_indicator",
+            culprit: culprit("4"),
+        }),
+    );
+}
+
+#[test]
+fn test_listing() {
+    let program = [
+        "path(X, Y) :- edge(X, Y).",
+        "path(X, Y) :- edge(X, Z), path(Z, [Y, X|_]).",
+        "path(a).",
+        ":- dynamic(counter/1).",
+        "counter(0).",
+    ];
+    check(
+        &program,
+        "listing(path).",
+        Prints(
+            "path(a).\n\npath(A,B) :-\n    edge(A,B).\npath(A,B) :-\n    edge(A,C),\n    path(C,[B,A|_]).\n\n",
+        ),
+    );
+    check(&program, "listing(path/1).", Prints("path(a).\n\n"));
+    check(
+        &program,
+        "listing(counter).",
+        Prints(":- dynamic counter/1.\n\ncounter(0).\n\n"),
+    );
+}
+
+#[test]
+fn test_write() {
+    let write = |term: &str| {
+        run(&[], &format!("write({}).", term), "")
+            .output()
+            .to_string()
+    };
+    let writeq = |term: &str| {
+        run(&[], &format!("writeq({}).", term), "")
+            .output()
+            .to_string()
+    };
+
+    assert_eq!(write("f(a, 'B c', [1, 2|T])"), "f(a,B c,[1,2|_G0])");
+    assert_eq!(
+        writeq("f(a, 'B c', [], '[]', {x, y})"),
+        "f(a,'B c',[],[],{x,y})"
+    );
+    assert_eq!(
+        writeq("['hello\\nworld', 'don''t', '', ',', '|', ;, !]"),
+        "['hello\\nworld','don\\'t','',',','|',;,!]"
+    );
+    assert_eq!(writeq("1 + 2 * 3 - (4 - 5)"), "1+2*3-(4-5)");
+    assert_eq!(writeq("(a :- b, c ; d -> e)"), "a:-b,c;d->e");
+    assert_eq!(writeq("f((a, b), (c :- d))"), "f((a,b),(c:-d))");
+    assert_eq!(writeq("- (1)"), "- 1");
+    assert_eq!(writeq("- a"), "-a");
+    assert_eq!(writeq("1 - -1"), "1- -1");
+    assert_eq!(writeq("a = \\+ b"), "a=(\\+b)");
+    assert_eq!(writeq("a = -b"), "a= -b");
+    assert_eq!(writeq("- (- a)"), "- -a");
+    assert_eq!(writeq("X is 7 mod 2"), "_G0 is 7 mod 2");
+    assert_eq!(writeq("-(-)"), "- (-)");
+    assert_eq!(writeq("'$VAR'(27)"), "B1");
+    check(
+        &[],
+        "write_canonical(f(X, 'a b', [1], 1 + 2, '$VAR'(1))).",
+        Prints("f(_G0,'a b',[1],+(1,2),'$VAR'(1))"),
+    );
+    check(
+        &[],
+        "write_term([1, 2, 3, 4], [max_depth(3)]).",
+        Prints("[1,2|...]"),
+    );
+    check(
+        &[],
+        "write_term(f(g(h(i))), [max_depth(2)]).",
+        Prints("f(g(...))"),
+    );
+    check(
+        &[],
+        "write_term(f('A', 1 + 2), [quoted(true), ignore_ops(true)]).",
+        Prints("f('A',+(1,2))"),
+    );
+    check(
+        &["hello :- write('Hello'), nl, print(world), nl."],
+        "hello.",
+        Prints("Hello\nworld\n"),
+    );
+    // List tails are written in a loop, only the elements are written recursively.
+    let pairs = (1..=100000)
+        .map(|i| format!("{}-[{}]", i, i))
+        .collect::<Vec<_>>();
+    check(
+        &[
+            "pairs(H, H, [H-[H]]) :- !.",
+            "pairs(L, H, [L-[L]|T]) :- M is L + 1, pairs(M, H, T).",
+        ],
+        "pairs(1, 100000, L), write(L).",
+        Prints(&format!("[{}]", pairs.join(","))),
+    );
+
+    check(
+        &["p."],
+        "write_term(a, [bogus(true)]).",
+        Raises(PrologError::DomainError {
+            domain: "write_option",
+            culprit: culprit("bogus(true)"),
+        }),
+    );
+    assert!(matches!(
+        run(&["p."], "write_term(a, [quoted(_)]).", "").exception,
+        Some(PrologError::InstantiationError)
+    ));
+}
+
+#[test]
+fn test_format() {
+    check(
+        &[],
+        "format(\"~w and ~q~n\", [f('A'), f('A')]).",
+        Prints("f(A) and f('A')\n"),
+    );
+    check(&[], "format('~a~~~p', [abc, 'x y']).", Prints("abc~'x y'"));
+    check(
+        &[],
+        "format(\"~d ~2d ~D ~2D\", [42, 314, 1234567, 1234567]).",
+        Prints("42 3.14 1,234,567 12,345.67"),
+    );
+    check(&[], "format(\"~2d\", [-5]).", Prints("-0.05"));
+    check(
+        &[],
+        "format(\"~f ~2f ~0f ~3e\", [1.5, 2, 2.5, 1234.5]).",
+        Prints("1.500000 2.00 2 1.234e+03"),
+    );
+    check(
+        &[],
+        "format(\"~s and ~c~3c\", [\"codes\", 65, 66]).",
+        Prints("codes and ABBB"),
+    );
+    check(
+        &[],
+        "format(\"~8r ~16R ~*c\", [64, 255, 2, 0'x]).",
+        Prints("100 FF xx"),
+    );
+    check(&[], "format(\"~w~i~w\", [a, b, c]).", Prints("ac"));
+    check(&[], "format(hello, []).", Prints("hello"));
+    check(&[], "format(\"~w\", hello).", Prints("hello"));
+    check(
+        &[],
+        "format(\"abcdefghijklmnopqrstuvwxyz~n\", []).",
+        Prints("abcdefghijklmnopqrstuvwxyz\n"),
+    );
+    check(
+        &["p."],
+        "X = f(g(0,1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16)), write(X).",
+        Prints("f(g(0,1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16))"),
+    );
+
+    // Column alignment.
+    check(
+        &[],
+        "format(\"~a~10|~a\", [abc, def]).",
+        Prints("abc       def"),
+    );
+    check(
+        &[],
+        "format(\"~t~a~10|~a\", [abc, def]).",
+        Prints("       abcdef"),
+    );
+    check(&[], "format(\"~t~a~t~11|\", [abc]).", Prints("    abc    "));
+    check(
+        &[],
+        "format(\"~`-t~30|~n\").",
+        Prints(&format!("{}\n", "-".repeat(30))),
+    );
+    check(
+        &[],
+        "format(\"~a~t~8+~a~t~8+~a\", [a, bb, ccc]).",
+        Prints("a       bb      ccc"),
+    );
+    check(
+        &[],
+        "format(\"~t~d~6|~t~d~6+\", [1, 22]).",
+        Prints("     1    22"),
+    );
+    check(
+        &["t :- write(abc), format(\"~t~w~6|\", [x])."],
+        "t.",
+        Prints("abc  x"),
+    );
+
+    check(
+        &["p."],
+        "format(atom(A), \"~w-~w\", [a, 1]).",
+        Answers("A = 'a-1'"),
+    );
+    check(
+        &["p."],
+        "format(codes(C), \"~w\", [ab]).",
+        Answers("C = [97,98]"),
+    );
+    check(
+        &["p."],
+        "format(string(S), \"x\", []).",
+        Answers("S = \"x\""),
+    );
+
+    for query in [
+        "format(\"~d\", [a]).",
+        "format(\"~d\", [1.0]).",
+        "format(\"~a\", [f(x)]).",
+        "format(\"~w ~w\", [a]).",
+        "format(\"~w\", [a, b]).",
+        "format(\"~z\", [a]).",
+        "format(\"~r\", [10]).",
+        "format(\"~s\", [abc]).",
+        "format(\"~c\", [a]).",
+    ] {
+        assert!(
+            matches!(
+                run(&["p."], query, "").exception,
+                Some(PrologError::FormatError(_))
+            ),
+            "{}",
+            query
+        );
+    }
+}
+
+#[test]
+fn test_streams() {
+    let path = std::env::temp_dir().join(format!("prolog_wan_streams_{}.txt", std::process::id()));
+    let file = format!("file('{}').", path.display());
+    let program = [
+        file.as_str(),
+        "chars(S, Cs) :-
+            get_char(S, C),
+            (   C == end_of_file
+            ->  Cs = []
+            ;   Cs = [C|Rest],
+                chars(S, Rest)
+            ).",
+        "codes(S, Cs) :-
+            get_code(S, C),
+            (   C =:= -1
+            ->  Cs = []
+            ;   Cs = [C|Rest],
+                codes(S, Rest)
+            ).",
+        "write_file :-
+            file(F),
+            open(F, write, S),
+            write(S, f('A b', 1)),
+            nl(S),
+            put_char(S, z),
+            close(S).",
+        "read_file(A) :-
+            file(F),
+            open(F, read, S),
+            codes(S, Cs),
+            close(S),
+            format(atom(A), \"~s\", [Cs]).",
+        "written(A) :-
+            write_file,
+            read_file(A).",
+        "appended(A) :-
+            write_file,
+            file(F),
+            open(F, append, _, [alias(log)]),
+            format(log, \"~a~n\", [tail]),
+            close(log),
+            read_file(A).",
+        "position(Peek, Next, Lines, End) :-
+            write_file,
+            file(F),
+            open(F, read, S),
+            peek_char(S, Peek),
+            get_char(S, Next),
+            chars(S, _),
+            line_count(S, Lines),
+            stream_property(S, end_of_stream(End)),
+            close(S).",
+        "past_end :-
+            write_file,
+            file(F),
+            open(F, read, S),
+            chars(S, _),
+            get_char(S, _).",
+        "eof_code(C) :-
+            write_file,
+            file(F),
+            open(F, read, S, [eof_action(eof_code)]),
+            chars(S, _),
+            get_char(S, C).",
+        "binary(B) :-
+            write_file,
+            file(F),
+            open(F, read, S, [type(binary)]),
+            get_byte(S, B).",
+        "binary_as_text :-
+            write_file,
+            file(F),
+            open(F, read, S, [type(binary)]),
+            get_char(S, _).",
+        "edinburgh :-
+            file(F),
+            tell(F),
+            write(hello),
+            told,
+            telling(user),
+            see(F),
+            get_char(C),
+            seen,
+            write(C).",
+        "failed_capture :-
+            \\+ with_output_to(atom(_), (write(lost), fail)),
+            write(ok).",
+    ];
+
+    check(&program, "written(A).", Answers("A = 'f(A b,1)\\nz'"));
+    check(
+        &program,
+        "appended(A).",
+        Answers("A = 'f(A b,1)\\nztail\\n'"),
+    );
+    check(
+        &program,
+        "position(P, N, L, E).",
+        Answers("P = f, N = f, L = 2, E = past"),
+    );
+    check(&program, "eof_code(C).", Answers("C = end_of_file"));
+    check(&program, "binary(B).", Answers("B = 102"));
+    check(&program, "edinburgh.", Prints("h"));
+    check(&program, "failed_capture.", Prints("ok"));
+    check(
+        &["p."],
+        "with_output_to(atom(A), (write(a), nl, print('B'))).",
+        Answers("A = 'a\\n\\'B\\''"),
+    );
+    check(
+        &["p."],
+        "with_output_to(chars(C), put_code(0'x)).",
+        Answers("C = [x]"),
+    );
+    check(
+        &["p."],
+        "with_output_to(string(S), write(f(x))), string(S).",
+        Answers("S = \"f(x)\""),
+    );
+    check(
+        &["p."],
+        "stream_property(S, alias(user_error)).",
+        Answers("S = '$stream'(2)"),
+    );
+    check(
+        &["p."],
+        "stream_property(user_input, end_of_stream(at)).",
+        Succeeds,
+    );
+
+    check(
+        &program,
+        "past_end.",
+        Raises(PrologError::PermissionError {
+            action: "input",
+            kind: "past_end_of_stream",
+            culprit: culprit("'$stream'(4)"),
+        }),
+    );
+    check(
+        &program,
+        "binary_as_text.",
+        Raises(PrologError::PermissionError {
+            action: "input",
+            kind: "binary_stream",
+            culprit: culprit("'$stream'(4)"),
+        }),
+    );
+    check(
+        &["p."],
+        "get_char(user_output, C).",
+        Raises(PrologError::PermissionError {
+            action: "input",
+            kind: "stream",
+            culprit: culprit("user_output"),
+        }),
+    );
+    check(
+        &["p."],
+        "write(nowhere, x).",
+        Raises(PrologError::ExistenceError {
+            kind: "stream",
+            culprit: culprit("nowhere"),
+        }),
+    );
+    check(
+        &["p."],
+        "close('$stream'(99)).",
+        Raises(PrologError::ExistenceError {
+            kind: "stream",
+            culprit: culprit("'$stream'(99)"),
+        }),
+    );
+    check(
+        &["p."],
+        "put_char(f(x), a).",
+        Raises(PrologError::DomainError {
+            domain: "stream_or_alias",
+            culprit: culprit("f(x)"),
+        }),
+    );
+    check(
+        &["p."],
+        "put_char(user_output, 1).",
+        Raises(PrologError::TypeError {
+            expected: "character",
+            culprit: culprit("1"),
+        }),
+    );
+    check(
+        &["p."],
+        "open('/nonexistent/prolog_wan', read, S).",
+        Raises(PrologError::ExistenceError {
+            kind: "source_sink",
+            culprit: culprit("'/nonexistent/prolog_wan'"),
+        }),
+    );
+    check(
+        &["p."],
+        "open(f, update, S).",
+        Raises(PrologError::DomainError {
+            domain: "io_mode",
+            culprit: culprit("update"),
+        }),
+    );
+    check(
+        &["p."],
+        "open(f, read, S, [bad]).",
+        Raises(PrologError::DomainError {
+            domain: "stream_option",
+            culprit: culprit("bad"),
+        }),
+    );
+    check(
+        &["p."],
+        "with_output_to(file, true).",
+        Raises(PrologError::DomainError {
+            domain: "output_sink",
+            culprit: culprit("file"),
+        }),
+    );
+    check(
+        &["p."],
+        "close(S).",
+        Raises(PrologError::InstantiationError),
+    );
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn test_standard_streams() {
+    let interpreter = run(
+        &[
+            "t :- get_char(C), peek_code(D), get_char(E), write(C-D-E), get_char(F), write(F),
+            format(user_error, \"oops\", []).",
+        ],
+        "t.",
+        "ab",
+    );
+    assert_eq!(interpreter.exception, None);
+    assert_eq!(interpreter.output(), "a-98-bend_of_file");
+    assert_eq!(interpreter.error_output(), "oops");
+}
+
+#[test]
+fn test_read_term() {
+    let program = [
+        "variables :-
+            read_term(T, [variable_names(Vs), singletons(Ss), variables(Vars)]),
+            T = f(A, B, C, A, D),
+            Vs == ['X'=A, 'Y'=B, '_Z'=D],
+            Ss == ['Y'=B, '_Z'=D],
+            Vars == [A, B, C, D],
+            read(E),
+            write(E).",
+        "canonical :-
+            read(T),
+            write_canonical(T),
+            nl,
+            canonical_rest.",
+        "canonical_rest :-
+            read(T),
+            (   T == end_of_file
+            ->  true
+            ;   write_canonical(T),
+                nl,
+                canonical_rest
+            ).",
+        "failing :-
+            \\+ read_term(_, [syntax_errors(fail)]),
+            read(T),
+            write(T).",
+        "dec10 :-
+            read_term(T, [syntax_errors(dec10)]),
+            write(T).",
+    ];
+
+    let interpreter = run(&program, "variables.", "f(X, Y, _, X, _Z).\n");
+    assert_eq!(interpreter.exception, None);
+    assert_eq!(interpreter.output(), "end_of_file");
+
+    let interpreter = run(
+        &program,
+        "canonical.",
+        "a :- b, c.\n- 1 + 2 . % comment\n/* x. */ p('a. b', \"c.\", 0'., 1.5, [x|y]).\n",
+    );
+    assert_eq!(interpreter.exception, None);
+    assert_eq!(
+        interpreter.output(),
+        ":-(a,','(b,c))\n+(-(1),2)\np('a. b',[99,46],46,1.5,[x|y])\n"
+    );
+
+    let interpreter = run(&program, "failing.", "foo(.\nbar.\n");
+    assert_eq!(interpreter.output(), "bar");
+    let interpreter = run(&program, "dec10.", "foo(.\nbar.\n");
+    assert_eq!(interpreter.output(), "bar");
+    assert!(interpreter.error_output().starts_with("Syntax error"));
+    let interpreter = run(&program, "variables.", "foo(.\n");
+    assert!(matches!(
+        interpreter.exception,
+        Some(PrologError::SyntaxError(_))
+    ));
+    check(
+        &["p."],
+        "read_term(T, [bad]).",
+        Raises(PrologError::DomainError {
+            domain: "read_option",
+            culprit: culprit("bad"),
+        }),
+    );
+}
+
+#[test]
+fn test_text() {
+    check(&["p."], "atom_length(hello, L).", Answers("L = 5"));
+    check(&["p."], "atom_chars(abc, L).", Answers("L = [a,b,c]"));
+    check(&["p."], "atom_chars(A, [a, b]).", Answers("A = ab"));
+    check(&["p."], "atom_codes(A, \"hi\").", Answers("A = hi"));
+    check(&["p."], "atom_codes(hi, L).", Answers("L = [104,105]"));
+    check(&["p."], "char_code(C, 0'a).", Answers("C = a"));
+    check(&["p."], "char_code(b, X).", Answers("X = 98"));
+    check(&["p."], "number_codes(N, \" 42\").", Answers("N = 42"));
+    check(&["p."], "number_codes(N, \"-1.5\").", Answers("N = -1.5"));
+    check(&["p."], "number_chars(12, L).", Answers("L = ['1','2']"));
+    check(&["p."], "upcase_atom('mIx 1', U).", Answers("U = 'MIX 1'"));
+    check(&["p."], "atom_concat(ab, cd, A).", Answers("A = abcd"));
+    let solutions =
+        |goal: &str| helper_execute(&format!("t(L) :- findall(X, ({}), L).", goal), "t(L).").output;
+    assert_eq!(
+        solutions("atom_concat(A, B, abc), X = A+B"),
+        "L = [''+abc,a+bc,ab+c,abc+'']"
+    );
+    check(&["p."], "atom_concat(X, c, abc).", Answers("X = ab"));
+    assert_eq!(
+        solutions("sub_atom(abcab, B, N, A, ab), X = B-N-A"),
+        "L = [0-2-3,3-2-0]"
+    );
+    assert_eq!(solutions("sub_atom(abc, _, 2, _, X)"), "L = [ab,bc]");
+    check(
+        &["p."],
+        "sub_atom(hello, 1, 3, A, S).",
+        Answers("A = 1, S = ell"),
+    );
+
+    check(
+        &["p."],
+        "string_concat(\"ab\", cd, S).",
+        Answers("S = \"abcd\""),
+    );
+    check(
+        &["p."],
+        "string_concat(ab, X, \"abc\").",
+        Answers("X = \"c\""),
+    );
+    check(&["p."], "string_concat(ab, c, \"abc\").", Succeeds);
+    check(&["p."], "string_chars(S, [h, i]).", Answers("S = \"hi\""));
+    check(
+        &["p."],
+        "string_chars(S, [h, i]), string_codes(S, L).",
+        Answers("S = \"hi\", L = [104,105]"),
+    );
+    check(&["p."], "string_length(\"four\", L).", Answers("L = 4"));
+    check(&["p."], "string_code(2, \"abc\", C).", Answers("C = 98"));
+    check(&["p."], "string_code(4, \"abc\", C).", Fails);
+    check(&["p."], "string_to_atom(\"xy\", A).", Answers("A = xy"));
+    check(&["p."], "string_to_atom(S, xy).", Answers("S = \"xy\""));
+    check(&["p."], "string_upper(\"ab\", S).", Answers("S = \"AB\""));
+    check(
+        &["p."],
+        "sub_string(\"hello\", 0, 2, A, S).",
+        Answers("A = 3, S = \"he\""),
+    );
+    check(
+        &["p."],
+        "split_string(\"a,b,,c\", \",\", \"\", L).",
+        Answers("L = [\"a\",\"b\",\"\",\"c\"]"),
+    );
+    check(
+        &["p."],
+        "split_string(\"  padded  \", \"\", \" \", L).",
+        Answers("L = [\"padded\"]"),
+    );
+    // Strings are atomic, compare by their text and are ordered after atoms.
+    check(
+        &["p."],
+        "string_concat(a, b, S), string(S), atomic(S), \\+ atom(S), \\+ compound(S).",
+        Answers("S = \"ab\""),
+    );
+    check(&["p."], "string(\"ab\").", Fails);
+    check(
+        &["p."],
+        "string_concat(a, b, S), string_concat(ab, \"\", S).",
+        Succeeds,
+    );
+    check(
+        &["p."],
+        "string_concat(b, \"\", S), msort([S, f(x), a, 1], L).",
+        Answers("S = \"b\", L = [1,a,\"b\",f(x)]"),
+    );
+    check(
+        &["p."],
+        "string_concat(\"it's\", \"\", S), format(atom(A), \"~w|~q\", [S, S]).",
+        Answers("S = \"it's\", A = 'it\\'s|\"it\\'s\"'"),
+    );
+    check(
+        &["p."],
+        "string_chars(S, [a]), findall(S-X, member(X, [1, 2]), L), copy_term(L, C).",
+        Answers("S = \"a\", L = [\"a\"-1,\"a\"-2], C = [\"a\"-1,\"a\"-2]"),
+    );
+    check(
+        &["p."],
+        "string_chars(S, [a, b]), assertz(text(S, f(S))), text(T, f(T)), string(T).",
+        Answers("S = \"ab\", T = \"ab\""),
+    );
+
+    check(
+        &["p."],
+        "atom_length(X, L).",
+        Raises(PrologError::InstantiationError),
+    );
+    check(
+        &["p."],
+        "atom_length(f(x), L).",
+        Raises(PrologError::TypeError {
+            expected: "atom",
+            culprit: culprit("f(x)"),
+        }),
+    );
+    check(
+        &["p."],
+        "atom_length(abc, -1).",
+        Raises(PrologError::DomainError {
+            domain: "not_less_than_zero",
+            culprit: culprit("-1"),
+        }),
+    );
+    check(
+        &["p."],
+        "atom_chars(A, [a, f(x)]).",
+        Raises(PrologError::TypeError {
+            expected: "character",
+            culprit: culprit("f(x)"),
+        }),
+    );
+    check(
+        &["p."],
+        "atom_concat(X, Y, Z).",
+        Raises(PrologError::InstantiationError),
+    );
+    assert!(matches!(
+        run(&["p."], "number_codes(N, \"3x\").", "").exception,
+        Some(PrologError::SyntaxError(_))
+    ));
+    assert!(matches!(
+        run(&["p."], "char_code(C, -1).", "").exception,
+        Some(PrologError::RepresentationError("character_code"))
+    ));
+}
+
+#[test]
+fn test_library() {
+    let solutions =
+        |goal: &str| helper_execute(&format!("t(L) :- findall(X, ({}), L).", goal), "t(L).").output;
+
+    check(&["p."], "append([a], [b, c], L).", Answers("L = [a,b,c]"));
+    assert_eq!(
+        solutions("append(A, B, [1, 2]), X = A-B"),
+        "L = [[]-[1,2],[1]-[2],[1,2]-[]]"
+    );
+    assert_eq!(solutions("member(X, [a, b])"), "L = [a,b]");
+    check(&["p."], "length([a, b, c], N).", Answers("N = 3"));
+    check(
+        &["t(L) :- length(L, 2), L = [a|_], L = [_, b]."],
+        "t(L).",
+        Answers("L = [a,b]"),
+    );
+    check(&["p."], "length([a], 2).", Fails);
+    // Partial lists are completed or enumerated from the elements they already have.
+    check(&["p."], "length([a|T], 3).", Answers("T = [_A,_B]"));
+    check(
+        &["p."],
+        "once((length([a|T], N), N >= 3)).",
+        Answers("T = [_A,_B], N = 3"),
+    );
+    check(&["p."], "length([a, b|T], 1).", Fails);
+    // The helpers behind length/2, once the library is loaded.
+    check(
+        &["p."],
+        "length([], _), '$length'([a, b], 1, N).",
+        Answers("N = 3"),
+    );
+    check(
+        &["p."],
+        "length([], _), '$length_make'(L, 2).",
+        Answers("L = [_A,_B]"),
+    );
+    check(&["p."], "length([], _), '$length_make'([a], 2).", Fails);
+    check(&["p."], "reverse([1, 2, 3], L).", Answers("L = [3,2,1]"));
+    check(&["p."], "nth0(1, [a, b, c], X).", Answers("X = b"));
+    check(&["p."], "nth1(1, [a, b, c], X).", Answers("X = a"));
+    assert_eq!(solutions("nth1(I, [a, b], E), X = I-E"), "L = [1-a,2-b]");
+    check(&["p."], "last([a, b, c], X).", Answers("X = c"));
+    check(
+        &["p."],
+        "list_to_set([c, a, c, b, a], S).",
+        Answers("S = [c,a,b]"),
+    );
+    check(&["p."], "sum_list([1, 2, 3.5], S).", Answers("S = 6.5"));
+    assert_eq!(solutions("between(1, 3, X)"), "L = [1,2,3]");
+    assert_eq!(solutions("between(3, 1, X)"), "L = []");
+    check(&["p."], "between(1, inf, 100).", Succeeds);
+    check(&["p."], "succ(X, 4).", Answers("X = 3"));
+    check(&["p."], "succ(3, X).", Answers("X = 4"));
+    check(&["p."], "succ(X, 0).", Fails);
+    check(&["p."], "plus(1, X, 5).", Answers("X = 4"));
+
+    // The program's own definitions take precedence over the library.
+    check(
+        &["last(_, mine).", "t(X) :- last([a], X)."],
+        "t(X).",
+        Answers("X = mine"),
+    );
+    check(
+        &[
+            ":- use_module(library(lists)).",
+            "t(L) :- reverse([a, b], L).",
+        ],
+        "t(L).",
+        Answers("L = [b,a]"),
+    );
+
+    check(
+        &["p."],
+        "between(1, X, 2).",
+        Raises(PrologError::InstantiationError),
+    );
+    check(
+        &["p."],
+        "succ(a, X).",
+        Raises(PrologError::TypeError {
+            expected: "integer",
+            culprit: culprit("a"),
+        }),
+    );
+    check(
+        &["p."],
+        "length(L, -1).",
+        Raises(PrologError::DomainError {
+            domain: "not_less_than_zero",
+            culprit: culprit("-1"),
+        }),
+    );
+    check(
+        &["p."],
+        "length([a], a).",
+        Raises(PrologError::TypeError {
+            expected: "integer",
+            culprit: culprit("a"),
+        }),
+    );
+    // Cyclic lists and lists with another tail than `[]` or a variable are no lists.
+    for goal in [
+        "X = [a|X], length(X, N).",
+        "X = [a, b|X], length(X, 3).",
+        "length([a|b], N).",
+    ] {
+        assert!(
+            matches!(
+                run(&["p."], goal, "").exception,
+                Some(PrologError::TypeError {
+                    expected: "list",
+                    ..
+                })
+            ),
+            "{}",
+            goal
+        );
+    }
+    check(
+        &["p."],
+        "must_be(var, a).",
+        Raises(PrologError::UninstantiationError(culprit("a"))),
+    );
+}
+
+#[test]
+fn test_modules() {
+    let shapes = [
+        ":- module(shapes, [area/2]).",
+        "area(square(S), A) :- side(S, A).",
+        "side(S, A) :- A is S * S.",
+        "helper(shapes).",
+    ];
+
+    // Exports are imported into `user`, the rest is only reachable qualified.
+    check(&shapes, "area(square(3), A).", Answers("A = 9"));
+    check(&shapes, "shapes:side(2, A).", Answers("A = 4"));
+    check(
+        &shapes,
+        "side(2, A).",
+        Raises(PrologError::ExistenceError {
+            kind: "procedure",
+            culprit: culprit("side/2"),
+        }),
+    );
+
+    // Modules don't see each other's predicates, but do see `user`.
+    let program = [
+        "helper(user).",
+        "user:t(X, Y) :- a:which(X), b:which(Y).",
+        ":- module(a, []).",
+        "which(X) :- helper(X).",
+        ":- module(b, []).",
+        "helper(b).",
+        "which(X) :- helper(X).",
+    ];
+    check(&program, "t(X, Y).", Answers("X = user, Y = b"));
+
+    // Meta-predicates called from a module run their goals in that module.
+    let program = [
+        ":- module(m, [run/1]).",
+        "run(L) :- findall(X, item(X), L0), maplist(double, L0, L).",
+        "item(1).",
+        "item(2).",
+        "double(X, Y) :- Y is X * 2.",
+        "user:t(L) :- findall(X, m:(item(X), X > 1), L).",
+    ];
+    check(&program, "run(L).", Answers("L = [2,4]"));
+    check(&program, "t(L).", Answers("L = [2]"));
+    check(&program, "call(m:double, 5, Y).", Answers("Y = 10"));
+
+    let program = [
+        ":- module(a, [x/1]).",
+        "x(1).",
+        "y(2).",
+        ":- module(b, []).",
+        ":- use_module(a).",
+        ":- use_module(a, [y/1]).",
+        "z(X + Y) :- x(X), y(Y).",
+        "user:t(X) :- b:z(X).",
+    ];
+    check(&program, "t(X).", Answers("X = 1+2"));
+
+    // Conflicting imports and definitions are refused.
+    let mut compiler = Compiler::new();
+    for clause in [
+        ":- module(p, [f/1]).",
+        "f(p).",
+        ":- module(q, []).",
+        "f(q).",
+    ] {
+        compiler.add_program(&parse(clause).unwrap()).unwrap();
+    }
+    assert!(matches!(
+        compiler.import("q", "p", None),
+        Err(PrologError::PermissionError {
+            action: "import_into",
+            ..
+        })
+    ));
+    assert!(matches!(
+        compiler.import("user", "r", None),
+        Err(PrologError::ExistenceError { kind: "module", .. })
+    ));
+    check(
+        &[":- module(p, [f/1]).", "f(p)."],
+        "assertz(f(x)).",
+        Raises(PrologError::PermissionError {
+            action: "redefine",
+            kind: "imported_procedure",
+            culprit: culprit("p:f/1"),
+        }),
+    );
+    // Clauses redefining an imported predicate are refused the same way.
+    for clause in ["f(x).", "f(X) :- X = x."] {
+        let mut compiler = Compiler::new();
+        for program in [":- module(p, [f/1]).", "f(p)."] {
+            compiler.add_program(&parse(program).unwrap()).unwrap();
+        }
+        compiler.set_source_module("user");
+        assert!(matches!(
+            compiler.add_program(&parse(clause).unwrap()),
+            Err(PrologError::PermissionError {
+                action: "redefine",
+                kind: "imported_procedure",
+                ..
+            })
+        ));
+    }
+
+    // An exported predicate the module does not define is unknown, not looked up forever.
+    for query in ["missing(X).", "m3:missing(X)."] {
+        let program = [":- module(m3, [exists/0, missing/1]).", "exists."];
+        check(
+            &program,
+            query,
+            Raises(PrologError::ExistenceError {
+                kind: "procedure",
+                culprit: culprit("m3:missing/1"),
+            }),
+        );
+    }
+}
+
+#[test]
+fn test_consult() {
+    let directory = std::env::temp_dir().join(format!("prolog_wan_consult_{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let write = |name: &str, text: &str| std::fs::write(directory.join(name), text).unwrap();
+    write(
+        "main.pl",
+        ":- include(log).
+:- ensure_loaded(lib).
+:- ensure_loaded('lib.pl').
+:- initialization(log(init)).
+:- log(directive).
+p(1).
+p(2).
+q(X) :- p(X), lib(X).
+r(file).
+:- fail.
+",
+    );
+    write(
+        "log.pl",
+        ":- dynamic(logged/1).
+log(X) :- assertz(logged(X)).
+:- log(included).
+",
+    );
+    write("lib.pl", "lib(1).\n:- log(lib).\n");
+    write(
+        "shapes.pl",
+        ":- module(shapes, [area/2]).\narea(S, A) :- A is S * S.\n",
+    );
+    let main = directory.join("main.pl");
+    let main = format!("main('{}').", main.display());
+
+    // Directives run in order, `initialization/1` once the file has been loaded, and files
+    // loaded by `ensure_loaded/1` only once.
+    let program = [
+        main.as_str(),
+        "t(L, Ps) :- main(F), consult(F), findall(X, logged(X), L), findall(P, q(P), Ps).",
+        "r(program).",
+    ];
+    check(
+        &program,
+        "t(L, Ps).",
+        Answers("L = [included,lib,directive,init], Ps = [1]"),
+    );
+
+    // Reconsulting replaces the predicates of the file.
+    let program = [
+        main.as_str(),
+        "t(Ps, Ls) :- main(F), consult(F), consult(F), findall(P, p(P), Ps), \
+            findall(X, logged(X), L), length(L, Ls).",
+    ];
+    check(&program, "t(Ps, Ls).", Answers("Ps = [1,2], Ls = 7"));
+
+    // Warnings point to the file and line.
+    let program = [main.as_str(), "r(program).", "t :- main(F), consult(F)."];
+    let interpreter = run(&program, "t.", "");
+    let file = directory.join("main.pl").canonicalize().unwrap();
+    assert_eq!(
+        interpreter.error_output(),
+        format!(
+            "Warning: {0}:9: Redefined static procedure r/1\n\
+             Warning: {0}:10: Goal (directive) failed: fail\n",
+            file.display()
+        )
+    );
+
+    // Module files are imported by `use_module/1`.
+    let shapes = directory.join("shapes");
+    let program = [format!(
+        "t(A) :- use_module('{}'), area(3, A).",
+        shapes.display()
+    )];
+    let program = program.iter().map(String::as_str).collect::<Vec<_>>();
+    check(&program, "t(A).", Answers("A = 9"));
+
+    // Loading from Rust.
+    let interpreter = Interpreter::consult(Compiler::new(), &shapes.display().to_string());
+    assert_eq!(interpreter.execution_state, ExecutionState::Normal);
+    let mut compiler = interpreter.into_compiler();
+    let artifact = compiler.compile(&parse("area(2, X).").unwrap());
+    let mut interpreter = Interpreter::new(compiler, &artifact);
+    while interpreter.step() {}
+    assert_eq!(interpreter.answer(), "X = 4");
+
+    // The loading internals fail when no file is being loaded.
+    for query in [
+        "'$end_consult'(X).",
+        "'$load_source'(X, f).",
+        "'$add_clause'(c, 1).",
+    ] {
+        assert!(!helper_execute("p.", query).success, "{}", query);
+    }
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_dcg() {
+    let program = [
+        "greeting --> [hello], name.",
+        "name --> [world].",
+        "name --> \"prolog\".",
+        "digits([D|Ds]) --> digit(D), !, digits(Ds).",
+        "digits([]) --> [].",
+        "digit(D) --> [C], { C >= 48, C =< 57, D is C - 48 }.",
+        "look(X), [X] --> [X].",
+        "pair(X, Y) --> call(item, X), [-], call(item, Y).",
+        "item(X) --> [X].",
+        "not_a --> \\+ [a], [_].",
+        "sign(S) --> ( [-] -> { S = -1 } ; { S = 1 } ).",
+        "t1(R) :- phrase(greeting, [hello, world, more], R).",
+        "t2(Ds, R) :- phrase(digits(Ds), \"12a\", R).",
+        "t3(X, R) :- phrase(look(X), [a, b], R).",
+        "t4(A, B) :- phrase(pair(A, B), [1, -, 2]).",
+        "t5(S, R) :- phrase(sign(S), [-, 3], R).",
+        "t6(L) :- findall(X, phrase(([X] ; [b, X]), [b, c]), L).",
+        ":- module(g, [parse/2]).",
+        "parse(L, X) :- phrase(tok(X), L).",
+        "tok(x) --> [x].",
+    ];
+
+    check(&program, "phrase(greeting, [hello, world]).", Succeeds);
+    check(&program, "phrase(greeting, [hello|\"prolog\"]).", Succeeds);
+    check(&program, "t1(R).", Answers("R = [more]"));
+    check(&program, "t2(Ds, R).", Answers("Ds = [1,2], R = [97]"));
+    check(&program, "t3(X, R).", Answers("X = a, R = [a,b]"));
+    check(&program, "t4(A, B).", Answers("A = 1, B = 2"));
+    check(&program, "t5(S, R).", Answers("S = -1, R = [3]"));
+    check(&program, "t6(L).", Answers("L = [c]"));
+    check(&program, "phrase(not_a, [b]).", Succeeds);
+    check(&program, "phrase(not_a, [a]).", Fails);
+    check(&program, "parse([x], x).", Succeeds);
+    check(
+        &program,
+        "phrase(_, []).",
+        Raises(PrologError::InstantiationError),
+    );
+}
+
+#[test]
+fn test_expansion() {
+    let path = std::env::temp_dir().join(format!("prolog_wan_expansion_{}.pl", std::process::id()));
+    std::fs::write(
+        &path,
+        "term_expansion(gen(N), Clauses) :- findall(num(I), between(1, N, I), Clauses).
+term_expansion(skip(_), []).
+term_expansion(clash, atom(x)).
+goal_expansion(double(X, Y), Y is X * 2).
+goal_expansion(twice(G), (G, G)).
+:- dynamic(logged/1).
+log(X) :- assertz(logged(X)).
+gen(3).
+skip(me).
+d(X, Y) :- double(X, Y).
+t :- twice(log(a)).
+g(Y) --> [X], { double(X, Y) }.
+clash.
+",
+    )
+    .unwrap();
+    let file = format!("file('{}').", path.display());
+    let program = [
+        file.as_str(),
+        "t(Ns, Y, L, Z) :- file(F), consult(F), findall(N, num(N), Ns), d(4, Y), t, \
+            findall(X, logged(X), L), phrase(g(Z), [5]).",
+        "skipped :- file(F), consult(F), \\+ current_predicate(skip/1).",
+        "loaded :- file(F), consult(F).",
+    ];
+
+    // Clauses from term_expansion/2 replace the term read, goal_expansion/2 rewrites goals
+    // until nothing changes.
+    check(
+        &program,
+        "t(Ns, Y, L, Z).",
+        Answers("Ns = [1,2,3], Y = 8, L = [a,a], Z = 10"),
+    );
+    check(&program, "skipped.", Succeeds);
+
+    // Errors in expanded clauses point to the term read.
+    let interpreter = run(&program, "loaded.", "");
+    assert_eq!(
+        interpreter.error_output(),
+        format!(
+            "Error: {}:13: permission_error(modify,static_procedure,atom/1)\n",
+            path.canonicalize().unwrap().display()
+        )
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_flags() {
+    let program = [
+        "unknown(V) :- set_prolog_flag(unknown, fail), current_prolog_flag(unknown, V), \
+            \\+ undefined.",
+        "warn :- set_prolog_flag(unknown, warning), \\+ undefined.",
+        "chars(X) :- set_prolog_flag(double_quotes, chars), read(X).",
+        "as_atom(X) :- set_prolog_flag(double_quotes, atom), read(X).",
+        "as_string(X) :- set_prolog_flag(double_quotes, string), read(X), string(X).",
+    ];
+    check(
+        &program,
+        "current_prolog_flag(bounded, B).",
+        Answers("B = false"),
+    );
+    check(
+        &program,
+        "current_prolog_flag(max_integer, M).",
+        Answers("M = 9223372036854775807"),
+    );
+    check(
+        &program,
+        "current_prolog_flag(max_arity, A).",
+        Answers("A = 1048576"),
+    );
+    check(
+        &program,
+        "current_prolog_flag(occurs_check, O).",
+        Answers("O = false"),
+    );
+    check(
+        &program,
+        "set_prolog_flag(prefer_rationals, true), X is 1 / 3.",
+        Answers("X = 1r3"),
+    );
+    check(
+        &program,
+        "set_prolog_flag(debug, true), current_prolog_flag(debug, D).",
+        Answers("D = true"),
+    );
+    check(&program, "unknown(V).", Answers("V = fail"));
+    // A bound flag name is looked up directly, an unbound one enumerates every flag.
+    assert!(helper_execute_multi(&program, "current_prolog_flag(double_quotes, X).").deterministic);
+    assert!(!helper_execute_multi(&program, "current_prolog_flag(F, X).").deterministic);
+
+    let interpreter = run(&program, "warn.", "");
+    assert_eq!(
+        interpreter.error_output(),
+        "Warning: Unknown procedure: undefined/0\n"
+    );
+    let interpreter = run(&program, "chars(X).", "\"ab\".");
+    assert_eq!(interpreter.answer(), "X = [a,b]");
+    let interpreter = run(&program, "as_atom(X).", "\"ab\".");
+    assert_eq!(interpreter.answer(), "X = ab");
+    let interpreter = run(&program, "as_string(X).", "\"ab\".");
+    assert_eq!(interpreter.answer(), "X = \"ab\"");
+
+    check(
+        &program,
+        "set_prolog_flag(bounded, true).",
+        Raises(PrologError::PermissionError {
+            action: "modify",
+            kind: "flag",
+            culprit: culprit("bounded"),
+        }),
+    );
+    check(
+        &program,
+        "set_prolog_flag(colour, red).",
+        Raises(PrologError::DomainError {
+            domain: "prolog_flag",
+            culprit: culprit("colour"),
+        }),
+    );
+    check(
+        &program,
+        "set_prolog_flag(unknown, 1).",
+        Raises(PrologError::DomainError {
+            domain: "flag_value",
+            culprit: culprit("unknown+1"),
+        }),
+    );
+    check(
+        &program,
+        "set_prolog_flag(_, true).",
+        Raises(PrologError::InstantiationError),
+    );
+
+    // The same validation applies from Rust.
+    let mut flags = Flags::default();
+    assert_eq!(flags.set("debug", "true"), Ok(()));
+    assert_eq!(flags.get("debug"), Some(FlagValue::Atom("true")));
+    assert_eq!(flags.set("debug", "maybe"), Err(FlagError::InvalidValue));
+    assert_eq!(flags.set("prefer_rationals", "true"), Ok(()));
+    assert_eq!(flags.get("prefer_rationals"), Some(FlagValue::Atom("true")));
+    assert_eq!(
+        flags.set("prefer_rationals", "maybe"),
+        Err(FlagError::InvalidValue)
+    );
+    assert_eq!(flags.set("bounded", "true"), Err(FlagError::ReadOnly));
+    assert_eq!(flags.set("colour", "red"), Err(FlagError::UnknownFlag));
+}
+
+#[test]
+fn test_occurs_check() {
+    let program = [
+        "same(X, X).",
+        "wrap(X, f(X)).",
+        "nest(X, f(g(X), a)).",
+        "pair(f(X), X).",
+        "check(Mode, Goal) :- set_prolog_flag(occurs_check, Mode), call(Goal).",
+        "first(X) :- X = f(Y), Y = g(Z), Z = 1.",
+        "cyclic :- wrap(X, X), X = f(_).",
+        "apart(X) :- X \\= f(X).",
+    ];
+    // Without the flag, cycles are created silently.
+    check(&program, "cyclic.", Succeeds);
+    check(&program, "check(false, \\+ \\+ nest(X, X)).", Succeeds);
+    check(&program, "check(true, X = f(X)).", Fails);
+    check(&program, "check(true, same(X, f(X))).", Fails);
+    check(&program, "check(true, wrap(X, X)).", Fails);
+    check(&program, "check(true, nest(X, X)).", Fails);
+    check(&program, "check(true, pair(X, X)).", Fails);
+    check(&program, "check(true, apart(_)).", Succeeds);
+    check(
+        &program,
+        "check(true, (wrap(X, Y), Y = f(f(Z)), X == f(Z))).",
+        Succeeds,
+    );
+    check(&program, "check(true, first(_)).", Succeeds);
+    check(&program, "check(true, (wrap(X, X) ; X = a)).", Succeeds);
+    check(&program, "unify_with_occurs_check(X, g(X)).", Fails);
+
+    let exception = |query: &str| run(&program, query, "").exception;
+    let exception = |query: &str| run(&program, query, "").exception;
+    // The error names the variable after its address, so the term is compared with that name.
+    for (query, term) in [
+        ("check(error, X = f(X)).", "f(V)"),
+        ("check(error, wrap(X, X)).", "f(V)"),
+        ("check(error, (fail ; nest(Y, Y))).", "g(f(V,a))"),
+    ] {
+        let Some(PrologError::OccursCheck {
+            variable,
+            term: actual,
+        }) = exception(query)
+        else {
+            panic!("{}", query);
+        };
+        let ErrorCulprit::Term(AbstractTerm::Variable(name)) = variable.as_ref() else {
+            panic!("{}: {:?}", query, variable);
+        };
+        assert_eq!(*actual, culprit(&term.replace('V', name)), "{}", query);
+    }
+    assert!(matches!(
+        exception("check(error, (X = a ; nest(Y, Y)))."),
+        None
+    ));
+}
+
+#[test]
+fn test_cyclic_terms() {
+    let program = [
+        "loop(X) :- X = f(X).",
+        "same :- X = f(X), Y = f(f(Y)), X = Y, X == Y, compare(=, X, Y).",
+        "copy :- X = f(X, A), copy_term(X, Y), Y = f(Z, B), Z == Y, B \\== A.",
+        "stored :- X = [a|X], findall(X, true, [Y]), Y = [a, a|Z], Z == Y.",
+        "shapes :- X = [a|X], cyclic_term(X), \\+ acyclic_term(X), \\+ is_list(X), \
+            acyclic_term(f(Y, Y)), ground(X).",
+        "show :- X = f(X, Y), Y = g(Y), write(X).",
+        "both(X, Y) :- loop(X), Y = g(X, Y).",
+        "mismatch :- loop(X), loop(Y), X = g(Y).",
+        "assert_loop :- loop(X), assertz(X).",
+        "evaluate_loop :- X = X + 1, _ is X.",
+        "call_loop :- X = (true, X), call(X).",
+    ];
+    let query = |query: &str| helper_execute_multi(&program, query);
+    check(&program, "loop(X).", Answers("X = _S1, _S1 = f(_S1)"));
+    check(
+        &program,
+        "both(X, Y).",
+        Answers("X = _S1, Y = _S2, _S1 = f(_S1), _S2 = g(_S1,_S2)"),
+    );
+    for goal in ["same.", "copy.", "stored.", "shapes."] {
+        assert!(query(goal).success, "{}", goal);
+    }
+    check(&program, "mismatch.", Fails);
+    check(
+        &program,
+        "show.",
+        Prints("@(_S1,[_S1=f(_S1,_S2),_S2=g(_S2)])"),
+    );
+
+    let exception = |query: &str| run(&program, query, "").exception;
+    for goal in ["assert_loop.", "evaluate_loop.", "call_loop."] {
+        assert!(
+            matches!(
+                exception(goal),
+                Some(PrologError::TypeError {
+                    expected: "acyclic_term",
+                    ..
+                })
+            ),
+            "{}",
+            goal
+        );
+    }
+}
+
+#[test]
+fn test_coroutining() {
+    let program = [
+        "colour(red).",
+        "colour(green).",
+        "domain:attr_unify_hook(Domain, Value) :- memberchk(Value, Domain).",
+    ];
+
+    check(
+        &program,
+        "put_attr(X, m, 1), put_attr(X, m, 2), get_attr(X, m, V).",
+        Answers("V = 2, put_attr(X,m,2)"),
+    );
+    check(
+        &program,
+        "put_attr(X, m, 1), del_attr(X, m), attvar(X).",
+        Fails,
+    );
+    check(
+        &program,
+        "put_attr(X, m, 1), (put_attr(X, m, 2), fail ; get_attr(X, m, V)).",
+        Answers("V = 1, put_attr(X,m,1)"),
+    );
+    check(&program, "(put_attr(X, m, 1), fail ; attvar(X)).", Fails);
+    check(
+        &program,
+        "put_attr(X, domain, [a, b]), X = a.",
+        Answers("X = a"),
+    );
+    check(&program, "put_attr(X, domain, [a, b]), X = c.", Fails);
+    check(
+        &program,
+        "put_attr(X, m, 1), copy_term(X, Y), get_attr(Y, m, V).",
+        Answers("V = 1, put_attr(X,m,1), put_attr(Y,m,1)"),
+    );
+    check(
+        &program,
+        "term_variables(f(X, g(Y, X)), Vs).",
+        Answers("Vs = [X,Y]"),
+    );
+    check(
+        &program,
+        "put_attr(a, m, 1).",
+        Raises(PrologError::UninstantiationError(culprit("a"))),
+    );
+
+    check(
+        &program,
+        "freeze(X, Y = done).",
+        Answers("freeze(X,Y=done)"),
+    );
+    check(
+        &program,
+        "freeze(X, Y = done), X = 1.",
+        Answers("X = 1, Y = done"),
+    );
+    check(&program, "freeze(X, fail), X = 1.", Fails);
+    check(
+        &program,
+        "freeze(X, Y = X), colour(X).",
+        Answers("X = red, Y = red\nX = green, Y = green"),
+    );
+    check(
+        &program,
+        "freeze(X, true), frozen(X, G).",
+        Answers("G = freeze(X,true), freeze(X,true)"),
+    );
+
+    check(&program, "dif(X, a).", Answers("dif(X,a)"));
+    check(&program, "dif(X, a), X = a.", Fails);
+    check(&program, "dif(X, a), X = b.", Answers("X = b"));
+    check(&program, "dif(X, Y), X = Y.", Fails);
+    check(&program, "dif(f(X, Y), f(a, b)), X = c.", Answers("X = c"));
+    check(&program, "dif(X, red), colour(X).", Answers("X = green"));
+
+    check(
+        &program,
+        "when(nonvar(X), Y = ok), X = 1.",
+        Answers("X = 1, Y = ok"),
+    );
+    check(
+        &program,
+        "when(ground(f(X, Y)), Z = ok), X = 1.",
+        Answers("X = 1, when(ground(f(1,Y)),Z=ok)"),
+    );
+    check(
+        &program,
+        "when((nonvar(X) ; nonvar(Y)), Z = ok), X = 1, Y = 2.",
+        Answers("X = 1, Y = 2, Z = ok"),
+    );
+    check(
+        &program,
+        "when(?=(X, Y), Z = ok), X = a, Y = b.",
+        Answers("X = a, Y = b, Z = ok"),
+    );
+    check(
+        &program,
+        "when((nonvar(X), nonvar(Y)), Z = ok).",
+        Answers("when((nonvar(X),nonvar(Y)),Z=ok)"),
+    );
+}
+
+#[test]
+fn test_clpfd() {
+    let program = [
+        "puzzle([S,E,N,D,M,O,R,Y]) :- Vars = [S,E,N,D,M,O,R,Y], Vars ins 0..9, all_different(Vars), \
+         S #\\= 0, M #\\= 0, 1000*S + 100*E + 10*N + D + 1000*M + 100*O + 10*R + E \
+         #= 10000*M + 1000*O + 100*N + 10*E + Y, label(Vars).",
+    ];
+
+    check(&program, "X #= 1 + 2.", Answers("X = 3"));
+    check(&program, "3 #= X + 1.", Answers("X = 2"));
+    check(&program, "X #> 3.", Answers("X in 4..sup"));
+    check(&program, "X in 1..3, X #\\= 2.", Answers("X in 1\\/3"));
+    check(
+        &program,
+        "X in 1..3, Y #= X + 1.",
+        Answers("X in 1..3, Y#=X+1, Y in 2..4"),
+    );
+    check(
+        &program,
+        "X in 1..3 \\/ 5..7, X #> 3, X #< 6.",
+        Answers("X = 5"),
+    );
+    check(&program, "X in 1..3, X = 4.", Fails);
+    check(&program, "X #> Y, Y #> X, X in 0..10.", Fails);
+
+    check(
+        &program,
+        "X in 1..3, X #\\= 2, label([X]).",
+        Answers("X = 1\nX = 3"),
+    );
+    check(
+        &program,
+        "[X, Y] ins 1..3, X #< Y, labeling([down], [X, Y]).",
+        Answers("X = 2, Y = 3\nX = 1, Y = 3\nX = 1, Y = 2"),
+    );
+    check(
+        &program,
+        "X*Y #= 12, [X, Y] ins 1..12, X #< Y, labeling([ff], [X, Y]).",
+        Answers("X = 1, Y = 12\nX = 2, Y = 6\nX = 3, Y = 4"),
+    );
+    check(
+        &program,
+        "abs(X) #= 3, label([X]).",
+        Answers("X = -3\nX = 3"),
+    );
+    check(
+        &program,
+        "X #= 7 // 2 + 7 mod 3 + -7 rem 3.",
+        Answers("X = 3"),
+    );
+    check(&program, "X #= max(3, 5) - min(3, 5).", Answers("X = 2"));
+    check(
+        &program,
+        "X in 0..10, X mod 3 #= 2, X // 4 #= 1, label([X]).",
+        Answers("X = 5"),
+    );
+    check(
+        &program,
+        "X in 0..5, min(X, 3) #>= 3, max(X, 4) #= 4, label([X]).",
+        Answers("X = 3\nX = 4"),
+    );
+    check(&program, "X #= 1 // 0.", Fails);
+    check(
+        &program,
+        "X in 1..3, labeling([max(X)], [X]).",
+        Answers("X = 3\nX = 2\nX = 1"),
+    );
+    check(
+        &program,
+        "[X, Y] ins 1..3, X + Y #= 4, labeling([min(X - Y)], [X, Y]).",
+        Answers("X = 1, Y = 3\nX = 2, Y = 2\nX = 3, Y = 1"),
+    );
+    check(
+        &program,
+        "Vs = [A, B, C], Vs ins 1..3, all_distinct(Vs), sum(Vs, #=, 6), A #> B, B #> C.",
+        Answers("Vs = [3,2,1], A = 3, B = 2, C = 1"),
+    );
+    check(
+        &program,
+        "[A, B, C] ins 1..2, all_distinct([A, B, C]).",
+        Fails,
+    );
+    check(&program, "puzzle(Vs).", Answers("Vs = [9,5,6,7,1,0,8,2]"));
+
+    assert!(matches!(
+        run(&program, "label([X]).", "").exception,
+        Some(PrologError::InstantiationError)
+    ));
+    check(
+        &program,
+        "X #= a.",
+        Raises(PrologError::TypeError {
+            expected: "integer",
+            culprit: culprit("a"),
+        }),
+    );
+    for (options, option) in [
+        ("[foo]", "foo"),
+        ("[foo(1)]", "foo(1)"),
+        ("[ff, up, 1]", "1"),
+    ] {
+        check(
+            &program,
+            &format!("X in 1..3, labeling({}, [X]).", options),
+            Raises(PrologError::DomainError {
+                domain: "labeling_option",
+                culprit: culprit(option),
+            }),
+        );
+    }
+    check(
+        &program,
+        "X in 1..3, labeling([_], [X]).",
+        Raises(PrologError::InstantiationError),
+    );
+}